@@ -0,0 +1,87 @@
+// build.rs collects information about the build, and writes it into a
+// Rust source file in OUT_DIR. The engine includes this file (see
+// misc/build_info.rs) so it can report exactly which binary is running.
+// This helps a lot when handling bug reports.
+
+use std::{
+    env, fs,
+    path::Path,
+    process::Command,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+const UNKNOWN: &str = "unknown";
+const SECONDS_PER_DAY: u64 = 86_400;
+
+fn main() {
+    let git_hash = command_output("git", &["rev-parse", "--short", "HEAD"]);
+    let rustc = env::var("RUSTC").unwrap_or_else(|_| String::from("rustc"));
+    let rustc_version = command_output(&rustc, &["--version"]);
+    let build_date = build_date();
+
+    let out_dir = env::var("OUT_DIR").expect("Cargo did not set OUT_DIR");
+    let file = Path::new(&out_dir).join("build_info.rs");
+    let contents = format!(
+        "pub const GIT_HASH: &str = {git_hash:?};\n\
+         pub const BUILD_DATE: &str = {build_date:?};\n\
+         pub const RUSTC_VERSION: &str = {rustc_version:?};\n"
+    );
+    fs::write(file, contents).expect("Could not write build_info.rs");
+
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/index");
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+}
+
+// Runs a command and returns the first line of its output, or "unknown"
+// if the command is not available or fails.
+fn command_output(cmd: &str, args: &[&str]) -> String {
+    Command::new(cmd)
+        .args(args)
+        .output()
+        .ok()
+        .filter(|o| o.status.success())
+        .and_then(|o| String::from_utf8(o.stdout).ok())
+        .and_then(|s| s.lines().next().map(|l| l.trim().to_string()))
+        .filter(|s| !s.is_empty())
+        .unwrap_or_else(|| String::from(UNKNOWN))
+}
+
+// Returns the build date as YYYY-MM-DD (UTC). If SOURCE_DATE_EPOCH is set,
+// that date is used instead of today, so builds can be reproduced.
+fn build_date() -> String {
+    let seconds = env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|s| s.parse::<u64>().ok())
+        .or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .ok()
+                .map(|d| d.as_secs())
+        });
+
+    match seconds {
+        Some(s) => {
+            let (y, m, d) = civil_from_days((s / SECONDS_PER_DAY) as i64);
+            format!("{y:04}-{m:02}-{d:02}")
+        }
+        None => String::from(UNKNOWN),
+    }
+}
+
+// Converts a number of days since 1970-01-01 into a (year, month, day)
+// date in the proleptic Gregorian calendar. (Howard Hinnant's algorithm.)
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let y = yoe + era * 400 + i64::from(m <= 2);
+
+    (y, m, d)
+}
@@ -0,0 +1,743 @@
+/* =======================================================================
+Rustic is a chess playing engine.
+Copyright (C) 2019-2024, Marcel Vanthoor
+https://rustic-chess.org/
+
+Rustic is written in the Rust programming language. It is an original
+work, not derived from any engine that came before it. However, it does
+use a lot of concepts which are well-known and are in use by most if not
+all classical alpha/beta-based chess engines.
+
+Rustic is free software: you can redistribute it and/or modify it under
+the terms of the GNU General Public License version 3 as published by
+the Free Software Foundation.
+
+Rustic is distributed in the hope that it will be useful, but WITHOUT
+ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License
+for more details.
+
+You should have received a copy of the GNU General Public License along
+with this program.  If not, see <http://www.gnu.org/licenses/>.
+======================================================================= */
+//! The public API of Rustic, for programs that want to use its move
+//! generator and search without talking UCI to a separate process.
+//!
+//! - [`Position`] is a chess position. It can be set up from a FEN-string,
+//!   lists its legal moves, and plays moves in long algebraic notation.
+//!   It can also count its leaf nodes (perft), per root move if needed.
+//! - [`Game`] is a game in PGN: a starting position, the moves played
+//!   from there, and the headers. [`read_pgn`] reads games from a text.
+//! - [`EpdRecord`] is a position with EPD operations, as found in test
+//!   suites.
+//! - [`Engine`] searches a position. It is configured with an
+//!   [`EngineBuilder`], and keeps its hash table between searches.
+//! - [`SearchLimits`] determines when a search stops.
+//! - [`UciEngine`] is the complete engine, driven with UCI commands like
+//!   a separate process, but running inside this program.
+//! - [`SyncEngine`] searches on the calling thread. It works where
+//!   threads are not available, such as in WebAssembly. ([`Engine`] and
+//!   [`UciEngine`] need threads, and are not compiled for WebAssembly.)
+//!
+//! A search blocks until it is done. Progress can be followed through a
+//! callback, which is called after each completed depth.
+//!
+//! ```no_run
+//! use librustic::api::{Engine, Position, SearchLimits};
+//!
+//! let mut position = Position::new();
+//! position.play("e2e4").unwrap();
+//!
+//! let mut engine = Engine::builder().hash_size(64).threads(2).build();
+//! let limits = SearchLimits::new().depth(8);
+//! let result = engine.search_with(&position, &limits, |progress| {
+//!     println!("depth {} score {:?} pv {:?}", progress.depth, progress.score, progress.pv);
+//! });
+//! println!("best move {:?}", result.best_move);
+//! ```
+
+use crate::{
+    board::{defs::ReplayError, Board},
+    defs::Sides,
+    engine::defs::{EngineOptionDefaults, ErrFatal, SearchData, TT},
+    evaluation,
+    misc::{
+        perft::{self, PerftCache},
+        pgn::{self, GameResult, PgnGame},
+    },
+    movegen::{
+        defs::{Move, MoveList, MoveType},
+        MoveGenerator,
+    },
+    search::{
+        defs::{GameTime, SearchMode, SearchParams, SearchReport, SearchSummary},
+        Search,
+    },
+};
+use std::{
+    cell::RefCell,
+    fmt::{self, Display},
+    sync::{Arc, Mutex, OnceLock, RwLock},
+};
+
+// Engine and UciEngine run their search in a thread of their own.
+#[cfg(not(target_arch = "wasm32"))]
+use crate::{
+    comm::uci::Uci,
+    engine,
+    engine::defs::{info_channel, InfoReceiver, Information},
+    misc::cpu,
+    search::defs::SearchControl,
+};
+#[cfg(not(target_arch = "wasm32"))]
+use crossbeam_channel::{Receiver, Sender};
+#[cfg(not(target_arch = "wasm32"))]
+use std::{
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+pub use crate::misc::epd::EpdRecord;
+
+// All positions and engines share one move generator. Creating it means
+// calculating the magic tables, which only needs to be done once.
+fn move_generator() -> Arc<MoveGenerator> {
+    static MG: OnceLock<Arc<MoveGenerator>> = OnceLock::new();
+    MG.get_or_init(|| Arc::new(MoveGenerator::new())).clone()
+}
+
+/// Errors returned by the API.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ApiError {
+    /// The FEN-string could not be read.
+    Fen(String),
+    /// The move is not legal in the position, or can't be read.
+    IllegalMove(String),
+    /// The game could not be read from PGN, or written to it.
+    Pgn(String),
+}
+
+impl Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApiError::Fen(fen) => write!(f, "invalid FEN-string: {fen}"),
+            ApiError::IllegalMove(m) => write!(f, "illegal move: {m}"),
+            ApiError::Pgn(e) => write!(f, "invalid PGN: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+/// A chess position.
+#[derive(Clone)]
+pub struct Position {
+    board: Board,
+    mg: Arc<MoveGenerator>,
+}
+
+impl Position {
+    /// The starting position.
+    pub fn new() -> Self {
+        let mut board = Board::new();
+        board
+            .fen_read(None)
+            .expect("The starting position is valid");
+        Self {
+            board,
+            mg: move_generator(),
+        }
+    }
+
+    /// Sets up a position from a FEN-string. The move counters may be
+    /// left out.
+    pub fn from_fen(fen: &str) -> Result<Self, ApiError> {
+        let mut board = Board::new();
+        match board.fen_read(Some(fen)) {
+            Ok(()) => Ok(Self {
+                board,
+                mg: move_generator(),
+            }),
+            Err(_) => Err(ApiError::Fen(fen.to_string())),
+        }
+    }
+
+    /// Sets up a position from a FEN-string, or the starting position
+    /// without one, and plays the moves in long algebraic notation. Each
+    /// move must be legal in the position it is played in.
+    pub fn from_moves(fen: Option<&str>, moves: &[&str]) -> Result<Self, ApiError> {
+        let mg = move_generator();
+        let mut board = Board::new();
+        match board.replay_moves(fen, moves, &mg) {
+            Ok(()) => Ok(Self { board, mg }),
+            Err(ReplayError::Fen) => Err(ApiError::Fen(fen.unwrap_or("").to_string())),
+            Err(ReplayError::Move(m)) => Err(ApiError::IllegalMove(m)),
+        }
+    }
+
+    /// The position as a FEN-string.
+    pub fn fen(&self) -> String {
+        self.board.fen_write()
+    }
+
+    /// True if White is to move.
+    pub fn white_to_move(&self) -> bool {
+        self.board.us() == Sides::WHITE
+    }
+
+    /// The legal moves, in long algebraic notation ("e2e4", "e7e8q").
+    pub fn legal_moves(&self) -> Vec<String> {
+        let mut move_list = MoveList::new();
+        self.mg
+            .generate_moves(&self.board, &mut move_list, MoveType::Legal);
+
+        (0..move_list.len())
+            .map(|i| move_list.get_move(i).as_string())
+            .collect()
+    }
+
+    /// Plays a move in long algebraic notation. If the move is not
+    /// legal, the position doesn't change.
+    pub fn play(&mut self, m: &str) -> Result<(), ApiError> {
+        if self.board.play_move(m, &self.mg) {
+            Ok(())
+        } else {
+            Err(ApiError::IllegalMove(m.to_string()))
+        }
+    }
+
+    /// Takes back the last move played, if there is one.
+    pub fn undo(&mut self) {
+        if self.board.history.len() > 0 {
+            self.board.unmake();
+        }
+    }
+
+    /// The static evaluation in centipawns, from the point of view of the
+    /// side to move.
+    pub fn evaluate(&self) -> i16 {
+        evaluation::evaluate_position(&self.board)
+    }
+
+    /// The number of leaf nodes at the given depth (perft).
+    pub fn perft(&self, depth: i8) -> u64 {
+        let mut board = self.board.clone();
+        let mut cache = PerftCache::new(0);
+        perft::perft(&mut board, depth, &self.mg, &mut cache)
+    }
+
+    /// The number of leaf nodes at the given depth for each legal move,
+    /// sorted by move ("perft divide"). The moves are counted on the given
+    /// number of threads.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn divide(&self, depth: i8, threads: usize) -> Vec<(String, u64)> {
+        let mut caches = PerftCache::per_thread(0, threads);
+        perft::divide(&self.board, depth, &self.mg, &mut caches)
+            .into_iter()
+            .map(|(m, leaf_nodes)| (m.as_string(), leaf_nodes))
+            .collect()
+    }
+}
+
+impl Default for Position {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Returns the static evaluation of the position in centipawns, from the
+/// point of view of the side to move.
+pub fn evaluate_fen(fen: &str) -> Result<i16, ApiError> {
+    Ok(Position::from_fen(fen)?.evaluate())
+}
+
+/// Returns the number of leaf nodes of the position at the given depth.
+///
+/// ```
+/// let fen = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+/// assert_eq!(librustic::perft_fen(fen, 3), Ok(8902));
+/// ```
+pub fn perft_fen(fen: &str, depth: i8) -> Result<u64, ApiError> {
+    Ok(Position::from_fen(fen)?.perft(depth))
+}
+
+/// A game: the starting position, the moves played from there, and the
+/// headers (tag pairs) of its PGN. Comments and annotations are kept when
+/// a game is read, and written back out.
+///
+/// ```
+/// use librustic::api::{Game, Position};
+///
+/// let mut game = Game::new(&Position::new());
+/// game.play("e2e4").unwrap();
+/// game.play("e7e5").unwrap();
+/// assert!(game.to_pgn().unwrap().contains("1. e4 e5"));
+/// ```
+#[derive(Clone)]
+pub struct Game {
+    game: PgnGame,
+    mg: Arc<MoveGenerator>,
+}
+
+impl Game {
+    /// An empty game, starting from the given position.
+    pub fn new(start: &Position) -> Self {
+        Self {
+            game: PgnGame::new(Some(&start.fen())),
+            mg: move_generator(),
+        }
+    }
+
+    /// The value of the header with this key ("White", "Event").
+    pub fn header(&self, key: &str) -> Option<&str> {
+        self.game.header(key)
+    }
+
+    /// Sets the value of a header, adding it if it doesn't exist yet.
+    pub fn set_header(&mut self, key: &str, value: &str) {
+        self.game.set_header(key, value);
+    }
+
+    /// The moves of the game, in long algebraic notation.
+    pub fn moves(&self) -> Vec<String> {
+        self.game.moves.iter().map(|pm| pm.m.as_string()).collect()
+    }
+
+    /// The result: "1-0", "0-1", "1/2-1/2", or "*" if it is not known.
+    pub fn result(&self) -> &'static str {
+        self.game.result.as_str()
+    }
+
+    /// Sets the result, written as in [`Game::result`].
+    pub fn set_result(&mut self, result: &str) -> Result<(), ApiError> {
+        match GameResult::parse(result) {
+            Some(r) => {
+                self.game.result = r;
+                Ok(())
+            }
+            None => Err(ApiError::Pgn(format!("unknown result: {result}"))),
+        }
+    }
+
+    /// The position at the end of the game.
+    pub fn position(&self) -> Result<Position, ApiError> {
+        let board = self.game.replay(&self.mg, |_| ()).map_err(ApiError::Pgn)?;
+        Ok(Position {
+            board,
+            mg: Arc::clone(&self.mg),
+        })
+    }
+
+    /// Plays a move in long algebraic notation at the end of the game.
+    pub fn play(&mut self, m: &str) -> Result<(), ApiError> {
+        let mut position = self.position()?;
+        position.play(m)?;
+
+        let history = &position.board.history;
+        self.game.push(history.get_ref(history.len() - 1).next_move);
+        Ok(())
+    }
+
+    /// The game in PGN, with the moves in SAN.
+    pub fn to_pgn(&self) -> Result<String, ApiError> {
+        self.game.write(&self.mg).map_err(ApiError::Pgn)
+    }
+}
+
+/// Reads all the games in a PGN text. A game that can't be read gives an
+/// error, but doesn't stop the games after it from being read.
+pub fn read_pgn(text: &str) -> Vec<Result<Game, ApiError>> {
+    let mg = move_generator();
+    pgn::read(text, &mg)
+        .into_iter()
+        .map(|result| match result {
+            Ok(game) => Ok(Game {
+                game,
+                mg: Arc::clone(&mg),
+            }),
+            Err(e) => Err(ApiError::Pgn(e)),
+        })
+        .collect()
+}
+
+/// Determines when a search stops. Without limits, the search runs until
+/// it reaches the maximum depth. If several limits are set, the last one
+/// counts.
+#[derive(Clone)]
+pub struct SearchLimits {
+    params: SearchParams,
+}
+
+impl SearchLimits {
+    /// A search without limits.
+    pub fn new() -> Self {
+        let mut params = SearchParams::new();
+        params.search_mode = SearchMode::Depth;
+        Self { params }
+    }
+
+    /// Search to the given depth, in plies.
+    pub fn depth(mut self, depth: i8) -> Self {
+        self.params.depth = depth.max(1);
+        self.params.search_mode = SearchMode::Depth;
+        self
+    }
+
+    /// Search this many nodes.
+    pub fn nodes(mut self, nodes: usize) -> Self {
+        self.params.nodes = nodes;
+        self.params.search_mode = SearchMode::Nodes;
+        self
+    }
+
+    /// Search for this many milliseconds.
+    pub fn move_time(mut self, msecs: u128) -> Self {
+        self.params.move_time = msecs;
+        self.params.search_mode = SearchMode::MoveTime;
+        self
+    }
+
+    /// Let the engine decide how long to search, from the time left on
+    /// the clocks and the increments, in milliseconds.
+    pub fn game_time(mut self, wtime: u128, btime: u128, winc: u128, binc: u128) -> Self {
+        self.params.game_time = GameTime::new(wtime, btime, winc, binc, None);
+        self.params.search_mode = SearchMode::GameTime;
+        self
+    }
+
+    /// The number of moves until the next time control, for game_time().
+    pub fn moves_to_go(mut self, moves: usize) -> Self {
+        self.params.game_time.moves_to_go = Some(moves);
+        self
+    }
+
+    /// Search until a mate in at most this many moves is found.
+    pub fn mate(mut self, moves: u8) -> Self {
+        self.params.search_mode = SearchMode::Mate(moves);
+        self
+    }
+}
+
+impl Default for SearchLimits {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The score of a position, from the point of view of the side to move.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Score {
+    /// Centipawns.
+    Cp(i16),
+    /// Mate in this many moves. Negative if the side to move is mated.
+    Mate(i16),
+}
+
+/// The state of a search after a completed depth.
+#[derive(Debug, Clone)]
+pub struct SearchProgress {
+    pub depth: i8,
+    pub seldepth: i16,
+    pub score: Score,
+    pub nodes: usize,
+    pub nps: usize,
+    pub time_ms: u128,
+    pub pv: Vec<String>,
+}
+
+impl SearchProgress {
+    fn from_summary(s: &SearchSummary) -> Self {
+        Self {
+            depth: s.depth,
+            seldepth: s.seldepth,
+            score: s.mate_in().map_or(Score::Cp(s.cp), Score::Mate),
+            nodes: s.nodes,
+            nps: s.nps,
+            time_ms: s.time,
+            pv: s.pv.iter().map(|m| m.as_string()).collect(),
+        }
+    }
+}
+
+/// The result of a search. If the position has no legal moves, there is
+/// no best move.
+#[derive(Debug, Clone)]
+pub struct SearchResult {
+    pub best_move: Option<String>,
+    pub ponder_move: Option<String>,
+    pub progress: Option<SearchProgress>,
+}
+
+impl SearchResult {
+    fn new(best_move: Move, ponder_move: Option<Move>, progress: Option<SearchProgress>) -> Self {
+        Self {
+            best_move: (best_move.get_move() != 0).then(|| best_move.as_string()),
+            ponder_move: ponder_move.map(|m| m.as_string()),
+            progress,
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+/// Configures an [`Engine`].
+pub struct EngineBuilder {
+    hash_size: usize,
+    threads: usize,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl EngineBuilder {
+    /// Size of the hash table in megabytes. 0 disables it.
+    pub fn hash_size(mut self, megabytes: usize) -> Self {
+        self.hash_size = megabytes;
+        self
+    }
+
+    /// Number of search threads.
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = threads.clamp(
+            EngineOptionDefaults::THREADS_MIN,
+            EngineOptionDefaults::THREADS_MAX,
+        );
+        self
+    }
+
+    /// Creates the engine, and starts its search thread.
+    pub fn build(self) -> Engine {
+        let board = Arc::new(Mutex::new(Board::new()));
+        let tt = Arc::new(RwLock::new(TT::<SearchData>::new(self.hash_size)));
+        let (info_tx, info_rx) = info_channel();
+        let mut search = Search::new();
+        search.init(
+            info_tx,
+            Arc::clone(&board),
+            move_generator(),
+            Arc::clone(&tt),
+            self.hash_size > 0,
+        );
+
+        Engine {
+            search,
+            board,
+            tt,
+            info_rx,
+            threads: self.threads,
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+/// A search engine. It runs its search in its own thread, which stops
+/// when the engine is dropped.
+pub struct Engine {
+    search: Search,
+    board: Arc<Mutex<Board>>,
+    tt: Arc<RwLock<TT<SearchData>>>,
+    info_rx: InfoReceiver,
+    threads: usize,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Engine {
+    /// Starts configuring an engine. By default, it has a hash table of
+    /// 32 MB and searches with one thread.
+    pub fn builder() -> EngineBuilder {
+        EngineBuilder {
+            hash_size: EngineOptionDefaults::HASH_DEFAULT,
+            threads: EngineOptionDefaults::THREADS_DEFAULT,
+        }
+    }
+
+    /// Clears the hash table, so the next game starts fresh.
+    pub fn new_game(&mut self) {
+        self.tt.write().expect(ErrFatal::LOCK).clear();
+    }
+
+    /// Searches the position, and blocks until the search is done.
+    pub fn search(&mut self, position: &Position, limits: &SearchLimits) -> SearchResult {
+        self.search_with(position, limits, |_| ())
+    }
+
+    /// Searches the position, and blocks until the search is done. The
+    /// callback is called after each completed depth.
+    pub fn search_with<F>(
+        &mut self,
+        position: &Position,
+        limits: &SearchLimits,
+        mut on_progress: F,
+    ) -> SearchResult
+    where
+        F: FnMut(&SearchProgress),
+    {
+        *self.board.lock().expect(ErrFatal::LOCK) = position.board.clone();
+
+        let mut sp = limits.params.clone();
+        sp.threads = self.threads;
+        sp.pawn_hash = EngineOptionDefaults::PAWN_HASH_DEFAULT;
+        self.search.send(SearchControl::Start(Box::new(sp)));
+
+        let mut progress = None;
+        loop {
+            match self.info_rx.recv().expect(ErrFatal::CHANNEL) {
+                Information::Search(SearchReport::SearchSummary(s)) => {
+                    let p = SearchProgress::from_summary(&s);
+                    on_progress(&p);
+                    progress = Some(p);
+                }
+                Information::Search(SearchReport::Finished(best_move, ponder_move)) => {
+                    return SearchResult::new(best_move, ponder_move, progress);
+                }
+                _ => (),
+            }
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Drop for Engine {
+    fn drop(&mut self) {
+        self.search.send(SearchControl::Quit);
+        self.search.wait_for_shutdown();
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+/// The complete engine, running in a thread of this program instead of
+/// in a separate process. It is driven with UCI commands, and answers
+/// with UCI responses, exactly like the engine binary does on stdin and
+/// stdout. The custom commands for use in a terminal are not available.
+///
+/// The engine quits when it receives "quit", or when it is dropped.
+pub struct UciEngine {
+    input: Option<Sender<String>>,
+    output: Receiver<String>,
+    handle: Option<JoinHandle<()>>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl UciEngine {
+    /// Starts the engine. All options have their default values.
+    pub fn start() -> Self {
+        let (input_tx, input_rx) = crossbeam_channel::unbounded::<String>();
+        let (output_tx, output_rx) = crossbeam_channel::unbounded::<String>();
+
+        let handle = thread::spawn(move || {
+            cpu::init();
+            let comm = Box::new(Uci::in_memory(input_rx, output_tx));
+            let mut engine = engine::Engine::embedded(comm);
+            let _ = engine.run_embedded();
+        });
+
+        Self {
+            input: Some(input_tx),
+            output: output_rx,
+            handle: Some(handle),
+        }
+    }
+
+    /// Sends one or more commands, separated by line breaks. Commands sent
+    /// after the engine quit are ignored.
+    pub fn send(&self, commands: &str) {
+        if let Some(tx) = &self.input {
+            let _ = tx.send(commands.to_string());
+        }
+    }
+
+    /// Waits for the next line the engine sends. Returns None if the
+    /// engine has quit and all its output was read.
+    pub fn recv(&self) -> Option<String> {
+        self.output.recv().ok()
+    }
+
+    /// Waits for the next line the engine sends, for at most the given
+    /// time. Returns None if there was no output in time.
+    pub fn recv_timeout(&self, timeout: Duration) -> Option<String> {
+        self.output.recv_timeout(timeout).ok()
+    }
+
+    /// Returns the next line the engine sent, if there is one, without
+    /// waiting.
+    pub fn try_recv(&self) -> Option<String> {
+        self.output.try_recv().ok()
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Drop for UciEngine {
+    // Closing the input makes the engine quit.
+    fn drop(&mut self) {
+        self.input = None;
+        if let Some(h) = self.handle.take() {
+            let _ = h.join();
+        }
+    }
+}
+
+/// A search engine that searches on the calling thread, instead of in a
+/// thread of its own. It is meant for platforms without threads, such as
+/// WebAssembly in a browser. Nothing can stop a search from the outside,
+/// so always give it limits; a search without them runs until it reaches
+/// the maximum depth. The hash table is kept between searches.
+pub struct SyncEngine {
+    tt: Arc<RwLock<TT<SearchData>>>,
+    hash_size: usize,
+}
+
+impl SyncEngine {
+    /// Creates an engine with a hash table of the given size in
+    /// megabytes. 0 disables it.
+    pub fn new(hash_size: usize) -> Self {
+        Self {
+            tt: Arc::new(RwLock::new(TT::<SearchData>::new(hash_size))),
+            hash_size,
+        }
+    }
+
+    /// Clears the hash table, so the next game starts fresh.
+    pub fn new_game(&mut self) {
+        self.tt.write().expect(ErrFatal::LOCK).clear();
+    }
+
+    /// Searches the position, and returns when the search is done.
+    pub fn search(&mut self, position: &Position, limits: &SearchLimits) -> SearchResult {
+        self.search_with(position, limits, |_| ())
+    }
+
+    /// Searches the position, and returns when the search is done. The
+    /// callback is called after each completed depth.
+    pub fn search_with<F>(
+        &mut self,
+        position: &Position,
+        limits: &SearchLimits,
+        on_progress: F,
+    ) -> SearchResult
+    where
+        F: FnMut(&SearchProgress),
+    {
+        let mut sp = limits.params.clone();
+        sp.pawn_hash = EngineOptionDefaults::PAWN_HASH_DEFAULT;
+
+        // The search only lends out the report; the callback needs to be
+        // mutable, and the last progress is kept for the result.
+        let on_progress = RefCell::new(on_progress);
+        let progress = RefCell::new(None);
+        let on_report = |report: SearchReport| {
+            if let SearchReport::SearchSummary(s) = report {
+                let p = SearchProgress::from_summary(&s);
+                (on_progress.borrow_mut())(&p);
+                *progress.borrow_mut() = Some(p);
+            }
+        };
+
+        let (best_move, ponder_move) = Search::run_sync(
+            &position.board,
+            &position.mg,
+            &self.tt,
+            self.hash_size > 0,
+            sp,
+            &on_report,
+        );
+
+        SearchResult::new(best_move, ponder_move, progress.into_inner())
+    }
+}
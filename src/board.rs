@@ -26,24 +26,36 @@ mod fen;
 mod gamestate;
 mod history;
 mod playmove;
+mod replay;
 mod utils;
-mod zobrist;
+pub mod zobrist;
 
 use self::{
     defs::{Pieces, BB_SQUARES},
     gamestate::GameState,
     history::History,
-    zobrist::{ZobristKey, ZobristRandoms},
+    zobrist::{ZobristKey, ZobristRandoms, PAWN_KEY_BASE},
 };
 use crate::{
     defs::{Bitboard, NrOf, Piece, Side, Sides, Square, EMPTY},
-    evaluation::psqt::{self, FLIP, PSQT_MG},
+    evaluation::{
+        params::EvalParams,
+        psqt::{self, FLIP},
+    },
     misc::bits,
 };
 use std::sync::Arc;
 
 // This file implements the engine's board representation; it is bit-board
-// based, with the least significant bit being A1.
+// based, with the least significant bit being A1. Next to the bitboards,
+// the piece list holds the piece on each square (or NONE), so finding the
+// piece on a square never needs a scan: move generation stores captured
+// pieces in the move from there. There are no per-piece lists of squares.
+// Bitboards already provide those, and SEE picks its least valuable
+// attacker by intersecting the attackers with the piece bitboards, which a
+// list of squares can't do faster. Keeping such lists would cost time in
+// every make() and unmake(). In debug mode, check_incrementals() verifies
+// the piece list and side bitboards against the piece bitboards.
 #[derive(Clone)]
 pub struct Board {
     pub bb_pieces: [[Bitboard; NrOf::PIECE_TYPES]; Sides::BOTH],
@@ -52,6 +64,7 @@ pub struct Board {
     pub history: History,
     pub piece_list: [Piece; NrOf::SQUARES],
     zr: Arc<ZobristRandoms>,
+    params: Arc<EvalParams>,
 }
 
 // Public functions for use by other modules.
@@ -65,6 +78,7 @@ impl Board {
             history: History::new(),
             piece_list: [Pieces::NONE; NrOf::SQUARES],
             zr: Arc::new(ZobristRandoms::new()),
+            params: Arc::new(EvalParams::DEFAULT),
         }
     }
 
@@ -73,11 +87,25 @@ impl Board {
         self.bb_pieces[side][piece]
     }
 
+    // Return the number of pieces of a certain type for one of the sides.
+    pub fn piece_count(&self, piece: Piece, side: Side) -> u8 {
+        self.game_state.piece_count[side][piece]
+    }
+
     // Return a bitboard containing all the pieces on the board.
     pub fn occupancy(&self) -> Bitboard {
         self.bb_side[Sides::WHITE] | self.bb_side[Sides::BLACK]
     }
 
+    // True if each side has exactly one king. The engine can't search or
+    // evaluate a position without them.
+    pub fn has_valid_kings(&self) -> bool {
+        let w_king = self.bb_pieces[Sides::WHITE][Pieces::KING].count_ones();
+        let b_king = self.bb_pieces[Sides::BLACK][Pieces::KING].count_ones();
+
+        w_king == 1 && b_king == 1
+    }
+
     // Returns the side to move.
     pub fn us(&self) -> usize {
         self.game_state.active_color as usize
@@ -88,40 +116,74 @@ impl Board {
         (self.game_state.active_color ^ 1) as usize
     }
 
+    // Returns the evaluation parameters this board is evaluated with.
+    pub fn params(&self) -> &EvalParams {
+        &self.params
+    }
+
+    // Evaluate the board with other parameters from now on. The
+    // incrementally updated PSQT values are recalculated.
+    pub fn set_params(&mut self, params: Arc<EvalParams>) {
+        self.params = params;
+
+        let psqt = psqt::apply(self);
+        self.game_state.psqt[Sides::WHITE] = psqt.0;
+        self.game_state.psqt[Sides::BLACK] = psqt.1;
+    }
+
     // Returns the square the king is currently on.
     pub fn king_square(&self, side: Side) -> Square {
         self.bb_pieces[side][Pieces::KING].trailing_zeros() as Square
     }
 
     // Remove a piece from the board, for the given side, piece, and square.
+    #[inline(always)]
     pub fn remove_piece(&mut self, side: Side, piece: Piece, square: Square) {
         self.bb_pieces[side][piece] ^= BB_SQUARES[square];
         self.bb_side[side] ^= BB_SQUARES[square];
         self.piece_list[square] = Pieces::NONE;
         self.game_state.zobrist_key ^= self.zr.piece(side, piece, square);
+        if piece == Pieces::PAWN {
+            self.game_state.pawn_key ^= self.zr.piece(side, piece, square);
+        }
+
+        // The removed piece was the n-th one of its type.
+        self.game_state.piece_count[side][piece] -= 1;
+        let n = self.game_state.piece_count[side][piece] as usize;
+        self.game_state.material_key ^= self.zr.material(side, piece, n);
 
         // Incremental updates
         // =============================================================
         let flip = side == Sides::WHITE;
         let s = if flip { FLIP[square] } else { square };
-        self.game_state.psqt[side] -= PSQT_MG[piece][s];
+        self.game_state.psqt[side] -= self.params.psqt[piece][s];
     }
 
     // Put a piece onto the board, for the given side, piece, and square.
+    #[inline(always)]
     pub fn put_piece(&mut self, side: Side, piece: Piece, square: Square) {
         self.bb_pieces[side][piece] |= BB_SQUARES[square];
         self.bb_side[side] |= BB_SQUARES[square];
         self.piece_list[square] = piece;
         self.game_state.zobrist_key ^= self.zr.piece(side, piece, square);
+        if piece == Pieces::PAWN {
+            self.game_state.pawn_key ^= self.zr.piece(side, piece, square);
+        }
+
+        // The added piece is the n-th one of its type.
+        let n = self.game_state.piece_count[side][piece] as usize;
+        self.game_state.piece_count[side][piece] += 1;
+        self.game_state.material_key ^= self.zr.material(side, piece, n);
 
         // Incremental updates
         // =============================================================
         let flip = side == Sides::WHITE;
         let s = if flip { FLIP[square] } else { square };
-        self.game_state.psqt[side] += PSQT_MG[piece][s];
+        self.game_state.psqt[side] += self.params.psqt[piece][s];
     }
 
     // Remove a piece from the from-square, and put it onto the to-square.
+    #[inline(always)]
     pub fn move_piece(&mut self, side: Side, piece: Piece, from: Square, to: Square) {
         self.remove_piece(side, piece, from);
         self.put_piece(side, piece, to);
@@ -154,6 +216,53 @@ impl Board {
         self.game_state.castling = new_permissions;
         self.game_state.zobrist_key ^= self.zr.castling(self.game_state.castling);
     }
+
+    // Returns true if the incrementally updated keys are the same as the
+    // keys created from scratch. In debug mode, make() already checks this
+    // after every move; this function makes the check available to the
+    // castling and en-passant audit, which also runs in release mode.
+    #[cfg(feature = "extra")]
+    pub fn keys_are_consistent(&self) -> bool {
+        let (zobrist_key, pawn_key) = self.recompute_keys();
+        self.game_state.zobrist_key == zobrist_key
+            && self.game_state.pawn_key == pawn_key
+            && self.game_state.material_key == self.init_material_key()
+    }
+
+    // Calculates the Zobrist key and the pawn key from scratch, without
+    // touching the incrementally updated keys in the game state. Comparing
+    // the two finds a make() or unmake() that hashes a move incorrectly,
+    // before it shows up as a corrupted transposition table.
+    pub fn recompute_keys(&self) -> (ZobristKey, ZobristKey) {
+        (self.init_zobrist_key(), self.init_pawn_key())
+    }
+
+    // Returns the position with the colors swapped: the board is flipped
+    // top to bottom, white pieces become black and vice versa, and the
+    // other side is to move. The history is not mirrored. A symmetrical
+    // evaluation gives the mirrored position the same score for the side
+    // to move.
+    #[cfg(feature = "extra")]
+    pub fn mirrored(&self) -> Board {
+        let mut mirror = self.clone();
+        mirror.reset();
+
+        for side in [Sides::WHITE, Sides::BLACK] {
+            for (piece, bitboard) in self.bb_pieces[side].iter().enumerate() {
+                mirror.bb_pieces[side ^ 1][piece] = bitboard.swap_bytes();
+            }
+        }
+
+        let castling = self.game_state.castling;
+        mirror.game_state.active_color = self.game_state.active_color ^ 1;
+        mirror.game_state.castling = ((castling & 0b0011) << 2) | (castling >> 2);
+        mirror.game_state.en_passant = self.game_state.en_passant.map(|square| square ^ 56);
+        mirror.game_state.halfmove_clock = self.game_state.halfmove_clock;
+        mirror.game_state.fullmove_number = self.game_state.fullmove_number;
+        mirror.init();
+
+        mirror
+    }
 }
 
 // Private board functions (for initializating on startup)
@@ -180,6 +289,9 @@ impl Board {
         // later be updated incrementally.
         self.piece_list = self.init_piece_list();
         self.game_state.zobrist_key = self.init_zobrist_key();
+        self.game_state.pawn_key = self.init_pawn_key();
+        self.game_state.piece_count = self.init_piece_count();
+        self.game_state.material_key = self.init_material_key();
 
         let psqt = psqt::apply(self);
         self.game_state.psqt[Sides::WHITE] = psqt.0;
@@ -280,4 +392,166 @@ impl Board {
         // Done; return the key.
         key
     }
+
+    // Initialize the pawn key. It hashes only the pawns, so it is the same
+    // for all positions with the same pawn structure. It will later be
+    // updated incrementally.
+    fn init_pawn_key(&self) -> ZobristKey {
+        let mut key = PAWN_KEY_BASE;
+
+        for side in [Sides::WHITE, Sides::BLACK] {
+            let mut pawns = self.bb_pieces[side][Pieces::PAWN];
+            while pawns > 0 {
+                let square = bits::next(&mut pawns);
+                key ^= self.zr.piece(side, Pieces::PAWN, square);
+            }
+        }
+
+        key
+    }
+
+    // Count the pieces of each type for each side. The counts will later
+    // be updated incrementally.
+    fn init_piece_count(&self) -> [[u8; NrOf::PIECE_TYPES]; Sides::BOTH] {
+        let mut count = [[0; NrOf::PIECE_TYPES]; Sides::BOTH];
+
+        for side in [Sides::WHITE, Sides::BLACK] {
+            for (piece, bitboard) in self.bb_pieces[side].iter().enumerate() {
+                count[side][piece] = bitboard.count_ones() as u8;
+            }
+        }
+
+        count
+    }
+
+    // Initialize the material key. It hashes only the number of pieces of
+    // each type for each side, so it is the same for all positions with
+    // the same material on the board, wherever it is. It will later be
+    // updated incrementally.
+    fn init_material_key(&self) -> ZobristKey {
+        let mut key: u64 = 0;
+
+        for side in [Sides::WHITE, Sides::BLACK] {
+            for piece in 0..NrOf::PIECE_TYPES {
+                let count = self.bb_pieces[side][piece].count_ones() as usize;
+                for n in 0..count {
+                    key ^= self.zr.material(side, piece, n);
+                }
+            }
+        }
+
+        key
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::defs::ReplayError;
+    use crate::defs::FEN_START_POSITION;
+    use crate::movegen::{
+        defs::{MoveList, MoveType},
+        MoveGenerator,
+    };
+
+    // Positions with castling, en-passant, promotions and captures of
+    // every kind, so each way a move can change the keys is played.
+    const FENS: [&str; 4] = [
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        "8/2p5/3p4/KP5r/1R2Pp1k/8/6P1/8 b - e3 0 1",
+        "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+    ];
+
+    fn assert_keys(board: &Board) {
+        let (zobrist_key, pawn_key) = board.recompute_keys();
+        assert_eq!(board.game_state.zobrist_key, zobrist_key);
+        assert_eq!(board.game_state.pawn_key, pawn_key);
+        assert_eq!(board.game_state.material_key, board.init_material_key());
+        assert_eq!(board.game_state.piece_count, board.init_piece_count());
+    }
+
+    // Plays every legal move to the given depth, checking the keys after
+    // each make(), and checking that unmake() restores them.
+    fn walk(board: &mut Board, depth: i8, mg: &MoveGenerator) {
+        if depth == 0 {
+            return;
+        }
+
+        let mut move_list = MoveList::new();
+        mg.generate_moves(board, &mut move_list, MoveType::All);
+        let before = board.game_state;
+
+        for i in 0..move_list.len() {
+            if board.make(move_list.get_move(i), mg) {
+                assert_keys(board);
+                walk(board, depth - 1, mg);
+                board.unmake();
+            }
+
+            assert_eq!(board.game_state.zobrist_key, before.zobrist_key);
+            assert_eq!(board.game_state.pawn_key, before.pawn_key);
+            assert_eq!(board.game_state.material_key, before.material_key);
+            assert_eq!(board.game_state.piece_count, before.piece_count);
+        }
+    }
+
+    #[test]
+    fn incremental_keys_match_keys_from_scratch() {
+        let mg = MoveGenerator::new();
+        for fen in FENS {
+            let mut board = Board::new();
+            board.fen_read(Some(fen)).unwrap();
+            assert_keys(&board);
+            walk(&mut board, 3, &mg);
+        }
+    }
+
+    #[test]
+    fn material_key_depends_only_on_material() {
+        let mut a = Board::new();
+        let mut b = Board::new();
+        a.fen_read(Some("4k3/8/8/8/8/8/1N6/4K3 w - - 0 1")).unwrap();
+        b.fen_read(Some("4k3/8/8/3N4/8/8/8/4K3 b - - 0 1")).unwrap();
+        assert_eq!(a.game_state.material_key, b.game_state.material_key);
+
+        let mut c = Board::new();
+        c.fen_read(Some("4k3/8/8/3B4/8/8/8/4K3 b - - 0 1")).unwrap();
+        assert_ne!(a.game_state.material_key, c.game_state.material_key);
+    }
+
+    #[test]
+    fn incomplete_placement_is_rejected() {
+        let mut board = Board::new();
+        assert!(board.fen_read(Some("4k3/4K3 w - - 0 1")).is_err());
+        assert!(board
+            .fen_read(Some("4k3/8/8/8/8/8/8/4K2 w - - 0 1"))
+            .is_err());
+        assert!(board
+            .fen_read(Some("4k3/8/8/8/8/8/8/4K3 w - - 0 1"))
+            .is_ok());
+    }
+
+    #[test]
+    fn castling_and_en_passant_need_their_pieces() {
+        let mut board = Board::new();
+        assert!(board.fen_read(Some("6KQ/8/8/8/8/8/8/7k b Q - 0 1")).is_err());
+        assert!(board.fen_read(Some("4k3/8/8/8/8/8/8/R3K3 w Q - 0 1")).is_ok());
+        assert!(board.fen_read(Some("8/5k2/8/2PR4/2B5/1K6/8/8 w - d6 0 1")).is_err());
+        assert!(board.fen_read(Some("8/5k2/8/2Pp4/2B5/1K6/8/8 w - d6 0 1")).is_ok());
+        assert!(board.fen_read(Some("8/5k2/8/2Pp4/2B5/1K6/8/8 b - d6 0 1")).is_err());
+    }
+
+    #[test]
+    fn side_not_to_move_in_check_is_rejected() {
+        let mg = MoveGenerator::new();
+        let mut board = Board::new();
+        board.fen_read(None).unwrap();
+        let fen = Some("4k3/4Q3/8/8/8/8/8/4K3 w - - 0 1");
+        assert_eq!(board.replay_moves(fen, &[], &mg), Err(ReplayError::Fen));
+        assert_eq!(board.fen_write(), FEN_START_POSITION);
+
+        let fen = Some("4k3/4Q3/8/8/8/8/8/4K3 b - - 0 1");
+        assert_eq!(board.replay_moves(fen, &[], &mg), Ok(()));
+    }
 }
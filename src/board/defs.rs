@@ -172,3 +172,11 @@ pub enum Direction {
     DownRight,
     DownLeft,
 }
+
+// Reasons why a position can't be set up from a FEN-string and a list of
+// moves. A move that is not legal is returned as it was given.
+#[derive(Debug, PartialEq)]
+pub enum ReplayError {
+    Fen,
+    Move(String),
+}
@@ -22,19 +22,21 @@ with this program.  If not, see <http://www.gnu.org/licenses/>.
 ======================================================================= */
 
 // fen.rs reads an FEN-string and converts it into a board position.
-// If the procedure fails, the original position is not changed. Note that
-// checking position legality is not the responsibility of this module. It
-// is perfectly possible to set up a position with two white kings, both
-// kings in check at the same time, or with black in check but white to
-// move.
+// If the procedure fails, the original position is not changed. This
+// module only checks what the engine needs to be able to work with the
+// board: eight ranks of eight squares, one king for each side, no pawns
+// on the first or last rank, and castling rights only for a king and rook
+// that are still on their original squares. Whether the position can be reached
+// in a game is not checked here; a position with the side not to move in
+// check is rejected by replay_moves(), which has a move generator.
 
 use super::{
-    defs::{Files, Pieces, Ranks, Squares, BB_SQUARES},
+    defs::{Files, Pieces, Ranks, Squares, BB_RANKS, BB_SQUARES, SQUARE_NAME},
     Board,
 };
 use crate::{
     defs::{Castling, Sides, Square, FEN_START_POSITION, MAX_GAME_MOVES, MAX_MOVE_RULE},
-    misc::parse,
+    misc::{parse, print},
 };
 use if_chain::if_chain;
 use std::ops::RangeInclusive;
@@ -43,6 +45,7 @@ use std::ops::RangeInclusive;
 const NR_OF_FEN_PARTS: usize = 6;
 const SHORT_FEN_PARTS: usize = 4;
 const LIST_OF_PIECES: &str = "kqrbnpKQRBNP";
+const PIECE_CHAR: [char; 6] = ['k', 'q', 'r', 'b', 'n', 'p'];
 const EP_SQUARES_WHITE: RangeInclusive<Square> = Squares::A3..=Squares::H3;
 const EP_SQUARES_BLACK: RangeInclusive<Square> = Squares::A6..=Squares::H6;
 const WHITE_OR_BLACK: &str = "wb";
@@ -105,6 +108,57 @@ impl Board {
 
         result
     }
+
+    // Writes the position as an FEN-string. Reading this string with
+    // fen_read() sets up the same position again.
+    pub fn fen_write(&self) -> String {
+        let mut placement = String::new();
+
+        for rank in (Ranks::R1..=Ranks::R8).rev() {
+            let mut empty = 0;
+            for file in Files::A..=Files::H {
+                let square = rank * 8 + file;
+                let piece = self.piece_list[square];
+                if piece == Pieces::NONE {
+                    empty += 1;
+                    continue;
+                }
+
+                if empty > 0 {
+                    placement.push_str(&empty.to_string());
+                    empty = 0;
+                }
+                let white = self.bb_side[Sides::WHITE] & BB_SQUARES[square] > 0;
+                let c = PIECE_CHAR[piece];
+                placement.push(if white { c.to_ascii_uppercase() } else { c });
+            }
+
+            if empty > 0 {
+                placement.push_str(&empty.to_string());
+            }
+            if rank > Ranks::R1 {
+                placement.push(SPLITTER);
+            }
+        }
+
+        let gs = &self.game_state;
+        let color = if gs.active_color as usize == Sides::WHITE {
+            "w"
+        } else {
+            "b"
+        };
+        let ep = match gs.en_passant {
+            Some(square) => SQUARE_NAME[square as usize].to_string(),
+            None => DASH.to_string(),
+        };
+
+        format!(
+            "{placement} {color} {} {ep} {} {}",
+            print::castling_as_string(gs.castling),
+            gs.halfmove_clock,
+            gs.fullmove_number
+        )
+    }
 }
 
 // ===== Private functions =====
@@ -119,6 +173,13 @@ fn pieces(board: &mut Board, part: &str) -> bool {
 
     // Parse each character; it should be a piece, square count, or splitter.
     for c in part.chars() {
+        // A piece beyond the H-file would end up on the next rank, or even
+        // outside of the board.
+        if LIST_OF_PIECES.contains(c) && file >= 8 {
+            result = false;
+            break;
+        }
+
         let square = ((rank * 8) + file) as usize;
         match c {
             'k' => board.bb_pieces[Sides::BLACK][Pieces::KING] |= BB_SQUARES[square],
@@ -137,10 +198,12 @@ fn pieces(board: &mut Board, part: &str) -> bool {
                 if let Some(x) = c.to_digit(10) {
                     file += x as u8;
                 }
+                result = file <= 8;
             }
             SPLITTER => {
-                result = file == 8;
-                rank -= 1;
+                // There is no rank below the first one.
+                result = file == 8 && rank > 0;
+                rank = rank.saturating_sub(1);
                 file = 0;
             }
             // Unknown character: result becomes false.
@@ -158,6 +221,19 @@ fn pieces(board: &mut Board, part: &str) -> bool {
         }
     }
 
+    // All eight ranks must be complete; a short piece placement such as
+    // "4k3/4K3" leaves the lower ranks undefined.
+    result = result && rank == Ranks::R1 as u8 && file == 8;
+
+    // The engine can't work without exactly one king for each side, or
+    // with pawns on the first or last rank.
+    if result {
+        let pawns = board.bb_pieces[Sides::WHITE][Pieces::PAWN]
+            | board.bb_pieces[Sides::BLACK][Pieces::PAWN];
+        let back_ranks = BB_RANKS[Ranks::R1] | BB_RANKS[Ranks::R8];
+        result = board.has_valid_kings() && (pawns & back_ranks) == 0;
+    }
+
     result
 }
 
@@ -211,8 +287,25 @@ fn castling(board: &mut Board, part: &str) -> bool {
     }
 
     // Counted correct characters should be at least 1, and equal to the
-    // length of the part.
-    (length >= 1) && (char_ok == length)
+    // length of the part. Each castling right also needs the king and the
+    // rook on their original squares; otherwise, castling would move
+    // pieces that aren't there.
+    (length >= 1) && (char_ok == length) && castling_pieces_ok(board)
+}
+
+fn castling_pieces_ok(board: &Board) -> bool {
+    let rights = [
+        (Castling::WK, Sides::WHITE, Squares::E1, Squares::H1),
+        (Castling::WQ, Sides::WHITE, Squares::E1, Squares::A1),
+        (Castling::BK, Sides::BLACK, Squares::E8, Squares::H8),
+        (Castling::BQ, Sides::BLACK, Squares::E8, Squares::A8),
+    ];
+
+    rights.iter().all(|&(right, side, king, rook)| {
+        let has_king = board.bb_pieces[side][Pieces::KING] & BB_SQUARES[king] > 0;
+        let has_rook = board.bb_pieces[side][Pieces::ROOK] & BB_SQUARES[rook] > 0;
+        board.game_state.castling & right == 0 || (has_king && has_rook)
+    })
 }
 
 // Part 4: Parse the en passant square
@@ -235,7 +328,7 @@ fn ep(board: &mut Board, part: &str) -> bool {
         let square = parse::algebraic_square_to_number(part);
 
         match square {
-            Some(s) if EP_SQUARES_WHITE.contains(&s) || EP_SQUARES_BLACK.contains(&s) => {
+            Some(s) if ep_pawn_ok(board, s) => {
                 board.game_state.en_passant = Some(s as u8);
                 char_ok += 2;
             }
@@ -248,6 +341,27 @@ fn ep(board: &mut Board, part: &str) -> bool {
     (length == 1 || length == 2) && (length == char_ok)
 }
 
+// The en-passant square must be behind a pawn of the side that just moved,
+// which has come from the square on the other side of it. Otherwise, an
+// en-passant capture would remove a piece that isn't a pawn, or no piece
+// at all.
+fn ep_pawn_ok(board: &Board, square: Square) -> bool {
+    let (mover, from, to) = match board.game_state.active_color as usize {
+        Sides::WHITE if EP_SQUARES_BLACK.contains(&square) => {
+            (Sides::BLACK, square + 8, square - 8)
+        }
+        Sides::BLACK if EP_SQUARES_WHITE.contains(&square) => {
+            (Sides::WHITE, square - 8, square + 8)
+        }
+        _ => return false,
+    };
+
+    // The side bitboards are not set up yet while the FEN is being read.
+    let occupied = board.bb_pieces.iter().flatten().fold(0, |acc, bb| acc | bb);
+    let empty = occupied & (BB_SQUARES[square] | BB_SQUARES[from]) == 0;
+    empty && board.bb_pieces[mover][Pieces::PAWN] & BB_SQUARES[to] > 0
+}
+
 // Part 5: Half-move clock: parse number of moves since last capture or pawn push.
 fn hmc(board: &mut Board, part: &str) -> bool {
     let length = part.len();
@@ -23,7 +23,7 @@ with this program.  If not, see <http://www.gnu.org/licenses/>.
 
 use crate::{
     board::defs::{Pieces, PIECE_NAME, SQUARE_NAME},
-    defs::Sides,
+    defs::{NrOf, Sides},
     misc::print,
     movegen::defs::Move,
 };
@@ -38,9 +38,13 @@ pub struct GameState {
     pub active_color: u8,
     pub castling: u8,
     pub halfmove_clock: u8,
+    pub last_irreversible: u16,
     pub en_passant: Option<u8>,
     pub fullmove_number: u16,
     pub zobrist_key: u64,
+    pub pawn_key: u64,
+    pub material_key: u64,
+    pub piece_count: [[u8; NrOf::PIECE_TYPES]; Sides::BOTH],
     pub psqt: [i16; Sides::BOTH],
     pub next_move: Move,
 }
@@ -52,8 +56,12 @@ impl GameState {
             castling: 0,
             en_passant: None,
             halfmove_clock: 0,
+            last_irreversible: 0,
             fullmove_number: 0,
             zobrist_key: 0,
+            pawn_key: 0,
+            material_key: 0,
+            piece_count: [[0; NrOf::PIECE_TYPES]; Sides::BOTH],
             psqt: [0; Sides::BOTH],
             next_move: Move::new(0),
         }
@@ -73,12 +81,13 @@ impl GameState {
         };
 
         format!(
-            "zk: {:x} ac: {} cperm: {} ep: {} hmc: {} fmn: {}, psqt: {}/{} next: {}{}{}",
+            "zk: {:x} ac: {} cperm: {} ep: {} hmc: {} irr: {} fmn: {}, psqt: {}/{} next: {}{}{}",
             self.zobrist_key,
             self.active_color,
             print::castling_as_string(self.castling),
             ep,
             self.halfmove_clock,
+            self.last_irreversible,
             self.fullmove_number,
             self.psqt[Sides::WHITE],
             self.psqt[Sides::BLACK],
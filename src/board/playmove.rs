@@ -86,6 +86,7 @@ impl Board {
         let is_promotion = promoted != Pieces::NONE;
         let is_capture = captured != Pieces::NONE;
         let has_permissions = self.game_state.castling > 0;
+        let permissions = self.game_state.castling;
 
         // Assume this is not a pawn move or a capture.
         self.game_state.halfmove_clock += 1;
@@ -142,6 +143,13 @@ impl Board {
             }
         }
 
+        // A capture, a pawn move or a loss of castling rights can't be
+        // undone. No position before this one can ever repeat, so the
+        // repetition check doesn't have to look further back than here.
+        if self.game_state.halfmove_clock == 0 || self.game_state.castling != permissions {
+            self.game_state.last_irreversible = self.history.len() as u16;
+        }
+
         // Swap the side to move.
         self.swap_side();
 
@@ -159,6 +167,8 @@ impl Board {
         // When running in debug mode, check the incrementally updated
         // values such as Zobrist key and meterial count.
         debug_assert!(check_incrementals(self));
+        #[cfg(feature = "check-keys")]
+        assert!(check_keys(self), "Zobrist keys differ after make()");
 
         // Report if the move was legal or not.
         is_legal
@@ -218,6 +228,55 @@ impl Board {
         if en_passant {
             put_piece(self, opponent, Pieces::PAWN, to ^ 8);
         }
+
+        // The game state came from the history, but the pieces were moved
+        // back by hand; both have to describe the same position.
+        debug_assert!(check_incrementals(self));
+        #[cfg(feature = "check-keys")]
+        assert!(check_keys(self), "Zobrist keys differ after unmake()");
+    }
+
+    // Recalculates the incrementally updated values and compares them to
+    // the board's values. Used by the fuzzer, which runs in release mode,
+    // where make() doesn't check them.
+    #[cfg(feature = "extra")]
+    pub fn incrementals_ok(&self) -> bool {
+        check_incrementals(self)
+    }
+}
+
+/*** ================================================================================ ***/
+
+// A null move passes the turn to the opponent without moving a piece. It
+// is not a legal chess move; the search uses it for null move pruning. In
+// the history, a null move is stored as an empty move.
+impl Board {
+    pub fn make_null(&mut self) {
+        let mut current_game_state = self.game_state;
+        current_game_state.next_move = Move::new(0);
+        self.history.push(current_game_state);
+
+        if self.game_state.en_passant.is_some() {
+            self.clear_ep_square();
+        }
+
+        self.game_state.halfmove_clock += 1;
+        self.swap_side();
+
+        debug_assert!(check_keys(self));
+        #[cfg(feature = "check-keys")]
+        assert!(check_keys(self), "Zobrist keys differ after make_null()");
+    }
+
+    // Nothing moved on the board, so restoring the game state is enough.
+    pub fn unmake_null(&mut self) {
+        self.game_state = self.history.pop();
+    }
+
+    // Returns true if the last move in the history was a null move.
+    pub fn last_move_was_null(&self) -> bool {
+        let count = self.history.len();
+        count > 0 && self.history.get_ref(count - 1).next_move.get_move() == 0
     }
 }
 
@@ -259,16 +318,51 @@ fn reverse_move(board: &mut Board, side: Side, piece: Piece, remove: Square, put
 // runs in debug mode.
 
 fn check_incrementals(board: &Board) -> bool {
-    let from_scratch_key = board.init_zobrist_key();
+    let (from_scratch_key, from_scratch_pawn_key) = board.recompute_keys();
+    let from_scratch_material_key = board.init_material_key();
+    let from_scratch_piece_count = board.init_piece_count();
     let from_scratch_psqt = crate::evaluation::psqt::apply(board);
+    let from_scratch_sides = board.init_pieces_per_side_bitboards();
+    let from_scratch_piece_list = board.init_piece_list();
     let mut result = true;
 
+    // The piece list and side bitboards are used as a fast path for
+    // finding pieces (captures, for example), so they must always match
+    // the piece bitboards.
+    if result
+        && (from_scratch_sides.0 != board.bb_side[Sides::WHITE]
+            || from_scratch_sides.1 != board.bb_side[Sides::BLACK])
+    {
+        println!("Check Incrementals: Error in side bitboards.");
+        result = false;
+    };
+
+    if result && from_scratch_piece_list != board.piece_list {
+        println!("Check Incrementals: Error in piece list.");
+        result = false;
+    };
+
     // Waterfall: only report first error encountered and skip any others.
     if result && from_scratch_key != board.game_state.zobrist_key {
         println!("Check Incrementals: Error in Zobrist key.");
         result = false;
     };
 
+    if result && from_scratch_pawn_key != board.game_state.pawn_key {
+        println!("Check Incrementals: Error in pawn key.");
+        result = false;
+    };
+
+    if result && from_scratch_piece_count != board.game_state.piece_count {
+        println!("Check Incrementals: Error in piece count.");
+        result = false;
+    };
+
+    if result && from_scratch_material_key != board.game_state.material_key {
+        println!("Check Incrementals: Error in material key.");
+        result = false;
+    };
+
     if result && from_scratch_psqt.0 != board.game_state.psqt[Sides::WHITE] {
         println!("Check Incrementals: Error in PSQT for white.");
         result = false;
@@ -281,3 +375,31 @@ fn check_incrementals(board: &Board) -> bool {
 
     result
 }
+
+// A cheaper version of check_incrementals(), which only recalculates the
+// Zobrist key and the pawn key. Building with the "check-keys" feature
+// runs it after every make() and unmake(), also in release mode, so a
+// hashing error is caught where it happens instead of showing up as
+// mysterious transposition table corruption much later.
+fn check_keys(board: &Board) -> bool {
+    let (zobrist_key, pawn_key) = board.recompute_keys();
+    let mut result = true;
+
+    if zobrist_key != board.game_state.zobrist_key {
+        println!(
+            "Check Keys: Zobrist key is {:016x}, should be {:016x}.",
+            board.game_state.zobrist_key, zobrist_key
+        );
+        result = false;
+    }
+
+    if pawn_key != board.game_state.pawn_key {
+        println!(
+            "Check Keys: pawn key is {:016x}, should be {:016x}.",
+            board.game_state.pawn_key, pawn_key
+        );
+        result = false;
+    }
+
+    result
+}
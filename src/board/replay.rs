@@ -0,0 +1,89 @@
+/* =======================================================================
+Rustic is a chess playing engine.
+Copyright (C) 2019-2024, Marcel Vanthoor
+https://rustic-chess.org/
+
+Rustic is written in the Rust programming language. It is an original
+work, not derived from any engine that came before it. However, it does
+use a lot of concepts which are well-known and are in use by most if not
+all classical alpha/beta-based chess engines.
+
+Rustic is free software: you can redistribute it and/or modify it under
+the terms of the GNU General Public License version 3 as published by
+the Free Software Foundation.
+
+Rustic is distributed in the hope that it will be useful, but WITHOUT
+ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License
+for more details.
+
+You should have received a copy of the GNU General Public License along
+with this program.  If not, see <http://www.gnu.org/licenses/>.
+======================================================================= */
+
+use super::{defs::ReplayError, Board};
+use crate::{
+    misc::parse,
+    movegen::{
+        defs::{MoveList, MoveType},
+        MoveGenerator,
+    },
+};
+
+impl Board {
+    // Sets up the position from the FEN-string (or the starting position
+    // if there is none) and plays the given moves in long algebraic
+    // notation. Each move is checked against the move generator before
+    // it is played. If a move is not legal, replaying stops, and the
+    // board is left in the position before that move. If the FEN-string
+    // can't be read, or describes a position in which the side not to
+    // move is in check, the board is not changed.
+    pub fn replay_moves(
+        &mut self,
+        fen: Option<&str>,
+        moves: &[&str],
+        mg: &MoveGenerator,
+    ) -> Result<(), ReplayError> {
+        let mut board = self.clone();
+        if board.fen_read(fen).is_err() || !board.is_legal_position(mg) {
+            return Err(ReplayError::Fen);
+        }
+        *self = board;
+
+        for m in moves.iter() {
+            if !self.play_move(m, mg) {
+                return Err(ReplayError::Move(m.to_string()));
+            }
+        }
+
+        Ok(())
+    }
+
+    // The side that is not to move can't be in check: its king could be
+    // captured, and the search can't handle that.
+    pub fn is_legal_position(&self, mg: &MoveGenerator) -> bool {
+        !mg.square_attacked(self, self.us(), self.king_square(self.opponent()))
+    }
+
+    // Plays a single move in long algebraic notation, if it is legal in
+    // the current position. Returns false if it isn't. The move is checked
+    // before it is played, so an illegal move never touches the board.
+    pub fn play_move(&mut self, m: &str, mg: &MoveGenerator) -> bool {
+        let (from, to, promoted) = match parse::algebraic_move_to_number(m) {
+            Ok(potential_move) => potential_move,
+            Err(_) => return false,
+        };
+
+        let mut move_list = MoveList::new();
+        mg.generate_moves(self, &mut move_list, MoveType::All);
+
+        for i in 0..move_list.len() {
+            let current = move_list.get_move(i);
+            if current.from() == from && current.to() == to && current.promoted() == promoted {
+                return mg.is_legal(self, current) && self.make(current, mg);
+            }
+        }
+
+        false
+    }
+}
@@ -23,8 +23,8 @@ with this program.  If not, see <http://www.gnu.org/licenses/>.
 
 use super::{defs::Location, Board};
 use crate::{
-    board::defs::Ranks,
-    defs::{Side, Sides, Square},
+    board::defs::{Pieces, Ranks, BB_FILES},
+    defs::{Bitboard, Side, Sides, Square},
 };
 
 impl Board {
@@ -57,4 +57,33 @@ impl Board {
             Ranks::R1
         }
     }
+
+    // A pawn is passed if no opposing pawn is in front of it, on its own
+    // file or one of the files next to it.
+    pub fn is_passed_pawn(&self, side: Side, square: Square) -> bool {
+        let (file, rank) = Board::square_on_file_rank(square);
+        let (file, rank) = (file as usize, rank as usize);
+
+        let mut files: Bitboard = BB_FILES[file];
+        if file > 0 {
+            files |= BB_FILES[file - 1];
+        }
+        if file < 7 {
+            files |= BB_FILES[file + 1];
+        }
+
+        // All the squares on the ranks in front of the pawn.
+        let ahead: Bitboard = if side == Sides::WHITE {
+            if rank < 7 {
+                !0 << ((rank + 1) * 8)
+            } else {
+                0
+            }
+        } else {
+            (1 << (rank * 8)) - 1
+        };
+
+        let opponent_pawns = self.get_pieces(Pieces::PAWN, side ^ 1);
+        opponent_pawns & files & ahead == 0
+    }
 }
@@ -31,26 +31,44 @@ type CastlingRandoms = [u64; NrOf::CASTLING_PERMISSIONS];
 type SideRandoms = [u64; Sides::BOTH];
 type EpRandoms = [u64; NrOf::SQUARES + 1];
 
+/* Random number for all sides for all pieces, for the first, second,
+third... piece of that type. A side can't have more pieces of one type
+than there are squares. */
+type MaterialRandoms = [[[u64; NrOf::SQUARES]; NrOf::PIECE_TYPES]; Sides::BOTH];
+
 pub type ZobristKey = u64;
 
 // 256 bit (8 bits x 32) seed
-const RNG_SEED: [u8; 32] = [125; 32];
+pub const RNG_SEED: [u8; 32] = [125; 32];
+
+// The pawn key starts from this number instead of 0. Otherwise a position
+// without pawns would have the same key as an unused entry in the pawn
+// hash table.
+pub const PAWN_KEY_BASE: ZobristKey = 0x9E37_79B9_7F4A_7C15;
 
 pub struct ZobristRandoms {
     rnd_pieces: PieceRandoms,
     rnd_castling: CastlingRandoms,
     rnd_sides: SideRandoms,
     rnd_en_passant: EpRandoms,
+    rnd_material: MaterialRandoms,
 }
 
 impl ZobristRandoms {
     pub fn new() -> Self {
-        let mut random = ChaChaRng::from_seed(RNG_SEED);
+        ZobristRandoms::with_seed(RNG_SEED)
+    }
+
+    // The engine always uses RNG_SEED. Other seeds are only used to
+    // generate different sets of random numbers in extra::generate.
+    pub fn with_seed(seed: [u8; 32]) -> Self {
+        let mut random = ChaChaRng::from_seed(seed);
         let mut zobrist_randoms = Self {
             rnd_pieces: [[[EMPTY; NrOf::SQUARES]; NrOf::PIECE_TYPES]; Sides::BOTH],
             rnd_castling: [EMPTY; NrOf::CASTLING_PERMISSIONS],
             rnd_sides: [EMPTY; Sides::BOTH],
             rnd_en_passant: [EMPTY; NrOf::SQUARES + 1],
+            rnd_material: [[[EMPTY; NrOf::SQUARES]; NrOf::PIECE_TYPES]; Sides::BOTH],
         };
 
         zobrist_randoms.rnd_pieces.iter_mut().for_each(|side| {
@@ -76,6 +94,16 @@ impl ZobristRandoms {
             .iter_mut()
             .for_each(|ep| *ep = random.gen::<u64>());
 
+        // Generated last, so the numbers above stay the same as before
+        // the material key was added.
+        zobrist_randoms.rnd_material.iter_mut().for_each(|side| {
+            side.iter_mut().for_each(|piece| {
+                piece
+                    .iter_mut()
+                    .for_each(|count| *count = random.gen::<u64>())
+            })
+        });
+
         zobrist_randoms
     }
 
@@ -91,6 +119,11 @@ impl ZobristRandoms {
         self.rnd_sides[side]
     }
 
+    // The number for the n-th piece of this type, counting from 0.
+    pub fn material(&self, side: Side, piece: Piece, n: usize) -> ZobristKey {
+        self.rnd_material[side][piece][n]
+    }
+
     pub fn en_passant(&self, en_passant: Option<u8>) -> ZobristKey {
         match en_passant {
             Some(ep) => self.rnd_en_passant[ep as usize],
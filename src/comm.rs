@@ -21,16 +21,20 @@ You should have received a copy of the GNU General Public License along
 with this program.  If not, see <http://www.gnu.org/licenses/>.
 ======================================================================= */
 
+pub mod console;
+pub mod help;
 pub mod uci;
 // pub mod xboard;
 
 use crate::{
     board::Board,
-    engine::defs::{EngineOption, Information},
+    defs::Bitboard,
+    engine::defs::{EngineOption, InfoSender, UiElement},
+    misc::protocol_log::ProtocolLog,
     movegen::defs::Move,
     search::defs::{SearchCurrentMove, SearchStats, SearchSummary},
 };
-use crossbeam_channel::Sender;
+use console::ConsoleReport;
 use std::sync::{Arc, Mutex};
 use uci::UciReport;
 
@@ -39,22 +43,109 @@ pub struct CommType;
 impl CommType {
     pub const XBOARD: &'static str = "xboard";
     pub const UCI: &'static str = "uci";
+    pub const CONSOLE: &'static str = "console";
+}
+
+// Describes what a Comm module supports, so tools that drive the engine
+// can configure themselves without reading the source code.
+pub struct CommInfo {
+    pub protocol: &'static str,             // Name of the protocol.
+    pub stateful: bool,                     // The engine keeps the game between commands.
+    pub buffered: bool,                     // Commands are queued, even while searching.
+    pub option_command: &'static str,       // Format of the command that sets an option.
+    pub custom_commands: Vec<&'static str>, // Commands that are not part of the protocol.
+}
+
+impl CommInfo {
+    // Creates a JSON object with the protocol's features and the options
+    // the engine announces, on a single line.
+    pub fn to_json(&self, options: &[EngineOption]) -> String {
+        let options: Vec<String> = options.iter().map(CommInfo::option_json).collect();
+        let custom: Vec<String> = self
+            .custom_commands
+            .iter()
+            .map(|c| json_string(c))
+            .collect();
+
+        format!(
+            "{{\"protocol\":{},\"stateful\":{},\"buffered\":{},\"option_command\":{},\"options\":[{}],\"custom_commands\":[{}]}}",
+            json_string(self.protocol),
+            self.stateful,
+            self.buffered,
+            json_string(self.option_command),
+            options.join(","),
+            custom.join(",")
+        )
+    }
+
+    fn option_json(option: &EngineOption) -> String {
+        let mut fields = vec![
+            format!("\"name\":{}", json_string(option.name)),
+            format!("\"type\":{}", json_string(option.ui_element.name())),
+        ];
+
+        let values = [
+            ("default", &option.default),
+            ("min", &option.min),
+            ("max", &option.max),
+        ];
+        for (key, value) in values {
+            if let Some(v) = value {
+                fields.push(format!("\"{key}\":{}", json_value(&option.ui_element, v)));
+            }
+        }
+
+        if let UiElement::Combo(vars) = option.ui_element {
+            let vars: Vec<String> = vars.iter().map(|v| json_string(v)).collect();
+            fields.push(format!("\"vars\":[{}]", vars.join(",")));
+        }
+
+        format!("{{{}}}", fields.join(","))
+    }
+}
+
+// The values of a spin are numbers, and those of a check are booleans.
+// Anything else, or a value that doesn't parse, is written as a string.
+fn json_value(ui_element: &UiElement, value: &str) -> String {
+    match ui_element {
+        UiElement::Spin if value.parse::<i64>().is_ok() => value.to_string(),
+        UiElement::Check if value.parse::<bool>().is_ok() => value.to_string(),
+        _ => json_string(value),
+    }
+}
+
+// Quotes a string for use in JSON.
+fn json_string(s: &str) -> String {
+    let mut quoted = String::from("\"");
+
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+
+    quoted.push('"');
+    quoted
 }
 
 // Defines the public functions a Comm module must implement.
 pub trait IComm {
     fn init(
         &mut self,
-        report_tx: Sender<Information>,
+        report_tx: InfoSender,
         board: Arc<Mutex<Board>>,
         options: Arc<Vec<EngineOption>>,
+        log: ProtocolLog,
     );
     fn send(&self, msg: CommControl);
     fn wait_for_shutdown(&mut self);
     fn get_protocol_name(&self) -> &'static str;
+    fn is_strict(&self) -> bool;
 }
 
-#[derive(PartialEq)]
 pub enum CommControl {
     // Reactions of engine to incoming commands.
     Update,                            // Request Comm module to update its state.
@@ -63,14 +154,23 @@ pub enum CommControl {
     Ready,                             // Transmit that the engine is ready.
     SearchSummary(SearchSummary),      // Transmit search information.
     SearchCurrMove(SearchCurrentMove), // Transmit currently considered move.
+    SearchRefutation(Vec<Move>),       // Transmit a refuted root move.
     SearchStats(SearchStats),          // Transmit search Statistics.
     InfoString(String),                // Transmit general information.
-    BestMove(Move),                    // Transmit the engine's best move.
+    BestMove(Move, Option<Move>),      // Transmit best move and ponder move.
+    ScoresFromWhite(bool),             // Report scores from White's view.
+    OptionSet(String, String),         // Acknowledge the value of an option.
+    Group(Vec<CommControl>),           // Transmit messages without interruption.
 
-    // Output to screen when running in a terminal window.
-    PrintBoard,
+    // Output to screen when running in a terminal window. The board is
+    // printed as it was when the engine sent it, not as it is by the time
+    // the Comm module gets to it.
+    PrintBoard(Box<Board>),
     PrintHistory,
-    PrintHelp,
+    PrintBitboards(Vec<(String, Bitboard)>),
+    PrintHeatmap,
+    PrintCommQuery,
+    PrintHelp(String),
 }
 
 // These are the commands a Comm module can create and send back to the
@@ -78,6 +178,7 @@ pub enum CommControl {
 #[derive(PartialEq, Clone)]
 pub enum CommReport {
     Uci(UciReport),
+    Console(ConsoleReport),
 }
 
 impl CommReport {
@@ -85,3 +186,47 @@ impl CommReport {
         true
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_string_quotes_and_escapes() {
+        assert_eq!(json_string(""), r#""""#);
+        assert_eq!(json_string("Hash"), r#""Hash""#);
+        assert_eq!(json_string(r#"say "hi""#), r#""say \"hi\"""#);
+        assert_eq!(json_string(r"C:\engines"), r#""C:\\engines""#);
+        assert_eq!(json_string("a\nb\tc"), r#""a\u000ab\u0009c""#);
+        assert_eq!(json_string("Schäfer ♞"), "\"Schäfer ♞\"");
+    }
+
+    #[test]
+    fn option_values_have_json_types() {
+        let some = |v: &str| Some(v.to_string());
+        let spin = EngineOption::new("Hash", UiElement::Spin, some("32"), some("0"), some("64"));
+        assert_eq!(
+            CommInfo::option_json(&spin),
+            r#"{"name":"Hash","type":"spin","default":32,"min":0,"max":64}"#
+        );
+
+        let check = EngineOption::new("Ponder", UiElement::Check, some("false"), None, None);
+        assert_eq!(
+            CommInfo::option_json(&check),
+            r#"{"name":"Ponder","type":"check","default":false}"#
+        );
+
+        let string = EngineOption::new("File", UiElement::String, some("32"), None, None);
+        assert_eq!(
+            CommInfo::option_json(&string),
+            r#"{"name":"File","type":"string","default":"32"}"#
+        );
+
+        const VARS: &[&str] = &["a", "b"];
+        let combo = EngineOption::new("Style", UiElement::Combo(VARS), some("a"), None, None);
+        assert_eq!(
+            CommInfo::option_json(&combo),
+            r#"{"name":"Style","type":"combo","default":"a","vars":["a","b"]}"#
+        );
+    }
+}
@@ -0,0 +1,343 @@
+/* =======================================================================
+Rustic is a chess playing engine.
+Copyright (C) 2019-2024, Marcel Vanthoor
+https://rustic-chess.org/
+
+Rustic is written in the Rust programming language. It is an original
+work, not derived from any engine that came before it. However, it does
+use a lot of concepts which are well-known and are in use by most if not
+all classical alpha/beta-based chess engines.
+
+Rustic is free software: you can redistribute it and/or modify it under
+the terms of the GNU General Public License version 3 as published by
+the Free Software Foundation.
+
+Rustic is distributed in the hope that it will be useful, but WITHOUT
+ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License
+for more details.
+
+You should have received a copy of the GNU General Public License along
+with this program.  If not, see <http://www.gnu.org/licenses/>.
+======================================================================= */
+
+// This file implements the Console communication module. It is meant for
+// a user playing or analyzing in a terminal window: moves are entered in
+// SAN or long algebraic notation, "go" has the engine play a move, and
+// the board is printed after every move, with unicode chess pieces if the
+// terminal can show them. Input that isn't a console command is read as a
+// UCI command, so the custom commands and commands such as "position" work
+// here as well.
+
+use super::{
+    help,
+    uci::{Uci, UciReport},
+    CommControl, CommReport, CommType, IComm,
+};
+use crate::{
+    board::Board,
+    defs::{About, Sides},
+    engine::defs::{EngineOption, ErrFatal, InfoSender, Information},
+    misc::{
+        print::{self, BoardStyle},
+        protocol_log::ProtocolLog,
+        san,
+    },
+    movegen::MoveGenerator,
+    search::defs::{ScoreBound, SearchSummary},
+};
+use crossbeam_channel::{self, Sender};
+use std::{
+    fs, io,
+    sync::{Arc, Mutex},
+    thread::{self, JoinHandle},
+};
+
+// Input will be turned into a report, which wil be sent to the engine. The
+// main engine thread will react accordingly.
+#[derive(PartialEq, Clone)]
+pub enum ConsoleReport {
+    New,              // Start a new game.
+    Move(String),     // Play a move in SAN or long algebraic notation.
+    Undo,             // Take back the last move.
+    Go(Option<u128>), // Think for this many msecs, or on the clock, and play.
+    Analyze,          // Search until stopped, without playing a move.
+}
+
+// This struct is used to instantiate the Comm Console module.
+pub struct Console {
+    control_handle: Option<JoinHandle<()>>,
+    report_handle: Option<JoinHandle<()>>,
+    control_tx: Option<Sender<CommControl>>,
+    script: Option<String>,
+    board_style: BoardStyle,
+}
+
+// Public functions
+impl Console {
+    // Create a new console. The commands in the "script" file are handled
+    // before reading stdin. The board is printed in the given style.
+    pub fn new(script: Option<String>, board_style: BoardStyle) -> Self {
+        Self {
+            control_handle: None,
+            report_handle: None,
+            control_tx: None,
+            script,
+            board_style,
+        }
+    }
+}
+
+// Any communication module must implement the trait IComm.
+impl IComm for Console {
+    fn init(
+        &mut self,
+        report_tx: InfoSender,
+        board: Arc<Mutex<Board>>,
+        _options: Arc<Vec<EngineOption>>,
+        _log: ProtocolLog, // A user at the console sees everything already.
+    ) {
+        Console::print_welcome();
+
+        // Start threads
+        self.report_thread(report_tx);
+        self.control_thread(board);
+    }
+
+    // The creator of the Comm module can use this function to send
+    // messages or commands into the Control thread.
+    fn send(&self, msg: CommControl) {
+        if let Some(tx) = &self.control_tx {
+            tx.send(msg).expect(ErrFatal::CHANNEL);
+        }
+    }
+
+    // After the engine sends 'quit' to the control thread, it will call
+    // wait_for_shutdown() and then wait here until shutdown is completed.
+    fn wait_for_shutdown(&mut self) {
+        if let Some(h) = self.report_handle.take() {
+            h.join().expect(ErrFatal::THREAD);
+        }
+
+        if let Some(h) = self.control_handle.take() {
+            h.join().expect(ErrFatal::THREAD);
+        }
+    }
+
+    // This function just returns the name of the communication protocol.
+    fn get_protocol_name(&self) -> &'static str {
+        CommType::CONSOLE
+    }
+
+    // The console is meant for a user, who may see everything.
+    fn is_strict(&self) -> bool {
+        false
+    }
+}
+
+// Implement the report thread
+impl Console {
+    // The Report thread sends incoming data to the engine thread.
+    fn report_thread(&mut self, report_tx: InfoSender) {
+        // Create thread-local variables
+        let t_report_tx = report_tx; // Report sender
+        let t_script = self.script.take();
+
+        // Actual thread creation.
+        let report_handle = thread::spawn(move || {
+            let mut quit = false;
+
+            // Commands from the script are handled first, as if they were
+            // typed in. If the script can't be read, the engine continues.
+            if let Some(path) = t_script {
+                match fs::read_to_string(&path) {
+                    Ok(script) => quit = Console::handle_input(&script, &t_report_tx),
+                    Err(e) => println!("Script {path}: {e}"),
+                }
+            }
+
+            // Keep running as long as 'quit' is not detected. At the end
+            // of the input, nobody can type "quit" anymore.
+            while !quit {
+                let mut incoming_data = String::from("");
+                let read = io::stdin()
+                    .read_line(&mut incoming_data)
+                    .expect(ErrFatal::READ_IO);
+
+                if read == 0 {
+                    incoming_data = String::from("quit");
+                }
+
+                quit = Console::handle_input(&incoming_data, &t_report_tx);
+            }
+        });
+
+        // Store the handle.
+        self.report_handle = Some(report_handle);
+    }
+
+    // Handle the commands in the data one by one, in order. Returns true
+    // if "quit" was found; commands after it are not handled.
+    fn handle_input(data: &str, report_tx: &InfoSender) -> bool {
+        let commands = data.split(['\n', '\r']).filter(|c| !c.trim().is_empty());
+
+        for command in commands {
+            let new_report = Console::create_report(command);
+
+            if new_report.is_valid() {
+                report_tx
+                    .send(Information::Comm(new_report.clone()))
+                    .expect(ErrFatal::HANDLE);
+
+                if new_report == CommReport::Uci(UciReport::Quit) {
+                    return true;
+                }
+            }
+        }
+
+        false
+    }
+
+    // Console commands come first. Anything else is a UCI command, and
+    // if it isn't that either, it may be a move.
+    fn create_report(input: &str) -> CommReport {
+        let i = input.trim();
+
+        match i {
+            "new" => CommReport::Console(ConsoleReport::New),
+            "undo" => CommReport::Console(ConsoleReport::Undo),
+            "analyze" => CommReport::Console(ConsoleReport::Analyze),
+            "go" => CommReport::Console(ConsoleReport::Go(None)),
+            cmd if cmd.starts_with("go ") => match Uci::msecs(&cmd[3..], 1000) {
+                Some(msecs) if msecs > 0 => CommReport::Console(ConsoleReport::Go(Some(msecs))),
+                _ => Uci::create_report(cmd),
+            },
+            cmd => match Uci::create_report(cmd) {
+                CommReport::Uci(UciReport::Unknown) => {
+                    CommReport::Console(ConsoleReport::Move(cmd.to_string()))
+                }
+                report => report,
+            },
+        }
+    }
+}
+
+// The state of the control thread.
+struct ControlState {
+    board: Arc<Mutex<Board>>,
+    mg: MoveGenerator,
+    board_style: BoardStyle,
+    quit: bool,
+}
+
+// Implement the control thread
+impl Console {
+    // The control thread receives commands from the engine thread.
+    fn control_thread(&mut self, board: Arc<Mutex<Board>>) {
+        // Create an incoming channel for the control thread.
+        let (control_tx, control_rx) = crossbeam_channel::unbounded::<CommControl>();
+        let board_style = self.board_style;
+
+        // Create the control thread.
+        let control_handle = thread::spawn(move || {
+            let mut state = ControlState {
+                board: Arc::clone(&board),
+                mg: MoveGenerator::new(),
+                board_style,
+                quit: false,
+            };
+
+            // Keep running as long as Quit is not received.
+            while !state.quit {
+                let control = control_rx.recv().expect(ErrFatal::CHANNEL);
+                Console::handle_control(control, &mut state);
+            }
+        });
+
+        // Store handle and control sender.
+        self.control_handle = Some(control_handle);
+        self.control_tx = Some(control_tx);
+    }
+
+    // Perform command as sent by the engine thread.
+    fn handle_control(control: CommControl, state: &mut ControlState) {
+        match control {
+            CommControl::Group(group) => {
+                let _stdout = io::stdout().lock();
+                for control in group {
+                    Console::handle_control(control, state);
+                }
+            }
+            CommControl::Identify => Console::print_welcome(),
+            CommControl::Ready => println!("readyok"),
+            CommControl::Quit => state.quit = true,
+            CommControl::SearchSummary(summary) => Console::print_summary(&summary, state),
+            CommControl::InfoString(msg) => println!("{msg}"),
+            CommControl::BestMove(m, _) => {
+                let mut board = state.board.lock().expect(ErrFatal::LOCK).clone();
+                match m.get_move() {
+                    0 => println!("There is no legal move."),
+                    _ => println!("Best move: {}", san::move_to_san(&mut board, &state.mg, m)),
+                }
+            }
+            CommControl::OptionSet(name, value) => println!("Option {name} set to {value}"),
+            CommControl::PrintBoard(board) => print::position_compact(&board, state.board_style),
+            CommControl::PrintHistory => Uci::print_history(&state.board),
+            CommControl::PrintBitboards(layers) => Uci::print_bitboards(&layers),
+            CommControl::PrintHeatmap => Uci::print_heatmap(&state.board),
+            CommControl::PrintHelp(topic) => {
+                for line in help::help_text(CommType::CONSOLE, &topic) {
+                    println!("{line}");
+                }
+            }
+
+            // Too much detail for a user at the console, or only of use
+            // to a GUI.
+            CommControl::SearchCurrMove(_)
+            | CommControl::SearchRefutation(_)
+            | CommControl::SearchStats(_)
+            | CommControl::ScoresFromWhite(_)
+            | CommControl::PrintCommQuery
+            | CommControl::Update => (),
+        }
+    }
+}
+
+// Private functions for this module.
+impl Console {
+    fn print_welcome() {
+        println!("{} {} by {}", About::ENGINE, About::VERSION, About::AUTHOR);
+        println!("Enter a move, \"go\" to let the engine move, or \"help\".");
+        println!();
+    }
+
+    // Only exact scores are printed; a bound is just a step on the way.
+    // Scores are from White's point of view, in pawns, and the line is
+    // in SAN.
+    fn print_summary(s: &SearchSummary, state: &ControlState) {
+        if s.bound != ScoreBound::Exact {
+            return;
+        }
+
+        let board = state.board.lock().expect(ErrFatal::LOCK);
+        let sign = if board.us() == Sides::WHITE { 1 } else { -1 };
+        let line = san::moves_to_san(&board, &state.mg, &s.pv);
+        std::mem::drop(board);
+
+        let score = match s.mate_in() {
+            Some(moves) => format!("#{}", moves * sign),
+            None => format!("{:+.2}", (s.cp * sign) as f32 / 100.0),
+        };
+        let seconds = s.time as f32 / 1000.0;
+        let line = if s.multi_pv > 1 {
+            format!("({}) {line}", s.multi_pv)
+        } else {
+            line
+        };
+
+        println!(
+            "{:>3}/{:<3} {score:>7} {seconds:>7.2}s {:>12} nodes  {line}",
+            s.depth, s.seldepth, s.nodes
+        );
+    }
+}
@@ -0,0 +1,356 @@
+/* =======================================================================
+Rustic is a chess playing engine.
+Copyright (C) 2019-2024, Marcel Vanthoor
+https://rustic-chess.org/
+
+Rustic is written in the Rust programming language. It is an original
+work, not derived from any engine that came before it. However, it does
+use a lot of concepts which are well-known and are in use by most if not
+all classical alpha/beta-based chess engines.
+
+Rustic is free software: you can redistribute it and/or modify it under
+the terms of the GNU General Public License version 3 as published by
+the Free Software Foundation.
+
+Rustic is distributed in the hope that it will be useful, but WITHOUT
+ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License
+for more details.
+
+You should have received a copy of the GNU General Public License along
+with this program.  If not, see <http://www.gnu.org/licenses/>.
+======================================================================= */
+
+// help.rs holds the registry of custom commands: the commands a Comm
+// module understands that are not part of its protocol. Each command
+// has its usage, a one-line summary for the list printed by "help", and
+// a longer description for "help <command>". The registry is the single
+// place where a new custom command is described; the help text and the
+// "commquery" report are created from it.
+
+use super::CommType;
+
+pub struct CustomCommand {
+    pub name: &'static str,                 // What the user types.
+    pub usage: &'static str,                // Name and arguments.
+    pub summary: &'static str,              // One line, for the list.
+    pub details: &'static [&'static str],   // Printed by "help <command>".
+    pub protocols: &'static [&'static str], // Comm modules that have it.
+}
+
+const UCI: &[&str] = &[CommType::UCI];
+const CONSOLE: &[&str] = &[CommType::CONSOLE];
+const UCI_CONSOLE: &[&str] = &[CommType::UCI, CommType::CONSOLE];
+
+pub const CUSTOM_COMMANDS: [CustomCommand; 27] = [
+    CustomCommand {
+        name: "move",
+        usage: "<move>",
+        summary: "Play a move, such as 'Nf3' or 'g1f3'.",
+        details: &[
+            "Plays a move in SAN or in long algebraic notation, and prints the",
+            "board. Type 'go' to have the engine answer.",
+        ],
+        protocols: CONSOLE,
+    },
+    CustomCommand {
+        name: "go",
+        usage: "go [time]",
+        summary: "Let the engine think, and play its move.",
+        details: &[
+            "Thinks for the given time, such as '5s', '500ms' or '2m', and plays",
+            "the best move it found. A number without a unit is in seconds;",
+            "without a time, the engine plays on its clock, or thinks for 5",
+            "seconds if there is no clock. UCI arguments, such as 'go depth 8',",
+            "search without playing the move.",
+        ],
+        protocols: CONSOLE,
+    },
+    CustomCommand {
+        name: "analyze",
+        usage: "analyze",
+        summary: "Search the position until 'stop'.",
+        details: &[
+            "Searches without a limit, and prints each line the engine finds,",
+            "from White's point of view. 'stop' ends the analysis.",
+        ],
+        protocols: CONSOLE,
+    },
+    CustomCommand {
+        name: "undo",
+        usage: "undo",
+        summary: "Take back the last move.",
+        details: &["Takes back the last move, played by either side."],
+        protocols: CONSOLE,
+    },
+    CustomCommand {
+        name: "new",
+        usage: "new",
+        summary: "Start a new game.",
+        details: &["Sets up the starting position, for a new game."],
+        protocols: CONSOLE,
+    },
+    CustomCommand {
+        name: "help",
+        usage: "help [command]",
+        summary: "This help information.",
+        details: &[
+            "Without a command, lists all custom commands.",
+            "With a command, prints how to use that command.",
+        ],
+        protocols: UCI_CONSOLE,
+    },
+    CustomCommand {
+        name: "board",
+        usage: "board",
+        summary: "Print the current board state.",
+        details: &["Prints the board, the side to move, castling rights and the keys."],
+        protocols: UCI_CONSOLE,
+    },
+    CustomCommand {
+        name: "history",
+        usage: "history",
+        summary: "Print a list of past board states.",
+        details: &["Prints the moves played to reach the current position."],
+        protocols: UCI_CONSOLE,
+    },
+    CustomCommand {
+        name: "eval",
+        usage: "eval",
+        summary: "Print evaluation for side to move.",
+        details: &["Prints the static evaluation, split into its terms."],
+        protocols: UCI_CONSOLE,
+    },
+    CustomCommand {
+        name: "heatmap",
+        usage: "heatmap",
+        summary: "Print what each square adds to the evaluation.",
+        details: &["Scores are from White's point of view, in centipawns."],
+        protocols: UCI_CONSOLE,
+    },
+    CustomCommand {
+        name: "eco",
+        usage: "eco",
+        summary: "Print the ECO code and name of the opening.",
+        details: &["Looks up the longest known opening the game went through."],
+        protocols: UCI_CONSOLE,
+    },
+    CustomCommand {
+        name: "pgn",
+        usage: "pgn [file]",
+        summary: "Print the game as PGN, or set up a game from a file.",
+        details: &[
+            "Without a file, prints the moves played so far as a PGN game.",
+            "With a file, sets up the position at the end of its first game.",
+        ],
+        protocols: UCI_CONSOLE,
+    },
+    CustomCommand {
+        name: "perft",
+        usage: "perft <depth>",
+        summary: "Count leaf nodes to the given depth for each move.",
+        details: &[
+            "Counts the leaf nodes of the move tree from the current position,",
+            "for each legal move. Used to check the move generator.",
+        ],
+        protocols: UCI_CONSOLE,
+    },
+    CustomCommand {
+        name: "bench",
+        usage: "bench [depth]",
+        summary: "Search the bench positions to the given depth.",
+        details: &[
+            "Searches a fixed set of positions, and reports the node count",
+            "and speed. Without a depth, the default bench depth is used.",
+        ],
+        protocols: UCI_CONSOLE,
+    },
+    CustomCommand {
+        name: "explain",
+        usage: "explain",
+        summary: "Explain the move played after the last search.",
+        details: &["Shows why the last best move was chosen over the alternatives."],
+        protocols: UCI_CONSOLE,
+    },
+    CustomCommand {
+        name: "showbb",
+        usage: "showbb <expr>[, ...]",
+        summary: "Print bitboard expressions, such as 'attacks(e4) & black'.",
+        details: &[
+            "Prints the squares of a bitboard expression. Separate expressions",
+            "with a comma to overlay them on one board.",
+        ],
+        protocols: UCI_CONSOLE,
+    },
+    CustomCommand {
+        name: "compare",
+        usage: "compare <fen> [<fen>]",
+        summary: "Evaluate two positions side by side, term by term.",
+        details: &[
+            "Breaks the evaluation of both positions down into its terms, from",
+            "White's point of view, and prints them next to each other. The",
+            "terms that differ most come first. With one FEN-string, the",
+            "current position is compared to it.",
+        ],
+        protocols: UCI_CONSOLE,
+    },
+    CustomCommand {
+        name: "options",
+        usage: "options",
+        summary: "Print the current value of every option.",
+        details: &["Lists each engine option with the value it has now."],
+        protocols: UCI_CONSOLE,
+    },
+    CustomCommand {
+        name: "commquery",
+        usage: "commquery",
+        summary: "Print the protocol's features and options as JSON.",
+        details: &["Meant for tools that configure themselves for the engine."],
+        protocols: UCI,
+    },
+    CustomCommand {
+        name: "about",
+        usage: "about",
+        summary: "Print engine and build information.",
+        details: &["Include this information in bug reports."],
+        protocols: UCI_CONSOLE,
+    },
+    CustomCommand {
+        name: "pause",
+        usage: "pause",
+        summary: "Pause the running search and its clock.",
+        details: &["The search keeps its state, and continues after \"resume\"."],
+        protocols: UCI_CONSOLE,
+    },
+    CustomCommand {
+        name: "resume",
+        usage: "resume",
+        summary: "Resume a paused search.",
+        details: &["Continues a search that was paused with \"pause\"."],
+        protocols: UCI_CONSOLE,
+    },
+    CustomCommand {
+        name: "play",
+        usage: "play <move>",
+        summary: "Play a move against the engine, which replies.",
+        details: &[
+            "Plays the move, in SAN or long algebraic notation, on the board.",
+            "The engine then thinks for 5 seconds, or plays on its clock if",
+            "there is one, and plays its reply.",
+        ],
+        protocols: UCI_CONSOLE,
+    },
+    CustomCommand {
+        name: "clock",
+        usage: "clock [time [increment] | off]",
+        summary: "Play with a chess clock, or show the time left.",
+        details: &[
+            "Starts a clock with the given time for each side, such as '5m', and",
+            "the increment added after each move, such as '3s'. Without a unit,",
+            "the time is in minutes and the increment in seconds. The engine",
+            "plays its replies on its own clock. A side that runs out of time",
+            "loses. 'clock off' plays without a clock again.",
+        ],
+        protocols: UCI_CONSOLE,
+    },
+    CustomCommand {
+        name: "blindfold",
+        usage: "blindfold",
+        summary: "Switch blindfold mode on or off.",
+        details: &[
+            "In blindfold mode, the board is not printed after a move. Both",
+            "sides' moves are shown in SAN, so the position has to be kept",
+            "in mind. 'reveal' shows the board, and 'hint' suggests a move.",
+        ],
+        protocols: UCI_CONSOLE,
+    },
+    CustomCommand {
+        name: "reveal",
+        usage: "reveal",
+        summary: "Show the board, also in blindfold mode.",
+        details: &["Prints the board once; blindfold mode stays on."],
+        protocols: UCI_CONSOLE,
+    },
+    CustomCommand {
+        name: "hint",
+        usage: "hint",
+        summary: "Suggest a move.",
+        details: &[
+            "Does a short search, and prints the best move it found, without",
+            "playing it or showing the line behind it.",
+        ],
+        protocols: UCI_CONSOLE,
+    },
+];
+
+// The custom commands of the given protocol.
+pub fn custom_commands(protocol: &str) -> impl Iterator<Item = &'static CustomCommand> + '_ {
+    CUSTOM_COMMANDS
+        .iter()
+        .filter(move |c| c.protocols.contains(&protocol))
+}
+
+// Creates the help text. Without a topic, this lists all the custom
+// commands of the protocol; with a topic, it describes that command.
+pub fn help_text(protocol: &str, topic: &str) -> Vec<String> {
+    if topic.is_empty() {
+        return command_list(protocol);
+    }
+
+    match custom_commands(protocol).find(|c| c.name == topic) {
+        Some(command) => {
+            let mut text = vec![format!("Usage: {}", command.usage), String::new()];
+            text.extend(command.details.iter().map(|line| line.to_string()));
+            text.push(String::new());
+            text
+        }
+        None => vec![format!(
+            "Unknown command '{topic}'. Type 'help' for a list of commands."
+        )],
+    }
+}
+
+fn command_list(protocol: &str) -> Vec<String> {
+    let width = custom_commands(protocol)
+        .map(|c| c.usage.len())
+        .max()
+        .unwrap_or(0);
+
+    let mut text = if protocol == CommType::CONSOLE {
+        vec![
+            String::from("The engine is in console mode, for use in a terminal window."),
+            String::from("Input that is not one of these commands is handled as UCI."),
+            String::new(),
+            String::from("Commands"),
+        ]
+    } else {
+        vec![
+            format!(
+                "The engine is in {} communication mode. It supports some custom",
+                protocol.to_uppercase()
+            ),
+            format!(
+                "non-{} commands to make use through a terminal window easier.",
+                protocol.to_uppercase()
+            ),
+            String::from("These commands can also be very useful for debugging purposes."),
+            String::new(),
+            String::from("Custom commands"),
+        ]
+    };
+    text.push(String::from(
+        "================================================================",
+    ));
+
+    for c in custom_commands(protocol) {
+        text.push(format!("{:<width$} : {}", c.usage, c.summary));
+    }
+
+    text.push(format!("{:<width$} : Quit/Exit the engine.", "exit"));
+    text.push(String::new());
+    text.push(String::from(
+        "Type 'help <command>' for more information about a command.",
+    ));
+    text.push(String::new());
+    text
+}
@@ -23,24 +23,38 @@ with this program.  If not, see <http://www.gnu.org/licenses/>.
 
 // This file implements the UCI communication module.
 
-use super::{CommControl, CommReport, CommType, IComm};
+use super::{help, CommControl, CommInfo, CommReport, CommType, IComm};
 use crate::{
     board::Board,
-    defs::{About, FEN_START_POSITION},
-    engine::defs::{EngineOption, EngineOptionName, ErrFatal, Information, UiElement},
-    misc::print,
-    movegen::defs::Move,
-    search::defs::{
-        GameTime, SearchCurrentMove, SearchStats, SearchSummary, CHECKMATE, CHECKMATE_THRESHOLD,
+    defs::{About, Bitboard, Sides, FEN_START_POSITION, MAX_DEPTH},
+    engine::defs::{EngineOption, EngineOptionName, ErrFatal, InfoSender, Information, UiElement},
+    evaluation::{draw, heatmap},
+    misc::{
+        bench::BENCH_DEPTH,
+        print::{self, BoardStyle},
+        protocol_log::ProtocolLog,
     },
+    movegen::defs::Move,
+    search::defs::{GameTime, ScoreBound, SearchCurrentMove, SearchStats, SearchSummary},
 };
-use crossbeam_channel::{self, Sender};
+use crossbeam_channel::{self, Receiver, Sender};
 use std::{
-    io::{self},
-    sync::{Arc, Mutex},
+    fs,
+    io::{self, IsTerminal, StdoutLock},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
     thread::{self, JoinHandle},
+    time::Duration,
 };
 
+// A script waits this long for the best move of a search, on top of the
+// time a timed search may use. A search without a time limit can take
+// as long as it needs, so the script gives it up to ten minutes.
+const SCRIPT_WAIT_EXTRA: u128 = 5_000;
+const SCRIPT_WAIT_UNTIMED: u128 = 600_000;
+
 // Input will be turned into a report, which wil be sent to the engine. The
 // main engine thread will react accordingly.
 #[derive(PartialEq, Clone)]
@@ -51,11 +65,14 @@ pub enum UciReport {
     IsReady,
     SetOption(EngineOptionName),
     Position(String, Vec<String>),
-    GoInfinite,
-    GoDepth(i8),
-    GoMoveTime(u128),
-    GoNodes(usize),
-    GoGameTime(GameTime),
+    GoInfinite(Vec<String>),
+    GoDepth(i8, Vec<String>),
+    GoMoveTime(u128, Vec<String>),
+    GoNodes(usize, Vec<String>),
+    GoGameTime(GameTime, Vec<String>),
+    GoPonder(GameTime, Vec<String>),
+    GoMate(u8, Vec<String>),
+    PonderHit,
     Stop,
     Quit,
 
@@ -63,42 +80,143 @@ pub enum UciReport {
     Board,
     History,
     Eval,
-    Help,
+    Heatmap,
+    Eco,
+    Pgn(String),
+    Perft(i8),
+    Bench(i8),
+    Explain,
+    ShowBitboard(String),
+    Compare(String),
+    Options,
+    CommQuery,
+    About,
+    Pause,
+    Resume,
+    Play(String),
+    Blindfold,
+    Reveal,
+    Hint,
+    Clock(u128, u128),
+    ClockOff,
+    ShowClock,
+    Help(String),
 
     // Empty or unknown command.
     Unknown,
 }
 
-// This struct is used to instantiate the Comm Console module.
+// Where the responses to the (G)UI go: to stdout, or into a channel if
+// the engine is embedded in another program.
+#[derive(Clone)]
+enum Output {
+    Stdout,
+    Channel(Sender<String>),
+}
+
+impl Output {
+    fn write(&self, output: &str) {
+        if output.is_empty() {
+            return;
+        }
+
+        match self {
+            Output::Stdout => println!("{output}"),
+            Output::Channel(tx) => {
+                // If the program stopped listening, there's nobody left
+                // to tell anything.
+                let _ = tx.send(output.to_string());
+            }
+        }
+    }
+
+    // Lock stdout, so a group of responses can be written without anything
+    // else in between. The control thread is the only writer to a channel,
+    // so that needs no lock.
+    fn lock(&self) -> Option<StdoutLock<'static>> {
+        match self {
+            Output::Stdout => Some(io::stdout().lock()),
+            Output::Channel(_) => None,
+        }
+    }
+}
+
+// This struct is used to instantiate the Comm UCI module.
 pub struct Uci {
     control_handle: Option<JoinHandle<()>>,
     report_handle: Option<JoinHandle<()>>,
     control_tx: Option<Sender<CommControl>>,
+    allow_custom: bool,
+    script: Option<String>,
+    strict: Arc<AtomicBool>,
+    input: Option<Receiver<String>>,
+    output: Output,
+    board_style: BoardStyle,
 }
 
 // Public functions
 impl Uci {
-    // Create a new console.
-    pub fn new() -> Self {
+    // Create the UCI backend. If "allow_custom" is set, the custom commands
+    // stay available even when a GUI is connected (for debugging). The
+    // commands in the "script" file are handled before reading stdin.
+    // The "board" command prints the board in the given style.
+    pub fn new(allow_custom: bool, script: Option<String>, board_style: BoardStyle) -> Self {
+        Self {
+            control_handle: None,
+            report_handle: None,
+            control_tx: None,
+            allow_custom,
+            script,
+            strict: Arc::new(AtomicBool::new(false)),
+            input: None,
+            output: Output::Stdout,
+            board_style,
+        }
+    }
+
+    // Create a UCI module that doesn't use stdin and stdout, for a program
+    // that embeds the engine. Commands are received through "input", one or
+    // more lines at a time, and responses are sent into "output", one line
+    // at a time. There is no user typing in a terminal, so the custom
+    // commands are not available. Closing the input quits the engine.
+    pub fn in_memory(input: Receiver<String>, output: Sender<String>) -> Self {
         Self {
             control_handle: None,
             report_handle: None,
             control_tx: None,
+            allow_custom: false,
+            script: None,
+            strict: Arc::new(AtomicBool::new(true)),
+            input: Some(input),
+            output: Output::Channel(output),
+            board_style: BoardStyle::Ascii,
         }
     }
+
+    // Parses one line of input, without a running report thread. This is
+    // used by the fuzzer.
+    #[cfg(feature = "extra")]
+    pub fn parse(input: &str) -> CommReport {
+        Uci::create_report(input)
+    }
 }
 
 // Any communication module must implement the trait IComm.
 impl IComm for Uci {
     fn init(
         &mut self,
-        report_tx: Sender<Information>,
+        report_tx: InfoSender,
         board: Arc<Mutex<Board>>,
         options: Arc<Vec<EngineOption>>,
+        log: ProtocolLog,
     ) {
+        // The control thread tells the report thread when it has sent a
+        // best move, so a script can wait for its searches to finish.
+        let (best_move_tx, best_move_rx) = crossbeam_channel::unbounded::<()>();
+
         // Start threads
-        self.report_thread(report_tx);
-        self.control_thread(board, options);
+        self.report_thread(report_tx, log.clone(), best_move_rx);
+        self.control_thread(board, options, log, best_move_tx);
     }
 
     // The creator of the Comm module can use this function to send
@@ -125,42 +243,120 @@ impl IComm for Uci {
     fn get_protocol_name(&self) -> &'static str {
         CommType::UCI
     }
+
+    // In strict mode, a GUI only gets what the protocol defines.
+    fn is_strict(&self) -> bool {
+        self.strict.load(Ordering::Relaxed)
+    }
+}
+
+// Describe the features of this Comm module.
+impl Uci {
+    // UCI is stateless: the GUI sends the entire game before each search.
+    // Input is read on its own thread, so commands can be sent at any
+    // time, including during a search.
+    fn comm_info() -> CommInfo {
+        CommInfo {
+            protocol: CommType::UCI,
+            stateful: false,
+            buffered: true,
+            option_command: "setoption name <name> value <value>",
+            custom_commands: help::custom_commands(CommType::UCI)
+                .map(|c| c.name)
+                .collect(),
+        }
+    }
 }
 
 // Implement the report thr
 impl Uci {
     // The Report thread sends incoming data to the engine thread.
-    fn report_thread(&mut self, report_tx: Sender<Information>) {
+    fn report_thread(
+        &mut self,
+        report_tx: InfoSender,
+        log: ProtocolLog,
+        best_move_rx: Receiver<()>,
+    ) {
         // Create thread-local variables
         let mut t_incoming_data = String::from("");
         let t_report_tx = report_tx; // Report sender
+        let t_log = log; // Log of all input and output
+        let t_script = self.script.take();
+        let t_strict = Arc::clone(&self.strict);
+        let t_input = self.input.take();
+
+        // Custom commands are available to a user typing in a terminal, or
+        // if they were explicitly allowed.
+        let t_allow_custom = self.allow_custom || (t_input.is_none() && io::stdin().is_terminal());
 
         // Actual thread creation.
         let report_handle = thread::spawn(move || {
             let mut quit = false;
 
+            // Commands from the script are handled first, as if they were
+            // typed in. If the script can't be read, the engine continues.
+            // A GUI waits for "bestmove" before it sends the next command,
+            // and so does the script; otherwise, a "quit" right after a
+            // "go" would end the search before it reported anything. The
+            // script counts the searches it started, so the best move of a
+            // search it stopped earlier isn't taken for the last one's.
+            if let Some(path) = t_script {
+                match fs::read_to_string(&path) {
+                    Ok(script) => {
+                        let mut searches = 0;
+                        for command in Uci::split_commands(&script) {
+                            let report = Uci::create_report(command);
+                            quit = Uci::handle_input(
+                                command,
+                                &t_report_tx,
+                                &t_strict,
+                                t_allow_custom,
+                                &t_log,
+                            );
+                            if quit {
+                                break;
+                            }
+                            if Uci::starts_search(&report) {
+                                searches += 1;
+                            }
+                            if Uci::ends_by_itself(&report) {
+                                let wait = Uci::best_move_wait(&report);
+                                Uci::wait_for_best_moves(&best_move_rx, &mut searches, wait);
+                            }
+                        }
+                    }
+                    Err(e) => {
+                        Output::Stdout.write(&Uci::info_string(&format!("Script {path}: {e}")))
+                    }
+                }
+            }
+
+            // Nobody waits for a best move after the script.
+            std::mem::drop(best_move_rx);
+
             // Keep running as long as 'quit' is not detected.
             while !quit {
-                // Get data from stdin.
-                io::stdin()
-                    .read_line(&mut t_incoming_data)
-                    .expect(ErrFatal::READ_IO);
-
-                // Create a report from the incoming data.
-                let new_report = Uci::create_report(&t_incoming_data);
-
-                // Check if the created report is valid, so it is something
-                // the engine will understand.
-                if new_report.is_valid() {
-                    // Send it to the engine thread.
-                    t_report_tx
-                        .send(Information::Comm(new_report.clone()))
-                        .expect(ErrFatal::HANDLE);
-
-                    // Terminate the reporting thread if "Quit" was detected.
-                    quit = new_report == CommReport::Uci(UciReport::Quit);
+                // Get data from stdin, or from the input channel. If the
+                // channel is closed, nobody can send "quit" anymore.
+                match &t_input {
+                    Some(rx) => {
+                        t_incoming_data = rx.recv().unwrap_or_else(|_| String::from("quit"));
+                    }
+                    None => {
+                        io::stdin()
+                            .read_line(&mut t_incoming_data)
+                            .expect(ErrFatal::READ_IO);
+                    }
                 }
 
+                quit = Uci::handle_input(
+                    &t_incoming_data,
+                    &t_report_tx,
+                    &t_strict,
+                    t_allow_custom,
+                    &t_log,
+                );
+
                 // Clear for next input
                 t_incoming_data = String::from("");
             }
@@ -169,48 +365,110 @@ impl Uci {
         // Store the handle.
         self.report_handle = Some(report_handle);
     }
+
+    // The data can contain more than one command, separated by line
+    // breaks. Handle them one by one, in order. Returns true if "quit"
+    // was found; commands after it are not handled.
+    fn handle_input(
+        data: &str,
+        report_tx: &InfoSender,
+        strict: &AtomicBool,
+        allow_custom: bool,
+        log: &ProtocolLog,
+    ) -> bool {
+        for command in Uci::split_commands(data) {
+            log.incoming(command);
+
+            // Create a report from the incoming command.
+            let mut new_report = Uci::create_report(command);
+
+            // If a GUI starts UCI mode, it is not a user typing on a
+            // terminal. Switch to strict mode, so the custom commands
+            // can't be triggered by accident.
+            if new_report == CommReport::Uci(UciReport::Uci) {
+                strict.store(!allow_custom, Ordering::Relaxed);
+            }
+
+            // In strict mode, custom commands are treated as unknown.
+            if strict.load(Ordering::Relaxed) && Uci::is_custom(&new_report) {
+                new_report = CommReport::Uci(UciReport::Unknown);
+            }
+
+            // Check if the created report is valid, so it is something
+            // the engine will understand.
+            if new_report.is_valid() {
+                // Send it to the engine thread.
+                report_tx
+                    .send(Information::Comm(new_report.clone()))
+                    .expect(ErrFatal::HANDLE);
+
+                // Terminate the reporting thread if "Quit" was detected.
+                if new_report == CommReport::Uci(UciReport::Quit) {
+                    return true;
+                }
+            }
+        }
+
+        false
+    }
+}
+
+// The state of the control thread. It is kept together, so a group of
+// messages can be handled one by one, with the same state.
+struct ControlState {
+    output: Output,
+    log: ProtocolLog,
+    best_move_tx: Sender<()>,
+    board: Arc<Mutex<Board>>,
+    options: Arc<Vec<EngineOption>>,
+    strict: Arc<AtomicBool>,
+    board_style: BoardStyle,
+    scores_from_white: bool,
+    quit: bool,
+}
+
+impl ControlState {
+    // Everything that is sent to the GUI is also logged.
+    fn write(&self, output: &str) {
+        self.log.outgoing(output);
+        self.output.write(output);
+    }
 }
 
 // Implement the control thread
 impl Uci {
     // The control thread receives commands from the engine thread.
-    fn control_thread(&mut self, board: Arc<Mutex<Board>>, options: Arc<Vec<EngineOption>>) {
+    fn control_thread(
+        &mut self,
+        board: Arc<Mutex<Board>>,
+        options: Arc<Vec<EngineOption>>,
+        log: ProtocolLog,
+        best_move_tx: Sender<()>,
+    ) {
         // Create an incoming channel for the control thread.
         let (control_tx, control_rx) = crossbeam_channel::unbounded::<CommControl>();
+        let strict = Arc::clone(&self.strict);
+        let output = self.output.clone();
+        let board_style = self.board_style;
 
         // Create the control thread.
         let control_handle = thread::spawn(move || {
-            let mut quit = false;
-            let t_board = Arc::clone(&board);
-            let t_options = Arc::clone(&options);
+            let mut state = ControlState {
+                output,
+                log,
+                best_move_tx,
+                board: Arc::clone(&board),
+                options: Arc::clone(&options),
+                strict: Arc::clone(&strict),
+                board_style,
+                scores_from_white: false,
+                quit: false,
+            };
 
             // Keep running as long as Quit is not received.
-            while !quit {
+            while !state.quit {
                 let control = control_rx.recv().expect(ErrFatal::CHANNEL);
-
-                // Perform command as sent by the engine thread.
-                match control {
-                    CommControl::Identify => {
-                        Uci::id();
-                        Uci::options(&t_options);
-                        Uci::uciok();
-                    }
-                    CommControl::Ready => Uci::readyok(),
-                    CommControl::Quit => quit = true,
-                    CommControl::SearchSummary(summary) => Uci::search_summary(&summary),
-                    CommControl::SearchCurrMove(current) => Uci::search_currmove(&current),
-                    CommControl::SearchStats(stats) => Uci::search_stats(&stats),
-                    CommControl::InfoString(msg) => Uci::info_string(&msg),
-                    CommControl::BestMove(bm) => Uci::best_move(&bm),
-
-                    // Custom prints for use in the console.
-                    CommControl::PrintBoard => Uci::print_board(&t_board),
-                    CommControl::PrintHistory => Uci::print_history(&t_board),
-                    CommControl::PrintHelp => Uci::print_help(),
-
-                    // Comm Control commands that are not (yet) used.
-                    CommControl::Update => (),
-                }
+                Uci::handle_control(control, &mut state);
             }
         });
 
@@ -218,13 +476,78 @@ impl Uci {
         self.control_handle = Some(control_handle);
         self.control_tx = Some(control_tx);
     }
+
+    // Perform command as sent by the engine thread.
+    fn handle_control(control: CommControl, state: &mut ControlState) {
+        match control {
+            // Messages that belong together are written while holding the
+            // lock on stdout, so nothing else can be written in between.
+            CommControl::Group(group) => {
+                let _stdout = state.output.lock();
+                for control in group {
+                    Uci::handle_control(control, state);
+                }
+            }
+            CommControl::Identify => {
+                let _stdout = state.output.lock();
+                state.write(&Uci::id());
+                state.write(&Uci::options(&state.options));
+                state.write(&Uci::uciok());
+            }
+            CommControl::Ready => state.write(&Uci::readyok()),
+            CommControl::Quit => state.quit = true,
+            CommControl::SearchSummary(summary) => {
+                // The position may have changed since the search sent
+                // this line, so the side to move is taken from the line.
+                let flip = state.scores_from_white && summary.side == Sides::BLACK;
+                state.write(&Uci::search_summary(&summary, flip))
+            }
+            CommControl::SearchCurrMove(current) => state.write(&Uci::search_currmove(&current)),
+            CommControl::SearchRefutation(line) => state.write(&Uci::search_refutation(&line)),
+            CommControl::SearchStats(stats) => state.write(&Uci::search_stats(&stats)),
+            CommControl::InfoString(msg) => state.write(&Uci::info_string(&msg)),
+            CommControl::BestMove(bm, pm) => {
+                state.write(&Uci::best_move(&bm, &pm));
+
+                // If the script already finished, nobody is listening.
+                let _ = state.best_move_tx.send(());
+            }
+            CommControl::ScoresFromWhite(v) => state.scores_from_white = v,
+
+            // A GUI doesn't need to hear back about every option
+            // it sets, so in strict mode this is left out.
+            CommControl::OptionSet(name, value) => {
+                if !state.strict.load(Ordering::Relaxed) {
+                    let msg = format!("option {name} set to {value}");
+                    state.write(&Uci::info_string(&msg));
+                }
+            }
+
+            // Custom prints for use in the console.
+            CommControl::PrintBoard(board) => print::position(&board, None, state.board_style),
+            CommControl::PrintHistory => Uci::print_history(&state.board),
+            CommControl::PrintBitboards(layers) => Uci::print_bitboards(&layers),
+            CommControl::PrintHeatmap => Uci::print_heatmap(&state.board),
+            CommControl::PrintCommQuery => state.write(&Uci::comm_info().to_json(&state.options)),
+            CommControl::PrintHelp(topic) => Uci::print_help(&topic),
+
+            // Comm Control commands that are not (yet) used.
+            CommControl::Update => (),
+        }
+    }
 }
 
 // Private functions for this module.
 impl Uci {
+    // Split incoming data into separate commands. Both LF and CR are
+    // accepted as line breaks, and empty lines are skipped.
+    fn split_commands(data: &str) -> impl Iterator<Item = &str> {
+        data.split(['\n', '\r']).filter(|c| !c.trim().is_empty())
+    }
+
     // This function turns the incoming data into UciReports which the
     // engine is able to understand and react to.
-    fn create_report(input: &str) -> CommReport {
+    pub fn create_report(input: &str) -> CommReport {
         // Trim CR/LF so only the usable characters remain.
         let i = input.trim_end().to_string();
 
@@ -234,6 +557,7 @@ impl Uci {
             cmd if cmd == "uci" => CommReport::Uci(UciReport::Uci),
             cmd if cmd == "ucinewgame" => CommReport::Uci(UciReport::UciNewGame),
             cmd if cmd == "isready" => CommReport::Uci(UciReport::IsReady),
+            cmd if cmd == "ponderhit" => CommReport::Uci(UciReport::PonderHit),
             cmd if cmd == "stop" => CommReport::Uci(UciReport::Stop),
             cmd if cmd == "quit" || cmd == "exit" => CommReport::Uci(UciReport::Quit),
             cmd if cmd.starts_with("setoption") => Uci::parse_setoption(&cmd),
@@ -244,13 +568,172 @@ impl Uci {
             cmd if cmd == "board" => CommReport::Uci(UciReport::Board),
             cmd if cmd == "history" => CommReport::Uci(UciReport::History),
             cmd if cmd == "eval" => CommReport::Uci(UciReport::Eval),
-            cmd if cmd == "help" => CommReport::Uci(UciReport::Help),
+            cmd if cmd == "heatmap" => CommReport::Uci(UciReport::Heatmap),
+            cmd if cmd == "eco" => CommReport::Uci(UciReport::Eco),
+            cmd if cmd == "pgn" || cmd.starts_with("pgn ") => {
+                CommReport::Uci(UciReport::Pgn(cmd[3..].trim().to_string()))
+            }
+            cmd if cmd == "perft" || cmd.starts_with("perft ") => {
+                let depth = cmd[5..].trim().parse::<i8>().unwrap_or(0);
+                CommReport::Uci(UciReport::Perft(depth))
+            }
+            cmd if cmd == "bench" || cmd.starts_with("bench ") => {
+                let depth = cmd[5..].trim().parse::<i8>().unwrap_or(BENCH_DEPTH);
+                CommReport::Uci(UciReport::Bench(depth))
+            }
+            cmd if cmd == "explain" => CommReport::Uci(UciReport::Explain),
+            cmd if cmd == "showbb" || cmd.starts_with("showbb ") => {
+                CommReport::Uci(UciReport::ShowBitboard(cmd[6..].trim().to_string()))
+            }
+            cmd if cmd == "compare" || cmd.starts_with("compare ") => {
+                CommReport::Uci(UciReport::Compare(cmd[7..].trim().to_string()))
+            }
+            cmd if cmd == "options" => CommReport::Uci(UciReport::Options),
+            cmd if cmd == "commquery" => CommReport::Uci(UciReport::CommQuery),
+            cmd if cmd == "about" => CommReport::Uci(UciReport::About),
+            cmd if cmd == "pause" => CommReport::Uci(UciReport::Pause),
+            cmd if cmd == "resume" => CommReport::Uci(UciReport::Resume),
+            cmd if cmd.starts_with("play ") => {
+                CommReport::Uci(UciReport::Play(cmd[5..].trim().to_string()))
+            }
+            cmd if cmd == "blindfold" => CommReport::Uci(UciReport::Blindfold),
+            cmd if cmd == "reveal" => CommReport::Uci(UciReport::Reveal),
+            cmd if cmd == "hint" => CommReport::Uci(UciReport::Hint),
+            cmd if cmd == "clock" || cmd.starts_with("clock ") => Uci::parse_clock(&cmd),
+            cmd if cmd == "help" || cmd.starts_with("help ") => {
+                CommReport::Uci(UciReport::Help(cmd[4..].trim().to_string()))
+            }
 
             // Everything else is ignored.
             _ => CommReport::Uci(UciReport::Unknown),
         }
     }
 
+    // Returns true if the report starts a search, which is going to report
+    // a best move sooner or later.
+    fn starts_search(report: &CommReport) -> bool {
+        Uci::ends_by_itself(report)
+            || matches!(
+                report,
+                CommReport::Uci(UciReport::GoInfinite(..) | UciReport::GoPonder(..))
+            )
+    }
+
+    // A search that ends by itself, without waiting for "stop" or
+    // "ponderhit", reports a best move when it is done.
+    fn ends_by_itself(report: &CommReport) -> bool {
+        matches!(
+            report,
+            CommReport::Uci(
+                UciReport::GoDepth(..)
+                    | UciReport::GoMoveTime(..)
+                    | UciReport::GoNodes(..)
+                    | UciReport::GoGameTime(..)
+                    | UciReport::GoMate(..)
+            )
+        )
+    }
+
+    // How long a script waits for the best move of a search. A timed
+    // search reports shortly after its time is used up.
+    fn best_move_wait(report: &CommReport) -> Duration {
+        let ms = match report {
+            CommReport::Uci(UciReport::GoMoveTime(ms, _)) => *ms,
+            CommReport::Uci(UciReport::GoGameTime(gt, _)) => gt.wtime.max(gt.btime),
+            _ => SCRIPT_WAIT_UNTIMED,
+        };
+
+        Duration::from_millis((ms + SCRIPT_WAIT_EXTRA) as u64)
+    }
+
+    // Waits until every search the script started has sent its best move.
+    // A search that never does (because "ucinewgame" abandoned it) doesn't
+    // keep the script waiting forever; the script gives up on it and
+    // continues with the next command.
+    fn wait_for_best_moves(best_move_rx: &Receiver<()>, searches: &mut usize, wait: Duration) {
+        while *searches > 0 {
+            if best_move_rx.recv_timeout(wait).is_err() {
+                let msg = format!("Script: no best move after {} ms", wait.as_millis());
+                Output::Stdout.write(&Uci::info_string(&msg));
+                *searches = 0;
+                return;
+            }
+            *searches -= 1;
+        }
+    }
+
+    // Returns true if the report is a custom command, not part of UCI.
+    fn is_custom(report: &CommReport) -> bool {
+        matches!(
+            report,
+            CommReport::Uci(
+                UciReport::Board
+                    | UciReport::History
+                    | UciReport::Eval
+                    | UciReport::Heatmap
+                    | UciReport::Eco
+                    | UciReport::Pgn(_)
+                    | UciReport::Perft(_)
+                    | UciReport::Bench(_)
+                    | UciReport::Explain
+                    | UciReport::ShowBitboard(_)
+                    | UciReport::Compare(_)
+                    | UciReport::Options
+                    | UciReport::CommQuery
+                    | UciReport::About
+                    | UciReport::Pause
+                    | UciReport::Resume
+                    | UciReport::Play(_)
+                    | UciReport::Blindfold
+                    | UciReport::Reveal
+                    | UciReport::Hint
+                    | UciReport::Clock(_, _)
+                    | UciReport::ClockOff
+                    | UciReport::ShowClock
+                    | UciReport::Help(_)
+            )
+        )
+    }
+
+    // "clock 5m 3s" starts a chess clock with 5 minutes for each side,
+    // and 3 seconds added after each move. Without a unit, the time is in
+    // minutes and the increment in seconds, so "clock 5 3" is the same.
+    // "clock off" stops using the clock, and "clock" shows the time left.
+    fn parse_clock(cmd: &str) -> CommReport {
+        let args: Vec<&str> = cmd[5..].split_whitespace().collect();
+        let time = |t: &str| Uci::msecs(t, 60_000).filter(|msecs| *msecs > 0);
+        let increment = |i: &str| Uci::msecs(i, 1000);
+
+        let report = match args[..] {
+            [] => Some(UciReport::ShowClock),
+            ["off"] => Some(UciReport::ClockOff),
+            [t] => time(t).map(|t| UciReport::Clock(t, 0)),
+            [t, i] => time(t)
+                .zip(increment(i))
+                .map(|(t, i)| UciReport::Clock(t, i)),
+            _ => None,
+        };
+
+        CommReport::Uci(report.unwrap_or(UciReport::Unknown))
+    }
+
+    // A time such as "5s", "500ms" or "2m", in milliseconds. A number
+    // without a unit is multiplied by the given factor.
+    pub fn msecs(time: &str, factor_without_unit: u128) -> Option<u128> {
+        let time = time.trim();
+        let (number, factor) = if let Some(ms) = time.strip_suffix("ms") {
+            (ms, 1)
+        } else if let Some(s) = time.strip_suffix('s') {
+            (s, 1000)
+        } else if let Some(m) = time.strip_suffix('m') {
+            (m, 60_000)
+        } else {
+            (time, factor_without_unit)
+        };
+
+        number.trim().parse::<u128>().ok().map(|n| n * factor)
+    }
+
     fn parse_position(cmd: &str) -> CommReport {
         enum Tokens {
             Nothing,
@@ -298,17 +781,32 @@ impl Uci {
             WInc,
             BInc,
             MovesToGo,
+            Mate,
+            SearchMoves,
+        }
+
+        // Search modes other than GameTime, and their limits.
+        enum Mode {
+            Infinite,
+            Depth(i8),
+            MoveTime(u128),
+            Nodes(usize),
+            Mate(u8),
         }
 
         let parts: Vec<String> = cmd.split_whitespace().map(|s| s.to_string()).collect();
-        let mut report = CommReport::Uci(UciReport::Unknown);
+        let mut is_go = false;
+        let mut mode = Mode::Infinite;
         let mut token = Tokens::Nothing;
         let mut game_time = GameTime::new(0, 0, 0, 0, None);
+        let mut ponder = false;
+        let mut search_moves: Vec<String> = Vec::new();
 
         for p in parts {
             match p {
-                t if t == "go" => report = CommReport::Uci(UciReport::GoInfinite),
-                t if t == "infinite" => break, // Already Infinite; nothing more to do.
+                t if t == "go" => is_go = true,
+                t if t == "infinite" => mode = Mode::Infinite,
+                t if t == "ponder" => ponder = true,
                 t if t == "depth" => token = Tokens::Depth,
                 t if t == "movetime" => token = Tokens::MoveTime,
                 t if t == "nodes" => token = Tokens::Nodes,
@@ -317,50 +815,56 @@ impl Uci {
                 t if t == "winc" => token = Tokens::WInc,
                 t if t == "binc" => token = Tokens::BInc,
                 t if t == "movestogo" => token = Tokens::MovesToGo,
+                t if t == "mate" => token = Tokens::Mate,
+                t if t == "searchmoves" => token = Tokens::SearchMoves,
                 _ => match token {
                     Tokens::Nothing => (),
                     Tokens::Depth => {
-                        let depth = p.parse::<i8>().unwrap_or(1);
-                        report = CommReport::Uci(UciReport::GoDepth(depth));
-                        break; // break for-loop: nothing more to do.
-                    }
-                    Tokens::MoveTime => {
-                        let milliseconds = p.parse::<u128>().unwrap_or(1000);
-                        report = CommReport::Uci(UciReport::GoMoveTime(milliseconds));
-                        break; // break for-loop: nothing more to do.
-                    }
-                    Tokens::Nodes => {
-                        let nodes = p.parse::<usize>().unwrap_or(1);
-                        report = CommReport::Uci(UciReport::GoNodes(nodes));
-                        break; // break for-loop: nothing more to do.
+                        let depth = p.parse::<i16>().unwrap_or(1).clamp(0, MAX_DEPTH as i16);
+                        mode = Mode::Depth(depth as i8)
                     }
+                    Tokens::MoveTime => mode = Mode::MoveTime(p.parse::<u128>().unwrap_or(1000)),
+                    Tokens::Nodes => mode = Mode::Nodes(p.parse::<usize>().unwrap_or(1)),
+                    Tokens::Mate => mode = Mode::Mate(p.parse::<u8>().unwrap_or(1).max(1)),
                     Tokens::WTime => game_time.wtime = p.parse::<u128>().unwrap_or(0),
                     Tokens::BTime => game_time.btime = p.parse::<u128>().unwrap_or(0),
                     Tokens::WInc => game_time.winc = p.parse::<u128>().unwrap_or(0),
                     Tokens::BInc => game_time.binc = p.parse::<u128>().unwrap_or(0),
-                    Tokens::MovesToGo => {
-                        game_time.moves_to_go = if let Ok(x) = p.parse::<usize>() {
-                            Some(x)
-                        } else {
-                            None
-                        }
-                    }
+                    Tokens::MovesToGo => game_time.moves_to_go = p.parse::<usize>().ok(),
+                    Tokens::SearchMoves => search_moves.push(p),
                 }, // end match token
             } // end match p
         } // end for
 
+        if !is_go {
+            return CommReport::Uci(UciReport::Unknown);
+        }
+
         // If we are still in the default "go infinite" mode, we must
         // switch to GameTime mode if at least one parameter of "go wtime
         // btime winc binc" was set to something else but 0.
-        let is_default_mode = report == CommReport::Uci(UciReport::GoInfinite);
+        let is_default_mode = matches!(mode, Mode::Infinite);
         let has_time = game_time.wtime > 0 || game_time.btime > 0;
         let has_inc = game_time.winc > 0 || game_time.binc > 0;
         let is_game_time = has_time || has_inc;
-        if is_default_mode && is_game_time {
-            report = CommReport::Uci(UciReport::GoGameTime(game_time));
-        }
 
-        report
+        // Pondering is only supported in GameTime mode. The time is
+        // needed for the search that continues after a "ponderhit".
+        let report = if ponder && (is_default_mode || is_game_time) {
+            UciReport::GoPonder(game_time, search_moves)
+        } else if is_default_mode && is_game_time {
+            UciReport::GoGameTime(game_time, search_moves)
+        } else {
+            match mode {
+                Mode::Infinite => UciReport::GoInfinite(search_moves),
+                Mode::Depth(depth) => UciReport::GoDepth(depth, search_moves),
+                Mode::MoveTime(msecs) => UciReport::GoMoveTime(msecs, search_moves),
+                Mode::Nodes(nodes) => UciReport::GoNodes(nodes, search_moves),
+                Mode::Mate(moves) => UciReport::GoMate(moves, search_moves),
+            }
+        };
+
+        CommReport::Uci(report)
     } // end parse_go()
 
     fn parse_setoption(cmd: &str) -> CommReport {
@@ -374,6 +878,7 @@ impl Uci {
         let mut token = Tokens::Nothing;
         let mut name = String::from(""); // Option name provided by the UCI command.
         let mut value = String::from(""); // Option value provided by the UCI command.
+        let mut raw_value = String::from(""); // Complete value, as typed (for paths).
         let mut eon = EngineOptionName::Nothing; // Engine Option Name to send to the engine.
 
         for p in parts {
@@ -383,7 +888,10 @@ impl Uci {
                 t if t == "value" => token = Tokens::Value,
                 _ => match token {
                     Tokens::Name => name = format!("{name} {p}"),
-                    Tokens::Value => value = p.to_lowercase(),
+                    Tokens::Value => {
+                        value = p.to_lowercase();
+                        raw_value = format!("{raw_value} {p}");
+                    }
                     Tokens::Nothing => (),
                 },
             }
@@ -394,7 +902,47 @@ impl Uci {
             name = name.to_lowercase().trim().to_string();
             match &name[..] {
                 "hash" => eon = EngineOptionName::Hash(value),
+                "pawnhash" => eon = EngineOptionName::PawnHash(value),
                 "clear hash" => eon = EngineOptionName::ClearHash,
+                "clear hash on new game" => eon = EngineOptionName::ClearHashOnNewGame(value),
+                "multipv" => eon = EngineOptionName::MultiPv(value),
+                "ponder" => eon = EngineOptionName::Ponder(value),
+                "threads" => eon = EngineOptionName::Threads(value),
+                "promotion restriction" => eon = EngineOptionName::PromotionRestriction(value),
+                "promotion restriction in search" => {
+                    eon = EngineOptionName::PromotionRestrictionSearch(value)
+                }
+                "analysis root move nodes" => eon = EngineOptionName::RootMoveNodes(value),
+                "max depth" => eon = EngineOptionName::MaxDepth(value),
+                "uci_analysemode" => eon = EngineOptionName::AnalyseMode(value),
+                "analysis scores from white" => eon = EngineOptionName::ScoresFromWhite(value),
+                "uci_showrefutations" => eon = EngineOptionName::ShowRefutations(value),
+                "uci_showcurrline" => eon = EngineOptionName::ShowCurrLine(value),
+                "qsearch checks" => eon = EngineOptionName::QSearchChecks(value),
+                "show sharpness" => eon = EngineOptionName::ShowSharpness(value),
+                "low memory" => eon = EngineOptionName::LowMemory(value),
+                "auto hash" => eon = EngineOptionName::AutoHash(value),
+                "uci_limitstrength" => eon = EngineOptionName::LimitStrength(value),
+                "uci_elo" => eon = EngineOptionName::Elo(value),
+                "eval player" => eon = EngineOptionName::EvalPlayer(value),
+                "warm up" => eon = EngineOptionName::WarmUp(value),
+                "evalfile" => eon = EngineOptionName::EvalFile(raw_value.trim().to_string()),
+                "clear hash on eval change" => eon = EngineOptionName::ClearHashOnEvalChange(value),
+                "search stats file" => {
+                    eon = EngineOptionName::StatsFile(raw_value.trim().to_string())
+                }
+                "debug log file" => {
+                    eon = EngineOptionName::DebugLogFile(raw_value.trim().to_string())
+                }
+                n if n.starts_with("nullmove") => {
+                    eon = EngineOptionName::NullMove(n.to_string(), value)
+                }
+                n if n.starts_with("razoring") => {
+                    eon = EngineOptionName::Razoring(n.to_string(), value)
+                }
+                n if n.starts_with("timeopening") => {
+                    eon = EngineOptionName::OpeningTime(n.to_string(), value)
+                }
                 _ => (),
             }
         }
@@ -404,21 +952,26 @@ impl Uci {
     }
 }
 
-// Implements UCI responses to send to the G(UI).
+// Implements UCI responses to send to the G(UI). These functions only
+// format the response and return it as a string; they don't print
+// anything. Responses spanning multiple lines are separated by newlines.
 impl Uci {
-    fn id() {
-        println!("id name {} {}", About::ENGINE, About::VERSION);
-        println!("id author {}", About::AUTHOR);
+    fn id() -> String {
+        format!(
+            "id name {} {}\nid author {}",
+            About::ENGINE,
+            About::VERSION,
+            About::AUTHOR
+        )
     }
 
-    fn options(options: &Arc<Vec<EngineOption>>) {
+    fn options(options: &Arc<Vec<EngineOption>>) -> String {
+        let mut lines: Vec<String> = Vec::new();
+
         for o in options.iter() {
             let name = format!("option name {}", o.name);
 
-            let ui_element = match o.ui_element {
-                UiElement::Spin => String::from("type spin"),
-                UiElement::Button => String::from("type button"),
-            };
+            let ui_element = format!("type {}", o.ui_element.name());
 
             let value_default = if let Some(v) = &o.default {
                 format!("default {}", (*v).clone())
@@ -426,6 +979,15 @@ impl Uci {
                 String::from("")
             };
 
+            let value_vars = if let UiElement::Combo(vars) = o.ui_element {
+                vars.iter()
+                    .map(|v| format!("var {v}"))
+                    .collect::<Vec<String>>()
+                    .join(" ")
+            } else {
+                String::from("")
+            };
+
             let value_min = if let Some(v) = &o.min {
                 format!("min {}", (*v).clone())
             } else {
@@ -438,42 +1000,56 @@ impl Uci {
                 String::from("")
             };
 
-            let option = format!("{name} {ui_element} {value_default} {value_min} {value_max}")
-                .trim()
-                .to_string();
-
-            println!("{option}");
+            let option = [
+                name,
+                ui_element,
+                value_default,
+                value_vars,
+                value_min,
+                value_max,
+            ]
+            .into_iter()
+            .filter(|part| !part.is_empty())
+            .collect::<Vec<String>>()
+            .join(" ");
+
+            lines.push(option);
         }
-    }
 
-    fn uciok() {
-        println!("uciok");
+        lines.join("\n")
     }
 
-    fn readyok() {
-        println!("readyok");
+    fn uciok() -> String {
+        String::from("uciok")
     }
 
-    fn search_summary(s: &SearchSummary) {
-        // If mate found, report this; otherwise report normal score.
-        let score = if (s.cp.abs() >= CHECKMATE_THRESHOLD) && (s.cp.abs() < CHECKMATE) {
-            // Number of plies to mate.
-            let ply = CHECKMATE - s.cp.abs();
-
-            // Check if the number of ply's is odd
-            let is_odd = ply % 2 == 1;
-
-            // Calculate number of moves to mate
-            let moves = if is_odd { (ply + 1) / 2 } else { ply / 2 };
+    fn readyok() -> String {
+        String::from("readyok")
+    }
 
-            // If the engine is being mated itself, flip the score.
-            let flip = if s.cp < 0 { -1 } else { 1 };
+    // The score is from the side to move's point of view. If "flip" is
+    // set, it is reported from the opponent's point of view instead; this
+    // turns a lower bound into an upper bound and vice versa.
+    fn search_summary(s: &SearchSummary, flip: bool) -> String {
+        let sign = if flip { -1 } else { 1 };
 
-            // Report the mate
-            format!("mate {}", moves * flip)
+        // If mate found, report this; otherwise report normal score.
+        let score = if let Some(moves) = s.mate_in() {
+            format!("mate {}", moves * sign)
         } else {
-            // Report the normal score if there's no mate detected.
-            format!("cp {}", s.cp)
+            format!("cp {}", s.cp * sign)
+        };
+
+        // Tell the GUI if the score is only a bound, so it is not taken
+        // for the real score (for adjudication, for example).
+        let score = match (s.bound, flip) {
+            (ScoreBound::Exact, _) => score,
+            (ScoreBound::Lower, false) | (ScoreBound::Upper, true) => {
+                format!("{score} lowerbound")
+            }
+            (ScoreBound::Upper, false) | (ScoreBound::Lower, true) => {
+                format!("{score} upperbound")
+            }
         };
 
         // Report depth and seldepth (if available).
@@ -492,52 +1068,80 @@ impl Uci {
 
         let pv = s.pv_as_string();
 
-        let info = format!(
-            "info score {} {} time {} nodes {} nps {}{}pv {}",
-            score, depth, s.time, s.nodes, s.nps, hash_full, pv,
-        );
-
-        println!("{info}");
+        format!(
+            "info multipv {} score {} {} time {} nodes {} nps {}{}tbhits {} cpuload {} pv {}",
+            s.multi_pv, score, depth, s.time, s.nodes, s.nps, hash_full, s.tb_hits, s.cpu_load, pv,
+        )
     }
 
-    fn search_currmove(c: &SearchCurrentMove) {
-        println!(
+    // The current line is only reported if it was collected; the search
+    // does that if the GUI turned on UCI_ShowCurrLine. Only the main
+    // thread reports, so the line is always that of CPU 1.
+    fn search_currmove(c: &SearchCurrentMove) -> String {
+        let currmove = format!(
             "info currmove {} currmovenumber {}",
             c.curr_move.as_string(),
             c.curr_move_number
         );
+
+        if c.curr_line.is_empty() {
+            currmove
+        } else {
+            let line = Uci::moves_as_string(&c.curr_line);
+            format!("{currmove}\ninfo currline 1 {line}")
+        }
     }
 
-    fn search_stats(s: &SearchStats) {
+    fn search_refutation(line: &[Move]) -> String {
+        format!("info refutation {}", Uci::moves_as_string(line))
+    }
+
+    // A null move in a line is sent as "0000", as the protocol requires.
+    fn moves_as_string(moves: &[Move]) -> String {
+        moves
+            .iter()
+            .map(|m| match m.get_move() {
+                0 => String::from("0000"),
+                _ => m.as_string(),
+            })
+            .collect::<Vec<String>>()
+            .join(" ")
+    }
+
+    fn search_stats(s: &SearchStats) -> String {
         let hash_full = if s.hash_full > 0 {
             format!(" hashfull {}", s.hash_full)
         } else {
             String::from("")
         };
 
-        println!(
-            "info time {} nodes {} nps {}{}",
-            s.time, s.nodes, s.nps, hash_full
-        );
+        format!(
+            "info time {} nodes {} nps {}{} tbhits {} cpuload {}",
+            s.time, s.nodes, s.nps, hash_full, s.tb_hits, s.cpu_load
+        )
     }
 
-    fn info_string(msg: &str) {
-        println!("info string {msg}");
+    fn info_string(msg: &str) -> String {
+        format!("info string {msg}")
     }
 
-    fn best_move(m: &Move) {
-        println!("bestmove {}", m.as_string());
+    // Without a legal move, UCI expects the null move "0000".
+    fn best_move(m: &Move, ponder: &Option<Move>) -> String {
+        if m.get_move() == 0 {
+            return String::from("bestmove 0000");
+        }
+
+        match ponder {
+            Some(p) => format!("bestmove {} ponder {}", m.as_string(), p.as_string()),
+            None => format!("bestmove {}", m.as_string()),
+        }
     }
 }
 
 // implements handling of custom commands. These are mostly used when using
 // the UCI protocol directly in a terminal window.
 impl Uci {
-    fn print_board(board: &Arc<Mutex<Board>>) {
-        print::position(&board.lock().expect(ErrFatal::LOCK), None);
-    }
-
-    fn print_history(board: &Arc<Mutex<Board>>) {
+    pub fn print_history(board: &Arc<Mutex<Board>>) {
         let mtx_board = board.lock().expect(ErrFatal::LOCK);
         let length = mtx_board.history.len();
 
@@ -553,18 +1157,280 @@ impl Uci {
         std::mem::drop(mtx_board);
     }
 
-    fn print_help() {
-        println!("The engine is in UCI communication mode. It supports some custom");
-        println!("non-UCI commands to make use through a terminal window easier.");
-        println!("These commands can also be very useful for debugging purposes.");
+    // Print the evaluation heatmap, and what the squares add up to.
+    pub fn print_heatmap(board: &Arc<Mutex<Board>>) {
+        let mtx_board = board.lock().expect(ErrFatal::LOCK);
+        let map = heatmap::square_map(&mtx_board);
+        let total: i16 = map.iter().sum();
+
+        print::eval_map(&mtx_board, &map);
+        println!("{:<20}{total:+} centipawns", "Total (White):");
+        if draw::is_material_draw(&mtx_board) {
+            println!("The position is a draw by insufficient material.");
+        }
         println!();
-        println!("Custom commands");
-        println!("================================================================");
-        println!("help      :   This help information.");
-        println!("board     :   Print the current board state.");
-        println!("history   :   Print a list of past board states.");
-        println!("eval      :   Print evaluation for side to move.");
-        println!("exit      :   Quit/Exit the engine.");
+
+        std::mem::drop(mtx_board);
+    }
+
+    // Print the bitboards on top of each other. Each layer is marked
+    // with its own number, which the legend below the board explains.
+    pub fn print_bitboards(layers: &[(String, Bitboard)]) {
+        let marked: Vec<(Bitboard, char)> = layers
+            .iter()
+            .enumerate()
+            .map(|(i, (_, bb))| (*bb, Uci::layer_mark(i)))
+            .collect();
+
+        print::bitboards("Bitboards", &marked);
+        for (i, (expr, bb)) in layers.iter().enumerate() {
+            let squares = print::squares_as_string(*bb);
+            println!("{}: {expr} = 0x{bb:016x}", Uci::layer_mark(i));
+            println!("   {squares}");
+        }
         println!();
     }
+
+    fn layer_mark(layer: usize) -> char {
+        char::from_digit((layer as u32 + 1) % 36, 36).unwrap_or('*')
+    }
+
+    fn print_help(topic: &str) {
+        for line in help::help_text(CommType::UCI, topic) {
+            println!("{line}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        board::defs::Pieces,
+        engine::defs::info_channel,
+        movegen::defs::Shift,
+        search::defs::{CHECKMATE, CHECKMATE_THRESHOLD},
+    };
+
+    // A pawn move from one square to another, promoting to the given
+    // piece. Only the squares and the promotion are printed.
+    fn pawn_move(from: usize, to: usize, promoted: usize) -> Move {
+        Move::new(
+            Pieces::PAWN
+                | from << Shift::FROM_SQ
+                | to << Shift::TO_SQ
+                | Pieces::NONE << Shift::CAPTURE
+                | promoted << Shift::PROMOTION,
+        )
+    }
+
+    fn e2e4() -> Move {
+        pawn_move(12, 28, Pieces::NONE)
+    }
+
+    fn e7e5() -> Move {
+        pawn_move(52, 36, Pieces::NONE)
+    }
+
+    fn summary(cp: i16, bound: ScoreBound) -> SearchSummary {
+        SearchSummary {
+            depth: 5,
+            seldepth: 9,
+            time: 120,
+            cp,
+            bound,
+            mate: 0,
+            nodes: 5000,
+            nps: 41666,
+            hash_full: 0,
+            tb_hits: 0,
+            cpu_load: 0,
+            multi_pv: 1,
+            pv: vec![e2e4(), e7e5()],
+            side: Sides::WHITE,
+        }
+    }
+
+    // Feeds the data to handle_input() as if it came in at once, and
+    // returns what it sent to the engine, and whether it found "quit".
+    fn handle(data: &str, strict: &AtomicBool, allow_custom: bool) -> (Vec<CommReport>, bool) {
+        let (report_tx, report_rx) = info_channel();
+        let quit = Uci::handle_input(data, &report_tx, strict, allow_custom, &ProtocolLog::new());
+        std::mem::drop(report_tx);
+
+        let mut reports = Vec::new();
+        while let Ok(Information::Comm(report)) = report_rx.recv() {
+            reports.push(report);
+        }
+
+        (reports, quit)
+    }
+
+    #[test]
+    fn split_commands_accepts_any_line_break() {
+        let data = "uci\r\nisready\n\n  \nposition startpos\rgo depth 1";
+        let commands: Vec<&str> = Uci::split_commands(data).collect();
+        assert_eq!(
+            commands,
+            ["uci", "isready", "position startpos", "go depth 1"]
+        );
+    }
+
+    #[test]
+    fn handle_input_sends_all_commands_in_order() {
+        let strict = AtomicBool::new(false);
+        let (reports, quit) = handle("isready\nposition startpos\ngo depth 3\n", &strict, false);
+
+        let expected = [
+            CommReport::Uci(UciReport::IsReady),
+            CommReport::Uci(UciReport::Position(
+                String::from(FEN_START_POSITION),
+                Vec::new(),
+            )),
+            CommReport::Uci(UciReport::GoDepth(3, Vec::new())),
+        ];
+        assert!(reports == expected);
+        assert!(!quit);
+    }
+
+    #[test]
+    fn handle_input_stops_at_quit() {
+        let strict = AtomicBool::new(false);
+        let (reports, quit) = handle("stop\nquit\nisready\n", &strict, false);
+
+        let expected = [
+            CommReport::Uci(UciReport::Stop),
+            CommReport::Uci(UciReport::Quit),
+        ];
+        assert!(reports == expected);
+        assert!(quit);
+    }
+
+    // A script that stopped a "go infinite" waits for that search's best
+    // move and for the next one, and gives up on a search that never
+    // reports.
+    #[test]
+    fn script_waits_for_every_search_it_started() {
+        let (tx, rx) = crossbeam_channel::unbounded::<()>();
+        let wait = Duration::from_millis(50);
+        let mut searches = 2;
+        tx.send(()).unwrap();
+        tx.send(()).unwrap();
+        tx.send(()).unwrap();
+        Uci::wait_for_best_moves(&rx, &mut searches, wait);
+        assert!(searches == 0 && rx.len() == 1);
+
+        rx.recv().unwrap();
+        let mut searches = 1;
+        Uci::wait_for_best_moves(&rx, &mut searches, wait);
+        assert!(searches == 0);
+
+        let go_infinite = Uci::create_report("go infinite");
+        let go_movetime = Uci::create_report("go movetime 1000");
+        assert!(Uci::starts_search(&go_infinite) && !Uci::ends_by_itself(&go_infinite));
+        assert!(Uci::ends_by_itself(&go_movetime));
+        assert!(Uci::best_move_wait(&go_movetime) == Duration::from_millis(6_000));
+    }
+
+    #[test]
+    fn handle_input_turns_on_strict_mode_in_the_same_data() {
+        // Once "uci" is seen, the custom command right after it is no
+        // longer accepted.
+        let strict = AtomicBool::new(false);
+        let (reports, _) = handle("board\nuci\nboard\n", &strict, false);
+        let expected = [
+            CommReport::Uci(UciReport::Board),
+            CommReport::Uci(UciReport::Uci),
+            CommReport::Uci(UciReport::Unknown),
+        ];
+        assert!(reports == expected);
+        assert!(strict.load(Ordering::Relaxed));
+
+        // Unless custom commands were explicitly allowed.
+        let strict = AtomicBool::new(false);
+        let (reports, _) = handle("uci\nboard\n", &strict, true);
+        assert!(reports[1] == CommReport::Uci(UciReport::Board));
+    }
+
+    #[test]
+    fn search_summary_reports_centipawns_and_pv() {
+        let line = Uci::search_summary(&summary(35, ScoreBound::Exact), false);
+        assert_eq!(
+            line,
+            "info multipv 1 score cp 35 depth 5 seldepth 9 time 120 nodes 5000 nps 41666 tbhits 0 cpuload 0 pv e2e4 e7e5"
+        );
+    }
+
+    #[test]
+    fn search_summary_reports_hash_full_only_if_used() {
+        let mut s = summary(35, ScoreBound::Exact);
+        s.hash_full = 12;
+        let line = Uci::search_summary(&s, false);
+        assert!(line.contains("nps 41666 hashfull 12 tbhits 0"), "{line}");
+    }
+
+    #[test]
+    fn search_summary_reports_mate_in_moves() {
+        let line = Uci::search_summary(&summary(CHECKMATE - 3, ScoreBound::Exact), false);
+        assert!(line.contains("score mate 2 depth"), "{line}");
+
+        let line = Uci::search_summary(&summary(-(CHECKMATE - 4), ScoreBound::Exact), false);
+        assert!(line.contains("score mate -2 depth"), "{line}");
+
+        let line = Uci::search_summary(&summary(CHECKMATE_THRESHOLD - 1, ScoreBound::Exact), false);
+        assert!(line.contains("score cp"), "{line}");
+    }
+
+    #[test]
+    fn search_summary_flips_score_and_bound() {
+        let line = Uci::search_summary(&summary(35, ScoreBound::Lower), false);
+        assert!(line.contains("score cp 35 lowerbound depth"), "{line}");
+
+        let line = Uci::search_summary(&summary(35, ScoreBound::Lower), true);
+        assert!(line.contains("score cp -35 upperbound depth"), "{line}");
+
+        let line = Uci::search_summary(&summary(CHECKMATE - 1, ScoreBound::Upper), true);
+        assert!(line.contains("score mate -1 lowerbound depth"), "{line}");
+    }
+
+    #[test]
+    fn best_move_with_and_without_ponder_move() {
+        assert_eq!(Uci::best_move(&e2e4(), &None), "bestmove e2e4");
+        assert_eq!(
+            Uci::best_move(&e2e4(), &Some(e7e5())),
+            "bestmove e2e4 ponder e7e5"
+        );
+        assert_eq!(
+            Uci::best_move(&Move::new(0), &Some(e7e5())),
+            "bestmove 0000"
+        );
+    }
+
+    #[test]
+    fn info_string_is_prefixed() {
+        assert_eq!(Uci::info_string("Hash: 32 MB"), "info string Hash: 32 MB");
+    }
+
+    #[test]
+    fn moves_as_string_writes_null_moves_as_0000() {
+        let promotion = pawn_move(48, 56, Pieces::QUEEN);
+        let moves = [e2e4(), Move::new(0), promotion];
+        assert_eq!(Uci::moves_as_string(&moves), "e2e4 0000 a7a8q");
+        assert_eq!(Uci::moves_as_string(&[]), "");
+    }
+
+    #[test]
+    fn search_currmove_adds_the_current_line_if_collected() {
+        let c = SearchCurrentMove::new(e2e4(), 3, Vec::new());
+        assert_eq!(
+            Uci::search_currmove(&c),
+            "info currmove e2e4 currmovenumber 3"
+        );
+
+        let c = SearchCurrentMove::new(e2e4(), 3, vec![e2e4(), e7e5()]);
+        assert_eq!(
+            Uci::search_currmove(&c),
+            "info currmove e2e4 currmovenumber 3\ninfo currline 1 e2e4 e7e5"
+        );
+    }
 }
@@ -72,7 +72,8 @@ impl Castling {
 pub const EMPTY: u64 = 0;
 pub const MAX_GAME_MOVES: usize = 2048;
 pub const MAX_LEGAL_MOVES: u8 = 255;
-pub const MAX_PLY: i8 = 125;
+pub const MAX_PLY: i16 = 192; // Plies from the root, including extensions
+pub const MAX_DEPTH: i8 = i8::MAX; // Deepest iteration the search can start
 pub const MAX_MOVE_RULE: u8 = 100; // 50/75 move rule
 
 // Define errors
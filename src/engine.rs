@@ -22,8 +22,14 @@ with this program.  If not, see <http://www.gnu.org/licenses/>.
 ======================================================================= */
 
 mod about;
+mod analysis_cache;
+mod chess_clock;
 mod comm_reports;
+mod console_game;
 pub mod defs;
+mod eval_player;
+mod explain;
+mod game_stats;
 mod main_loop;
 mod search_reports;
 mod transposition;
@@ -31,24 +37,36 @@ mod utils;
 
 use crate::{
     board::Board,
-    comm::{uci::Uci, CommControl, CommType, IComm},
-    defs::EngineRunResult,
+    comm::{console::Console, uci::Uci, CommControl, CommType, IComm},
+    defs::{EngineRunResult, FEN_START_POSITION},
     engine::defs::{
-        EngineOption, EngineOptionDefaults, EngineOptionName, ErrFatal, Information, Settings,
-        UiElement,
+        EngineOption, EngineOptionDefaults, EngineOptionName, EngineState, ErrFatal, ErrNormal,
+        InfoReceiver, Information, Settings, UiElement,
+    },
+    misc::{
+        bench, cmdline::CmdLine, perft, pgn::PgnGame, print::BoardStyle, protocol_log::ProtocolLog,
+        stats_csv::StatsCsv,
     },
-    misc::{cmdline::CmdLine, perft},
     movegen::MoveGenerator,
-    search::{defs::SearchControl, Search},
+    search::{
+        defs::{NullMoveParams, OpeningTimeParams, PromotionFilter, RazoringParams, SearchControl},
+        Search,
+    },
+};
+use analysis_cache::AnalysisCache;
+use chess_clock::ChessClock;
+use explain::SearchLog;
+use game_stats::GameStats;
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex, RwLock},
 };
-use crossbeam_channel::Receiver;
-use std::sync::{Arc, Mutex};
-use transposition::{PerftData, SearchData, TT};
+use transposition::{SearchData, TT};
 
 #[cfg(feature = "extra")]
 use crate::{
     board::defs::Pieces,
-    extra::{testsuite, wizardry},
+    extra::{audit, fuzz, generate, selftest, tactics, testsuite, texel::Tuner, wizardry},
 };
 
 // This struct holds the chess engine and its functions, so they are not
@@ -56,48 +74,108 @@ use crate::{
 pub struct Engine {
     quit: bool,                             // Flag that will quit the main thread.
     settings: Settings,                     // Struct holding all the settings.
+    state: EngineState,                     // What the engine is doing.
+    game_stats: GameStats,                  // Records of this game's searches.
+    game: PgnGame,                          // Moves played since the position was set up.
+    analysis: AnalysisCache,                // Results of recent searches.
+    search_log: SearchLog,                  // Results of the last search.
+    stats_csv: StatsCsv,                    // Iteration statistics written to file.
+    protocol_log: ProtocolLog,              // Input and output written to file.
     options: Arc<Vec<EngineOption>>,        // Engine options exported to the GUI
     cmdline: CmdLine,                       // Command line interpreter.
     comm: Box<dyn IComm>,                   // Communications (active).
     board: Arc<Mutex<Board>>,               // This is the main engine board.
-    tt_perft: Arc<Mutex<TT<PerftData>>>,    // TT for running perft.
-    tt_search: Arc<Mutex<TT<SearchData>>>,  // TT for search information.
+    search_root: Board,                     // Position the last search started in.
+    perft_hash: usize,                      // Perft cache size in MB.
+    tt_search: Arc<RwLock<TT<SearchData>>>, // TT for search information.
     mg: Arc<MoveGenerator>,                 // Move Generator.
-    info_rx: Option<Receiver<Information>>, // Receiver for incoming information.
+    info_rx: Option<InfoReceiver>,          // Receiver for incoming information.
+    pending: VecDeque<Information>,         // Information put aside to handle later.
     search: Search,                         // Search object (active).
+    position_set: bool,                     // A position was set up to search.
+    play_best_move: bool,                   // Play the search's move on the board.
+    hint: bool,                             // Suggest the search's move to the user.
+    blindfold: bool,                        // Don't show the board to the user.
+    clock: Option<ChessClock>,              // Chess clock for the user's game.
+    auto_hash_pending: bool,                // Auto Hash has yet to choose a size.
+    nps: usize,                             // Speed measured in the last search.
+    hash_clamped: Option<usize>,            // Hash size requested, if it didn't fit.
     tmp_no_xboard: bool,                    // Temporary variable to disable xBoard
 }
 
 impl Engine {
     // Create e new engine.
     pub fn new() -> Self {
-        // Determine if the compiled engine is 32 or 64-bit
-        let is_64_bit = std::mem::size_of::<usize>() == 8;
-
         // Create the command-line object.
         let cmdline = CmdLine::new();
         let mut is_xboard = false;
 
         // Create the communication interface
+        let allow_custom = cmdline.has_no_strict();
+        let script = cmdline.script();
+        let pretty = cmdline.pretty();
+        let ascii = BoardStyle::select(pretty.as_deref(), BoardStyle::Ascii);
+        let unicode = BoardStyle::select(pretty.as_deref(), BoardStyle::Unicode);
         let comm: Box<dyn IComm> = match &cmdline.comm()[..] {
             CommType::XBOARD => {
                 is_xboard = true;
-                Box::new(Uci::new())
+                Box::new(Uci::new(allow_custom, script, ascii))
             }
-            CommType::UCI => Box::new(Uci::new()),
+            CommType::UCI => Box::new(Uci::new(allow_custom, script, ascii)),
+            CommType::CONSOLE => Box::new(Console::new(script, unicode)),
             _ => panic!("{}", ErrFatal::CREATE_COMM),
         };
 
+        Engine::create(cmdline, comm, is_xboard)
+    }
+
+    // Create an engine for a program that embeds it. It communicates
+    // through the given Comm module, and doesn't read the command line of
+    // the process: all settings start at their default values.
+    pub fn embedded(comm: Box<dyn IComm>) -> Self {
+        Engine::create(CmdLine::empty(), comm, false)
+    }
+
+    fn create(cmdline: CmdLine, comm: Box<dyn IComm>, is_xboard: bool) -> Self {
+        // Determine if the compiled engine is 32 or 64-bit
+        let is_64_bit = std::mem::size_of::<usize>() == 8;
+
         // Get engine settings from the command-line.
         let threads = cmdline.threads();
         let quiet = cmdline.has_quiet();
-        let tt_size = cmdline.hash();
         let tt_max = if is_64_bit {
             EngineOptionDefaults::HASH_MAX_64_BIT
         } else {
             EngineOptionDefaults::HASH_MAX_32_BIT
         };
 
+        // A hash table that doesn't fit into memory can't be allocated, so
+        // the size is reduced before the TT is created. The user is warned
+        // once the Comm module is running.
+        let hash_requested = cmdline.hash();
+        let tt_size = Engine::hash_fitting(hash_requested.min(tt_max));
+        let hash_clamped = (tt_size < hash_requested).then_some(hash_requested);
+
+        // A file given on the command-line is the default for the GUI.
+        let eval_file_default = cmdline
+            .eval_file()
+            .unwrap_or(EngineOptionDefaults::EVAL_FILE_DEFAULT.to_string());
+
+        // An Elo rating given on the command-line limits the strength.
+        let elo = cmdline.elo();
+        let limit_strength = elo.is_some();
+        let elo = elo.unwrap_or(EngineOptionDefaults::ELO_DEFAULT);
+
+        // A position given on the command-line was set up on purpose.
+        let position_set = cmdline.fen() != FEN_START_POSITION || cmdline.has_kiwipete();
+
+        // The warm-up search can be skipped from the command-line, because
+        // at startup the GUI hasn't had the chance to set the option yet.
+        let warm_up = EngineOptionDefaults::WARM_UP_DEFAULT && !cmdline.has_no_warm_up();
+
+        // On a computer with little RAM, start in low memory mode.
+        let low_memory = Settings::low_memory_detected();
+
         // List of options that should be announced to the GUI.
         let options = vec![
             EngineOption::new(
@@ -107,6 +185,20 @@ impl Engine {
                 Some(EngineOptionDefaults::HASH_MIN.to_string()),
                 Some(tt_max.to_string()),
             ),
+            EngineOption::new(
+                EngineOptionName::THREADS,
+                UiElement::Spin,
+                Some(EngineOptionDefaults::THREADS_DEFAULT.to_string()),
+                Some(EngineOptionDefaults::THREADS_MIN.to_string()),
+                Some(EngineOptionDefaults::THREADS_MAX.to_string()),
+            ),
+            EngineOption::new(
+                EngineOptionName::PAWN_HASH,
+                UiElement::Spin,
+                Some(EngineOptionDefaults::PAWN_HASH_DEFAULT.to_string()),
+                Some(EngineOptionDefaults::PAWN_HASH_MIN.to_string()),
+                Some(EngineOptionDefaults::PAWN_HASH_MAX.to_string()),
+            ),
             EngineOption::new(
                 EngineOptionName::CLEAR_HASH,
                 UiElement::Button,
@@ -114,36 +206,245 @@ impl Engine {
                 None,
                 None,
             ),
+            EngineOption::new(
+                EngineOptionName::CLEAR_HASH_ON_NEW_GAME,
+                UiElement::Check,
+                Some(EngineOptionDefaults::CLEAR_HASH_ON_NEW_GAME_DEFAULT.to_string()),
+                None,
+                None,
+            ),
+            EngineOption::new(
+                EngineOptionName::MULTI_PV,
+                UiElement::Spin,
+                Some(EngineOptionDefaults::MULTI_PV_DEFAULT.to_string()),
+                Some(EngineOptionDefaults::MULTI_PV_MIN.to_string()),
+                Some(EngineOptionDefaults::MULTI_PV_MAX.to_string()),
+            ),
+            EngineOption::new(
+                EngineOptionName::PONDER,
+                UiElement::Check,
+                Some(EngineOptionDefaults::PONDER_DEFAULT.to_string()),
+                None,
+                None,
+            ),
+            EngineOption::new(
+                EngineOptionName::PROMOTION_RESTRICTION,
+                UiElement::Combo(&EngineOptionDefaults::PROMOTION_RESTRICTION_VARS),
+                Some(EngineOptionDefaults::PROMOTION_RESTRICTION_DEFAULT.to_string()),
+                None,
+                None,
+            ),
+            EngineOption::new(
+                EngineOptionName::PROMOTION_RESTRICTION_SEARCH,
+                UiElement::Check,
+                Some(EngineOptionDefaults::PROMOTION_RESTRICTION_SEARCH_DEFAULT.to_string()),
+                None,
+                None,
+            ),
+            EngineOption::new(
+                EngineOptionName::ROOT_MOVE_NODES,
+                UiElement::Spin,
+                Some(EngineOptionDefaults::ROOT_MOVE_NODES_DEFAULT.to_string()),
+                Some(EngineOptionDefaults::ROOT_MOVE_NODES_MIN.to_string()),
+                Some(EngineOptionDefaults::ROOT_MOVE_NODES_MAX.to_string()),
+            ),
+            EngineOption::new(
+                EngineOptionName::MAX_DEPTH,
+                UiElement::Spin,
+                Some(EngineOptionDefaults::MAX_DEPTH_DEFAULT.to_string()),
+                Some(EngineOptionDefaults::MAX_DEPTH_MIN.to_string()),
+                Some(EngineOptionDefaults::MAX_DEPTH_MAX.to_string()),
+            ),
+            EngineOption::new(
+                EngineOptionName::ANALYSE_MODE,
+                UiElement::Check,
+                Some(EngineOptionDefaults::ANALYSE_MODE_DEFAULT.to_string()),
+                None,
+                None,
+            ),
+            EngineOption::new(
+                EngineOptionName::SCORES_FROM_WHITE,
+                UiElement::Check,
+                Some(EngineOptionDefaults::SCORES_FROM_WHITE_DEFAULT.to_string()),
+                None,
+                None,
+            ),
+            EngineOption::new(
+                EngineOptionName::SHOW_REFUTATIONS,
+                UiElement::Check,
+                Some(EngineOptionDefaults::SHOW_REFUTATIONS_DEFAULT.to_string()),
+                None,
+                None,
+            ),
+            EngineOption::new(
+                EngineOptionName::SHOW_CURR_LINE,
+                UiElement::Check,
+                Some(EngineOptionDefaults::SHOW_CURR_LINE_DEFAULT.to_string()),
+                None,
+                None,
+            ),
+            EngineOption::new(
+                EngineOptionName::QSEARCH_CHECKS,
+                UiElement::Check,
+                Some(EngineOptionDefaults::QSEARCH_CHECKS_DEFAULT.to_string()),
+                None,
+                None,
+            ),
+            EngineOption::new(
+                EngineOptionName::SHOW_SHARPNESS,
+                UiElement::Check,
+                Some(EngineOptionDefaults::SHOW_SHARPNESS_DEFAULT.to_string()),
+                None,
+                None,
+            ),
+            EngineOption::new(
+                EngineOptionName::LOW_MEMORY,
+                UiElement::Check,
+                Some(low_memory.to_string()),
+                None,
+                None,
+            ),
+            EngineOption::new(
+                EngineOptionName::AUTO_HASH,
+                UiElement::Check,
+                Some(EngineOptionDefaults::AUTO_HASH_DEFAULT.to_string()),
+                None,
+                None,
+            ),
+            EngineOption::new(
+                EngineOptionName::LIMIT_STRENGTH,
+                UiElement::Check,
+                Some(limit_strength.to_string()),
+                None,
+                None,
+            ),
+            EngineOption::new(
+                EngineOptionName::ELO,
+                UiElement::Spin,
+                Some(elo.to_string()),
+                Some(EngineOptionDefaults::ELO_MIN.to_string()),
+                Some(EngineOptionDefaults::ELO_MAX.to_string()),
+            ),
+            EngineOption::new(
+                EngineOptionName::EVAL_PLAYER,
+                UiElement::Check,
+                Some(EngineOptionDefaults::EVAL_PLAYER_DEFAULT.to_string()),
+                None,
+                None,
+            ),
+            EngineOption::new(
+                EngineOptionName::WARM_UP,
+                UiElement::Check,
+                Some(warm_up.to_string()),
+                None,
+                None,
+            ),
+            EngineOption::new(
+                EngineOptionName::EVAL_FILE,
+                UiElement::String,
+                Some(eval_file_default),
+                None,
+                None,
+            ),
+            EngineOption::new(
+                EngineOptionName::CLEAR_HASH_ON_EVAL_CHANGE,
+                UiElement::Check,
+                Some(EngineOptionDefaults::CLEAR_HASH_ON_EVAL_CHANGE_DEFAULT.to_string()),
+                None,
+                None,
+            ),
+            EngineOption::new(
+                EngineOptionName::STATS_FILE,
+                UiElement::String,
+                Some(EngineOptionDefaults::STATS_FILE_DEFAULT.to_string()),
+                None,
+                None,
+            ),
+            EngineOption::new(
+                EngineOptionName::DEBUG_LOG_FILE,
+                UiElement::String,
+                Some(EngineOptionDefaults::DEBUG_LOG_FILE_DEFAULT.to_string()),
+                None,
+                None,
+            ),
         ];
 
-        // Initialize correct TT.
-        let tt_perft: Arc<Mutex<TT<PerftData>>>;
-        let tt_search: Arc<Mutex<TT<SearchData>>>;
+        // Low memory mode may reduce some of these while searching.
+        let settings = Settings {
+            threads,
+            quiet,
+            tt_size,
+            pawn_hash: EngineOptionDefaults::PAWN_HASH_DEFAULT,
+            multi_pv: EngineOptionDefaults::MULTI_PV_DEFAULT,
+            ponder: EngineOptionDefaults::PONDER_DEFAULT,
+            null_move: NullMoveParams::new(),
+            razoring: RazoringParams::new(),
+            opening_time: OpeningTimeParams::new(),
+            promotions: PromotionFilter::new(),
+            root_move_nodes: EngineOptionDefaults::ROOT_MOVE_NODES_DEFAULT,
+            max_depth: EngineOptionDefaults::MAX_DEPTH_DEFAULT,
+            eval_file: String::new(),
+            stats_file: String::new(),
+            log_file: String::new(),
+            eval_player: EngineOptionDefaults::EVAL_PLAYER_DEFAULT,
+            warm_up,
+            analyse_mode: EngineOptionDefaults::ANALYSE_MODE_DEFAULT,
+            scores_from_white: EngineOptionDefaults::SCORES_FROM_WHITE_DEFAULT,
+            show_refutations: EngineOptionDefaults::SHOW_REFUTATIONS_DEFAULT,
+            show_curr_line: EngineOptionDefaults::SHOW_CURR_LINE_DEFAULT,
+            qsearch_checks: EngineOptionDefaults::QSEARCH_CHECKS_DEFAULT,
+            show_sharpness: EngineOptionDefaults::SHOW_SHARPNESS_DEFAULT,
+            limit_strength,
+            elo,
+            low_memory,
+            auto_hash: EngineOptionDefaults::AUTO_HASH_DEFAULT,
+            auto_hash_size: 0,
+            clear_hash_on_new_game: EngineOptionDefaults::CLEAR_HASH_ON_NEW_GAME_DEFAULT,
+            clear_hash_on_eval_change: EngineOptionDefaults::CLEAR_HASH_ON_EVAL_CHANGE_DEFAULT,
+        };
+
+        // The memory goes either to the perft caches or to the search TT.
+        let hash_in_use = settings.hash_in_use();
+        let perft_hash: usize;
+        let tt_search: Arc<RwLock<TT<SearchData>>>;
         if cmdline.perft() > 0 {
-            tt_perft = Arc::new(Mutex::new(TT::<PerftData>::new(tt_size)));
-            tt_search = Arc::new(Mutex::new(TT::<SearchData>::new(0)));
+            perft_hash = hash_in_use;
+            tt_search = Arc::new(RwLock::new(TT::<SearchData>::new(0)));
         } else {
-            tt_perft = Arc::new(Mutex::new(TT::<PerftData>::new(0)));
-            tt_search = Arc::new(Mutex::new(TT::<SearchData>::new(tt_size)));
+            perft_hash = 0;
+            tt_search = Arc::new(RwLock::new(TT::<SearchData>::new(hash_in_use)));
         };
 
         // Create the engine itself.
         Self {
             quit: false,
-            settings: Settings {
-                threads,
-                quiet,
-                tt_size,
-            },
+            settings,
+            state: EngineState::Waiting,
+            game_stats: GameStats::new(),
+            game: PgnGame::new(None),
+            analysis: AnalysisCache::new(),
+            search_log: SearchLog::new(),
+            stats_csv: StatsCsv::new(),
+            protocol_log: ProtocolLog::new(),
             options: Arc::new(options),
             cmdline,
             comm,
             board: Arc::new(Mutex::new(Board::new())),
+            search_root: Board::new(),
             mg: Arc::new(MoveGenerator::new()),
-            tt_perft,
+            perft_hash,
             tt_search,
             info_rx: None,
+            pending: VecDeque::new(),
             search: Search::new(),
+            position_set,
+            play_best_move: false,
+            hint: false,
+            blindfold: false,
+            clock: None,
+            auto_hash_pending: true,
+            nps: 0,
+            hash_clamped,
             tmp_no_xboard: is_xboard,
         }
     }
@@ -157,7 +458,24 @@ impl Engine {
         }
 
         self.print_ascii_logo();
+
+        // Load evaluation parameters if requested. If this fails, the
+        // engine keeps running with the built-in parameters.
+        let eval_file = self.cmdline.eval_file().unwrap_or_default();
+        let eval_file_result = self.load_eval_file(&eval_file);
+
+        // Start logging input and output if requested, before the first
+        // line is read. If this fails, the engine runs without the log.
+        let log_file = self.cmdline.log().unwrap_or_default();
+        let log_file_result = self.open_log_file(&log_file);
+
         self.print_about(&self.settings);
+        if let Err(e) = eval_file_result {
+            println!("{e}. {}", ErrNormal::EVAL_FILE);
+        }
+        if let Err(e) = log_file_result {
+            println!("{e}. {}", ErrNormal::LOG_FILE);
+        }
         println!();
 
         // Setup position and abort if this fails.
@@ -173,11 +491,22 @@ impl Engine {
                 self.board.clone(),
                 self.cmdline.perft(),
                 Arc::clone(&self.mg),
-                Arc::clone(&self.tt_perft),
-                self.settings.tt_size > 0,
+                self.perft_hash,
+                self.settings.threads_in_use(),
+                BoardStyle::select(self.cmdline.pretty().as_deref(), BoardStyle::Ascii),
             );
         }
 
+        // Run the benchmark if requested.
+        if let Some(depth) = self.cmdline.bench() {
+            action_requested = true;
+            let mg = Arc::clone(&self.mg);
+            let result = bench::run(depth, mg, &self.stats_csv, |line| println!("{line}"));
+            for line in result.summary() {
+                println!("{line}");
+            }
+        }
+
         // === Only available with "extra" features enabled. ===
         #[cfg(feature = "extra")]
         // Generate magic numbers if requested.
@@ -187,22 +516,67 @@ impl Engine {
             wizardry::find_magics(Pieces::BISHOP);
         };
 
+        #[cfg(feature = "extra")]
+        // Regenerate precomputed data from a seed if requested.
+        if let Some(artifact) = self.cmdline.generate() {
+            action_requested = true;
+            generate::run(&artifact, self.cmdline.seed());
+        };
+
         #[cfg(feature = "extra")]
         // Run large EPD test suite if requested. Because the -p (perft)
         // option is not used in this scenario, the engine initializes the
-        // search TT instead of the one for perft. The -e option is
-        // not available in a non-extra compilation, so it cannot be
-        // checked there. Just fix the issue by resizing both the perft and
-        // search TT's appropriately for running the EPD suite.
+        // search TT instead of the perft cache. The -e option is not
+        // available in a non-extra compilation, so it cannot be checked
+        // there. Just fix the issue by freeing the search TT, and giving
+        // its memory to the perft cache of the EPD suite.
         if self.cmdline.has_test() {
             action_requested = true;
-            self.tt_perft
-                .lock()
-                .expect(ErrFatal::LOCK)
-                .resize(self.settings.tt_size);
-            self.tt_search.lock().expect(ErrFatal::LOCK).resize(0);
-            testsuite::run(Arc::clone(&self.tt_perft), self.settings.tt_size > 0);
+            self.tt_search.write().expect(ErrFatal::LOCK).resize(0);
+            testsuite::run(self.settings.hash_in_use());
+        }
+
+        #[cfg(feature = "extra")]
+        // Run a tactical EPD suite (bm/am) if requested.
+        if let Some(file_name) = self.cmdline.tactics() {
+            action_requested = true;
+            tactics::run(
+                &file_name,
+                self.cmdline.move_time(),
+                self.settings.threads_in_use(),
+                Arc::clone(&self.board),
+                Arc::clone(&self.mg),
+                Arc::clone(&self.tt_search),
+            );
+        }
+
+        #[cfg(feature = "extra")]
+        // Check the castling and en-passant edge cases if requested.
+        if self.cmdline.has_audit() {
+            action_requested = true;
+            audit::run();
+        }
+
+        #[cfg(feature = "extra")]
+        // Fuzz the input parsers and make/unmake if requested.
+        if let Some(iterations) = self.cmdline.fuzz() {
+            action_requested = true;
+            fuzz::run(iterations, self.cmdline.seed());
+        };
+
+        #[cfg(feature = "extra")]
+        // Check evaluation symmetry, hashing and move generation if requested.
+        if self.cmdline.has_selftest() {
+            action_requested = true;
+            selftest::run();
         }
+
+        #[cfg(feature = "extra")]
+        // Tune the evaluation weights on the given dataset if requested.
+        if let Some(file_name) = self.cmdline.tune() {
+            action_requested = true;
+            Tuner::new().run(&file_name, self.cmdline.has_resume());
+        };
         // =====================================================
 
         // In the main loop, the engine manages its resources so it will be
@@ -218,8 +592,19 @@ impl Engine {
         Ok(())
     }
 
+    // Run an embedded engine. There is no terminal to print to, and no
+    // command line to run actions from, so it goes straight into the main
+    // loop.
+    pub fn run_embedded(&mut self) -> EngineRunResult {
+        self.setup_position()?;
+        self.main_loop();
+
+        Ok(())
+    }
+
     // This function quits Commm, Search, and then the engine thread itself.
     pub fn quit(&mut self) {
+        self.report_game_stats();
         self.search.send(SearchControl::Quit);
         self.comm.send(CommControl::Quit);
         self.quit = true;
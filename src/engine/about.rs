@@ -22,7 +22,7 @@ with this program.  If not, see <http://www.gnu.org/licenses/>.
 ======================================================================= */
 
 use super::Engine;
-use crate::{defs::About, engine::defs::Settings};
+use crate::{defs::About, engine::defs::Settings, misc::build_info};
 
 // This notice is displayed if the engine is a debug binary. (Debug
 // binaries are unoptimized and slower than release binaries.)
@@ -44,27 +44,50 @@ impl Engine {
 
     // Print information about the engine.
     pub fn print_about(&self, s: &Settings) {
+        for line in Engine::about(s) {
+            println!("{line}");
+        }
+
+        #[cfg(debug_assertions)]
+        println!("{NOTICE_DEBUG_MODE}");
+    }
+
+    // Information about the engine and the binary, to be printed at
+    // startup, or sent on request. This should be included in bug
+    // reports, as it shows exactly which binary was running.
+    pub fn about(s: &Settings) -> Vec<String> {
         let bits = std::mem::size_of::<usize>() * 8;
-        let hash = if s.tt_size == 0 {
+        let hash = if s.hash_in_use() == 0 {
             String::from("off")
         } else {
-            format!("{} MB", s.tt_size)
+            format!("{} MB", s.hash_in_use())
         };
-        let threads = if s.threads == 1 {
-            String::from("1")
+
+        let eval = if s.eval_file.is_empty() {
+            "built-in"
         } else {
-            format!("{} (unused, always 1)", s.threads)
+            &s.eval_file
         };
 
-        println!("{:<10} {} {}", "Engine:", About::ENGINE, About::VERSION);
-        println!("{:<10} {}", "Author:", About::AUTHOR);
-        println!("{:<10} {}", "EMail:", About::EMAIL);
-        println!("{:<10} {}", "Website:", About::WEBSITE);
-        println!("{:<10} {bits}-bit", "Type:");
-        println!("{:<10} {hash}", "Hash:");
-        println!("{:<10} {threads}", "Threads:");
+        let strength = if s.limit_strength {
+            format!("{} Elo", s.elo)
+        } else {
+            String::from("full")
+        };
 
-        #[cfg(debug_assertions)]
-        println!("{NOTICE_DEBUG_MODE}");
+        let mut lines = vec![
+            format!("{:<10} {} {}", "Engine:", About::ENGINE, About::VERSION),
+            format!("{:<10} {}", "Author:", About::AUTHOR),
+            format!("{:<10} {}", "EMail:", About::EMAIL),
+            format!("{:<10} {}", "Website:", About::WEBSITE),
+            format!("{:<10} {bits}-bit", "Type:"),
+            format!("{:<10} {hash}", "Hash:"),
+            format!("{:<10} {}", "Threads:", s.threads_in_use()),
+            format!("{:<10} {eval}", "Eval:"),
+            format!("{:<10} {strength}", "Strength:"),
+        ];
+        lines.append(&mut build_info::lines());
+
+        lines
     }
 }
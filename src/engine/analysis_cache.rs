@@ -0,0 +1,139 @@
+/* =======================================================================
+Rustic is a chess playing engine.
+Copyright (C) 2019-2024, Marcel Vanthoor
+https://rustic-chess.org/
+
+Rustic is written in the Rust programming language. It is an original
+work, not derived from any engine that came before it. However, it does
+use a lot of concepts which are well-known and are in use by most if not
+all classical alpha/beta-based chess engines.
+
+Rustic is free software: you can redistribute it and/or modify it under
+the terms of the GNU General Public License version 3 as published by
+the Free Software Foundation.
+
+Rustic is distributed in the hope that it will be useful, but WITHOUT
+ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License
+for more details.
+
+You should have received a copy of the GNU General Public License along
+with this program.  If not, see <http://www.gnu.org/licenses/>.
+======================================================================= */
+
+// analysis_cache.rs remembers the results of the last few searches. When
+// the user goes back to a position that was analyzed before (which happens
+// a lot when clicking through a game in a GUI), the lines that were found
+// the last time are sent immediately, before the new search starts. This
+// way the GUI has something useful to show while the search catches up.
+
+use crate::{
+    board::defs::ZobristKey,
+    search::defs::{ScoreBound, SearchSummary},
+};
+use std::collections::VecDeque;
+
+// Number of analyzed positions to remember.
+const CAPACITY: usize = 16;
+
+// The lines found for one position, in MultiPV order.
+struct CachedAnalysis {
+    key: ZobristKey,
+    lines: Vec<SearchSummary>,
+}
+
+impl CachedAnalysis {
+    fn depth(&self) -> i8 {
+        self.lines.first().map_or(0, |s| s.depth)
+    }
+}
+
+pub struct AnalysisCache {
+    cache: VecDeque<CachedAnalysis>, // Most recent analysis at the front
+    current: Option<CachedAnalysis>, // Analysis of the running search
+}
+
+impl AnalysisCache {
+    pub fn new() -> Self {
+        Self {
+            cache: VecDeque::with_capacity(CAPACITY),
+            current: None,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.cache.clear();
+        self.current = None;
+    }
+
+    // A new search starts in the position with the given key. Returns the
+    // lines that were found for this position before, up to the requested
+    // number of lines. The time and node counts are cleared, because they
+    // don't belong to the new search.
+    pub fn begin(&mut self, key: ZobristKey, multi_pv: usize) -> Vec<SearchSummary> {
+        self.current = Some(CachedAnalysis {
+            key,
+            lines: Vec::new(),
+        });
+
+        match self.cache.iter().find(|c| c.key == key) {
+            Some(cached) => cached
+                .lines
+                .iter()
+                .filter(|s| s.multi_pv <= multi_pv)
+                .map(|s| SearchSummary {
+                    time: 0,
+                    nodes: 0,
+                    nps: 0,
+                    ..s.clone()
+                })
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    // A new search starts that is not going to be cached.
+    pub fn skip(&mut self) {
+        self.current = None;
+    }
+
+    // Keep the latest summary for each line. Bounded scores are not kept,
+    // as they are not the actual result of an iteration.
+    pub fn record(&mut self, summary: &SearchSummary) {
+        if summary.bound != ScoreBound::Exact || summary.multi_pv == 0 {
+            return;
+        }
+
+        if let Some(current) = self.current.as_mut() {
+            let index = summary.multi_pv - 1;
+            if index < current.lines.len() {
+                current.lines[index] = summary.clone();
+            } else if index == current.lines.len() {
+                current.lines.push(summary.clone());
+            }
+        }
+    }
+
+    // The search is done. Put its analysis in front of the cache, unless
+    // a deeper analysis of this position is already there. If the cache
+    // is full, the oldest analysis is dropped.
+    pub fn finish(&mut self) {
+        let current = match self.current.take() {
+            Some(c) if !c.lines.is_empty() => c,
+            _ => return,
+        };
+
+        let index = self.cache.iter().position(|c| c.key == current.key);
+        if let Some(previous) = index.and_then(|i| self.cache.remove(i)) {
+            if previous.depth() > current.depth() {
+                self.cache.push_front(previous);
+                return;
+            }
+        }
+
+        if self.cache.len() == CAPACITY {
+            self.cache.pop_back();
+        }
+        self.cache.push_front(current);
+    }
+}
@@ -0,0 +1,192 @@
+/* =======================================================================
+Rustic is a chess playing engine.
+Copyright (C) 2019-2024, Marcel Vanthoor
+https://rustic-chess.org/
+
+Rustic is written in the Rust programming language. It is an original
+work, not derived from any engine that came before it. However, it does
+use a lot of concepts which are well-known and are in use by most if not
+all classical alpha/beta-based chess engines.
+
+Rustic is free software: you can redistribute it and/or modify it under
+the terms of the GNU General Public License version 3 as published by
+the Free Software Foundation.
+
+Rustic is distributed in the hope that it will be useful, but WITHOUT
+ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License
+for more details.
+
+You should have received a copy of the GNU General Public License along
+with this program.  If not, see <http://www.gnu.org/licenses/>.
+======================================================================= */
+
+// chess_clock.rs holds the clock for a game played against the engine in
+// a terminal window. Each side has its own time, and one clock runs at a
+// time: the clock of the side to move. When a side moves, its clock is stopped and the increment
+// is added. A side that runs out of time before it moves loses the game.
+
+use crate::{
+    defs::{Side, Sides},
+    search::defs::GameTime,
+};
+use std::time::Instant;
+
+pub struct ChessClock {
+    base: u128,                       // Time for the game, in msecs
+    increment: u128,                  // Added after each move, in msecs
+    remaining: [u128; Sides::BOTH],   // Time left, when the clock started
+    running: Option<(Side, Instant)>, // Side whose clock is running
+    flagged: Option<Side>,            // Side that ran out of time
+}
+
+impl ChessClock {
+    pub fn new(base: u128, increment: u128) -> Self {
+        Self {
+            base,
+            increment,
+            remaining: [base; Sides::BOTH],
+            running: None,
+            flagged: None,
+        }
+    }
+
+    // Set both clocks back to the start of the game.
+    pub fn reset(&mut self) {
+        *self = ChessClock::new(self.base, self.increment);
+    }
+
+    // Start the clock of the given side, and stop the other one.
+    pub fn start(&mut self, side: Side) {
+        self.stop();
+        if self.flagged.is_none() {
+            self.running = Some((side, Instant::now()));
+        }
+    }
+
+    // Stop the running clock. Returns false if its side ran out of time;
+    // from then on, that side has lost and no clock runs anymore.
+    pub fn stop(&mut self) -> bool {
+        if let Some((side, _)) = self.running {
+            let left = self.time_left(side);
+            self.remaining[side] = left;
+            self.running = None;
+
+            if left == 0 {
+                self.flagged = Some(side);
+            }
+        }
+
+        self.flagged.is_none()
+    }
+
+    // The side moved: stop its clock, add the increment, and start the
+    // clock of the opponent. Returns false if the side was out of time.
+    pub fn punch(&mut self, side: Side) -> bool {
+        if !self.stop() {
+            return false;
+        }
+
+        self.remaining[side] += self.increment;
+        self.start(side ^ 1);
+        true
+    }
+
+    // The side that ran out of time, if any. A running clock is checked
+    // as well, so a flag fall is seen before the side moves.
+    pub fn flagged(&self) -> Option<Side> {
+        self.flagged.or_else(|| {
+            self.running
+                .filter(|(side, _)| self.time_left(*side) == 0)
+                .map(|(side, _)| side)
+        })
+    }
+
+    // The time a side has left, including the time running now.
+    pub fn time_left(&self, side: Side) -> u128 {
+        match self.running {
+            Some((s, started)) if s == side => {
+                self.remaining[side].saturating_sub(started.elapsed().as_millis())
+            }
+            _ => self.remaining[side],
+        }
+    }
+
+    // The clock as the search sees it.
+    pub fn game_time(&self) -> GameTime {
+        GameTime::new(
+            self.time_left(Sides::WHITE),
+            self.time_left(Sides::BLACK),
+            self.increment,
+            self.increment,
+            None,
+        )
+    }
+
+    // Both sides' time as "White 4:57.2  Black 5:00.0", with the side
+    // whose clock is running marked.
+    pub fn display(&self) -> String {
+        let running = self.running.map(|(side, _)| side);
+        let show = |side: Side, name: &str| {
+            let mark = if running == Some(side) { "*" } else { " " };
+            format!("{mark}{name} {}", ChessClock::format(self.time_left(side)))
+        };
+
+        format!(
+            "{}  {}",
+            show(Sides::WHITE, "White"),
+            show(Sides::BLACK, "Black")
+        )
+    }
+
+    // Minutes, seconds and tenths, such as "4:57.2". Hours are added for
+    // long games.
+    fn format(msecs: u128) -> String {
+        let tenths = msecs / 100;
+        let (hours, minutes) = (tenths / 36_000, tenths / 600 % 60);
+        let seconds = tenths / 10 % 60;
+
+        if hours > 0 {
+            format!("{hours}:{minutes:02}:{seconds:02}.{}", tenths % 10)
+        } else {
+            format!("{minutes}:{seconds:02}.{}", tenths % 10)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_shows_minutes_seconds_and_tenths() {
+        assert_eq!(ChessClock::format(0), "0:00.0");
+        assert_eq!(ChessClock::format(297_250), "4:57.2");
+        assert_eq!(ChessClock::format(3_723_400), "1:02:03.4");
+    }
+
+    #[test]
+    fn punch_adds_increment_and_switches_sides() {
+        let mut clock = ChessClock::new(60_000, 2000);
+        clock.start(Sides::WHITE);
+        assert!(clock.punch(Sides::WHITE));
+
+        assert!(clock.time_left(Sides::WHITE) > 60_000);
+        assert_eq!(clock.running.map(|(side, _)| side), Some(Sides::BLACK));
+        assert_eq!(clock.flagged(), None);
+    }
+
+    #[test]
+    fn side_without_time_loses() {
+        let mut clock = ChessClock::new(1, 0);
+        clock.start(Sides::BLACK);
+        std::thread::sleep(std::time::Duration::from_millis(5));
+
+        assert_eq!(clock.flagged(), Some(Sides::BLACK));
+        assert!(!clock.punch(Sides::BLACK));
+        assert_eq!(clock.time_left(Sides::WHITE), 1);
+
+        clock.start(Sides::WHITE);
+        assert!(clock.running.is_none());
+    }
+}
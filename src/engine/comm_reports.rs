@@ -26,10 +26,12 @@ use super::{
     Engine,
 };
 use crate::{
-    comm::{uci::UciReport, CommControl, CommReport},
-    defs::FEN_START_POSITION,
-    engine::defs::EngineOptionName,
+    board::defs::ReplayError,
+    comm::{console::ConsoleReport, uci::UciReport, CommControl, CommReport},
+    defs::{Sides, FEN_START_POSITION},
+    engine::defs::{EngineOptionDefaults, EngineOptionName, EngineState},
     evaluation::evaluate_position,
+    misc::{eco, pgn::PgnGame},
     search::defs::{SearchControl, SearchMode, SearchParams, OVERHEAD},
 };
 
@@ -40,25 +42,106 @@ impl Engine {
         // Split out the comm reports according to their source.
         match comm_report {
             CommReport::Uci(u) => self.comm_reports_uci(u),
+            CommReport::Console(c) => self.comm_reports_console(c),
         }
     }
 
+    // Handles "Console" Comm reports sent by the Console module. The
+    // board can't change while the engine is searching, so then moves are
+    // refused, as are new searches.
+    fn comm_reports_console(&mut self, c: &ConsoleReport) {
+        let mut sp = self.search_params();
+        let searching = self.state != EngineState::Waiting;
+
+        match c {
+            ConsoleReport::New => {
+                self.comm_reports_uci(&UciReport::UciNewGame);
+                self.game = PgnGame::new(None);
+                self.console_board();
+                if let Some(clock) = &mut self.clock {
+                    clock.reset();
+                    clock.start(Sides::WHITE);
+                }
+            }
+
+            ConsoleReport::Move(_)
+            | ConsoleReport::Undo
+            | ConsoleReport::Go(_)
+            | ConsoleReport::Analyze
+                if searching =>
+            {
+                let msg = String::from(ErrNormal::NOT_WAITING);
+                self.comm.send(CommControl::InfoString(msg));
+            }
+
+            ConsoleReport::Move(m) => {
+                self.console_move(m);
+            }
+            ConsoleReport::Undo => self.console_undo(),
+
+            // A side that lost on time can't move anymore.
+            ConsoleReport::Go(_) if self.console_flag_fall() => (),
+
+            // The user can see the position on the board, so it doesn't
+            // need to be set up by "position" first.
+            ConsoleReport::Go(msecs) => self.console_reply(sp, *msecs),
+
+            ConsoleReport::Analyze => {
+                sp.search_mode = SearchMode::Infinite;
+                self.position_set = true;
+                self.state = EngineState::Searching;
+                self.start_search(sp);
+            }
+        }
+    }
+
+    // The search parameters that follow from the engine's settings.
+    fn search_params(&self) -> SearchParams {
+        let mut sp = SearchParams::new();
+        sp.quiet = self.settings.quiet;
+        sp.multi_pv = self.settings.multi_pv;
+        sp.threads = self.settings.threads_in_use();
+        sp.pawn_hash = self.settings.pawn_hash_in_use();
+        sp.null_move = self.settings.null_move;
+        sp.razoring = self.settings.razoring;
+        sp.opening_time = self.settings.opening_time;
+        sp.moves_played = self.moves_since_start();
+        sp.promotions = self.settings.promotions;
+        sp.root_move_nodes = self.settings.root_move_nodes;
+        sp.show_refutations = self.settings.show_refutations;
+        sp.show_curr_line = self.settings.show_curr_line;
+        sp.qsearch_checks = self.settings.qsearch_checks;
+        sp.show_sharpness = self.settings.show_sharpness;
+
+        sp
+    }
+
     // Handles "Uci" Comm reports sent by the UCI-module.
     fn comm_reports_uci(&mut self, u: &UciReport) {
         // Setup default variables.
-        let mut sp = SearchParams::new();
-        sp.quiet = self.settings.quiet;
+        let mut sp = self.search_params();
 
         match u {
             UciReport::Uci => self.comm.send(CommControl::Identify),
 
+            // A search that is still running belongs to the old game. It
+            // is abandoned; its best move is not sent. Keeping the TT is
+            // useful when playing rematches of the same opening.
             UciReport::UciNewGame => {
+                self.abandon_search();
+                self.report_game_stats();
                 self.board
                     .lock()
                     .expect(ErrFatal::LOCK)
                     .fen_read(Some(FEN_START_POSITION))
                     .expect(ErrFatal::NEW_GAME);
-                self.tt_search.lock().expect(ErrFatal::LOCK).clear();
+                if self.settings.clear_hash_on_new_game {
+                    self.tt_search.write().expect(ErrFatal::LOCK).clear();
+                }
+                self.search.send(SearchControl::NewGame);
+                self.analysis.clear();
+                self.position_set = false;
+                self.auto_hash_pending = true;
             }
 
             UciReport::IsReady => self.comm.send(CommControl::Ready),
@@ -67,7 +150,27 @@ impl Engine {
                 match option {
                     EngineOptionName::Hash(value) => {
                         if let Ok(v) = value.parse::<usize>() {
-                            self.tt_search.lock().expect(ErrFatal::LOCK).resize(v);
+                            // Don't try to allocate more memory than there is.
+                            if let Some(w) = Engine::hash_warning(v) {
+                                let msg = format!("Warning: {w} {}", ErrNormal::NOT_CHANGED);
+                                self.comm.send(CommControl::InfoString(msg));
+                            } else {
+                                self.settings.tt_size = v;
+                                let mb = self.settings.hash_in_use();
+                                self.tt_search.write().expect(ErrFatal::LOCK).resize(mb);
+                                self.warm_up();
+                            }
+                        } else {
+                            let msg = String::from(ErrNormal::NOT_INT);
+                            self.comm.send(CommControl::InfoString(msg));
+                        }
+                    }
+
+                    EngineOptionName::PawnHash(value) => {
+                        if let Ok(v) = value.parse::<usize>() {
+                            let min = EngineOptionDefaults::PAWN_HASH_MIN;
+                            let max = EngineOptionDefaults::PAWN_HASH_MAX;
+                            self.settings.pawn_hash = v.clamp(min, max);
                         } else {
                             let msg = String::from(ErrNormal::NOT_INT);
                             self.comm.send(CommControl::InfoString(msg));
@@ -75,74 +178,433 @@ impl Engine {
                     }
 
                     EngineOptionName::ClearHash => {
-                        self.tt_search.lock().expect(ErrFatal::LOCK).clear()
+                        self.tt_search.write().expect(ErrFatal::LOCK).clear()
+                    }
+
+                    EngineOptionName::ClearHashOnNewGame(value) => {
+                        self.settings.clear_hash_on_new_game = value == "true"
+                    }
+
+                    EngineOptionName::MultiPv(value) => {
+                        if let Ok(v) = value.parse::<usize>() {
+                            let min = EngineOptionDefaults::MULTI_PV_MIN;
+                            let max = EngineOptionDefaults::MULTI_PV_MAX;
+                            self.settings.multi_pv = v.clamp(min, max);
+                        } else {
+                            let msg = String::from(ErrNormal::NOT_INT);
+                            self.comm.send(CommControl::InfoString(msg));
+                        }
+                    }
+
+                    EngineOptionName::Threads(value) => {
+                        if let Ok(v) = value.parse::<usize>() {
+                            let min = EngineOptionDefaults::THREADS_MIN;
+                            let max = EngineOptionDefaults::THREADS_MAX;
+                            self.settings.threads = v.clamp(min, max);
+                            self.check_settings();
+                        } else {
+                            let msg = String::from(ErrNormal::NOT_INT);
+                            self.comm.send(CommControl::InfoString(msg));
+                        }
+                    }
+
+                    EngineOptionName::PromotionRestriction(value) => {
+                        self.settings.promotions.set(value)
+                    }
+
+                    EngineOptionName::PromotionRestrictionSearch(value) => {
+                        self.settings.promotions.in_search = value == "true"
+                    }
+
+                    EngineOptionName::MaxDepth(value) => {
+                        if let Ok(v) = value.parse::<i16>() {
+                            let min = EngineOptionDefaults::MAX_DEPTH_MIN as i16;
+                            let max = EngineOptionDefaults::MAX_DEPTH_MAX as i16;
+                            self.settings.max_depth = v.clamp(min, max) as i8;
+                        } else {
+                            let msg = String::from(ErrNormal::NOT_INT);
+                            self.comm.send(CommControl::InfoString(msg));
+                        }
+                    }
+
+                    EngineOptionName::RootMoveNodes(value) => {
+                        if let Ok(v) = value.parse::<usize>() {
+                            let min = EngineOptionDefaults::ROOT_MOVE_NODES_MIN;
+                            let max = EngineOptionDefaults::ROOT_MOVE_NODES_MAX;
+                            self.settings.root_move_nodes = v.clamp(min, max);
+                        } else {
+                            let msg = String::from(ErrNormal::NOT_INT);
+                            self.comm.send(CommControl::InfoString(msg));
+                        }
+                    }
+
+                    // The parameters can be swapped between searches, to
+                    // compare them on the same position. The change in the
+                    // evaluation of that position is reported.
+                    EngineOptionName::EvalFile(value) => {
+                        if self.state != EngineState::Waiting {
+                            let msg = String::from(ErrNormal::NOT_WAITING);
+                            self.comm.send(CommControl::InfoString(msg));
+                            return; // The option didn't change; no acknowledgement.
+                        }
+
+                        let path = match &value[..] {
+                            EngineOptionDefaults::EVAL_FILE_DEFAULT => "",
+                            v => v,
+                        };
+                        let before = evaluate_position(&self.board.lock().expect(ErrFatal::LOCK));
+                        let msg = match self.load_eval_file(path) {
+                            Ok(()) if path.is_empty() => String::from(ErrNormal::EVAL_FILE),
+                            Ok(()) => format!("Evaluation parameters loaded from {path}"),
+                            Err(e) => format!("{e}. {}", ErrNormal::EVAL_FILE),
+                        };
+                        let after = evaluate_position(&self.board.lock().expect(ErrFatal::LOCK));
+                        self.comm.send(CommControl::InfoString(msg));
+
+                        let msg = format!("Evaluation: {before} -> {after} centipawns");
+                        self.comm.send(CommControl::InfoString(msg));
+                    }
+
+                    EngineOptionName::ClearHashOnEvalChange(value) => {
+                        self.settings.clear_hash_on_eval_change = value == "true"
+                    }
+
+                    EngineOptionName::StatsFile(value) => {
+                        let path = match &value[..] {
+                            EngineOptionDefaults::STATS_FILE_DEFAULT => "",
+                            v => v,
+                        };
+                        let result = self.stats_csv.open(path);
+                        self.settings.stats_file = match result {
+                            Ok(()) => path.to_string(),
+                            Err(_) => String::new(),
+                        };
+                        let msg = match result {
+                            Ok(()) if path.is_empty() => String::from(ErrNormal::STATS_FILE),
+                            Ok(()) => format!("Writing search statistics to {path}"),
+                            Err(e) => format!("{e}. {}", ErrNormal::STATS_FILE),
+                        };
+                        self.comm.send(CommControl::InfoString(msg));
+                    }
+
+                    EngineOptionName::DebugLogFile(value) => {
+                        let path = match &value[..] {
+                            EngineOptionDefaults::DEBUG_LOG_FILE_DEFAULT => "",
+                            v => v,
+                        };
+                        let msg = match self.open_log_file(path) {
+                            Ok(()) if path.is_empty() => String::from(ErrNormal::LOG_FILE),
+                            Ok(()) => format!("Logging input and output to {path}"),
+                            Err(e) => format!("{e}. {}", ErrNormal::LOG_FILE),
+                        };
+                        self.comm.send(CommControl::InfoString(msg));
+                    }
+
+                    EngineOptionName::NullMove(name, value) => {
+                        if let Ok(v) = value.parse::<i16>() {
+                            self.settings.null_move.set(name, v);
+                        } else {
+                            let msg = String::from(ErrNormal::NOT_INT);
+                            self.comm.send(CommControl::InfoString(msg));
+                        }
                     }
 
+                    EngineOptionName::Razoring(name, value) => {
+                        if let Ok(v) = value.parse::<i16>() {
+                            self.settings.razoring.set(name, v);
+                        } else {
+                            let msg = String::from(ErrNormal::NOT_INT);
+                            self.comm.send(CommControl::InfoString(msg));
+                        }
+                    }
+
+                    EngineOptionName::OpeningTime(name, value) => {
+                        if let Ok(v) = value.parse::<i16>() {
+                            self.settings.opening_time.set(name, v);
+                        } else {
+                            let msg = String::from(ErrNormal::NOT_INT);
+                            self.comm.send(CommControl::InfoString(msg));
+                        }
+                    }
+
+                    EngineOptionName::Ponder(value) => self.settings.ponder = value == "true",
+                    // Scores are only reported from White's point of view
+                    // in analysis; match play stays side to move.
+                    EngineOptionName::AnalyseMode(value) => {
+                        self.settings.analyse_mode = value == "true";
+                        self.update_score_perspective();
+                    }
+
+                    EngineOptionName::ScoresFromWhite(value) => {
+                        self.settings.scores_from_white = value == "true";
+                        self.update_score_perspective();
+                    }
+
+                    EngineOptionName::ShowRefutations(value) => {
+                        self.settings.show_refutations = value == "true"
+                    }
+
+                    EngineOptionName::ShowCurrLine(value) => {
+                        self.settings.show_curr_line = value == "true"
+                    }
+
+                    EngineOptionName::QSearchChecks(value) => {
+                        self.settings.qsearch_checks = value == "true"
+                    }
+
+                    EngineOptionName::ShowSharpness(value) => {
+                        self.settings.show_sharpness = value == "true"
+                    }
+
+                    EngineOptionName::LowMemory(value) => {
+                        let before = self.settings.hash_in_use();
+                        self.settings.low_memory = value == "true";
+                        let after = self.settings.hash_in_use();
+
+                        // Only reallocate if the mode changes the TT size.
+                        if after != before {
+                            self.tt_search.write().expect(ErrFatal::LOCK).resize(after);
+                            self.warm_up();
+                        }
+                    }
+
+                    // A size is chosen again at the next timed search.
+                    EngineOptionName::AutoHash(value) => {
+                        let before = self.settings.hash_in_use();
+                        self.settings.auto_hash = value == "true";
+                        self.settings.auto_hash_size = 0;
+                        self.auto_hash_pending = self.settings.auto_hash;
+                        let after = self.settings.hash_in_use();
+
+                        if after != before {
+                            self.tt_search.write().expect(ErrFatal::LOCK).resize(after);
+                            self.warm_up();
+                        }
+                    }
+
+                    EngineOptionName::LimitStrength(value) => {
+                        self.settings.limit_strength = value == "true"
+                    }
+
+                    EngineOptionName::Elo(value) => {
+                        if let Ok(v) = value.parse::<u16>() {
+                            let min = EngineOptionDefaults::ELO_MIN;
+                            let max = EngineOptionDefaults::ELO_MAX;
+                            self.settings.elo = v.clamp(min, max);
+                        } else {
+                            let msg = String::from(ErrNormal::NOT_INT);
+                            self.comm.send(CommControl::InfoString(msg));
+                        }
+                    }
+
+                    EngineOptionName::EvalPlayer(value) => {
+                        self.settings.eval_player = value == "true"
+                    }
+
+                    EngineOptionName::WarmUp(value) => self.settings.warm_up = value == "true",
+
                     EngineOptionName::Nothing => (),
                 };
+
+                self.acknowledge_option(option);
             }
 
             UciReport::Position(fen, moves) => {
-                let fen_result = self.board.lock().expect(ErrFatal::LOCK).fen_read(Some(fen));
+                let moves: Vec<&str> = moves.iter().map(|m| &m[..]).collect();
+                let result = self.board.lock().expect(ErrFatal::LOCK).replay_moves(
+                    Some(fen),
+                    &moves,
+                    &self.mg,
+                );
 
-                if fen_result.is_ok() {
-                    for m in moves.iter() {
-                        let ok = self.execute_move(m.clone());
-                        if !ok {
-                            let msg = format!("{}: {}", m, ErrNormal::NOT_LEGAL);
-                            self.comm.send(CommControl::InfoString(msg));
-                            break;
-                        }
-                    }
+                if result != Err(ReplayError::Fen) {
+                    let board = self.board.lock().expect(ErrFatal::LOCK);
+                    self.game = PgnGame::from_history(Some(fen), &board);
+                    self.position_set = true;
                 }
 
-                if fen_result.is_err() {
-                    let msg = ErrNormal::FEN_FAILED.to_string();
+                let msg = match result {
+                    Ok(()) => None,
+                    Err(ReplayError::Fen) => Some(ErrNormal::FEN_FAILED.to_string()),
+                    Err(ReplayError::Move(m)) => Some(format!("{}: {}", m, ErrNormal::NOT_LEGAL)),
+                };
+
+                if let Some(msg) = msg {
                     self.comm.send(CommControl::InfoString(msg));
                 }
             }
 
-            UciReport::GoInfinite => {
+            UciReport::GoInfinite(moves) => {
+                sp.search_moves = self.search_moves(moves);
                 sp.search_mode = SearchMode::Infinite;
-                self.search.send(SearchControl::Start(sp));
+                self.state = EngineState::Searching;
+                self.start_search(sp);
             }
 
-            UciReport::GoDepth(depth) => {
+            UciReport::GoDepth(depth, moves) => {
+                sp.search_moves = self.search_moves(moves);
                 sp.depth = *depth;
                 sp.search_mode = SearchMode::Depth;
-                self.search.send(SearchControl::Start(sp));
+                self.state = EngineState::Searching;
+                self.start_search(sp);
             }
 
-            UciReport::GoMoveTime(msecs) => {
+            UciReport::GoMoveTime(msecs, moves) => {
+                sp.search_moves = self.search_moves(moves);
                 sp.move_time = *msecs - (OVERHEAD as u128);
                 sp.search_mode = SearchMode::MoveTime;
-                self.search.send(SearchControl::Start(sp));
+                self.state = EngineState::Searching;
+                self.start_search(sp);
             }
 
-            UciReport::GoNodes(nodes) => {
+            UciReport::GoNodes(nodes, moves) => {
+                sp.search_moves = self.search_moves(moves);
                 sp.nodes = *nodes;
                 sp.search_mode = SearchMode::Nodes;
-                self.search.send(SearchControl::Start(sp));
+                self.state = EngineState::Searching;
+                self.start_search(sp);
             }
 
-            UciReport::GoGameTime(gt) => {
+            UciReport::GoGameTime(gt, moves) => {
+                self.auto_hash(gt);
+                sp.search_moves = self.search_moves(moves);
                 sp.game_time = *gt;
                 sp.search_mode = SearchMode::GameTime;
-                self.search.send(SearchControl::Start(sp));
+                self.state = EngineState::Searching;
+                self.start_search(sp);
             }
 
-            UciReport::Stop => self.search.send(SearchControl::Stop),
+            UciReport::GoMate(mate, moves) => {
+                sp.search_moves = self.search_moves(moves);
+                sp.search_mode = SearchMode::Mate(*mate);
+                self.state = EngineState::Searching;
+                self.start_search(sp);
+            }
+
+            UciReport::GoPonder(gt, moves) => {
+                self.auto_hash(gt);
+                sp.search_moves = self.search_moves(moves);
+                sp.game_time = *gt;
+                sp.search_mode = SearchMode::GameTime;
+                sp.ponder = true;
+                self.state = EngineState::Pondering;
+                self.start_search(sp);
+            }
+
+            // The opponent played the move we were pondering on. The
+            // search continues, now on our own time.
+            UciReport::PonderHit => {
+                if self.state == EngineState::Pondering {
+                    self.state = EngineState::Searching;
+                    if self.settings.eval_player {
+                        self.eval_player_move();
+                    } else {
+                        self.search.send(SearchControl::PonderHit);
+                    }
+                }
+            }
+
+            // Suspend the search and stop its clock, without losing what
+            // it has found so far. Resume continues where it left off.
+            UciReport::Pause => {
+                let pondering = match self.state {
+                    EngineState::Searching => Some(false),
+                    EngineState::Pondering => Some(true),
+                    _ => None,
+                };
+
+                if let Some(p) = pondering {
+                    self.state = EngineState::Paused { pondering: p };
+                    self.search.send(SearchControl::Pause);
+                }
+            }
+
+            UciReport::Resume => {
+                if let EngineState::Paused { pondering } = self.state {
+                    self.state = if pondering {
+                        EngineState::Pondering
+                    } else {
+                        EngineState::Searching
+                    };
+                    self.search.send(SearchControl::Resume);
+                }
+            }
+
+            // The eval player doesn't search while pondering; it picks
+            // its move when the ponder search would have been stopped.
+            UciReport::Stop => {
+                let pondering = matches!(
+                    self.state,
+                    EngineState::Pondering | EngineState::Paused { pondering: true }
+                );
+                if self.settings.eval_player && pondering {
+                    self.eval_player_move();
+                } else {
+                    self.search.send(SearchControl::Stop);
+                }
+            }
             UciReport::Quit => self.quit(),
 
             // Custom commands
-            UciReport::Board => self.comm.send(CommControl::PrintBoard),
+            UciReport::Board => self.print_board(),
             UciReport::History => self.comm.send(CommControl::PrintHistory),
             UciReport::Eval => {
                 let e = evaluate_position(&self.board.lock().expect(ErrFatal::LOCK));
                 let msg = format!("Evaluation: {e} centipawns");
                 self.comm.send(CommControl::InfoString(msg));
             }
-            UciReport::Help => self.comm.send(CommControl::PrintHelp),
+            UciReport::Heatmap => self.comm.send(CommControl::PrintHeatmap),
+            UciReport::Eco => {
+                let board = self.board.lock().expect(ErrFatal::LOCK);
+                let msg = match eco::classify(&board, &self.mg) {
+                    Some(opening) => format!("ECO {opening}"),
+                    None => String::from("ECO: opening not found"),
+                };
+                std::mem::drop(board);
+                self.comm.send(CommControl::InfoString(msg));
+            }
+            UciReport::Pgn(path) => {
+                if path.is_empty() {
+                    self.print_pgn();
+                } else {
+                    let msg = match self.load_pgn(path) {
+                        Ok(()) => format!("Game loaded from {path}"),
+                        Err(e) => format!("PGN: {e}"),
+                    };
+                    self.comm.send(CommControl::InfoString(msg));
+                }
+            }
+            UciReport::Perft(depth) => self.perft_divide(*depth),
+            UciReport::Bench(depth) => self.bench(*depth),
+            UciReport::Explain => self.explain(),
+            UciReport::ShowBitboard(expr) => self.show_bitboard(expr),
+            UciReport::Compare(fens) => self.compare(fens),
+            UciReport::Options => self.print_options(),
+            UciReport::CommQuery => self.comm.send(CommControl::PrintCommQuery),
+            UciReport::About => {
+                for line in Engine::about(&self.settings) {
+                    self.comm.send(CommControl::InfoString(line));
+                }
+            }
+
+            // A game against the engine. Its moves are played on the
+            // board, which can't change while the engine is searching.
+            UciReport::Play(_) | UciReport::Hint if self.state != EngineState::Waiting => {
+                let msg = String::from(ErrNormal::NOT_WAITING);
+                self.comm.send(CommControl::InfoString(msg));
+            }
+            UciReport::Play(m) => {
+                if self.console_move(m) {
+                    self.console_reply(sp, None);
+                }
+            }
+            UciReport::Blindfold => self.console_blindfold(),
+            UciReport::Reveal => self.print_board(),
+            UciReport::Hint => self.console_hint(sp),
+            UciReport::Clock(time, increment) => self.console_clock(*time, *increment),
+            UciReport::ClockOff => self.console_clock_off(),
+            UciReport::ShowClock => self.console_show_clock(),
+            UciReport::Help(topic) => self.comm.send(CommControl::PrintHelp(topic.clone())),
             UciReport::Unknown => (),
         }
     }
@@ -0,0 +1,331 @@
+/* =======================================================================
+Rustic is a chess playing engine.
+Copyright (C) 2019-2024, Marcel Vanthoor
+https://rustic-chess.org/
+
+Rustic is written in the Rust programming language. It is an original
+work, not derived from any engine that came before it. However, it does
+use a lot of concepts which are well-known and are in use by most if not
+all classical alpha/beta-based chess engines.
+
+Rustic is free software: you can redistribute it and/or modify it under
+the terms of the GNU General Public License version 3 as published by
+the Free Software Foundation.
+
+Rustic is distributed in the hope that it will be useful, but WITHOUT
+ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License
+for more details.
+
+You should have received a copy of the GNU General Public License along
+with this program.  If not, see <http://www.gnu.org/licenses/>.
+======================================================================= */
+
+// console_game.rs keeps the game a user plays against the engine in a
+// terminal window, in the console or with the "play" command. The moves
+// of both the user and the engine are played on the engine's board, and
+// the engine announces its moves in SAN. After each move the board is
+// printed, and the end of the game is announced. In blindfold mode, the
+// board is only printed when the user asks for it, and the user's moves
+// are repeated in SAN as well. With a chess clock, the time both sides
+// have left is shown after each move, the engine plays on its own clock,
+// and a side that runs out of time loses the game.
+
+use super::{
+    chess_clock::ChessClock,
+    defs::{EngineState, ErrFatal},
+    Engine,
+};
+use crate::{
+    board::Board,
+    comm::CommControl,
+    defs::{About, Sides, MAX_MOVE_RULE},
+    evaluation::draw,
+    misc::{pgn::PgnGame, san},
+    movegen::defs::Move,
+    search::{
+        defs::{SearchMode, SearchParams},
+        Search,
+    },
+};
+
+// Time the engine thinks about its move if it is given no time, and there
+// is no chess clock.
+pub const REPLY_MSECS: u128 = 5000;
+
+// Depth of the search for a hint. The hint should be there right away,
+// and a club player doesn't need more than a suggestion.
+pub const HINT_DEPTH: i8 = 6;
+
+impl Engine {
+    // Play the user's move, given in SAN or long algebraic notation.
+    // Returns true if the game goes on after the move.
+    pub fn console_move(&mut self, m: &str) -> bool {
+        let mut board = self.board.lock().expect(ErrFatal::LOCK);
+        let side = board.us();
+        let played = board.play_move(m, &self.mg)
+            || san::san_to_move(&mut board, &self.mg, m).is_some_and(|mv| board.make(mv, &self.mg));
+
+        // A move made after the flag fell doesn't count.
+        let in_time = !played || self.clock.as_mut().is_none_or(|clock| clock.punch(side));
+        if !in_time {
+            board.unmake();
+        }
+        std::mem::drop(board);
+
+        if !in_time {
+            self.console_flag_fall();
+            return false;
+        }
+
+        if !played {
+            let msg = format!("Unknown command or illegal move: {m}");
+            self.comm.send(CommControl::InfoString(msg));
+            return false;
+        }
+
+        if self.blindfold {
+            let san = self.last_move_san().unwrap_or_default();
+            let msg = format!("You play {san}");
+            self.comm.send(CommControl::InfoString(msg));
+        }
+        self.console_moved()
+    }
+
+    // Take back the last move, whoever played it.
+    pub fn console_undo(&mut self) {
+        let taken_back = self.last_move_san();
+        let mut board = self.board.lock().expect(ErrFatal::LOCK);
+        let has_move = board.history.len() > 0;
+        if has_move {
+            board.unmake();
+        }
+        let side = board.us();
+        std::mem::drop(board);
+
+        // The time used so far counts for the side that was to move, but
+        // the increment is not added. The clock of the side to move now
+        // runs again.
+        if let Some(clock) = self.clock.as_mut().filter(|_| has_move) {
+            clock.start(side);
+        }
+
+        if has_move {
+            if let Some(san) = taken_back.filter(|_| self.blindfold) {
+                let msg = format!("Took back {san}");
+                self.comm.send(CommControl::InfoString(msg));
+            }
+            self.console_moved();
+        } else {
+            let msg = String::from("There is no move to take back.");
+            self.comm.send(CommControl::InfoString(msg));
+        }
+    }
+
+    // Let the engine think about its move for the given time, and play it
+    // on the board. Without a time, the engine plays on its own clock, or
+    // thinks for REPLY_MSECS if there is no clock.
+    pub fn console_reply(&mut self, mut sp: SearchParams, msecs: Option<u128>) {
+        match (msecs, &self.clock) {
+            (None, Some(clock)) => {
+                sp.game_time = clock.game_time();
+                sp.search_mode = SearchMode::GameTime;
+            }
+            _ => {
+                sp.move_time = msecs.unwrap_or(REPLY_MSECS);
+                sp.search_mode = SearchMode::MoveTime;
+            }
+        }
+        self.play_best_move = true;
+        self.position_set = true;
+        self.state = EngineState::Searching;
+        self.start_search(sp);
+    }
+
+    // Play the move the engine found. Without a legal move, the search
+    // returns the null move.
+    pub fn console_play(&mut self, m: Move) {
+        if m.get_move() == 0 {
+            let msg = String::from("There is no legal move to play.");
+            self.comm.send(CommControl::InfoString(msg));
+            return;
+        }
+
+        let mut board = self.board.lock().expect(ErrFatal::LOCK);
+        let side = board.us();
+        let san = san::move_to_san(&mut board, &self.mg, m);
+        std::mem::drop(board);
+
+        if self.clock.as_mut().is_some_and(|clock| !clock.punch(side)) {
+            self.console_flag_fall();
+            return;
+        }
+
+        self.board.lock().expect(ErrFatal::LOCK).make(m, &self.mg);
+        let msg = format!("{} plays {san}", About::ENGINE);
+        self.comm.send(CommControl::InfoString(msg));
+        self.console_moved();
+    }
+
+    // Let a short search find a move for the user.
+    pub fn console_hint(&mut self, mut sp: SearchParams) {
+        sp.depth = HINT_DEPTH;
+        sp.search_mode = SearchMode::Depth;
+        self.hint = true;
+        self.position_set = true;
+        self.state = EngineState::Searching;
+        self.start_search(sp);
+    }
+
+    // Suggest the move the engine found to the user.
+    pub fn console_suggest(&mut self, m: Move) {
+        let msg = if m.get_move() == 0 {
+            String::from("There is no legal move to play.")
+        } else {
+            let mut board = self.board.lock().expect(ErrFatal::LOCK).clone();
+            format!("Hint: {}", san::move_to_san(&mut board, &self.mg, m))
+        };
+
+        self.comm.send(CommControl::InfoString(msg));
+    }
+
+    // Switch blindfold mode on or off. When it is switched off, the user
+    // gets to see the position again.
+    pub fn console_blindfold(&mut self) {
+        self.blindfold = !self.blindfold;
+
+        let msg = if self.blindfold {
+            "Blindfold mode on. Type 'reveal' to see the board, or 'hint' for a move."
+        } else {
+            "Blindfold mode off."
+        };
+        self.comm.send(CommControl::InfoString(msg.to_string()));
+        self.console_board();
+    }
+
+    // Start a chess clock, with the given time for each side and the
+    // increment after each move, in milliseconds. The clock of the side
+    // to move starts running right away.
+    pub fn console_clock(&mut self, time: u128, increment: u128) {
+        let side = self.board.lock().expect(ErrFatal::LOCK).us();
+        let mut clock = ChessClock::new(time, increment);
+        clock.start(side);
+        self.clock = Some(clock);
+
+        let msg = String::from("Clock set. The engine plays its replies on it.");
+        self.comm.send(CommControl::InfoString(msg));
+        self.console_show_clock();
+    }
+
+    pub fn console_clock_off(&mut self) {
+        self.clock = None;
+        let msg = String::from("Playing without a clock.");
+        self.comm.send(CommControl::InfoString(msg));
+    }
+
+    pub fn console_show_clock(&self) {
+        let msg = match &self.clock {
+            Some(clock) => clock.display(),
+            None => String::from("There is no clock. Start one with 'clock <time> [increment]'."),
+        };
+        self.comm.send(CommControl::InfoString(msg));
+    }
+
+    // If a side ran out of time, announce that it lost, and return true.
+    // The game is over then, until a new one is started.
+    pub fn console_flag_fall(&mut self) -> bool {
+        let flagged = self.clock.as_mut().and_then(|clock| {
+            let side = clock.flagged()?;
+            clock.stop();
+            Some(side)
+        });
+        let Some(side) = flagged else {
+            return false;
+        };
+
+        let loser = if side == Sides::WHITE {
+            "White"
+        } else {
+            "Black"
+        };
+        let msg = format!("Game over: {loser} lost on time");
+        self.comm.send(CommControl::InfoString(msg));
+        true
+    }
+
+    // Show the board, unless the user plays blindfold.
+    pub fn console_board(&self) {
+        if !self.blindfold {
+            self.print_board();
+        }
+    }
+
+    // The last move played on the board, in SAN.
+    fn last_move_san(&self) -> Option<String> {
+        let mut board = self.board.lock().expect(ErrFatal::LOCK).clone();
+        let last = board.history.len().checked_sub(1)?;
+        let m = board.history.get_ref(last).next_move;
+        board.unmake();
+
+        Some(san::move_to_san(&mut board, &self.mg, m))
+    }
+
+    // The board changed: keep the game up to date with it, and show it.
+    // Returns true if the game goes on.
+    fn console_moved(&mut self) -> bool {
+        let board = self.board.lock().expect(ErrFatal::LOCK);
+        let fen = self.game.fen().map(String::from);
+        self.game = PgnGame::from_history(fen.as_deref(), &board);
+        let game_over = self.game_over(&mut board.clone());
+        let snapshot = Box::new(board.clone());
+        std::mem::drop(board);
+
+        if !self.blindfold {
+            self.comm.send(CommControl::PrintBoard(snapshot));
+        }
+
+        // When the game is over, the clock stops.
+        if let Some(clock) = &mut self.clock {
+            if game_over.is_some() {
+                clock.stop();
+            }
+            self.comm.send(CommControl::InfoString(clock.display()));
+        }
+
+        match game_over {
+            Some(msg) => {
+                self.comm.send(CommControl::InfoString(msg));
+                false
+            }
+            None => true,
+        }
+    }
+
+    // Describes how the game ended, if it is over.
+    fn game_over(&self, board: &mut Board) -> Option<String> {
+        if !Engine::has_legal_move(board, &self.mg) {
+            if !Engine::in_check(board, &self.mg) {
+                return Some(String::from("Game over: stalemate"));
+            }
+
+            let winner = if board.us() == Sides::WHITE {
+                "Black"
+            } else {
+                "White"
+            };
+            return Some(format!("Game over: checkmate, {winner} wins"));
+        }
+
+        let reason = if draw::is_material_draw(board) {
+            "insufficient material"
+        } else if board.game_state.halfmove_clock >= MAX_MOVE_RULE {
+            "the 50-move rule"
+        } else if Search::is_repetition(board) >= 2 {
+            "threefold repetition"
+        } else {
+            return None;
+        };
+
+        Some(format!("Game over: draw by {reason}"))
+    }
+}
@@ -21,8 +21,17 @@ You should have received a copy of the GNU General Public License along
 with this program.  If not, see <http://www.gnu.org/licenses/>.
 ======================================================================= */
 
-pub use crate::engine::transposition::{HashFlag, PerftData, SearchData, TT};
-use crate::{comm::CommReport, search::defs::SearchReport};
+pub use crate::engine::transposition::{HashFlag, PawnData, SearchData, TTPrefetcher, TTStats, TT};
+use crate::{
+    comm::CommReport,
+    defs::{MAX_DEPTH, MAX_LEGAL_MOVES},
+    misc::system,
+    search::defs::{
+        NullMoveParams, OpeningTimeParams, PromotionFilter, RazoringParams, SearchReport,
+        StrengthLimit,
+    },
+};
+use crossbeam_channel::{RecvError, Select, SendError, Sender, TryRecvError};
 
 // This struct holds messages that are reported on fatal engine errors.
 // These should never happen; if they do the engine is in an unknown state,
@@ -37,6 +46,7 @@ impl ErrFatal {
     pub const THREAD: &'static str = "Thread has failed.";
     pub const CHANNEL: &'static str = "Broken channel.";
     pub const NO_INFO_RX: &'static str = "No incoming Info channel.";
+    pub const BENCH_FEN: &'static str = "Bench position is not valid.";
 }
 
 pub struct ErrNormal;
@@ -44,6 +54,19 @@ impl ErrNormal {
     pub const NOT_LEGAL: &'static str = "This is not a legal move in this position.";
     pub const NOT_INT: &'static str = "The value given was not an integer.";
     pub const FEN_FAILED: &'static str = "Setting up FEN failed. Board not changed.";
+    pub const NOT_CHANGED: &'static str = "Setting not changed.";
+    pub const EVAL_FILE: &'static str = "Using built-in evaluation parameters.";
+    pub const STATS_FILE: &'static str = "Not writing search statistics.";
+    pub const LOG_FILE: &'static str = "Not logging input and output.";
+    pub const NO_GAME: &'static str = "No game found";
+    pub const PERFT_DEPTH: &'static str = "Perft needs a depth of 1 or more.";
+    pub const NOT_WAITING: &'static str = "Not available while searching.";
+    pub const NO_SEARCH: &'static str = "There is no finished search to explain.";
+    pub const NO_POSITION: &'static str = "No position was set. Searching the start position.";
+    pub const NO_KINGS: &'static str =
+        "The board has no king for each side. Searching the start position.";
+    pub const ILLEGAL_POSITION: &'static str =
+        "The side not to move is in check. Searching the start position.";
 }
 
 // This struct holds the engine's settings.
@@ -51,6 +74,95 @@ pub struct Settings {
     pub threads: usize,
     pub quiet: bool,
     pub tt_size: usize,
+    pub pawn_hash: usize,
+    pub multi_pv: usize,
+    pub ponder: bool,
+    pub null_move: NullMoveParams,
+    pub razoring: RazoringParams,
+    pub opening_time: OpeningTimeParams,
+    pub promotions: PromotionFilter,
+    pub root_move_nodes: usize,
+    pub max_depth: i8,
+    pub eval_file: String,
+    pub stats_file: String,
+    pub log_file: String,
+    pub eval_player: bool,
+    pub warm_up: bool,
+    pub analyse_mode: bool,
+    pub scores_from_white: bool,
+    pub show_refutations: bool,
+    pub show_curr_line: bool,
+    pub qsearch_checks: bool,
+    pub show_sharpness: bool,
+    pub limit_strength: bool,
+    pub elo: u16,
+    pub low_memory: bool,
+    pub auto_hash: bool,
+    pub auto_hash_size: usize,
+    pub clear_hash_on_new_game: bool,
+    pub clear_hash_on_eval_change: bool,
+}
+
+// In low memory mode, the engine keeps the settings the user chose, but
+// uses less of everything while searching. Switching the mode off again
+// restores the chosen settings.
+impl Settings {
+    // The engine switches to low memory mode by itself if the computer has
+    // less RAM than the threshold. If the amount can't be determined, the
+    // engine assumes there is enough.
+    pub fn low_memory_detected() -> bool {
+        system::total_memory_mb()
+            .is_some_and(|mb| mb < EngineOptionDefaults::LOW_MEMORY_THRESHOLD_MB)
+    }
+
+    // Size of the TT in megabytes. With Auto Hash, this is the size that
+    // was chosen for the current game, once there is one.
+    pub fn hash_in_use(&self) -> usize {
+        let size = if self.auto_hash && self.auto_hash_size > 0 {
+            self.auto_hash_size
+        } else {
+            self.tt_size
+        };
+
+        if self.low_memory {
+            size.min(EngineOptionDefaults::LOW_MEMORY_HASH_MAX)
+        } else {
+            size
+        }
+    }
+
+    // Size of the pawn hash in megabytes. Each thread has its own, so it is
+    // switched off completely in low memory mode.
+    pub fn pawn_hash_in_use(&self) -> usize {
+        if self.low_memory {
+            0
+        } else {
+            self.pawn_hash
+        }
+    }
+
+    // Each thread has its own stacks and PV arena, so in low memory mode
+    // the number of threads depends on the amount of RAM. One thread is
+    // always allowed.
+    pub fn threads_in_use(&self) -> usize {
+        if self.low_memory {
+            let per_thread = EngineOptionDefaults::LOW_MEMORY_MB_PER_THREAD;
+            let max = system::total_memory_mb().map_or(1, |mb| (mb / per_thread).max(1));
+            self.threads.min(max)
+        } else {
+            self.threads
+        }
+    }
+}
+
+// The engine keeps track of what the search is doing, so it knows when a
+// "ponderhit", "pause" or "resume" is valid.
+#[derive(PartialEq, Copy, Clone)]
+pub enum EngineState {
+    Waiting,                    // Not searching.
+    Searching,                  // Searching on our own time.
+    Pondering,                  // Searching on the opponent's time.
+    Paused { pondering: bool }, // Search suspended until resumed.
 }
 
 // This enum provides informatin to the engine, with regard to incoming
@@ -61,9 +173,103 @@ pub enum Information {
     Search(SearchReport),
 }
 
+// The engine thread receives information through two lanes. Incoming
+// commands take the priority lane, so "stop" or "quit" is handled at once,
+// even if the search has queued up lots of reports in the normal lane.
+// Reports from the search stay in order, so the last "info" line is
+// always sent before the best move.
+//
+// The lanes are not ordered with respect to each other. A command can
+// overtake a search report that was sent before it: a "stop" that arrives
+// just after the search finished is handled before the search's Finished
+// report, while the engine still thinks it is searching. Stopping a search
+// that has already finished does nothing, so this is harmless. A GUI waits
+// for "bestmove" before it sends the next "position" or "go", and the best
+// move is only sent after Finished was handled, so a new search can't
+// overtake the end of the previous one.
+#[derive(Clone)]
+pub struct InfoSender {
+    priority: Sender<Information>,
+    normal: Sender<Information>,
+}
+
+impl InfoSender {
+    pub fn send(&self, information: Information) -> Result<(), SendError<Information>> {
+        match information {
+            Information::Comm(_) => self.priority.send(information),
+            Information::Search(_) => self.normal.send(information),
+        }
+    }
+}
+
+pub struct InfoReceiver {
+    priority: crossbeam_channel::Receiver<Information>,
+    normal: crossbeam_channel::Receiver<Information>,
+}
+
+impl InfoReceiver {
+    // Blocks until information is available, and returns it. The priority
+    // lane is always emptied first.
+    pub fn recv(&self) -> Result<Information, RecvError> {
+        loop {
+            if let Ok(information) = self.priority.try_recv() {
+                return Ok(information);
+            }
+
+            // Both lanes share their senders, so they disconnect together.
+            match self.normal.try_recv() {
+                Ok(information) => return Ok(information),
+                Err(TryRecvError::Disconnected) => {
+                    return self.priority.try_recv().map_err(|_| RecvError)
+                }
+                Err(TryRecvError::Empty) => (),
+            }
+
+            // Both lanes are empty. Wait until one of them isn't.
+            let mut select = Select::new();
+            select.recv(&self.priority);
+            select.recv(&self.normal);
+            select.ready();
+        }
+    }
+}
+
+// Creates the two-lane channel for sending information to the engine.
+pub fn info_channel() -> (InfoSender, InfoReceiver) {
+    let (priority_tx, priority_rx) = crossbeam_channel::unbounded::<Information>();
+    let (normal_tx, normal_rx) = crossbeam_channel::unbounded::<Information>();
+
+    let sender = InfoSender {
+        priority: priority_tx,
+        normal: normal_tx,
+    };
+    let receiver = InfoReceiver {
+        priority: priority_rx,
+        normal: normal_rx,
+    };
+
+    (sender, receiver)
+}
+
 pub enum UiElement {
     Spin,
     Button,
+    Check,
+    Combo(&'static [&'static str]),
+    String,
+}
+
+impl UiElement {
+    // The name of the element's type, as UCI writes it.
+    pub fn name(&self) -> &'static str {
+        match self {
+            UiElement::Spin => "spin",
+            UiElement::Button => "button",
+            UiElement::Check => "check",
+            UiElement::Combo(_) => "combo",
+            UiElement::String => "string",
+        }
+    }
 }
 
 pub struct EngineOption {
@@ -95,12 +301,108 @@ impl EngineOption {
 #[derive(PartialEq, Clone)]
 pub enum EngineOptionName {
     Hash(String),
+    PawnHash(String),
     ClearHash,
+    ClearHashOnNewGame(String),
+    MultiPv(String),
+    Ponder(String),
+    Threads(String),
+    PromotionRestriction(String),
+    PromotionRestrictionSearch(String),
+    RootMoveNodes(String),
+    MaxDepth(String),
+    EvalFile(String),
+    ClearHashOnEvalChange(String),
+    StatsFile(String),
+    DebugLogFile(String),
+    EvalPlayer(String),
+    WarmUp(String),
+    AnalyseMode(String),
+    ScoresFromWhite(String),
+    ShowRefutations(String),
+    ShowCurrLine(String),
+    QSearchChecks(String),
+    ShowSharpness(String),
+    LowMemory(String),
+    AutoHash(String),
+    LimitStrength(String),
+    Elo(String),
+    NullMove(String, String),
+    Razoring(String, String),
+    OpeningTime(String, String),
     Nothing,
 }
 impl EngineOptionName {
     pub const HASH: &'static str = "Hash";
+    pub const PAWN_HASH: &'static str = "PawnHash";
     pub const CLEAR_HASH: &'static str = "Clear Hash";
+    pub const CLEAR_HASH_ON_NEW_GAME: &'static str = "Clear Hash On New Game";
+    pub const MULTI_PV: &'static str = "MultiPV";
+    pub const PONDER: &'static str = "Ponder";
+    pub const THREADS: &'static str = "Threads";
+    pub const PROMOTION_RESTRICTION: &'static str = "Promotion Restriction";
+    pub const PROMOTION_RESTRICTION_SEARCH: &'static str = "Promotion Restriction In Search";
+    pub const ROOT_MOVE_NODES: &'static str = "Analysis Root Move Nodes";
+    pub const MAX_DEPTH: &'static str = "Max Depth";
+    pub const EVAL_FILE: &'static str = "EvalFile";
+    pub const CLEAR_HASH_ON_EVAL_CHANGE: &'static str = "Clear Hash On Eval Change";
+    pub const STATS_FILE: &'static str = "Search Stats File";
+    pub const DEBUG_LOG_FILE: &'static str = "Debug Log File";
+    pub const EVAL_PLAYER: &'static str = "Eval Player";
+    pub const WARM_UP: &'static str = "Warm Up";
+    pub const ANALYSE_MODE: &'static str = "UCI_AnalyseMode";
+    pub const SCORES_FROM_WHITE: &'static str = "Analysis Scores From White";
+    pub const SHOW_REFUTATIONS: &'static str = "UCI_ShowRefutations";
+    pub const SHOW_CURR_LINE: &'static str = "UCI_ShowCurrLine";
+    pub const QSEARCH_CHECKS: &'static str = "QSearch Checks";
+    pub const SHOW_SHARPNESS: &'static str = "Show Sharpness";
+    pub const LOW_MEMORY: &'static str = "Low Memory";
+    pub const AUTO_HASH: &'static str = "Auto Hash";
+    pub const LIMIT_STRENGTH: &'static str = "UCI_LimitStrength";
+    pub const ELO: &'static str = "UCI_Elo";
+
+    // The name of the option, as the GUI sent it. A button has no value,
+    // so it has no name to acknowledge either.
+    pub fn name(&self) -> Option<&str> {
+        let name = match self {
+            EngineOptionName::Hash(_) => EngineOptionName::HASH,
+            EngineOptionName::PawnHash(_) => EngineOptionName::PAWN_HASH,
+            EngineOptionName::ClearHashOnNewGame(_) => EngineOptionName::CLEAR_HASH_ON_NEW_GAME,
+            EngineOptionName::MultiPv(_) => EngineOptionName::MULTI_PV,
+            EngineOptionName::Ponder(_) => EngineOptionName::PONDER,
+            EngineOptionName::Threads(_) => EngineOptionName::THREADS,
+            EngineOptionName::PromotionRestriction(_) => EngineOptionName::PROMOTION_RESTRICTION,
+            EngineOptionName::PromotionRestrictionSearch(_) => {
+                EngineOptionName::PROMOTION_RESTRICTION_SEARCH
+            }
+            EngineOptionName::RootMoveNodes(_) => EngineOptionName::ROOT_MOVE_NODES,
+            EngineOptionName::MaxDepth(_) => EngineOptionName::MAX_DEPTH,
+            EngineOptionName::EvalFile(_) => EngineOptionName::EVAL_FILE,
+            EngineOptionName::ClearHashOnEvalChange(_) => {
+                EngineOptionName::CLEAR_HASH_ON_EVAL_CHANGE
+            }
+            EngineOptionName::StatsFile(_) => EngineOptionName::STATS_FILE,
+            EngineOptionName::DebugLogFile(_) => EngineOptionName::DEBUG_LOG_FILE,
+            EngineOptionName::EvalPlayer(_) => EngineOptionName::EVAL_PLAYER,
+            EngineOptionName::WarmUp(_) => EngineOptionName::WARM_UP,
+            EngineOptionName::AnalyseMode(_) => EngineOptionName::ANALYSE_MODE,
+            EngineOptionName::ScoresFromWhite(_) => EngineOptionName::SCORES_FROM_WHITE,
+            EngineOptionName::ShowRefutations(_) => EngineOptionName::SHOW_REFUTATIONS,
+            EngineOptionName::ShowCurrLine(_) => EngineOptionName::SHOW_CURR_LINE,
+            EngineOptionName::QSearchChecks(_) => EngineOptionName::QSEARCH_CHECKS,
+            EngineOptionName::ShowSharpness(_) => EngineOptionName::SHOW_SHARPNESS,
+            EngineOptionName::LowMemory(_) => EngineOptionName::LOW_MEMORY,
+            EngineOptionName::AutoHash(_) => EngineOptionName::AUTO_HASH,
+            EngineOptionName::LimitStrength(_) => EngineOptionName::LIMIT_STRENGTH,
+            EngineOptionName::Elo(_) => EngineOptionName::ELO,
+            EngineOptionName::NullMove(name, _)
+            | EngineOptionName::Razoring(name, _)
+            | EngineOptionName::OpeningTime(name, _) => name,
+            EngineOptionName::ClearHash | EngineOptionName::Nothing => return None,
+        };
+
+        Some(name)
+    }
 }
 
 pub struct EngineOptionDefaults;
@@ -109,4 +411,101 @@ impl EngineOptionDefaults {
     pub const HASH_MIN: usize = 0;
     pub const HASH_MAX_64_BIT: usize = 65536;
     pub const HASH_MAX_32_BIT: usize = 2048;
+    pub const PAWN_HASH_DEFAULT: usize = 2;
+    pub const PAWN_HASH_MIN: usize = 0;
+    pub const PAWN_HASH_MAX: usize = 256;
+    pub const CLEAR_HASH_ON_NEW_GAME_DEFAULT: bool = true;
+    pub const MULTI_PV_DEFAULT: usize = 1;
+    pub const MULTI_PV_MIN: usize = 1;
+    pub const MULTI_PV_MAX: usize = MAX_LEGAL_MOVES as usize;
+    pub const PONDER_DEFAULT: bool = false;
+    pub const THREADS_DEFAULT: usize = 1;
+    pub const THREADS_MIN: usize = 1;
+    pub const THREADS_MAX: usize = 128;
+    pub const PROMOTION_RESTRICTION_DEFAULT: &'static str = "Off";
+    pub const PROMOTION_RESTRICTION_VARS: [&'static str; 5] = ["Off", "Q", "QN", "QR", "QRN"];
+    pub const PROMOTION_RESTRICTION_SEARCH_DEFAULT: bool = false;
+    pub const ROOT_MOVE_NODES_DEFAULT: usize = 0;
+    pub const ROOT_MOVE_NODES_MIN: usize = 0;
+    pub const ROOT_MOVE_NODES_MAX: usize = 1_000_000_000;
+    pub const MAX_DEPTH_DEFAULT: i8 = MAX_DEPTH;
+    pub const MAX_DEPTH_MIN: i8 = 1;
+    pub const MAX_DEPTH_MAX: i8 = MAX_DEPTH;
+    pub const EVAL_FILE_DEFAULT: &'static str = "<empty>";
+    pub const CLEAR_HASH_ON_EVAL_CHANGE_DEFAULT: bool = true;
+    pub const STATS_FILE_DEFAULT: &'static str = "<empty>";
+    pub const DEBUG_LOG_FILE_DEFAULT: &'static str = "<empty>";
+    pub const EVAL_PLAYER_DEFAULT: bool = false;
+    pub const WARM_UP_DEFAULT: bool = true;
+    pub const ANALYSE_MODE_DEFAULT: bool = false;
+    pub const SCORES_FROM_WHITE_DEFAULT: bool = false;
+    pub const SHOW_REFUTATIONS_DEFAULT: bool = false;
+    pub const SHOW_CURR_LINE_DEFAULT: bool = false;
+    pub const QSEARCH_CHECKS_DEFAULT: bool = false;
+    pub const SHOW_SHARPNESS_DEFAULT: bool = false;
+    pub const LOW_MEMORY_THRESHOLD_MB: usize = 2048;
+    pub const LOW_MEMORY_HASH_MAX: usize = 16;
+    pub const LOW_MEMORY_MB_PER_THREAD: usize = 512;
+    pub const AUTO_HASH_DEFAULT: bool = false;
+    pub const AUTO_HASH_MIN: usize = 16;
+    pub const AUTO_HASH_MEMORY_SHARE: usize = 4; // At most 1/4 of the RAM.
+    pub const AUTO_HASH_NPS: usize = 1_000_000; // Per thread, until measured.
+    pub const AUTO_HASH_NPS_TIME: u128 = 100; // msecs, to measure nps.
+    pub const ELO_DEFAULT: u16 = 1500;
+    pub const ELO_MIN: u16 = StrengthLimit::ELO_MIN;
+    pub const ELO_MAX: u16 = StrengthLimit::ELO_MAX;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        api::UciEngine, comm::uci::UciReport, movegen::defs::Move, search::defs::SearchReport,
+    };
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn commands_overtake_search_reports() {
+        let (tx, rx) = info_channel();
+        let move_nr = |nr| SearchReport::InfoString(format!("move {nr}"));
+        for nr in 0..3 {
+            tx.send(Information::Search(move_nr(nr))).unwrap();
+        }
+        tx.send(Information::Comm(CommReport::Uci(UciReport::Stop)))
+            .unwrap();
+        tx.send(Information::Search(SearchReport::Finished(
+            Move::new(0),
+            None,
+        )))
+        .unwrap();
+        std::mem::drop(tx);
+
+        assert!(matches!(rx.recv(), Ok(Information::Comm(_))));
+        for nr in 0..3 {
+            assert!(rx.recv() == Ok(Information::Search(move_nr(nr))));
+        }
+        assert!(matches!(
+            rx.recv(),
+            Ok(Information::Search(SearchReport::Finished(..)))
+        ));
+        assert!(rx.recv().is_err());
+    }
+
+    // The search sends a report for each root move and each line it
+    // tries. Even with those queued up, "stop" must be answered quickly.
+    #[test]
+    fn stop_is_answered_quickly() {
+        let engine = UciEngine::start();
+        engine.send("uci\nsetoption name UCI_ShowCurrLine value true");
+        engine.send("position startpos\ngo infinite");
+        std::thread::sleep(Duration::from_millis(500));
+
+        let stopped = Instant::now();
+        engine.send("stop");
+        let best_move = std::iter::from_fn(|| engine.recv_timeout(Duration::from_secs(5)))
+            .find(|line| line.starts_with("bestmove"));
+
+        assert!(best_move.is_some());
+        assert!(stopped.elapsed() < Duration::from_secs(1));
+    }
 }
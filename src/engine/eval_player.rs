@@ -0,0 +1,151 @@
+/* =======================================================================
+Rustic is a chess playing engine.
+Copyright (C) 2019-2024, Marcel Vanthoor
+https://rustic-chess.org/
+
+Rustic is written in the Rust programming language. It is an original
+work, not derived from any engine that came before it. However, it does
+use a lot of concepts which are well-known and are in use by most if not
+all classical alpha/beta-based chess engines.
+
+Rustic is free software: you can redistribute it and/or modify it under
+the terms of the GNU General Public License version 3 as published by
+the Free Software Foundation.
+
+Rustic is distributed in the hope that it will be useful, but WITHOUT
+ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License
+for more details.
+
+You should have received a copy of the GNU General Public License along
+with this program.  If not, see <http://www.gnu.org/licenses/>.
+======================================================================= */
+
+// eval_player.rs lets the engine play without searching. Every legal move
+// is made on a copy of the board, and the resulting position is scored by
+// the static evaluation alone. The move with the best score is played.
+// This shows how much of the engine's strength comes from the evaluation
+// by itself. Moves that give checkmate, stalemate, a repetition or a draw
+// by the 50-move rule are recognized, so the eval player doesn't stumble
+// into (or miss) the end of the game.
+
+use super::{
+    defs::{EngineState, ErrFatal},
+    Engine,
+};
+use crate::{
+    board::Board,
+    comm::CommControl,
+    defs::MAX_MOVE_RULE,
+    evaluation::evaluate_position,
+    movegen::{
+        defs::{Move, MoveList, MoveType},
+        MoveGenerator,
+    },
+    search::{
+        defs::{ScoreBound, SearchReport, SearchSummary, CHECKMATE, DRAW},
+        Search,
+    },
+};
+use std::time::Instant;
+
+impl Engine {
+    // Pick a move by static evaluation and report it in the same way as
+    // a finished search does.
+    pub fn eval_player_move(&mut self) {
+        let now = Instant::now();
+        let mut board = self.board.lock().expect(ErrFatal::LOCK).clone();
+        let mut ml = MoveList::new();
+        let mut best: Option<(Move, i16)> = None;
+        let mut nodes = 0;
+
+        self.mg.generate_moves(&board, &mut ml, MoveType::All);
+        for i in 0..ml.len() {
+            let m = ml.get_move(i);
+            if board.make(m, &self.mg) {
+                let score = Engine::eval_player_score(&mut board, &self.mg);
+                board.unmake();
+                nodes += 1;
+
+                if best.is_none_or(|(_, s)| score > s) {
+                    best = Some((m, score));
+                }
+            }
+        }
+
+        match best {
+            Some((m, cp)) => {
+                let time = now.elapsed().as_millis();
+                let summary = SearchSummary {
+                    depth: 1,
+                    seldepth: 1,
+                    time,
+                    cp,
+                    bound: ScoreBound::Exact,
+                    mate: 0,
+                    nodes,
+                    nps: Search::nodes_per_second(nodes, time),
+                    hash_full: 0,
+                    tb_hits: 0,
+                    cpu_load: 0,
+                    multi_pv: 1,
+                    pv: vec![m],
+                    side: board.us(),
+                };
+                self.search_reports(&SearchReport::SearchSummary(summary));
+                self.search_reports(&SearchReport::Finished(m, None));
+            }
+
+            // No legal moves: the game is already over. Report this, and
+            // answer with an empty move, as the search does.
+            None => {
+                let msg = if Engine::in_check(&board, &self.mg) {
+                    "Game over: checkmate"
+                } else {
+                    "Game over: stalemate"
+                };
+                self.comm.send(CommControl::InfoString(msg.to_string()));
+                self.search_reports(&SearchReport::Finished(Move::new(0), None));
+            }
+        }
+
+        self.state = EngineState::Waiting;
+    }
+
+    // Score of the position after our move, from our point of view. The
+    // opponent is to move, so the evaluation is negated.
+    fn eval_player_score(board: &mut Board, mg: &MoveGenerator) -> i16 {
+        if !Engine::has_legal_move(board, mg) {
+            return if Engine::in_check(board, mg) {
+                CHECKMATE - 1
+            } else {
+                DRAW
+            };
+        }
+
+        let is_repetition = Search::is_repetition(board) > 0;
+        let is_max_move_rule = board.game_state.halfmove_clock >= MAX_MOVE_RULE;
+        if is_repetition || is_max_move_rule {
+            return DRAW;
+        }
+
+        -evaluate_position(board)
+    }
+
+    pub fn has_legal_move(board: &mut Board, mg: &MoveGenerator) -> bool {
+        let mut ml = MoveList::new();
+        mg.generate_moves(board, &mut ml, MoveType::All);
+
+        for i in 0..ml.len() {
+            if board.make(ml.get_move(i), mg) {
+                board.unmake();
+                return true;
+            }
+        }
+        false
+    }
+
+    pub fn in_check(board: &Board, mg: &MoveGenerator) -> bool {
+        mg.square_attacked(board, board.opponent(), board.king_square(board.us()))
+    }
+}
@@ -0,0 +1,173 @@
+/* =======================================================================
+Rustic is a chess playing engine.
+Copyright (C) 2019-2024, Marcel Vanthoor
+https://rustic-chess.org/
+
+Rustic is written in the Rust programming language. It is an original
+work, not derived from any engine that came before it. However, it does
+use a lot of concepts which are well-known and are in use by most if not
+all classical alpha/beta-based chess engines.
+
+Rustic is free software: you can redistribute it and/or modify it under
+the terms of the GNU General Public License version 3 as published by
+the Free Software Foundation.
+
+Rustic is distributed in the hope that it will be useful, but WITHOUT
+ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License
+for more details.
+
+You should have received a copy of the GNU General Public License along
+with this program.  If not, see <http://www.gnu.org/licenses/>.
+======================================================================= */
+
+// explain.rs keeps the results of the last search, so the user can ask
+// why the engine played the move it did. The explanation is assembled
+// from the search summaries the engine received: the final line, how the
+// score developed over the iterations, and how far the second best move
+// was behind.
+
+use crate::{
+    board::Board,
+    misc::san,
+    movegen::{defs::Move, MoveGenerator},
+    search::defs::{ScoreBound, SearchSummary},
+};
+
+pub struct SearchLog {
+    board: Option<Board>,           // Position the search started in
+    iterations: Vec<SearchSummary>, // Last main line summary per depth
+    second: Option<SearchSummary>,  // Latest second MultiPV line
+    best_move: Option<Move>,        // Move that was played
+}
+
+impl SearchLog {
+    pub fn new() -> Self {
+        Self {
+            board: None,
+            iterations: Vec::new(),
+            second: None,
+            best_move: None,
+        }
+    }
+
+    // A new search starts; forget the previous one.
+    pub fn begin(&mut self, board: &Board) {
+        self.board = Some(board.clone());
+        self.iterations.clear();
+        self.second = None;
+        self.best_move = None;
+    }
+
+    // Keep the last main line summary of each depth. A depth can report
+    // more than once if the score fell outside the aspiration window.
+    pub fn record(&mut self, summary: &SearchSummary) {
+        match summary.multi_pv {
+            1 => {
+                if let Some(last) = self.iterations.last_mut() {
+                    if last.depth == summary.depth {
+                        *last = summary.clone();
+                        return;
+                    }
+                }
+                self.iterations.push(summary.clone());
+            }
+            2 => self.second = Some(summary.clone()),
+            _ => (),
+        }
+    }
+
+    pub fn finish(&mut self, best_move: Move) {
+        self.best_move = Some(best_move);
+    }
+
+    // Explain the last finished search, one line of text per subject.
+    // Returns None if there is nothing to explain yet.
+    pub fn explain(&self, mg: &MoveGenerator) -> Option<Vec<String>> {
+        let board = self.board.as_ref()?;
+        let best_move = self.best_move?;
+        let last = self.iterations.last()?;
+        let mut lines: Vec<String> = Vec::new();
+
+        lines.push(format!(
+            "Played {} at depth {} (seldepth {}), score {}",
+            san::moves_to_san(board, mg, &[best_move]),
+            last.depth,
+            last.seldepth,
+            SearchLog::score(last)
+        ));
+
+        lines.push(format!(
+            "Principal variation: {}",
+            san::moves_to_san(board, mg, &last.pv)
+        ));
+
+        let trend: Vec<String> = self
+            .iterations
+            .iter()
+            .map(|s| format!("{}:{}", s.depth, SearchLog::score(s)))
+            .collect();
+        lines.push(format!("Score by depth: {}", trend.join(" ")));
+
+        if let Some(line) = self.best_move_changes(board, mg) {
+            lines.push(line);
+        }
+
+        lines.push(match &self.second {
+            Some(second) if !second.pv.is_empty() => format!(
+                "Second best: {} with score {}, {} cp behind",
+                san::moves_to_san(board, mg, &second.pv[..1]),
+                SearchLog::score(second),
+                last.cp as i32 - second.cp as i32
+            ),
+            _ => String::from("Second best: not searched (set MultiPV to 2 or more)"),
+        });
+
+        lines.push(format!(
+            "Searched {} nodes in {} ms ({} nps)",
+            last.nodes, last.time, last.nps
+        ));
+
+        Some(lines)
+    }
+}
+
+// Private functions
+impl SearchLog {
+    // The depths at which the search changed its mind about the best
+    // move. A move that was found early and never changed is a clear
+    // choice; a late change means the move was hard to find.
+    fn best_move_changes(&self, board: &Board, mg: &MoveGenerator) -> Option<String> {
+        let mut changes: Vec<String> = Vec::new();
+        let mut previous: Option<Move> = None;
+
+        for s in self.iterations.iter() {
+            let m = *s.pv.first()?;
+            if previous.is_some_and(|p| p.get_move() != m.get_move()) {
+                let san = san::moves_to_san(board, mg, &[m]);
+                changes.push(format!("{san} at depth {}", s.depth));
+            }
+            previous = Some(m);
+        }
+
+        if changes.is_empty() {
+            let first = self.iterations.first()?.depth;
+            Some(format!("Best move unchanged since depth {first}"))
+        } else {
+            Some(format!("Best move changed to {}", changes.join(", ")))
+        }
+    }
+
+    fn score(s: &SearchSummary) -> String {
+        let bound = match s.bound {
+            ScoreBound::Exact => "",
+            ScoreBound::Lower => ">=",
+            ScoreBound::Upper => "<=",
+        };
+
+        match s.mate_in() {
+            Some(moves) => format!("{bound}mate {moves}"),
+            None => format!("{bound}{:+}", s.cp),
+        }
+    }
+}
@@ -0,0 +1,188 @@
+/* =======================================================================
+Rustic is a chess playing engine.
+Copyright (C) 2019-2024, Marcel Vanthoor
+https://rustic-chess.org/
+
+Rustic is written in the Rust programming language. It is an original
+work, not derived from any engine that came before it. However, it does
+use a lot of concepts which are well-known and are in use by most if not
+all classical alpha/beta-based chess engines.
+
+Rustic is free software: you can redistribute it and/or modify it under
+the terms of the GNU General Public License version 3 as published by
+the Free Software Foundation.
+
+Rustic is distributed in the hope that it will be useful, but WITHOUT
+ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License
+for more details.
+
+You should have received a copy of the GNU General Public License along
+with this program.  If not, see <http://www.gnu.org/licenses/>.
+======================================================================= */
+
+// game_stats.rs keeps a record of every search the engine did during the
+// current game. When the game ends, these records are summarized, so the
+// user can see how the engine spent its time and how the game went.
+
+use crate::{
+    defs::{Side, Sides},
+    misc::eco::Opening,
+    search::defs::{SearchStats, SearchSummary},
+};
+
+// Everything that is remembered about a single search.
+#[derive(Clone, Copy, Default)]
+pub struct MoveRecord {
+    pub side: Side,     // Side the engine was searching for
+    pub time: u128,     // Time spent in milliseconds
+    pub depth: i8,      // Deepest completed depth
+    pub nodes: usize,   // Nodes searched
+    pub hash_full: u16, // TT use in permille at the end of the search
+    pub cp: i16,        // Evaluation from the side to move's perspective
+}
+
+pub struct GameStats {
+    records: Vec<MoveRecord>,
+    current: MoveRecord,
+    opening: Option<Opening>,
+}
+
+impl GameStats {
+    pub fn new() -> Self {
+        Self {
+            records: Vec::new(),
+            current: MoveRecord::default(),
+            opening: None,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.records.clear();
+        self.current = MoveRecord::default();
+        self.opening = None;
+    }
+
+    // Only the main line is recorded when running in MultiPV mode.
+    pub fn record_summary(&mut self, s: &SearchSummary) {
+        if s.multi_pv == 1 {
+            self.current.time = s.time;
+            self.current.depth = s.depth;
+            self.current.nodes = s.nodes;
+            self.current.hash_full = s.hash_full;
+            self.current.cp = s.cp;
+        }
+    }
+
+    // Stats are sent during the search, after the last summary. They
+    // have the most recent time and node count.
+    pub fn record_stats(&mut self, s: &SearchStats) {
+        self.current.time = self.current.time.max(s.time);
+        self.current.nodes = self.current.nodes.max(s.nodes);
+        self.current.hash_full = self.current.hash_full.max(s.hash_full);
+    }
+
+    pub fn finish_move(&mut self, side: Side) {
+        self.current.side = side;
+        self.records.push(self.current);
+        self.current = MoveRecord::default();
+    }
+
+    // The opening is classified at every search. The GUI may send only a
+    // FEN instead of the full move list, so once the game leaves the
+    // table, the last known opening is kept.
+    pub fn record_opening(&mut self, opening: Option<Opening>) {
+        if opening.is_some() {
+            self.opening = opening;
+        }
+    }
+}
+
+// Creates the summary of the game, one line of text per subject.
+impl GameStats {
+    pub fn summary(&self) -> Vec<String> {
+        let mut lines: Vec<String> = Vec::new();
+        let total_nodes: usize = self.records.iter().map(|r| r.nodes).sum();
+
+        lines.push(format!(
+            "Game statistics: {} searches, {total_nodes} nodes",
+            self.records.len()
+        ));
+
+        if let Some(opening) = self.opening {
+            lines.push(format!("Opening: {opening}"));
+        }
+
+        for (side, name) in [(Sides::WHITE, "White"), (Sides::BLACK, "Black")] {
+            if let Some(line) = self.side_summary(side, name) {
+                lines.push(line);
+            }
+        }
+
+        lines.push(self.hash_full_summary());
+
+        if let Some(line) = self.eval_swing_summary() {
+            lines.push(line);
+        }
+
+        lines
+    }
+
+    fn side_summary(&self, side: Side, name: &str) -> Option<String> {
+        let records: Vec<&MoveRecord> = self.records.iter().filter(|r| r.side == side).collect();
+        if records.is_empty() {
+            return None;
+        }
+
+        let time: u128 = records.iter().map(|r| r.time).sum();
+        let depth_total: usize = records.iter().map(|r| r.depth as usize).sum();
+        let depth_max = records.iter().map(|r| r.depth).max().unwrap_or(0);
+        let depth_avg = depth_total as f64 / records.len() as f64;
+
+        Some(format!(
+            "{name}: {} moves, time {time} ms, depth avg {depth_avg:.1} max {depth_max}",
+            records.len()
+        ))
+    }
+
+    // Shows how full the TT was at the start, the middle and the end of
+    // the game.
+    fn hash_full_summary(&self) -> String {
+        let first = self.records.first().map_or(0, |r| r.hash_full);
+        let middle = self.records[self.records.len() / 2].hash_full;
+        let last = self.records.last().map_or(0, |r| r.hash_full);
+
+        format!("Hash full (permille): start {first}, middle {middle}, end {last}")
+    }
+
+    // The largest change in evaluation between two consecutive searches.
+    // The evaluations are compared from White's point of view, so it
+    // doesn't matter which side(s) the engine played.
+    fn eval_swing_summary(&self) -> Option<String> {
+        let white_cp = |r: &MoveRecord| {
+            let cp = r.cp as i32;
+            if r.side == Sides::WHITE {
+                cp
+            } else {
+                -cp
+            }
+        };
+
+        let (index, swing) = self
+            .records
+            .windows(2)
+            .map(|w| (white_cp(&w[1]) - white_cp(&w[0])).abs())
+            .enumerate()
+            .max_by_key(|(_, swing)| *swing)?;
+
+        Some(format!(
+            "Largest eval swing: {swing} cp, between search {} and {}",
+            index + 1,
+            index + 2
+        ))
+    }
+}
@@ -22,16 +22,19 @@ with this program.  If not, see <http://www.gnu.org/licenses/>.
 ======================================================================= */
 
 use super::{
-    defs::{ErrFatal, Information},
+    defs::{self, EngineState, ErrFatal, Information},
     Engine,
 };
-use crate::comm::CommControl;
+use crate::{
+    comm::CommControl,
+    search::defs::{SearchControl, SearchReport},
+};
 use std::sync::Arc;
 
 impl Engine {
     pub fn main_loop(&mut self) {
         // Set up a channel for incoming information.
-        let (info_tx, info_rx) = crossbeam_channel::unbounded::<Information>();
+        let (info_tx, info_rx) = defs::info_channel();
 
         // Store the information receiver in the engine for use in other functions.
         self.info_rx = Some(info_rx);
@@ -41,18 +44,25 @@ impl Engine {
             info_tx.clone(),
             Arc::clone(&self.board),
             Arc::clone(&self.options),
+            self.protocol_log.clone(),
         );
         self.search.init(
             info_tx,
             Arc::clone(&self.board),
             Arc::clone(&self.mg),
             Arc::clone(&self.tt_search),
-            self.settings.tt_size > 0,
+            self.settings.hash_in_use() > 0,
         );
 
         // Update the Comm interface screen output (if any).
         self.comm.send(CommControl::Update);
 
+        // Warn the user about settings the computer can't support.
+        self.check_settings();
+
+        // Get the TT and the move generator ready for the first search.
+        self.warm_up();
+
         // Keep looping forever until 'quit' received.
         while !self.quit {
             let information = &self.info_rx();
@@ -68,11 +78,51 @@ impl Engine {
         self.search.wait_for_shutdown();
     }
 
-    // This is the main engine thread Information receiver.
+    // This is the main engine thread Information receiver. Information
+    // that was put aside while waiting for something else comes first.
     fn info_rx(&mut self) -> Information {
+        if let Some(information) = self.pending.pop_front() {
+            return information;
+        }
+
         match &self.info_rx {
             Some(i) => i.recv().expect(ErrFatal::CHANNEL),
             None => panic!("{}", ErrFatal::NO_INFO_RX),
         }
     }
+
+    // Stops a running search, and waits until it has finished. Its best
+    // move is thrown away, as are its other reports. Incoming commands are
+    // put aside until then, so they are handled in the order they came
+    // in. The eval player doesn't run a search while pondering, so then
+    // there is nothing to wait for. In a game against the user, the move
+    // of an abandoned search is neither played nor suggested.
+    pub fn abandon_search(&mut self) {
+        let pondering = matches!(
+            self.state,
+            EngineState::Pondering | EngineState::Paused { pondering: true }
+        );
+        let searching = self.state != EngineState::Waiting;
+
+        if searching && !(self.settings.eval_player && pondering) {
+            self.search.send(SearchControl::Stop);
+
+            loop {
+                let information = match &self.info_rx {
+                    Some(i) => i.recv().expect(ErrFatal::CHANNEL),
+                    None => panic!("{}", ErrFatal::NO_INFO_RX),
+                };
+
+                match information {
+                    Information::Search(SearchReport::Finished(_, _)) => break,
+                    Information::Search(_) => (),
+                    Information::Comm(_) => self.pending.push_back(information),
+                }
+            }
+        }
+
+        self.state = EngineState::Waiting;
+        self.play_best_move = false;
+        self.hint = false;
+    }
 }
@@ -21,28 +21,164 @@ You should have received a copy of the GNU General Public License along
 with this program.  If not, see <http://www.gnu.org/licenses/>.
 ======================================================================= */
 
-use super::Engine;
-use crate::{comm::CommControl, search::defs::SearchReport};
+use super::{
+    defs::{EngineOptionDefaults, EngineState, ErrFatal},
+    Engine,
+};
+use crate::{
+    comm::{CommControl, CommType},
+    defs::Sides,
+    misc::{eco, san},
+    search::defs::{ScoreBound, SearchReport, SearchSummary, STALEMATE},
+};
 
 impl Engine {
     pub fn search_reports(&mut self, search_report: &SearchReport) {
         match search_report {
-            SearchReport::Finished(m) => {
-                self.comm.send(CommControl::BestMove(*m));
+            SearchReport::Finished(m, ponder_move) => {
+                let (best_move, ponder_move) = self.verify_best_move(*m, *ponder_move);
+                let board = self.board.lock().expect(ErrFatal::LOCK);
+                let (us, opening) = (board.us(), eco::classify(&board, &self.mg));
+                std::mem::drop(board);
+                self.game_stats.record_opening(opening);
+                self.game_stats.finish_move(us);
+                self.report_tt_stats();
+                self.analysis.finish();
+                self.search_log.finish(best_move);
+                self.state = EngineState::Waiting;
+
+                // In a game against the user, the engine plays its move on
+                // the board, or suggests it to the user.
+                if self.play_best_move {
+                    self.play_best_move = false;
+                    self.console_play(best_move);
+                } else if self.hint {
+                    self.hint = false;
+                    self.console_suggest(best_move);
+                } else {
+                    self.comm
+                        .send(CommControl::BestMove(best_move, ponder_move));
+                }
                 self.comm.send(CommControl::Update);
             }
 
             SearchReport::SearchCurrentMove(curr_move) => {
-                self.comm.send(CommControl::SearchCurrMove(*curr_move));
+                self.comm
+                    .send(CommControl::SearchCurrMove(curr_move.clone()));
+            }
+
+            SearchReport::SearchRefutation(line) => {
+                self.comm.send(CommControl::SearchRefutation(line.clone()));
             }
 
+            // The lines of a search for a hint would give away more than
+            // the move itself, so they are not shown.
             SearchReport::SearchSummary(summary) => {
-                self.comm.send(CommControl::SearchSummary(summary.clone()));
+                if !self.hint {
+                    self.comm.send(CommControl::SearchSummary(summary.clone()));
+                }
+                self.record_summary(summary);
+            }
+
+            SearchReport::SearchSummaries(summaries) => {
+                if !self.hint {
+                    let group = summaries
+                        .iter()
+                        .map(|summary| CommControl::SearchSummary(summary.clone()))
+                        .collect();
+                    self.comm.send(CommControl::Group(group));
+                }
+                for summary in summaries {
+                    self.record_summary(summary);
+                }
             }
 
             SearchReport::SearchStats(stats) => {
                 self.comm.send(CommControl::SearchStats(*stats));
+                self.game_stats.record_stats(stats);
+            }
+
+            SearchReport::InfoString(msg) => {
+                self.comm.send(CommControl::InfoString(msg.clone()));
+            }
+        }
+    }
+
+    // Keep track of a line the search reported.
+    fn record_summary(&mut self, summary: &SearchSummary) {
+        // The speed is only measured over a search long enough to show it.
+        if summary.multi_pv == 1 && summary.time >= EngineOptionDefaults::AUTO_HASH_NPS_TIME {
+            self.nps = summary.nps;
+        }
+
+        self.game_stats.record_summary(summary);
+        self.analysis.record(summary);
+        self.search_log.record(summary);
+        self.stats_csv.record(summary);
+        self.report_mating_line(summary);
+    }
+
+    // If the search found a mate, spell it out for the user: who is
+    // mating in how many moves, and the mating line in SAN. This saves
+    // having to interpret "mate -3" from the side to move's perspective.
+    // A drawn line ending in stalemate is spelled out in the same way.
+    // The line is read from the position the search started in, because
+    // the engine's board may have changed since. This is meant for a user
+    // reading along: a GUI in strict mode only gets the "info" lines the
+    // protocol defines. A user asking for a hint only gets the move.
+    fn report_mating_line(&self, summary: &SearchSummary) {
+        let is_console = self.comm.get_protocol_name() == CommType::CONSOLE;
+        if self.hint || (!is_console && self.comm.is_strict()) {
+            return;
+        }
+
+        // Only the main line is spelled out; not every MultiPV line. A
+        // bounded score is not a proven mate.
+        if summary.multi_pv > 1 || summary.bound != ScoreBound::Exact {
+            return;
+        }
+
+        let board = &self.search_root;
+        let color = |side| match side {
+            Sides::WHITE => "White",
+            _ => "Black",
+        };
+
+        // The side making the last move of the line mates or stalemates.
+        let msg = if let Some(moves) = summary.mate_in() {
+            let mating_side = summary.side ^ usize::from(moves < 0);
+            let line = san::moves_to_san(board, &self.mg, &summary.pv);
+            format!("Mate in {} for {}: {line}", moves.abs(), color(mating_side))
+        } else if let Some(plies) = self.stalemate_in(summary) {
+            let stalemating_side = summary.side ^ usize::from(plies % 2 == 0);
+            let line = san::moves_to_san(board, &self.mg, &summary.pv);
+            let moves = plies.div_ceil(2);
+            format!(
+                "Stalemate in {moves} for {}: {line}",
+                color(stalemating_side)
+            )
+        } else {
+            return;
+        };
+
+        self.comm.send(CommControl::InfoString(msg));
+    }
+
+    // If a drawn PV ends in stalemate, this returns its length in plies.
+    fn stalemate_in(&self, summary: &SearchSummary) -> Option<usize> {
+        if summary.cp != STALEMATE || summary.pv.is_empty() {
+            return None;
+        }
+
+        let mut board = self.search_root.clone();
+        for m in summary.pv.iter() {
+            if !board.make(*m, &self.mg) {
+                return None;
             }
         }
+
+        let stalemate =
+            !Engine::has_legal_move(&mut board, &self.mg) && !Engine::in_check(&board, &self.mg);
+        stalemate.then_some(summary.pv.len())
     }
 }
@@ -21,7 +21,21 @@ You should have received a copy of the GNU General Public License along
 with this program.  If not, see <http://www.gnu.org/licenses/>.
 ======================================================================= */
 
-use crate::{board::defs::ZobristKey, movegen::defs::ShortMove, search::defs::CHECKMATE_THRESHOLD};
+use crate::{
+    board::defs::ZobristKey,
+    defs::{Side, Sides},
+    engine::defs::ErrFatal,
+    movegen::defs::ShortMove,
+    search::defs::CHECKMATE_THRESHOLD,
+};
+use std::{
+    marker::PhantomData,
+    ops::AddAssign,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Mutex,
+    },
+};
 
 const MEGABYTE: usize = 1024 * 1024;
 const ENTRIES_PER_BUCKET: usize = 4;
@@ -29,43 +43,27 @@ const HIGH_FOUR_BYTES: u64 = 0xFF_FF_FF_FF_00_00_00_00;
 const LOW_FOUR_BYTES: u64 = 0x00_00_00_00_FF_FF_FF_FF;
 const SHIFT_TO_LOWER: u64 = 32;
 
+// From this TT usage (in permille) onward, the TT is under pressure and
+// Exact entries are protected during replacement.
+const HASH_PRESSURE: usize = 900;
+
+// Marks a search entry without a static evaluation. (A position in check
+// is not evaluated, and not every node needs the evaluation.)
+const NO_STATIC_EVAL: i16 = i16::MIN;
+
 /* ===== Data ========================================================= */
 
+// The data is packed into a 64-bit word, plus 32 extra bits that share a
+// word with the verification. This way, an entry can be read and written
+// without locking the TT.
 pub trait IHashData {
     fn new() -> Self;
     fn depth(&self) -> i8;
-}
-#[derive(Copy, Clone)]
-pub struct PerftData {
-    depth: i8,
-    leaf_nodes: u64,
-}
-
-impl IHashData for PerftData {
-    fn new() -> Self {
-        Self {
-            depth: 0,
-            leaf_nodes: 0,
-        }
-    }
-
-    fn depth(&self) -> i8 {
-        self.depth
-    }
-}
-
-impl PerftData {
-    pub fn create(depth: i8, leaf_nodes: u64) -> Self {
-        Self { depth, leaf_nodes }
-    }
-
-    pub fn get(&self, depth: i8) -> Option<u64> {
-        if self.depth == depth {
-            Some(self.leaf_nodes)
-        } else {
-            None
-        }
-    }
+    fn is_exact(&self) -> bool;
+    fn generation(&self) -> u8;
+    fn set_generation(&mut self, generation: u8);
+    fn pack(&self) -> (u32, u64);
+    fn unpack(extra: u32, data: u64) -> Self;
 }
 
 #[derive(Copy, Clone)]
@@ -76,20 +74,43 @@ pub enum HashFlag {
     Beta,
 }
 
+impl HashFlag {
+    fn from_bits(bits: u32) -> Self {
+        match bits {
+            1 => HashFlag::Exact,
+            2 => HashFlag::Alpha,
+            3 => HashFlag::Beta,
+            _ => HashFlag::Nothing,
+        }
+    }
+}
+
+// Next to the search value, an entry holds the static evaluation of the
+// position, so a node that finds its position in the TT doesn't have to
+// evaluate it again. With the evaluation, an entry is 16 bytes, so a
+// bucket of four entries still fits into one 64-byte cache line. The
+// move, value, evaluation and depth fill the data word; the flag and the
+// generation of the search that stored the entry are the extra bits.
 #[derive(Copy, Clone)]
 pub struct SearchData {
     depth: i8,
     flag: HashFlag,
+    generation: u8,
     value: i16,
+    static_eval: i16,
     best_move: ShortMove,
 }
 
+const _: () = assert!(std::mem::size_of::<Bucket<SearchData>>() <= 64);
+
 impl IHashData for SearchData {
     fn new() -> Self {
         Self {
             depth: 0,
             flag: HashFlag::Nothing,
+            generation: 0,
             value: 0,
+            static_eval: NO_STATIC_EVAL,
             best_move: ShortMove::new(0),
         }
     }
@@ -97,10 +118,50 @@ impl IHashData for SearchData {
     fn depth(&self) -> i8 {
         self.depth
     }
+
+    fn is_exact(&self) -> bool {
+        matches!(self.flag, HashFlag::Exact)
+    }
+
+    fn generation(&self) -> u8 {
+        self.generation
+    }
+
+    fn set_generation(&mut self, generation: u8) {
+        self.generation = generation;
+    }
+
+    fn pack(&self) -> (u32, u64) {
+        let extra = self.flag as u32 | (self.generation as u32) << 8;
+        let data = (self.best_move.get_move() as u64) << 40
+            | (self.value as u16 as u64) << 24
+            | (self.static_eval as u16 as u64) << 8
+            | self.depth as u8 as u64;
+
+        (extra, data)
+    }
+
+    fn unpack(extra: u32, data: u64) -> Self {
+        Self {
+            depth: data as u8 as i8,
+            flag: HashFlag::from_bits(extra & 0xFF),
+            generation: (extra >> 8) as u8,
+            value: (data >> 24) as u16 as i16,
+            static_eval: (data >> 8) as u16 as i16,
+            best_move: ShortMove::new((data >> 40) as u32),
+        }
+    }
 }
 
 impl SearchData {
-    pub fn create(depth: i8, ply: i8, flag: HashFlag, value: i16, best_move: ShortMove) -> Self {
+    pub fn create(
+        depth: i8,
+        ply: i16,
+        flag: HashFlag,
+        value: i16,
+        static_eval: Option<i16>,
+        best_move: ShortMove,
+    ) -> Self {
         // This is the value we're going to save into the TT.
         let mut v = value;
 
@@ -110,135 +171,381 @@ impl SearchData {
         // rewritten as a comparative match expression. We don't, because
         // they're slower. (No inlining by the compiler.)
         if v > CHECKMATE_THRESHOLD {
-            v += ply as i16;
+            v += ply;
         }
 
         if v < CHECKMATE_THRESHOLD {
-            v -= ply as i16;
+            v -= ply;
         }
 
         Self {
             depth,
             flag,
+            generation: 0,
             value: v,
+            static_eval: static_eval.unwrap_or(NO_STATIC_EVAL),
             best_move,
         }
     }
 
-    pub fn get(&self, depth: i8, ply: i8, alpha: i16, beta: i16) -> (Option<i16>, ShortMove) {
+    pub fn get(&self, depth: i8, ply: i16, alpha: i16, beta: i16) -> (Option<i16>, ShortMove) {
         // We either do, or don't have a value to return from the TT.
         let mut value: Option<i16> = None;
 
         if self.depth >= depth {
             match self.flag {
-                HashFlag::Exact => {
-                    // Get the value from the data. We don't want to change
-                    // the value that is in the TT.
-                    let mut v = self.value;
-
-                    // Adjust for the number of plies from where this data
-                    // is probed, if we're dealing with checkmate. Same as
-                    // above: no comparative match expression.
-                    if v > CHECKMATE_THRESHOLD {
-                        v -= ply as i16;
-                    }
-
-                    if v < CHECKMATE_THRESHOLD {
-                        v += ply as i16;
-                    }
-
-                    // This is the value that will be returned.
-                    value = Some(v);
-                }
-                HashFlag::Alpha => {
-                    if self.value <= alpha {
-                        value = Some(alpha);
-                    }
-                }
-                HashFlag::Beta => {
-                    if self.value >= beta {
-                        value = Some(beta);
-                    }
-                }
+                HashFlag::Exact => value = Some(self.value_at_ply(ply)),
+                HashFlag::Alpha if self.value <= alpha => value = Some(alpha),
+                HashFlag::Beta if self.value >= beta => value = Some(beta),
                 _ => (),
             };
         }
         (value, self.best_move)
     }
-}
 
-/* ===== Entry ======================================================== */
+    // Get the value from the data, adjusted for the number of plies from
+    // where this data is probed, if we're dealing with checkmate. Same as
+    // above: no comparative match expression. (We don't want to change the
+    // value that is in the TT.)
+    fn value_at_ply(&self, ply: i16) -> i16 {
+        let mut v = self.value;
+
+        if v > CHECKMATE_THRESHOLD {
+            v -= ply;
+        }
+
+        if v < CHECKMATE_THRESHOLD {
+            v += ply;
+        }
+
+        v
+    }
+
+    pub fn best_move(&self) -> ShortMove {
+        self.best_move
+    }
+
+    pub fn static_eval(&self) -> Option<i16> {
+        (self.static_eval != NO_STATIC_EVAL).then_some(self.static_eval)
+    }
+
+    // Returns the depth and value of this entry, if the value is at least
+    // a lower bound of the real score. This is the case for Exact and Beta
+    // entries.
+    pub fn lower_bound(&self, ply: i16) -> Option<(i8, i16)> {
+        match self.flag {
+            HashFlag::Exact | HashFlag::Beta => Some((self.depth, self.value_at_ply(ply))),
+            _ => None,
+        }
+    }
+}
 
+// Data for the pawn hash table. The pawn structure score does not depend
+// on the search depth, so every entry is exact and has depth 0. The score
+// is from white's point of view.
 #[derive(Copy, Clone)]
-struct Entry<D> {
-    verification: u32,
-    data: D,
+pub struct PawnData {
+    score: i16,
+    open_files: u8,
+    half_open: [u8; Sides::BOTH],
 }
 
-impl<D: IHashData> Entry<D> {
-    pub fn new() -> Self {
+impl IHashData for PawnData {
+    fn new() -> Self {
+        Self {
+            score: 0,
+            open_files: 0,
+            half_open: [0; Sides::BOTH],
+        }
+    }
+
+    fn depth(&self) -> i8 {
+        0
+    }
+
+    fn is_exact(&self) -> bool {
+        true
+    }
+
+    // The pawn structure score never goes stale, so pawn entries don't
+    // keep a generation.
+    fn generation(&self) -> u8 {
+        0
+    }
+
+    fn set_generation(&mut self, _: u8) {}
+
+    fn pack(&self) -> (u32, u64) {
+        let data = self.score as u16 as u64
+            | (self.open_files as u64) << 16
+            | (self.half_open[Sides::WHITE] as u64) << 24
+            | (self.half_open[Sides::BLACK] as u64) << 32;
+
+        (0, data)
+    }
+
+    fn unpack(_: u32, data: u64) -> Self {
         Self {
-            verification: 0,
-            data: D::new(),
+            score: data as u16 as i16,
+            open_files: (data >> 16) as u8,
+            half_open: [(data >> 24) as u8, (data >> 32) as u8],
         }
     }
 }
 
+impl PawnData {
+    pub fn create(score: i16, open_files: u8, half_open: [u8; Sides::BOTH]) -> Self {
+        Self {
+            score,
+            open_files,
+            half_open,
+        }
+    }
+
+    pub fn score(&self) -> i16 {
+        self.score
+    }
+
+    // Files without any pawns, one bit per file, A-file is bit 0.
+    pub fn open_files(&self) -> u8 {
+        self.open_files
+    }
+
+    // Files without pawns of the given side, but with enemy pawns.
+    pub fn half_open(&self, side: Side) -> u8 {
+        self.half_open[side]
+    }
+}
+
+/* ===== Entry ======================================================== */
+
+// An entry consists of two words: the packed data, and the verification
+// with the extra bits, XOR-ed with a checksum of the data. All threads of
+// the search read and write entries at the same time. If two threads
+// write the same entry at once, a probe can get one word from each. The
+// XOR then garbles the verification, so the mixed up entry is never
+// found. Each bit of the data changes the upper half of the checksum, so
+// this also holds if the two entries only differ in depth or score.
+struct Entry {
+    key: AtomicU64,
+    data: AtomicU64,
+}
+
+impl Entry {
+    pub fn new<D: IHashData>() -> Self {
+        let entry = Self {
+            key: AtomicU64::new(0),
+            data: AtomicU64::new(0),
+        };
+        entry.write(0, &D::new());
+        entry
+    }
+
+    pub fn read<D: IHashData>(&self) -> (u32, D) {
+        let data = self.data.load(Ordering::Relaxed);
+        let key = self.key.load(Ordering::Relaxed) ^ Entry::checksum(data);
+        let verification = (key >> SHIFT_TO_LOWER) as u32;
+
+        (verification, D::unpack(key as u32, data))
+    }
+
+    pub fn write<D: IHashData>(&self, verification: u32, data: &D) {
+        let (extra, data) = data.pack();
+        let key = ((verification as u64) << SHIFT_TO_LOWER | extra as u64) ^ Entry::checksum(data);
+
+        self.key.store(key, Ordering::Relaxed);
+        self.data.store(data, Ordering::Relaxed);
+    }
+
+    // Multiplying by an odd constant is a one-to-one mapping that spreads
+    // each bit of the data over all the bits above it.
+    fn checksum(data: u64) -> u64 {
+        data.wrapping_mul(0x9E37_79B9_7F4A_7C15)
+    }
+}
+
 /* ===== Bucket ======================================================= */
 
-#[derive(Clone)]
 struct Bucket<D> {
-    bucket: [Entry<D>; ENTRIES_PER_BUCKET],
+    bucket: [Entry; ENTRIES_PER_BUCKET],
+    data: PhantomData<D>,
 }
 
-impl<D: IHashData + Copy> Bucket<D> {
+impl<D: IHashData> Bucket<D> {
     pub fn new() -> Self {
         Self {
-            bucket: [Entry::new(); ENTRIES_PER_BUCKET],
+            bucket: std::array::from_fn(|_| Entry::new::<D>()),
+            data: PhantomData,
         }
     }
 
-    // Store a position in the bucket. Replace the position with the stored
-    // lowest depth, as positions with higher depth are more valuable.
-    pub fn store(&mut self, verification: u32, data: D, used_entries: &mut usize) {
+    // Store a position in the bucket. Entries left behind by an earlier
+    // search are replaced first, as they are unlikely to be probed again.
+    // Within the same generation, replace the position with the stored
+    // lowest depth, as positions with higher depth are more valuable. If
+    // the TT is under pressure, an Exact entry is more valuable than an
+    // Alpha or Beta bound of the same depth, so the bound is replaced.
+    // Returns what the replacement policy did.
+    pub fn store(&self, verification: u32, data: D, pressure: bool) -> TTStats {
+        let entries: [(u32, D); ENTRIES_PER_BUCKET] =
+            std::array::from_fn(|i| self.bucket[i].read());
+        let generation = data.generation();
         let mut idx_lowest_depth = 0;
+        let mut exact_kept = false;
 
-        // Find the index of the entry with the lowest depth.
+        // Find the index of the entry with the lowest depth, preferring
+        // entries from older generations.
         for entry in 1..ENTRIES_PER_BUCKET {
-            if self.bucket[entry].data.depth() < data.depth() {
-                idx_lowest_depth = entry
+            let current = &entries[entry].1;
+            let lowest = &entries[idx_lowest_depth].1;
+            let current_is_old = current.generation() != generation;
+            let lowest_is_old = lowest.generation() != generation;
+
+            if current_is_old != lowest_is_old {
+                if current_is_old {
+                    idx_lowest_depth = entry;
+                    exact_kept = false;
+                }
+            } else if current.depth() < lowest.depth() {
+                idx_lowest_depth = entry;
+                exact_kept = false;
+            } else if pressure
+                && current.depth() == lowest.depth()
+                && lowest.is_exact()
+                && !current.is_exact()
+            {
+                idx_lowest_depth = entry;
+                exact_kept = true;
             }
         }
 
         // If the verifiaction was 0, this entry in the bucket was never
         // used before. Count the use of this entry.
-        if self.bucket[idx_lowest_depth].verification == 0 {
-            *used_entries += 1;
+        let mut stats = TTStats::default();
+        let (replaced_verification, replaced) = &entries[idx_lowest_depth];
+        if *replaced_verification == 0 {
+            stats.used_entries += 1;
+        } else if replaced.generation() != generation {
+            stats.old_replaced += 1;
+        }
+
+        // Keep track of what the replacement policy did.
+        stats.stores += 1;
+        if pressure {
+            stats.pressure_stores += 1;
+        }
+        if exact_kept {
+            stats.exact_kept += 1;
         }
 
         // Store.
-        self.bucket[idx_lowest_depth] = Entry { verification, data }
+        self.bucket[idx_lowest_depth].write(verification, &data);
+
+        stats
     }
 
     // Find a position in the bucket, where both the stored verification and
     // depth match the requested verification and depth.
-    pub fn find(&self, verification: u32) -> Option<&D> {
+    pub fn find(&self, verification: u32) -> Option<D> {
         for e in self.bucket.iter() {
-            if e.verification == verification {
-                return Some(&e.data);
+            let (v, data) = e.read();
+            if v == verification {
+                return Some(data);
             }
         }
         None
     }
 }
 
+/* ===== Stats ====================================================== */
+
+// Counters to verify the TT's replacement policy. The number of used
+// entries is kept over the TT's lifetime; the others can be reset. Each
+// search thread counts its own stores, and adds them to the TT's counters
+// when it is done, so the threads don't fight over them while searching.
+#[derive(Copy, Clone, Default, PartialEq)]
+pub struct TTStats {
+    pub used_entries: usize,    // Entries that are in use
+    pub stores: usize,          // Total number of stores
+    pub pressure_stores: usize, // Stores while the TT was under pressure
+    pub exact_kept: usize,      // Exact entries saved from replacement
+    pub old_replaced: usize,    // Entries of earlier searches replaced
+}
+
+impl AddAssign for TTStats {
+    fn add_assign(&mut self, other: Self) {
+        self.used_entries += other.used_entries;
+        self.stores += other.stores;
+        self.pressure_stores += other.pressure_stores;
+        self.exact_kept += other.exact_kept;
+        self.old_replaced += other.old_replaced;
+    }
+}
+
+/* ===== Prefetch ================================================== */
+
+// Tells the CPU which bucket a position is going to be probed in, so it
+// can start loading the bucket into its cache. This way the search
+// doesn't have to wait for main memory when the probe happens.
+//
+// The TT can be replaced while a search is running: a GUI may set the
+// Hash option at any time, and clearing the TT for a new game creates a
+// new table as well. The prefetcher then still points at the old table,
+// which may already be freed. That is harmless, because a prefetch is
+// only a hint that never faults; the search only prefetches useless
+// addresses until it ends, and the next search gets a new prefetcher.
+// Other CPUs than x86-64 don't prefetch, and have nothing to store.
+#[derive(Copy, Clone)]
+pub struct TTPrefetcher {
+    #[cfg(target_arch = "x86_64")]
+    address: usize,
+    #[cfg(target_arch = "x86_64")]
+    bucket_size: usize,
+    #[cfg(target_arch = "x86_64")]
+    total_buckets: usize,
+}
+
+impl TTPrefetcher {
+    #[inline(always)]
+    pub fn prefetch(&self, zobrist_key: ZobristKey) {
+        #[cfg(target_arch = "x86_64")]
+        if self.total_buckets > 0 {
+            use std::arch::x86_64::{_mm_prefetch, _MM_HINT_T0};
+
+            let index = bucket_index(zobrist_key, self.total_buckets);
+            let bucket = self.address + index * self.bucket_size;
+            // SAFETY: SSE is part of the x86-64 baseline, and a prefetch
+            // does not access memory.
+            unsafe { _mm_prefetch::<_MM_HINT_T0>(bucket as *const i8) };
+        }
+
+        #[cfg(not(target_arch = "x86_64"))]
+        let _ = zobrist_key;
+    }
+}
+
+// Calculate the index (bucket) where the data is going to be stored.
+// Use only the upper half of the Zobrist key for this, so the lower
+// half can be used to calculate a verification.
+#[inline(always)]
+fn bucket_index(zobrist_key: ZobristKey, total_buckets: usize) -> usize {
+    let key = (zobrist_key & HIGH_FOUR_BYTES) >> SHIFT_TO_LOWER;
+    let total = total_buckets as u64;
+
+    (key % total) as usize
+}
+
 /* ===== TT =================================================== */
 
-// Transposition Table
+// Transposition Table. Probing and storing don't need a mutable TT, so
+// the threads of a search can share it. Only resizing, clearing and
+// starting a new search do.
 pub struct TT<D> {
     tt: Vec<Bucket<D>>,
     megabytes: usize,
-    used_entries: usize,
+    used_entries: AtomicUsize,
+    stats: Mutex<TTStats>,
+    generation: u8,
     total_buckets: usize,
     total_entries: usize,
 }
@@ -252,9 +559,11 @@ impl<D: IHashData + Copy + Clone> TT<D> {
         let (total_buckets, total_entries) = Self::calculate_init_values(megabytes);
 
         Self {
-            tt: vec![Bucket::<D>::new(); total_buckets],
+            tt: Self::allocate(total_buckets),
             megabytes,
-            used_entries: 0,
+            used_entries: AtomicUsize::new(0),
+            stats: Mutex::new(TTStats::default()),
+            generation: 0,
             total_buckets,
             total_entries,
         }
@@ -263,30 +572,53 @@ impl<D: IHashData + Copy + Clone> TT<D> {
     // Resizes the TT by replacing the current TT with a
     // new one. (We don't use Vec's resize function, because it clones
     // elements. This can be problematic if TT sizes push the
-    // computer's memory limits.)
+    // computer's memory limits.) The old TT is freed first, so both never
+    // take up memory at the same time. The new TT starts a new generation,
+    // so a search that is still running can see that its results are no
+    // longer wanted.
     pub fn resize(&mut self, megabytes: usize) {
         let (total_buckets, total_entries) = TT::<D>::calculate_init_values(megabytes);
 
-        self.tt = vec![Bucket::<D>::new(); total_buckets];
+        self.tt = Vec::new();
+        self.tt = Self::allocate(total_buckets);
         self.megabytes = megabytes;
-        self.used_entries = 0;
+        self.used_entries = AtomicUsize::new(0);
+        self.stats = Mutex::new(TTStats::default());
+        self.generation = self.generation.wrapping_add(1);
         self.total_buckets = total_buckets;
         self.total_entries = total_entries;
     }
 
+    // The size in megabytes a TT needs to hold the given number of
+    // entries, rounded up.
+    pub fn megabytes_for(entries: usize) -> usize {
+        let bucket_size = std::mem::size_of::<Entry>() * ENTRIES_PER_BUCKET;
+        let buckets = entries.div_ceil(ENTRIES_PER_BUCKET);
+
+        (buckets * bucket_size).div_ceil(MEGABYTE)
+    }
+
     // Insert a position at the calculated index, by storing it in the
-    // index's bucket.
-    pub fn insert(&mut self, zobrist_key: ZobristKey, data: D) {
+    // index's bucket. Returns what the replacement policy did, so the
+    // caller can count it.
+    pub fn insert(&self, zobrist_key: ZobristKey, mut data: D) -> TTStats {
         if self.megabytes > 0 {
+            data.set_generation(self.generation);
             let index = self.calculate_index(zobrist_key);
             let verification = self.calculate_verification(zobrist_key);
-            self.tt[index].store(verification, data, &mut self.used_entries);
+            let pressure = self.is_under_pressure();
+            let stats = self.tt[index].store(verification, data, pressure);
+            self.used_entries
+                .fetch_add(stats.used_entries, Ordering::Relaxed);
+            stats
+        } else {
+            TTStats::default()
         }
     }
 
     // Probe the TT by both verification and depth. Both have to
     // match for the position to be the correct one we're looking for.
-    pub fn probe(&self, zobrist_key: ZobristKey) -> Option<&D> {
+    pub fn probe(&self, zobrist_key: ZobristKey) -> Option<D> {
         if self.megabytes > 0 {
             let index = self.calculate_index(zobrist_key);
             let verification = self.calculate_verification(zobrist_key);
@@ -297,6 +629,20 @@ impl<D: IHashData + Copy + Clone> TT<D> {
         }
     }
 
+    // Start a new generation. Entries stored before this are from an
+    // earlier search, and will be the first to be replaced. The counter
+    // wraps around; after 256 searches, the oldest entries are simply
+    // considered to be current again.
+    pub fn new_search(&mut self) {
+        self.generation = self.generation.wrapping_add(1);
+    }
+
+    // The current generation. A search remembers the generation it
+    // started in, and only stores results while it is still current.
+    pub fn generation(&self) -> u8 {
+        self.generation
+    }
+
     // Clear TT by replacing it with a new one.
     pub fn clear(&mut self) {
         self.resize(self.megabytes);
@@ -304,25 +650,60 @@ impl<D: IHashData + Copy + Clone> TT<D> {
 
     // Provides TT usage in permille (1 per 1000, as oppposed to percent,
     // which is 1 per 100.)
+    // Two threads can claim the same empty entry at the same time, so the
+    // count of used entries can be a little too high.
     pub fn hash_full(&self) -> u16 {
         if self.megabytes > 0 {
-            ((self.used_entries as f64 / self.total_entries as f64) * 1000f64).floor() as u16
+            let used = self.used_entries.load(Ordering::Relaxed);
+            let permille = ((used as f64 / self.total_entries as f64) * 1000f64).floor() as u16;
+            permille.min(1000)
         } else {
             0
         }
     }
+
+    // Returns the replacement counters collected since the last reset.
+    pub fn stats(&self) -> TTStats {
+        TTStats {
+            used_entries: self.used_entries.load(Ordering::Relaxed),
+            ..*self.stats.lock().expect(ErrFatal::LOCK)
+        }
+    }
+
+    // Adds the replacement counters of a search thread. (Its count of
+    // used entries was already added while it was storing.)
+    pub fn add_stats(&self, stats: &TTStats) {
+        let mut counters = self.stats.lock().expect(ErrFatal::LOCK);
+        *counters += TTStats {
+            used_entries: 0,
+            ..*stats
+        };
+    }
+
+    // Returns the location of the buckets, so they can be prefetched
+    // without locking the TT.
+    pub fn prefetcher(&self) -> TTPrefetcher {
+        TTPrefetcher {
+            #[cfg(target_arch = "x86_64")]
+            address: self.tt.as_ptr() as usize,
+            #[cfg(target_arch = "x86_64")]
+            bucket_size: std::mem::size_of::<Bucket<D>>(),
+            #[cfg(target_arch = "x86_64")]
+            total_buckets: self.total_buckets,
+        }
+    }
+
+    // Reset the replacement counters, but keep counting used entries.
+    pub fn reset_stats(&self) {
+        *self.stats.lock().expect(ErrFatal::LOCK) = TTStats::default();
+    }
 }
 
 // Private functions
 impl<D: IHashData + Copy + Clone> TT<D> {
-    // Calculate the index (bucket) where the data is going to be stored.
-    // Use only the upper half of the Zobrist key for this, so the lower
-    // half can be used to calculate a verification.
+    // The bucket where the data for this position is going to be stored.
     fn calculate_index(&self, zobrist_key: ZobristKey) -> usize {
-        let key = (zobrist_key & HIGH_FOUR_BYTES) >> SHIFT_TO_LOWER;
-        let total = self.total_buckets as u64;
-
-        (key % total) as usize
+        bucket_index(zobrist_key, self.total_buckets)
     }
 
     // Many positions will end up at the same index, and thus in the same
@@ -332,10 +713,23 @@ impl<D: IHashData + Copy + Clone> TT<D> {
         (zobrist_key & LOW_FOUR_BYTES) as u32
     }
 
+    // The TT is under pressure if it is so full that most stores are
+    // going to replace an entry that is still useful. This is the same
+    // calculation as hash_full(), but without the floating point math,
+    // as it runs on every insert.
+    fn is_under_pressure(&self) -> bool {
+        self.used_entries.load(Ordering::Relaxed) * 1000 >= self.total_entries * HASH_PRESSURE
+    }
+
+    // The buckets can't be cloned, so they are created one by one.
+    fn allocate(total_buckets: usize) -> Vec<Bucket<D>> {
+        (0..total_buckets).map(|_| Bucket::new()).collect()
+    }
+
     // This function calculates the values for total_buckets and
     // total_entries. These depend on the requested TT size.
     fn calculate_init_values(megabytes: usize) -> (usize, usize) {
-        let entry_size = std::mem::size_of::<Entry<D>>();
+        let entry_size = std::mem::size_of::<Entry>();
         let bucket_size = entry_size * ENTRIES_PER_BUCKET;
         let total_buckets = MEGABYTE / bucket_size * megabytes;
         let total_entries = total_buckets * ENTRIES_PER_BUCKET;
@@ -343,3 +737,74 @@ impl<D: IHashData + Copy + Clone> TT<D> {
         (total_buckets, total_entries)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entries_survive_packing() {
+        let tt = TT::<SearchData>::new(1);
+        let key: ZobristKey = 0x1234_5678_9ABC_DEF0;
+        let data = SearchData::create(
+            7,
+            0,
+            HashFlag::Beta,
+            -312,
+            Some(45),
+            ShortMove::new(0xABCDEF),
+        );
+        tt.insert(key, data);
+
+        let found = tt.probe(key).expect("entry was stored");
+        assert!(found.depth() == 7 && !found.is_exact());
+        assert!(found.lower_bound(0) == Some((7, -312)));
+        assert!(found.static_eval() == Some(45));
+        assert!(found.best_move() == ShortMove::new(0xABCDEF));
+
+        let pawns = PawnData::create(-17, 0b1000_0001, [0b10, 0b100]);
+        let (extra, word) = pawns.pack();
+        let unpacked = PawnData::unpack(extra, word);
+        assert!(unpacked.score() == -17 && unpacked.open_files() == 0b1000_0001);
+        assert!(unpacked.half_open(Sides::WHITE) == 0b10);
+        assert!(unpacked.half_open(Sides::BLACK) == 0b100);
+    }
+
+    // Two threads writing the same entry at once can leave the key of one
+    // store next to the data of the other. Such an entry must not be found.
+    #[test]
+    fn torn_entries_are_not_found() {
+        let key: ZobristKey = 0x0000_0001_0000_0001;
+        let first = SearchData::create(3, 0, HashFlag::Exact, 10, None, ShortMove::new(1));
+        let second = SearchData::create(9, 0, HashFlag::Alpha, -80, None, ShortMove::new(2));
+
+        let entry = Entry::new::<SearchData>();
+        entry.write(1, &first);
+        let torn_data = Entry::new::<SearchData>();
+        torn_data.write(1, &second);
+        entry
+            .data
+            .store(torn_data.data.load(Ordering::Relaxed), Ordering::Relaxed);
+
+        let (verification, _) = entry.read::<SearchData>();
+        assert!(verification != (key & LOW_FOUR_BYTES) as u32);
+    }
+
+    #[test]
+    fn torn_entries_with_the_same_move_are_not_found() {
+        let key: ZobristKey = 0x0000_0001_0000_0001;
+        let first = SearchData::create(3, 0, HashFlag::Exact, 10, None, ShortMove::new(1));
+        let second = SearchData::create(9, 0, HashFlag::Beta, 10, None, ShortMove::new(1));
+
+        let entry = Entry::new::<SearchData>();
+        entry.write(1, &first);
+        let torn_data = Entry::new::<SearchData>();
+        torn_data.write(1, &second);
+        entry
+            .data
+            .store(torn_data.data.load(Ordering::Relaxed), Ordering::Relaxed);
+
+        let (verification, _) = entry.read::<SearchData>();
+        assert!(verification != (key & LOW_FOUR_BYTES) as u32);
+    }
+}
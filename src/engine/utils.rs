@@ -21,19 +21,33 @@ You should have received a copy of the GNU General Public License along
 with this program.  If not, see <http://www.gnu.org/licenses/>.
 ======================================================================= */
 
-use super::{defs::ErrFatal, Engine};
+use super::{
+    defs::{
+        EngineOptionDefaults, EngineOptionName, EngineState, ErrFatal, ErrNormal, SearchData, TT,
+    },
+    Engine,
+};
 use crate::{
     board::Board,
-    defs::{EngineRunResult, FEN_KIWIPETE_POSITION},
-    misc::parse,
-    misc::parse::PotentialMove,
+    comm::CommControl,
+    defs::{Bitboard, EngineRunResult, FEN_KIWIPETE_POSITION, FEN_START_POSITION},
+    evaluation::{param_file, params::EvalParams, trace},
+    misc::system,
+    misc::{
+        bench, bitboard_expr, parse,
+        perft::{self, PerftCache},
+        pgn,
+    },
     movegen::{
-        defs::{Move, MoveList, MoveType},
+        defs::{Move, MoveList, MoveType, ShortMove},
         MoveGenerator,
     },
+    search::{
+        defs::{GameTime, SearchControl, SearchMode, SearchMoves, SearchParams, StrengthLimit},
+        Search,
+    },
 };
-use if_chain::if_chain;
-use std::sync::Mutex;
+use std::{fs, sync::Arc, time::Instant};
 
 impl Engine {
     // This function sets up a position using a given FEN-string.
@@ -49,55 +63,731 @@ impl Engine {
             .lock()
             .expect(ErrFatal::LOCK)
             .fen_read(Some(fen))?;
+        self.game = pgn::PgnGame::new(Some(fen));
 
         Ok(())
     }
 
-    // This function executes a move on the internal board, if it legal to
-    // do so in the given position.
-    pub fn execute_move(&mut self, m: String) -> bool {
-        // Prepare shorthand variables.
-        let empty = (0usize, 0usize, 0usize);
-        let potential_move = parse::algebraic_move_to_number(&m[..]).unwrap_or(empty);
-        let is_pseudo_legal = self.pseudo_legal(potential_move, &self.board, &self.mg);
-        let mut is_legal = false;
+    // Evaluate with the parameters from the given file, or with the
+    // built-in parameters if no file is given. If the file can't be used,
+    // the built-in parameters are used as well, and the error returned.
+    // Results of earlier searches were found with the old parameters, so
+    // they are forgotten. Keeping the TT is optional: its scores are out
+    // of date, but its best moves are still useful for move ordering. The
+    // search thread clears the pawn hash itself, when it sees that the
+    // parameters have changed.
+    pub fn load_eval_file(&mut self, path: &str) -> Result<(), String> {
+        let result = if path.is_empty() {
+            Ok(EvalParams::DEFAULT)
+        } else {
+            param_file::load(path)
+        };
+
+        self.settings.eval_file = match result {
+            Ok(_) => path.to_string(),
+            Err(_) => String::new(),
+        };
 
-        if let Ok(ips) = is_pseudo_legal {
-            is_legal = self.board.lock().expect(ErrFatal::LOCK).make(ips, &self.mg);
+        let params = result.as_ref().unwrap_or(&EvalParams::DEFAULT).clone();
+        self.board
+            .lock()
+            .expect(ErrFatal::LOCK)
+            .set_params(Arc::new(params));
+
+        self.analysis.clear();
+        if self.settings.clear_hash_on_eval_change {
+            self.tt_search.write().expect(ErrFatal::LOCK).clear();
         }
-        is_legal
+
+        result.map(|_| ())
     }
 
-    // After the engine receives an incoming move, it checks if this move
-    // is actually in the list of pseudo-legal moves for this position.
-    pub fn pseudo_legal(
-        &self,
-        m: PotentialMove,
-        board: &Mutex<Board>,
-        mg: &MoveGenerator,
-    ) -> Result<Move, ()> {
-        let mut result = Err(());
+    // Log all input and output to the given file from now on, or stop
+    // logging if the path is empty.
+    pub fn open_log_file(&mut self, path: &str) -> Result<(), String> {
+        let result = self.protocol_log.open(path);
+        self.settings.log_file = match result {
+            Ok(()) => path.to_string(),
+            Err(_) => String::new(),
+        };
 
-        // Get the pseudo-legal move list for this position.
-        let mut ml = MoveList::new();
-        let mtx_board = board.lock().expect(ErrFatal::LOCK);
-        mg.generate_moves(&mtx_board, &mut ml, MoveType::All);
-        std::mem::drop(mtx_board);
-
-        // Determine if the potential move is pseudo-legal. make() wil
-        // determine final legality when executing the move.
-        for i in 0..ml.len() {
-            let current = ml.get_move(i);
-            if_chain! {
-                if m.0 == current.from();
-                if m.1 == current.to();
-                if m.2 == current.promoted();
-                then {
-                    result = Ok(current);
-                    break;
+        result
+    }
+
+    // Send the game played on the board as PGN, one line at a time.
+    pub fn print_pgn(&self) {
+        let mut game = self.game.clone();
+        game.classify(&self.mg);
+
+        let lines = match game.write(&self.mg) {
+            Ok(pgn) => pgn.lines().map(String::from).collect(),
+            Err(e) => vec![format!("PGN: {e}")],
+        };
+
+        for line in lines {
+            self.comm.send(CommControl::InfoString(line));
+        }
+    }
+
+    // Explain the move the engine played after the last search.
+    pub fn explain(&self) {
+        if self.state != EngineState::Waiting {
+            self.comm
+                .send(CommControl::InfoString(ErrNormal::NOT_WAITING.to_string()));
+            return;
+        }
+
+        match self.search_log.explain(&self.mg) {
+            Some(lines) => {
+                for line in lines {
+                    self.comm.send(CommControl::InfoString(line));
                 }
             }
+            None => self
+                .comm
+                .send(CommControl::InfoString(ErrNormal::NO_SEARCH.to_string())),
+        }
+    }
+
+    // Evaluate one or more comma separated bitboard expressions in the
+    // current position and have the Comm module print them.
+    pub fn show_bitboard(&self, expressions: &str) {
+        let board = self.board.lock().expect(ErrFatal::LOCK);
+        let layers: Result<Vec<(String, Bitboard)>, String> = expressions
+            .split(',')
+            .map(str::trim)
+            .filter(|expr| !expr.is_empty())
+            .map(|expr| {
+                bitboard_expr::evaluate(expr, &board, &self.mg)
+                    .map(|bb| (expr.to_string(), bb))
+                    .map_err(|e| format!("showbb {expr}: {e}"))
+            })
+            .collect();
+        std::mem::drop(board);
+
+        match layers {
+            Ok(layers) if layers.is_empty() => {
+                let msg = String::from("showbb: no expression given");
+                self.comm.send(CommControl::InfoString(msg));
+            }
+            Ok(layers) => self.comm.send(CommControl::PrintBitboards(layers)),
+            Err(e) => self.comm.send(CommControl::InfoString(e)),
+        }
+    }
+
+    // Evaluate two positions and have the Comm module print their
+    // evaluation terms next to each other, ordered by how much they
+    // differ. With only one FEN-string, the first position is the current
+    // one.
+    pub fn compare(&self, fens: &str) {
+        let fens = Engine::split_fens(fens);
+        let mut boards = Vec::new();
+
+        if fens.len() == 1 {
+            boards.push(self.board.lock().expect(ErrFatal::LOCK).clone());
+        }
+
+        for fen in fens.iter() {
+            let mut board = self.board.lock().expect(ErrFatal::LOCK).clone();
+            if board.fen_read(Some(fen)).is_err() || !board.has_valid_kings() {
+                let msg = format!("compare: invalid FEN-string '{fen}'");
+                self.comm.send(CommControl::InfoString(msg));
+                return;
+            }
+            boards.push(board);
+        }
+
+        if boards.len() != 2 {
+            let msg = String::from("compare: give one or two FEN-strings");
+            self.comm.send(CommControl::InfoString(msg));
+            return;
+        }
+
+        let first = trace::trace(&boards[0]);
+        let second = trace::trace(&boards[1]);
+
+        // Both traces have the same terms, in the same order. Terms that
+        // are zero in both positions are left out.
+        let mut rows: Vec<(&str, i16, i16)> = first
+            .terms
+            .iter()
+            .zip(second.terms.iter())
+            .filter(|((_, a), (_, b))| *a != 0 || *b != 0)
+            .map(|((name, a), (_, b))| (name.as_str(), *a, *b))
+            .collect();
+        rows.sort_by_key(|(_, a, b)| std::cmp::Reverse((b - a).abs()));
+
+        let mut lines = vec![
+            String::from("Evaluation terms, from White's point of view"),
+            format!(
+                "{:<20} {:>8} {:>8} {:>8}",
+                "Term", "First", "Second", "Diff"
+            ),
+        ];
+        for (name, a, b) in rows {
+            lines.push(format!("{name:<20} {a:>8} {b:>8} {:>8}", b - a));
+        }
+        let (a, b) = (first.total(), second.total());
+        lines.push(format!("{:<20} {a:>8} {b:>8} {:>8}", "Total", b - a));
+        for (nr, t) in [(1, &first), (2, &second)] {
+            if t.material_draw {
+                lines.push(format!("Position {nr} is a material draw"));
+            } else if t.knn_v_k {
+                lines.push(format!("Position {nr} is scaled down: KNN v K"));
+            }
+        }
+
+        for line in lines {
+            self.comm.send(CommControl::InfoString(line));
+        }
+    }
+
+    // Split a string holding FEN-strings into the separate FEN-strings.
+    // Each FEN-string starts with the piece placement, the only part with
+    // a '/' in it.
+    fn split_fens(input: &str) -> Vec<String> {
+        let mut fens: Vec<String> = Vec::new();
+
+        for part in input.split_whitespace() {
+            match fens.last_mut() {
+                Some(fen) if !part.contains('/') => {
+                    fen.push(' ');
+                    fen.push_str(part);
+                }
+                _ => fens.push(part.to_string()),
+            }
+        }
+
+        fens
+    }
+
+    // The names and current values of all options, including the ones
+    // for tuning that are not announced to the GUI.
+    pub fn option_values(&self) -> Vec<(String, String)> {
+        let s = &self.settings;
+        let eval_file = if s.eval_file.is_empty() {
+            EngineOptionDefaults::EVAL_FILE_DEFAULT
+        } else {
+            &s.eval_file
+        };
+        let stats_file = if s.stats_file.is_empty() {
+            EngineOptionDefaults::STATS_FILE_DEFAULT
+        } else {
+            &s.stats_file
+        };
+        let log_file = if s.log_file.is_empty() {
+            EngineOptionDefaults::DEBUG_LOG_FILE_DEFAULT
+        } else {
+            &s.log_file
+        };
+
+        let mut values = vec![
+            (EngineOptionName::HASH, s.tt_size.to_string()),
+            (EngineOptionName::PAWN_HASH, s.pawn_hash.to_string()),
+            (
+                EngineOptionName::CLEAR_HASH_ON_NEW_GAME,
+                s.clear_hash_on_new_game.to_string(),
+            ),
+            (EngineOptionName::MULTI_PV, s.multi_pv.to_string()),
+            (EngineOptionName::PONDER, s.ponder.to_string()),
+            (EngineOptionName::THREADS, s.threads.to_string()),
+            (
+                EngineOptionName::PROMOTION_RESTRICTION,
+                s.promotions.value(),
+            ),
+            (
+                EngineOptionName::PROMOTION_RESTRICTION_SEARCH,
+                s.promotions.in_search.to_string(),
+            ),
+            (
+                EngineOptionName::ROOT_MOVE_NODES,
+                s.root_move_nodes.to_string(),
+            ),
+            (EngineOptionName::MAX_DEPTH, s.max_depth.to_string()),
+            (EngineOptionName::EVAL_FILE, eval_file.to_string()),
+            (
+                EngineOptionName::CLEAR_HASH_ON_EVAL_CHANGE,
+                s.clear_hash_on_eval_change.to_string(),
+            ),
+            (EngineOptionName::STATS_FILE, stats_file.to_string()),
+            (EngineOptionName::DEBUG_LOG_FILE, log_file.to_string()),
+            (EngineOptionName::EVAL_PLAYER, s.eval_player.to_string()),
+            (EngineOptionName::WARM_UP, s.warm_up.to_string()),
+            (EngineOptionName::ANALYSE_MODE, s.analyse_mode.to_string()),
+            (
+                EngineOptionName::SCORES_FROM_WHITE,
+                s.scores_from_white.to_string(),
+            ),
+            (
+                EngineOptionName::SHOW_REFUTATIONS,
+                s.show_refutations.to_string(),
+            ),
+            (
+                EngineOptionName::SHOW_CURR_LINE,
+                s.show_curr_line.to_string(),
+            ),
+            (
+                EngineOptionName::QSEARCH_CHECKS,
+                s.qsearch_checks.to_string(),
+            ),
+            (
+                EngineOptionName::SHOW_SHARPNESS,
+                s.show_sharpness.to_string(),
+            ),
+            (EngineOptionName::LOW_MEMORY, s.low_memory.to_string()),
+            (EngineOptionName::AUTO_HASH, s.auto_hash.to_string()),
+            (
+                EngineOptionName::LIMIT_STRENGTH,
+                s.limit_strength.to_string(),
+            ),
+            (EngineOptionName::ELO, s.elo.to_string()),
+        ];
+
+        let tuning = s
+            .null_move
+            .values()
+            .into_iter()
+            .chain(s.razoring.values())
+            .chain(s.opening_time.values());
+        values.extend(tuning.map(|(name, v)| (name, v.to_string())));
+
+        values
+            .into_iter()
+            .map(|(name, value)| (name.to_string(), value))
+            .collect()
+    }
+
+    // Tell the user which value an option has, now that it was set. This
+    // is the value the engine actually uses, after clamping it to the
+    // option's bounds. Option names are not case sensitive.
+    pub fn acknowledge_option(&self, option: &EngineOptionName) {
+        let name = match option.name() {
+            Some(name) => name.to_lowercase(),
+            None => return,
+        };
+
+        let values = self.option_values();
+        if let Some((name, value)) = values.iter().find(|(n, _)| n.to_lowercase() == name) {
+            self.comm
+                .send(CommControl::OptionSet(name.clone(), value.clone()));
+        }
+    }
+
+    // Send the current value of every option.
+    pub fn print_options(&self) {
+        for (name, value) in self.option_values() {
+            let msg = format!("{name:<32} {value}");
+            self.comm.send(CommControl::InfoString(msg));
+        }
+    }
+
+    // Send the leaf node count for each root move to the given depth, and
+    // the total. The root moves are counted on the engine's threads.
+    pub fn perft_divide(&self, depth: i8) {
+        if depth < 1 {
+            self.comm
+                .send(CommControl::InfoString(ErrNormal::PERFT_DEPTH.to_string()));
+            return;
+        }
+
+        if self.state != EngineState::Waiting {
+            self.comm
+                .send(CommControl::InfoString(ErrNormal::NOT_WAITING.to_string()));
+            return;
+        }
+
+        let board = self.board.lock().expect(ErrFatal::LOCK).clone();
+        let now = Instant::now();
+        let mut caches = PerftCache::per_thread(self.perft_hash, self.settings.threads_in_use());
+        let divided = perft::divide(&board, depth, &self.mg, &mut caches);
+        let elapsed = now.elapsed().as_millis();
+
+        for (m, leaf_nodes) in divided.iter() {
+            let msg = format!("{}: {leaf_nodes}", m.as_string());
+            self.comm.send(CommControl::InfoString(msg));
+        }
+
+        let total: u64 = divided.iter().map(|(_, leaf_nodes)| leaf_nodes).sum();
+        let msg = format!("Perft {depth}: {total} ({elapsed} ms)");
+        self.comm.send(CommControl::InfoString(msg));
+    }
+
+    // Run the benchmark. It uses its own board and TT, so the engine's
+    // position and TT are not changed.
+    pub fn bench(&self, depth: i8) {
+        if self.state != EngineState::Waiting {
+            self.comm
+                .send(CommControl::InfoString(ErrNormal::NOT_WAITING.to_string()));
+            return;
+        }
+
+        let mg = Arc::clone(&self.mg);
+        let result = bench::run(depth.max(1), mg, &self.stats_csv, |line| {
+            self.comm.send(CommControl::InfoString(line))
+        });
+
+        for line in result.summary() {
+            self.comm.send(CommControl::InfoString(line));
+        }
+    }
+
+    // Set up the final position of the first game in the PGN file. The
+    // board is not changed if the game can't be read.
+    pub fn load_pgn(&mut self, path: &str) -> Result<(), String> {
+        let text = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let game = pgn::read(&text, &self.mg)
+            .into_iter()
+            .next()
+            .ok_or(String::from(ErrNormal::NO_GAME))??;
+
+        let moves: Vec<String> = game.moves.iter().map(|pm| pm.m.as_string()).collect();
+        let moves: Vec<&str> = moves.iter().map(|m| &m[..]).collect();
+        let fen = game.fen().unwrap_or(FEN_START_POSITION);
+        let result =
+            self.board
+                .lock()
+                .expect(ErrFatal::LOCK)
+                .replay_moves(Some(fen), &moves, &self.mg);
+
+        match result {
+            Ok(()) => {
+                self.game = game;
+                Ok(())
+            }
+            Err(_) => Err(String::from(ErrNormal::FEN_FAILED)),
+        }
+    }
+
+    // Tell the Comm module from which side's point of view the scores
+    // are to be reported.
+    pub fn update_score_perspective(&self) {
+        let from_white = self.settings.analyse_mode && self.settings.scores_from_white;
+        self.comm.send(CommControl::ScoresFromWhite(from_white));
+    }
+
+    // The number of moves played since the start position, if the game
+    // began there. A position set up from any other FEN-string has an
+    // unknown history, even if its move number says it is move 1.
+    pub fn moves_since_start(&self) -> Option<usize> {
+        let plies = self.board.lock().expect(ErrFatal::LOCK).history.len();
+        self.game.fen().is_none().then_some(plies / 2)
+    }
+
+    // Converts the moves given by "go searchmoves" into a list the search
+    // can use. Moves that are not legal in this position are reported and
+    // left out.
+    pub fn search_moves(&self, moves: &[String]) -> SearchMoves {
+        let mut search_moves = SearchMoves::new();
+        let board = self.board.lock().expect(ErrFatal::LOCK).clone();
+        let legal_moves = Engine::legal_moves(&board, &self.mg);
+
+        for m in moves.iter() {
+            let potential_move = parse::algebraic_move_to_number(m);
+            let found = legal_moves.iter().find(|lm| {
+                let lm = Move::new(lm.get_move() as usize);
+                potential_move == Ok((lm.from(), lm.to(), lm.promoted()))
+            });
+
+            match found {
+                Some(lm) => search_moves.push(*lm),
+                None => {
+                    let msg = format!("{}: {}", m, ErrNormal::NOT_LEGAL);
+                    self.comm.send(CommControl::InfoString(msg));
+                }
+            }
+        }
+
+        search_moves
+    }
+
+    // Start a search in the current position. If this position was
+    // analyzed recently, the lines found back then are sent first, unless
+    // the search is for a hint, which only shows the move. The eval player
+    // doesn't search, but moves immediately; when pondering, it waits for
+    // "ponderhit" or "stop". Analysis and mate searches are always done at
+    // full strength.
+    pub fn start_search(&mut self, mut sp: SearchParams) {
+        self.check_position();
+        self.search_root = self.board.lock().expect(ErrFatal::LOCK).clone();
+
+        if self.settings.eval_player {
+            if !sp.ponder {
+                self.eval_player_move();
+            }
+            return;
+        }
+
+        let is_analysis = matches!(sp.search_mode, SearchMode::Infinite | SearchMode::Mate(_));
+        if self.settings.limit_strength && !is_analysis {
+            let limit = StrengthLimit::new(self.settings.elo);
+            sp.depth = sp.depth.min(limit.depth());
+            sp.threads = 1;
+            sp.strength = Some(limit);
+        }
+
+        // Iterative deepening never goes deeper than the user allows.
+        sp.depth = sp.depth.min(self.settings.max_depth);
+
+        let board = self.board.lock().expect(ErrFatal::LOCK);
+        let key = board.game_state.zobrist_key;
+        self.search_log.begin(&board);
+        self.stats_csv.begin(&board.fen_write());
+        std::mem::drop(board);
+
+        // Lines of a search restricted to some root moves, or of a weakened
+        // engine, don't describe the position as a whole. They are neither
+        // replayed from the cache nor stored in it.
+        if sp.search_moves.is_empty() && sp.strength.is_none() {
+            let cached = self.analysis.begin(key, sp.multi_pv);
+            if !self.hint {
+                for summary in cached {
+                    self.comm.send(CommControl::SearchSummary(summary));
+                }
+            }
+        } else {
+            self.analysis.skip();
+        }
+
+        self.search.send(SearchControl::Start(Box::new(sp)));
+    }
+
+    // Make sure there is a position the search can handle. If the GUI
+    // sends "go" without ever sending "position", the start position is
+    // searched, with a warning. A board without a king for each side, or
+    // with the side not to move in check, can't be searched at all, so the
+    // start position is set up instead.
+    fn check_position(&mut self) {
+        let mut board = self.board.lock().expect(ErrFatal::LOCK);
+        let illegal = if !board.has_valid_kings() {
+            Some(ErrNormal::NO_KINGS)
+        } else if !board.is_legal_position(&self.mg) {
+            Some(ErrNormal::ILLEGAL_POSITION)
+        } else {
+            None
+        };
+
+        let warning = if illegal.is_some() {
+            board
+                .fen_read(Some(FEN_START_POSITION))
+                .expect(ErrFatal::NEW_GAME);
+            self.game = pgn::PgnGame::new(None);
+            illegal
+        } else if !self.position_set {
+            Some(ErrNormal::NO_POSITION)
+        } else {
+            None
+        };
+        std::mem::drop(board);
+
+        if let Some(w) = warning {
+            self.position_set = true;
+            let msg = format!("Warning: {w}");
+            self.comm.send(CommControl::InfoString(msg));
+        }
+    }
+
+    // This function checks the engine's settings against what the computer
+    // is capable of, and warns the user about each setting that is a
+    // problem. It is better to tell the user now, than to have the engine
+    // fail (or become very slow) somewhere during a game.
+    pub fn check_settings(&mut self) {
+        let mut warnings: Vec<String> = Vec::new();
+
+        if let Some(requested) = self.hash_clamped.take() {
+            warnings.push(format!(
+                "Hash size of {requested} MB does not fit. Using {} MB instead.",
+                self.settings.tt_size
+            ));
+        }
+
+        if let Some(w) = Engine::hash_warning(self.settings.hash_in_use()) {
+            warnings.push(w);
+        }
+
+        if let Some(cores) = system::logical_cores() {
+            if self.settings.threads_in_use() > cores {
+                warnings.push(format!(
+                    "{} threads requested, but only {cores} logical core(s) available.",
+                    self.settings.threads_in_use()
+                ));
+            }
+        }
+
+        for w in warnings {
+            self.comm
+                .send(CommControl::InfoString(format!("Warning: {w}")));
+        }
+    }
+
+    // Run the hidden warm-up search, if it is enabled. It is skipped while
+    // the engine is searching, because it would share the TT with the
+    // running search. Nothing is reported, as the GUI may not even have
+    // sent "uci" yet.
+    pub fn warm_up(&self) {
+        if !self.settings.warm_up || self.state != EngineState::Waiting {
+            return;
+        }
+
+        bench::warm_up(
+            Arc::clone(&self.mg),
+            Arc::clone(&self.tt_search),
+            self.settings.hash_in_use() > 0,
+        );
+    }
+
+    // With Auto Hash, the TT size is chosen at the first timed search of
+    // each game. The TT should hold the nodes of an average move, as far
+    // as the time control and the measured speed tell; it is rounded up
+    // to a power of two. Resizing clears the TT, so it is only done once
+    // per game.
+    pub fn auto_hash(&mut self, game_time: &GameTime) {
+        if !self.settings.auto_hash || !self.auto_hash_pending {
+            return;
+        }
+        self.auto_hash_pending = false;
+
+        let us = self.board.lock().expect(ErrFatal::LOCK).us();
+        let msecs = Search::average_move_time(game_time, us);
+        let nps = if self.nps > 0 {
+            self.nps
+        } else {
+            EngineOptionDefaults::AUTO_HASH_NPS * self.settings.threads_in_use()
+        };
+        let nodes = (nps as u128 * msecs / 1000) as usize;
+
+        // Use no more than a share of the memory, and no more than the
+        // Hash option allows.
+        let hash_max = if std::mem::size_of::<usize>() == 8 {
+            EngineOptionDefaults::HASH_MAX_64_BIT
+        } else {
+            EngineOptionDefaults::HASH_MAX_32_BIT
+        };
+        let max = system::total_memory_mb()
+            .map_or(hash_max, |mb| {
+                mb / EngineOptionDefaults::AUTO_HASH_MEMORY_SHARE
+            })
+            .min(hash_max);
+        let megabytes = TT::<SearchData>::megabytes_for(nodes)
+            .next_power_of_two()
+            .max(EngineOptionDefaults::AUTO_HASH_MIN)
+            .min(max);
+
+        let before = self.settings.hash_in_use();
+        self.settings.auto_hash_size = megabytes;
+        let after = self.settings.hash_in_use();
+        if after != before {
+            self.tt_search.write().expect(ErrFatal::LOCK).resize(after);
+        }
+
+        let msg = format!(
+            "Auto Hash: {after} MB, for about {nodes} nodes per move ({msecs} ms at {nps} nps)"
+        );
+        self.comm.send(CommControl::InfoString(msg));
+    }
+
+    // The largest hash table the computer can hold. The engine, the
+    // operating system and other programs need memory too, so the TT gets
+    // at most half of it.
+    fn hash_limit() -> Option<usize> {
+        system::total_memory_mb().map(|memory| memory / 2)
+    }
+
+    // The given hash size, reduced to the limit if it is larger.
+    pub fn hash_fitting(megabytes: usize) -> usize {
+        match Engine::hash_limit() {
+            Some(limit) => megabytes.min(limit),
+            None => megabytes,
+        }
+    }
+
+    // Have the Comm module print the board as it is now.
+    pub fn print_board(&self) {
+        let board = self.board.lock().expect(ErrFatal::LOCK).clone();
+        self.comm.send(CommControl::PrintBoard(Box::new(board)));
+    }
+
+    // Returns a warning if a hash table of the given size does not fit
+    // into the computer's memory.
+    pub fn hash_warning(megabytes: usize) -> Option<String> {
+        match Engine::hash_limit() {
+            Some(limit) if megabytes > limit => Some(format!(
+                "Hash size of {megabytes} MB is larger than {limit} MB, half the computer's memory."
+            )),
+            _ => None,
+        }
+    }
+
+    // Before the best move is sent to the GUI, verify that it is legal in
+    // the engine's position. Sending an illegal move loses the game, so
+    // if the search came up with one (for example because of a corrupted
+    // TT entry), a legal move is played instead. The ponder move is
+    // dropped if it is not legal after the best move.
+    pub fn verify_best_move(&self, m: Move, ponder: Option<Move>) -> (Move, Option<Move>) {
+        let mut board = self.board.lock().expect(ErrFatal::LOCK).clone();
+        let legal_moves = Engine::legal_moves(&board, &self.mg);
+
+        // No legal moves: the game is over and there's nothing to fix.
+        if legal_moves.is_empty() {
+            return (m, None);
+        }
+
+        if !legal_moves.contains(&m.to_short_move()) {
+            let fallback = Move::new(legal_moves[0].get_move() as usize);
+            let msg = format!(
+                "Warning: best move {} is illegal. Playing {} instead.",
+                m.as_string(),
+                fallback.as_string()
+            );
+            self.comm.send(CommControl::InfoString(msg));
+            return (fallback, None);
+        }
+
+        // Check the ponder move in the position after the best move.
+        board.make(m, &self.mg);
+        let legal_replies = Engine::legal_moves(&board, &self.mg);
+        let ponder = ponder.filter(|p| legal_replies.contains(&p.to_short_move()));
+
+        (m, ponder)
+    }
+
+    // Returns all the legal moves in the given position. These are short
+    // moves, so they can be compared without the sort score getting in
+    // the way.
+    fn legal_moves(board: &Board, mg: &MoveGenerator) -> Vec<ShortMove> {
+        let mut ml = MoveList::new();
+        mg.generate_moves(board, &mut ml, MoveType::Legal);
+
+        (0..ml.len())
+            .map(|i| ml.get_move(i).to_short_move())
+            .collect()
+    }
+
+    // At the end of a game, report the statistics collected during that
+    // game, and start collecting for the next one. UCI doesn't tell the
+    // engine when a game ends, so this is done on "ucinewgame" and "quit".
+    pub fn report_game_stats(&mut self) {
+        if !self.game_stats.is_empty() {
+            for line in self.game_stats.summary() {
+                self.comm.send(CommControl::InfoString(line));
+            }
+            self.game_stats.clear();
+        }
+    }
+
+    // Report what the TT's replacement policy did during the last search,
+    // if the TT came under pressure. Then start counting for the next one.
+    pub fn report_tt_stats(&mut self) {
+        let mtx_tt = self.tt_search.read().expect(ErrFatal::LOCK);
+        let stats = mtx_tt.stats();
+        mtx_tt.reset_stats();
+        std::mem::drop(mtx_tt);
+
+        if stats.pressure_stores > 0 {
+            let msg = format!(
+                "TT: {} stores, {} under pressure, {} exact entries kept, {} old entries replaced",
+                stats.stores, stats.pressure_stores, stats.exact_kept, stats.old_replaced
+            );
+            self.comm.send(CommControl::InfoString(msg));
         }
-        result
     }
 }
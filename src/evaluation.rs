@@ -22,13 +22,57 @@ with this program.  If not, see <http://www.gnu.org/licenses/>.
 ======================================================================= */
 
 pub mod defs;
+pub mod draw;
+pub mod heatmap;
+pub mod param_file;
+pub mod params;
+pub mod pawns;
 pub mod psqt;
+pub mod trace;
 
-use crate::{board::Board, defs::Sides};
-use psqt::KING_EDGE;
+use crate::{
+    board::{defs::Pieces, Board},
+    defs::{Side, Sides},
+    engine::defs::{PawnData, TT},
+    misc::bits,
+    search::defs::DRAW,
+};
 
+// Default bonuses for rooks on (half-)open files.
+pub const ROOK_OPEN_FILE: i16 = 20;
+pub const ROOK_HALF_OPEN_FILE: i16 = 10;
+
+// If a side's PSQT total drops below this, it is down to a bare king.
+const KING_ONLY: i16 = 300; // PSQT-points
+
+// Evaluate the position, calculating the pawn structure from scratch.
 pub fn evaluate_position(board: &Board) -> i16 {
-    const KING_ONLY: i16 = 300; // PSQT-points
+    let pawn_data = pawns::evaluate(board);
+    evaluate(board, &pawn_data)
+}
+
+// Evaluate the position, taking the pawn structure from the pawn hash
+// table if possible. If it isn't there, it is calculated and stored.
+pub fn evaluate_position_cached(board: &Board, pawn_tt: &mut TT<PawnData>) -> i16 {
+    let pawn_key = board.game_state.pawn_key;
+    let pawn_data = match pawn_tt.probe(pawn_key) {
+        Some(data) => data,
+        None => {
+            let data = pawns::evaluate(board);
+            pawn_tt.insert(pawn_key, data);
+            data
+        }
+    };
+
+    evaluate(board, &pawn_data)
+}
+
+fn evaluate(board: &Board, pawn_data: &PawnData) -> i16 {
+    // Without mating material, the position is exactly a draw.
+    if draw::is_material_draw(board) {
+        return DRAW;
+    }
+
     let side = board.game_state.active_color as usize;
     let w_psqt = board.game_state.psqt[Sides::WHITE];
     let b_psqt = board.game_state.psqt[Sides::BLACK];
@@ -36,12 +80,18 @@ pub fn evaluate_position(board: &Board) -> i16 {
 
     // If one of the sides is down to a bare king, apply the KING_EDGE PSQT
     // to drive that king to the edge and mate it.
-    if w_psqt < KING_ONLY || b_psqt < KING_ONLY {
-        let w_king_edge = KING_EDGE[board.king_square(Sides::WHITE)];
-        let b_king_edge = KING_EDGE[board.king_square(Sides::BLACK)];
+    if has_bare_king(board) {
+        let king_edge = &board.params().king_edge;
+        let w_king_edge = king_edge[board.king_square(Sides::WHITE)];
+        let b_king_edge = king_edge[board.king_square(Sides::BLACK)];
         value += w_king_edge - b_king_edge;
     }
 
+    // Add the pawn structure, and reward rooks on (half-)open files.
+    value += pawn_data.score();
+    value += rooks_on_files(board, Sides::WHITE, pawn_data);
+    value -= rooks_on_files(board, Sides::BLACK, pawn_data);
+
     // This function calculates the evaluation from white's point of view:
     // a positive value means "white is better", a negative value means
     // "black is better". Alpha/Beta requires the value returned from the
@@ -49,7 +99,49 @@ pub fn evaluate_position(board: &Board) -> i16 {
     // black to move, the value must first be flipped to black's viewpoint
     // before it can be returned.
 
+    // Two knights can't force mate against a bare king. The evaluation
+    // is scaled down, but the search can still find a mate if the
+    // defending side allows one.
+    if draw::is_knn_v_k(board) {
+        value /= draw::KNN_V_K_SCALE;
+    }
+
     value = if side == Sides::BLACK { -value } else { value };
 
     value
 }
+
+// True if one of the sides has nothing left but its king.
+fn has_bare_king(board: &Board) -> bool {
+    let psqt = &board.game_state.psqt;
+    psqt[Sides::WHITE] < KING_ONLY || psqt[Sides::BLACK] < KING_ONLY
+}
+
+// Bonus for the rooks of the given side, standing on open or half-open
+// files.
+fn rooks_on_files(board: &Board, side: Side, pawn_data: &PawnData) -> i16 {
+    let (open, half_open) = rook_file_counts(board, side, pawn_data);
+    let params = board.params();
+
+    open * params.rook_open_file + half_open * params.rook_half_open_file
+}
+
+// Count the rooks of the given side on open and half-open files.
+pub fn rook_file_counts(board: &Board, side: Side, pawn_data: &PawnData) -> (i16, i16) {
+    let mut rooks = board.get_pieces(Pieces::ROOK, side);
+    let mut open = 0;
+    let mut half_open = 0;
+
+    while rooks > 0 {
+        let square = bits::next(&mut rooks);
+        let file = 1 << (square % 8);
+
+        if pawn_data.open_files() & file > 0 {
+            open += 1;
+        } else if pawn_data.half_open(side) & file > 0 {
+            half_open += 1;
+        }
+    }
+
+    (open, half_open)
+}
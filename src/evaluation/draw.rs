@@ -0,0 +1,136 @@
+/* =======================================================================
+Rustic is a chess playing engine.
+Copyright (C) 2019-2024, Marcel Vanthoor
+https://rustic-chess.org/
+
+Rustic is written in the Rust programming language. It is an original
+work, not derived from any engine that came before it. However, it does
+use a lot of concepts which are well-known and are in use by most if not
+all classical alpha/beta-based chess engines.
+
+Rustic is free software: you can redistribute it and/or modify it under
+the terms of the GNU General Public License version 3 as published by
+the Free Software Foundation.
+
+Rustic is distributed in the hope that it will be useful, but WITHOUT
+ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License
+for more details.
+
+You should have received a copy of the GNU General Public License along
+with this program.  If not, see <http://www.gnu.org/licenses/>.
+======================================================================= */
+
+// draw.rs recognizes positions where neither side can ever checkmate the
+// other, whatever moves are played. These are dead draws: the search
+// doesn't have to look at them, and the evaluation is exactly a draw.
+// It also recognizes two knights against a bare king. That isn't a dead
+// draw, because the defending side can still blunder into a mate, so it
+// is only evaluated close to a draw.
+
+use crate::{
+    board::{defs::Pieces, Board},
+    defs::{Bitboard, Piece, Sides},
+};
+
+// The dark squares. (A1 is a dark square.)
+const DARK_SQUARES: Bitboard = 0xAA55_AA55_AA55_AA55;
+
+// The evaluation of a KNNvK position is divided by this number.
+pub const KNN_V_K_SCALE: i16 = 16;
+
+// Returns true if the material on the board can't deliver checkmate:
+// KvK, KBvK, KNvK, and kings with only bishops that all stand on squares
+// of the same color.
+pub fn is_material_draw(board: &Board) -> bool {
+    let count = |piece| total(board, piece);
+
+    // Any pawn, rook or queen is enough to keep playing.
+    if count(Pieces::PAWN) + count(Pieces::ROOK) + count(Pieces::QUEEN) > 0 {
+        return false;
+    }
+
+    let knights = count(Pieces::KNIGHT);
+    let minors = knights + count(Pieces::BISHOP);
+
+    // KvK, KBvK and KNvK.
+    if minors <= 1 {
+        return true;
+    }
+
+    // Only bishops, all on squares of the same color.
+    let bishops = board.get_pieces(Pieces::BISHOP, Sides::WHITE)
+        | board.get_pieces(Pieces::BISHOP, Sides::BLACK);
+    knights == 0 && (bishops & DARK_SQUARES == 0 || bishops & !DARK_SQUARES == 0)
+}
+
+// Returns true if one side has two knights and nothing else, against a
+// bare king. Mate can only happen if the defending side blunders into it.
+pub fn is_knn_v_k(board: &Board) -> bool {
+    let others = [Pieces::PAWN, Pieces::BISHOP, Pieces::ROOK, Pieces::QUEEN];
+    let w_knights = board.piece_count(Pieces::KNIGHT, Sides::WHITE);
+    let b_knights = board.piece_count(Pieces::KNIGHT, Sides::BLACK);
+
+    let two_knights = matches!((w_knights, b_knights), (2, 0) | (0, 2));
+
+    two_knights && others.iter().all(|&piece| total(board, piece) == 0)
+}
+
+// The number of pieces of one type, for both sides together.
+fn total(board: &Board, piece: Piece) -> u8 {
+    board.piece_count(piece, Sides::WHITE) + board.piece_count(piece, Sides::BLACK)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::{Engine, Position, Score, SearchLimits};
+
+    fn board(fen: &str) -> Board {
+        let mut board = Board::new();
+        board.fen_read(Some(fen)).unwrap();
+        board
+    }
+
+    #[test]
+    fn dead_draws() {
+        let fens = [
+            "4k3/8/8/8/8/8/8/4K3 w - - 0 1",
+            "4k3/8/8/8/8/8/8/2B1K3 w - - 0 1",
+            "4k3/8/8/8/8/8/8/1N2K3 b - - 0 1",
+            "4kb2/8/8/8/8/8/8/2B1K1B1 w - - 0 1",
+        ];
+        for fen in fens {
+            assert!(is_material_draw(&board(fen)), "{fen}");
+        }
+
+        let fens = [
+            "4k3/8/8/8/8/8/8/1BB1K3 w - - 0 1",
+            "4k3/8/8/8/8/8/8/1NN1K3 w - - 0 1",
+            "4k3/8/8/8/8/8/8/1NB1K3 w - - 0 1",
+            "4k3/8/8/8/8/8/4P3/4K3 w - - 0 1",
+            "2b1k3/8/8/8/8/8/8/2B1K3 w - - 0 1",
+        ];
+        for fen in fens {
+            assert!(!is_material_draw(&board(fen)), "{fen}");
+        }
+    }
+
+    #[test]
+    fn two_knights_against_a_bare_king() {
+        assert!(is_knn_v_k(&board("4k3/8/8/8/8/8/8/1NN1K3 w - - 0 1")));
+        assert!(is_knn_v_k(&board("1nn1k3/8/8/8/8/8/8/4K3 w - - 0 1")));
+        assert!(!is_knn_v_k(&board("1n2k3/8/8/8/8/8/8/1N2K3 w - - 0 1")));
+        assert!(!is_knn_v_k(&board("4k3/8/8/8/8/8/p7/1NN1K3 w - - 0 1")));
+    }
+
+    #[test]
+    fn two_knights_can_still_mate() {
+        let position = Position::from_fen("k7/3N4/1K6/3N4/8/8/8/8 w - - 0 1").unwrap();
+        let mut engine = Engine::builder().hash_size(1).build();
+        let result = engine.search(&position, &SearchLimits::new().depth(3));
+
+        assert_eq!(result.best_move.as_deref(), Some("d5c7"));
+        assert_eq!(result.progress.map(|p| p.score), Some(Score::Mate(1)));
+    }
+}
@@ -0,0 +1,84 @@
+/* =======================================================================
+Rustic is a chess playing engine.
+Copyright (C) 2019-2024, Marcel Vanthoor
+https://rustic-chess.org/
+
+Rustic is written in the Rust programming language. It is an original
+work, not derived from any engine that came before it. However, it does
+use a lot of concepts which are well-known and are in use by most if not
+all classical alpha/beta-based chess engines.
+
+Rustic is free software: you can redistribute it and/or modify it under
+the terms of the GNU General Public License version 3 as published by
+the Free Software Foundation.
+
+Rustic is distributed in the hope that it will be useful, but WITHOUT
+ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License
+for more details.
+
+You should have received a copy of the GNU General Public License along
+with this program.  If not, see <http://www.gnu.org/licenses/>.
+======================================================================= */
+
+// heatmap.rs splits the evaluation into the contributions of the pieces
+// on each square: the piece's value and PSQT, the pawn structure terms of
+// each pawn, and the bonus for rooks on (half-)open files. When one side
+// has a bare king, the KING_EDGE value is added to the square of each
+// king. Added up, the squares give the evaluation from White's point of
+// view, except in positions that are a draw by insufficient material;
+// the evaluation then returns a draw without looking at the pieces.
+
+use super::{has_bare_king, pawns, psqt::FLIP};
+use crate::{
+    board::{defs::Pieces, Board},
+    defs::{NrOf, Sides},
+    misc::bits,
+};
+
+pub type EvalMap = [i16; NrOf::SQUARES];
+
+// Returns the contribution of each square to the evaluation, from White's
+// point of view: positive values are good for White.
+pub fn square_map(board: &Board) -> EvalMap {
+    let mut map: EvalMap = [0; NrOf::SQUARES];
+    let params = board.params();
+    let pawn_data = pawns::evaluate(board);
+    let bare_king = has_bare_king(board);
+
+    for side in [Sides::WHITE, Sides::BLACK] {
+        let sign = if side == Sides::WHITE { 1 } else { -1 };
+
+        for (piece, bb) in board.bb_pieces[side].iter().enumerate() {
+            let mut pieces = *bb;
+
+            while pieces > 0 {
+                let square = bits::next(&mut pieces);
+                let psqt_square = if side == Sides::WHITE {
+                    FLIP[square]
+                } else {
+                    square
+                };
+                let mut value = sign * params.psqt[piece][psqt_square];
+
+                match piece {
+                    Pieces::KING if bare_king => value += sign * params.king_edge[square],
+                    Pieces::PAWN => value += pawns::pawn_score(board, square),
+                    Pieces::ROOK => {
+                        let file = 1 << (square % 8);
+                        if pawn_data.open_files() & file > 0 {
+                            value += sign * params.rook_open_file;
+                        } else if pawn_data.half_open(side) & file > 0 {
+                            value += sign * params.rook_half_open_file;
+                        }
+                    }
+                    _ => (),
+                }
+
+                map[square] = value;
+            }
+        }
+    }
+
+    map
+}
@@ -0,0 +1,263 @@
+/* =======================================================================
+Rustic is a chess playing engine.
+Copyright (C) 2019-2024, Marcel Vanthoor
+https://rustic-chess.org/
+
+Rustic is written in the Rust programming language. It is an original
+work, not derived from any engine that came before it. However, it does
+use a lot of concepts which are well-known and are in use by most if not
+all classical alpha/beta-based chess engines.
+
+Rustic is free software: you can redistribute it and/or modify it under
+the terms of the GNU General Public License version 3 as published by
+the Free Software Foundation.
+
+Rustic is distributed in the hope that it will be useful, but WITHOUT
+ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License
+for more details.
+
+You should have received a copy of the GNU General Public License along
+with this program.  If not, see <http://www.gnu.org/licenses/>.
+======================================================================= */
+
+// param_file.rs loads evaluation parameters from a file, so other PSQT's
+// and weights can be tried without recompiling the engine. Two formats
+// are supported: TOML as written by the tuner, and JSON using the same
+// keys, with the PSQT's in a "psqt" object. Both are first reduced to a
+// list of named values, which are then validated and put into a copy of
+// the default parameters. Keys that are not in the file keep their
+// default value; unknown keys are an error, to catch typing mistakes.
+
+use super::params::{EvalParams, PSQT_NAMES};
+use std::{fs, iter::Peekable, str::Chars};
+
+// Largest absolute value accepted for a single weight. A queen on the
+// best square is worth less than this; larger values would risk pushing
+// the evaluation into the range of the checkmate scores.
+const MAX_VALUE: i16 = 1500;
+
+// Named values read from the file, with PSQT's named "psqt.<piece>".
+type Values = Vec<(String, Vec<i16>)>;
+
+// Load the parameters from the given file. The format is detected by
+// the content: a JSON file starts with an object, anything else is
+// treated as TOML.
+pub fn load(path: &str) -> Result<EvalParams, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("{path}: {e}"))?;
+    let values = if text.trim_start().starts_with('{') {
+        Json::new(&text).parse()?
+    } else {
+        parse_toml(&text)?
+    };
+
+    create_params(&values)
+}
+
+fn create_params(values: &Values) -> Result<EvalParams, String> {
+    let mut params = EvalParams::DEFAULT;
+
+    for (key, v) in values.iter() {
+        let target: &mut [i16] = match &key[..] {
+            "doubled" => std::slice::from_mut(&mut params.doubled),
+            "isolated" => std::slice::from_mut(&mut params.isolated),
+            "backward" => std::slice::from_mut(&mut params.backward),
+            "passed" => &mut params.passed,
+            "rook_open_file" => std::slice::from_mut(&mut params.rook_open_file),
+            "rook_half_open_file" => std::slice::from_mut(&mut params.rook_half_open_file),
+            "king_edge" => &mut params.king_edge,
+            k => match PSQT_NAMES.iter().position(|n| k == format!("psqt.{n}")) {
+                Some(piece) => &mut params.psqt[piece],
+                None => return Err(format!("Unknown key '{k}'")),
+            },
+        };
+
+        if v.len() != target.len() {
+            return Err(format!(
+                "'{key}' needs {} value(s), but has {}",
+                target.len(),
+                v.len()
+            ));
+        }
+
+        if let Some(x) = v.iter().find(|x| x.abs() > MAX_VALUE) {
+            return Err(format!("'{key}' has value {x}, outside of +/-{MAX_VALUE}"));
+        }
+
+        target.copy_from_slice(v);
+    }
+
+    Ok(params)
+}
+
+// Parses either a single number, or a list of numbers in brackets.
+fn parse_numbers(key: &str, text: &str) -> Result<Vec<i16>, String> {
+    let text = text.trim();
+    let list = match text.strip_prefix('[') {
+        Some(t) => t.strip_suffix(']').unwrap_or(t),
+        None => text,
+    };
+
+    list.split(',')
+        .map(|v| v.trim())
+        .filter(|v| !v.is_empty())
+        .map(|v| {
+            v.parse::<i16>()
+                .map_err(|_| format!("'{key}' has invalid value '{v}'"))
+        })
+        .collect()
+}
+
+// The subset of TOML written by EvalParams::to_toml(): comments, "key =
+// value" pairs, arrays that may span multiple lines, and a [psqt] table.
+fn parse_toml(text: &str) -> Result<Values, String> {
+    let mut values = Values::new();
+    let mut table = String::new();
+    let mut statement = String::new();
+    let mut depth = 0;
+
+    for line in text.lines() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        // Collect lines until all opened brackets are closed again.
+        statement.push_str(line);
+        statement.push(' ');
+        depth += line.matches('[').count() as i32 - line.matches(']').count() as i32;
+        if depth > 0 {
+            continue;
+        }
+
+        let s = std::mem::take(&mut statement);
+        match s.split_once('=') {
+            Some((key, value)) => {
+                let key = key.trim();
+                let key = if table.is_empty() {
+                    key.to_string()
+                } else {
+                    format!("{table}.{key}")
+                };
+                let numbers = parse_numbers(&key, value)?;
+                values.push((key, numbers));
+            }
+            None => match s.trim().strip_prefix('[').and_then(|t| t.strip_suffix(']')) {
+                Some(t) => table = t.trim().to_string(),
+                None => return Err(format!("Invalid line '{}'", s.trim())),
+            },
+        }
+    }
+
+    if !statement.is_empty() {
+        return Err(String::from("Unclosed bracket at the end of the file"));
+    }
+
+    Ok(values)
+}
+
+// A JSON object with numbers, lists of numbers, and nested objects. The
+// keys of nested objects are joined with a dot, as in TOML.
+struct Json<'a> {
+    chars: Peekable<Chars<'a>>,
+}
+
+impl<'a> Json<'a> {
+    fn new(text: &'a str) -> Self {
+        Self {
+            chars: text.chars().peekable(),
+        }
+    }
+
+    fn parse(&mut self) -> Result<Values, String> {
+        let mut values = Values::new();
+        self.object("", &mut values)?;
+
+        self.skip_whitespace();
+        match self.chars.next() {
+            None => Ok(values),
+            Some(c) => Err(format!("Unexpected '{c}' after the end of the object")),
+        }
+    }
+
+    fn object(&mut self, prefix: &str, values: &mut Values) -> Result<(), String> {
+        self.expect('{')?;
+        self.skip_whitespace();
+        if self.chars.peek() == Some(&'}') {
+            self.chars.next();
+            return Ok(());
+        }
+
+        loop {
+            let name = self.string()?;
+            let key = if prefix.is_empty() {
+                name
+            } else {
+                format!("{prefix}.{name}")
+            };
+
+            self.expect(':')?;
+            self.skip_whitespace();
+            match self.chars.peek() {
+                Some('{') => self.object(&key, values)?,
+                Some('[') => {
+                    let text = self.take_until(']')?;
+                    values.push((key.clone(), parse_numbers(&key, &text)?));
+                }
+                _ => {
+                    let text: String = self.take_while(|c| c == '-' || c.is_ascii_digit());
+                    values.push((key.clone(), parse_numbers(&key, &text)?));
+                }
+            }
+
+            self.skip_whitespace();
+            match self.chars.next() {
+                Some(',') => self.skip_whitespace(),
+                Some('}') => return Ok(()),
+                _ => return Err(format!("Expected ',' or '}}' after '{key}'")),
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+        let name = self.take_while(|c| c != '"');
+        self.expect('"')?;
+        Ok(name)
+    }
+
+    // Takes everything up to and including the closing character.
+    fn take_until(&mut self, close: char) -> Result<String, String> {
+        let mut text = self.take_while(|c| c != close);
+        self.expect(close)?;
+        text.push(close);
+        Ok(text)
+    }
+
+    fn take_while(&mut self, f: impl Fn(char) -> bool) -> String {
+        let mut text = String::new();
+        while let Some(&c) = self.chars.peek() {
+            if !f(c) {
+                break;
+            }
+            text.push(c);
+            self.chars.next();
+        }
+        text
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), String> {
+        self.skip_whitespace();
+        match self.chars.next() {
+            Some(x) if x == c => Ok(()),
+            Some(x) => Err(format!("Expected '{c}', found '{x}'")),
+            None => Err(format!("Expected '{c}', found the end of the file")),
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.chars.peek().is_some_and(|c| c.is_whitespace()) {
+            self.chars.next();
+        }
+    }
+}
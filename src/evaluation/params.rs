@@ -0,0 +1,145 @@
+/* =======================================================================
+Rustic is a chess playing engine.
+Copyright (C) 2019-2024, Marcel Vanthoor
+https://rustic-chess.org/
+
+Rustic is written in the Rust programming language. It is an original
+work, not derived from any engine that came before it. However, it does
+use a lot of concepts which are well-known and are in use by most if not
+all classical alpha/beta-based chess engines.
+
+Rustic is free software: you can redistribute it and/or modify it under
+the terms of the GNU General Public License version 3 as published by
+the Free Software Foundation.
+
+Rustic is distributed in the hope that it will be useful, but WITHOUT
+ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License
+for more details.
+
+You should have received a copy of the GNU General Public License along
+with this program.  If not, see <http://www.gnu.org/licenses/>.
+======================================================================= */
+
+// params.rs holds all the weights the evaluation uses. The built-in
+// defaults are in EvalParams::DEFAULT. The board holds a reference to the
+// parameters it is evaluated with, so they can be replaced while the
+// engine is running.
+
+use super::{
+    pawns::{BACKWARD, DOUBLED, ISOLATED, PASSED},
+    psqt::{Psqt, KING_EDGE, PSQT_MG},
+    ROOK_HALF_OPEN_FILE, ROOK_OPEN_FILE,
+};
+use crate::defs::NrOf;
+
+// Names of the piece square tables, in piece order.
+pub const PSQT_NAMES: [&str; NrOf::PIECE_TYPES] =
+    ["king", "queen", "rook", "bishop", "knight", "pawn"];
+
+#[derive(PartialEq, Clone)]
+pub struct EvalParams {
+    pub psqt: [Psqt; NrOf::PIECE_TYPES], // Piece values and placement
+    pub king_edge: Psqt,                 // Drives a bare king to the edge
+    pub doubled: i16,                    // Per doubled pawn
+    pub isolated: i16,                   // Per isolated pawn
+    pub backward: i16,                   // Per backward pawn
+    pub passed: [i16; NrOf::RANKS],      // Passed pawn, by relative rank
+    pub rook_open_file: i16,             // Rook on a file without pawns
+    pub rook_half_open_file: i16,        // Rook on a file without own pawns
+}
+
+impl EvalParams {
+    pub const DEFAULT: EvalParams = EvalParams {
+        psqt: PSQT_MG,
+        king_edge: KING_EDGE,
+        doubled: DOUBLED,
+        isolated: ISOLATED,
+        backward: BACKWARD,
+        passed: PASSED,
+        rook_open_file: ROOK_OPEN_FILE,
+        rook_half_open_file: ROOK_HALF_OPEN_FILE,
+    };
+}
+
+// Write the parameters as text, so they can be stored and used again.
+#[cfg(feature = "extra")]
+impl EvalParams {
+    // TOML, with each PSQT laid out as a chess board, A8 in the top left
+    // corner, in the same way as in psqt.rs.
+    pub fn to_toml(&self) -> String {
+        let mut lines: Vec<String> = vec![String::from("# Rustic evaluation parameters")];
+
+        lines.push(format!("doubled = {}", self.doubled));
+        lines.push(format!("isolated = {}", self.isolated));
+        lines.push(format!("backward = {}", self.backward));
+        lines.push(format!("passed = {}", list(&self.passed)));
+        lines.push(format!("rook_open_file = {}", self.rook_open_file));
+        lines.push(format!(
+            "rook_half_open_file = {}",
+            self.rook_half_open_file
+        ));
+        lines.push(format!("king_edge = {}", board(&self.king_edge, "    ")));
+
+        lines.push(String::new());
+        lines.push(String::from("[psqt]"));
+        for (name, table) in PSQT_NAMES.iter().zip(self.psqt.iter()) {
+            lines.push(format!("{name} = {}", board(table, "    ")));
+        }
+
+        lines.join("\n") + "\n"
+    }
+
+    // Rust source code, which can replace EvalParams::DEFAULT.
+    pub fn to_rust(&self) -> String {
+        let mut lines: Vec<String> = vec![
+            String::from("#[rustfmt::skip]"),
+            String::from("pub const TUNED: EvalParams = EvalParams {"),
+            String::from("    psqt: ["),
+        ];
+
+        for table in self.psqt.iter() {
+            lines.push(format!("        {},", board(table, "            ")));
+        }
+
+        lines.push(String::from("    ],"));
+        lines.push(format!(
+            "    king_edge: {},",
+            board(&self.king_edge, "        ")
+        ));
+        lines.push(format!("    doubled: {},", self.doubled));
+        lines.push(format!("    isolated: {},", self.isolated));
+        lines.push(format!("    backward: {},", self.backward));
+        lines.push(format!("    passed: {},", list(&self.passed)));
+        lines.push(format!("    rook_open_file: {},", self.rook_open_file));
+        lines.push(format!(
+            "    rook_half_open_file: {},",
+            self.rook_half_open_file
+        ));
+        lines.push(String::from("};"));
+
+        lines.join("\n") + "\n"
+    }
+}
+
+// A list of values on one line: [1, 2, 3]
+#[cfg(feature = "extra")]
+fn list(values: &[i16]) -> String {
+    let v: Vec<String> = values.iter().map(|v| v.to_string()).collect();
+    format!("[{}]", v.join(", "))
+}
+
+// A PSQT as eight rows of eight values, each row on its own line.
+#[cfg(feature = "extra")]
+fn board(table: &Psqt, indent: &str) -> String {
+    let rows: Vec<String> = table
+        .chunks(8)
+        .map(|row| {
+            let v: Vec<String> = row.iter().map(|v| format!("{v:>4}")).collect();
+            format!("{indent}{},", v.join(","))
+        })
+        .collect();
+
+    let close = &indent[4..];
+    format!("[\n{}\n{close}]", rows.join("\n"))
+}
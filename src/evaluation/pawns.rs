@@ -0,0 +1,229 @@
+/* =======================================================================
+Rustic is a chess playing engine.
+Copyright (C) 2019-2024, Marcel Vanthoor
+https://rustic-chess.org/
+
+Rustic is written in the Rust programming language. It is an original
+work, not derived from any engine that came before it. However, it does
+use a lot of concepts which are well-known and are in use by most if not
+all classical alpha/beta-based chess engines.
+
+Rustic is free software: you can redistribute it and/or modify it under
+the terms of the GNU General Public License version 3 as published by
+the Free Software Foundation.
+
+Rustic is distributed in the hope that it will be useful, but WITHOUT
+ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License
+for more details.
+
+You should have received a copy of the GNU General Public License along
+with this program.  If not, see <http://www.gnu.org/licenses/>.
+======================================================================= */
+
+// This module evaluates the pawn structure. The result only depends on
+// the pawns on the board, so it can be cached in the pawn hash table,
+// using the pawn key from the game state.
+
+use crate::{
+    board::{
+        defs::{Pieces, BB_FILES, BB_RANKS, BB_SQUARES},
+        Board,
+    },
+    defs::{Bitboard, NrOf, Side, Sides, Square},
+    engine::defs::PawnData,
+    evaluation::params::EvalParams,
+    misc::bits,
+};
+
+// Default weights of the pawn structure terms.
+pub const DOUBLED: i16 = -10;
+pub const ISOLATED: i16 = -12;
+pub const BACKWARD: i16 = -8;
+
+// Bonus for a passed pawn, by rank, as seen from the pawn's own side.
+pub const PASSED: [i16; NrOf::RANKS] = [0, 0, 5, 10, 20, 35, 60, 0];
+
+// The number of times each pawn structure term occurs in the position,
+// for white minus black. The score is calculated from these counts, so
+// the tuner can use them as well.
+#[derive(Copy, Clone, Default)]
+pub struct PawnTerms {
+    pub doubled: i16,
+    pub isolated: i16,
+    pub backward: i16,
+    pub passed: [i16; NrOf::RANKS],
+}
+
+impl PawnTerms {
+    pub fn score(&self, params: &EvalParams) -> i16 {
+        let mut score = params.doubled * self.doubled;
+        score += params.isolated * self.isolated;
+        score += params.backward * self.backward;
+
+        for (weight, count) in params.passed.iter().zip(self.passed.iter()) {
+            score += weight * count;
+        }
+
+        score
+    }
+}
+
+// Evaluate the pawn structure from scratch. The score is from white's
+// point of view.
+pub fn evaluate(board: &Board) -> PawnData {
+    let (open_files, half_open) = files(board);
+
+    let score = terms(board).score(board.params());
+
+    PawnData::create(score, open_files, half_open)
+}
+
+// Count the pawn structure terms for both sides.
+pub fn terms(board: &Board) -> PawnTerms {
+    let mut terms = PawnTerms::default();
+
+    count_terms(board, Sides::WHITE, 1, &mut terms);
+    count_terms(board, Sides::BLACK, -1, &mut terms);
+
+    terms
+}
+
+// Count the pawn structure terms for one side. Terms for white are added
+// and terms for black are subtracted, as determined by the sign.
+fn count_terms(board: &Board, side: Side, sign: i16, terms: &mut PawnTerms) {
+    let mut bb_pawns = board.get_pieces(Pieces::PAWN, side);
+
+    while bb_pawns > 0 {
+        let square = bits::next(&mut bb_pawns);
+        count_pawn_terms(board, side, square, sign, terms);
+    }
+}
+
+// The pawn structure score of the pawn on the given square, from white's
+// point of view. The scores of all the pawns add up to the score of the
+// entire pawn structure.
+pub fn pawn_score(board: &Board, square: Square) -> i16 {
+    let side = if board.bb_side[Sides::WHITE] & BB_SQUARES[square] > 0 {
+        Sides::WHITE
+    } else {
+        Sides::BLACK
+    };
+    let sign = if side == Sides::WHITE { 1 } else { -1 };
+    let mut terms = PawnTerms::default();
+
+    count_pawn_terms(board, side, square, sign, &mut terms);
+    terms.score(board.params())
+}
+
+// Count the terms that apply to a single pawn.
+fn count_pawn_terms(board: &Board, side: Side, square: Square, sign: i16, terms: &mut PawnTerms) {
+    let pawns = board.get_pieces(Pieces::PAWN, side);
+    let (file, rank) = Board::square_on_file_rank(square);
+    let (file, rank) = (file as usize, rank as usize);
+
+    // Every pawn after the first one on a file is a doubled pawn. The
+    // first one is the pawn furthest behind.
+    if pawns & BB_FILES[file] & ranks_behind(side, rank) > 0 {
+        terms.doubled += sign;
+    }
+
+    if pawns & adjacent_files(file) == 0 {
+        terms.isolated += sign;
+    } else if is_backward(board, side, square) {
+        terms.backward += sign;
+    }
+
+    if board.is_passed_pawn(side, square) {
+        let relative_rank = if side == Sides::WHITE { rank } else { 7 - rank };
+        terms.passed[relative_rank] += sign;
+    }
+}
+
+// A pawn is backward if none of its own pawns on the adjacent files are
+// level with or behind it (so they can't protect it when it advances),
+// while its stop square is attacked by an enemy pawn.
+fn is_backward(board: &Board, side: Side, square: Square) -> bool {
+    let (file, rank) = Board::square_on_file_rank(square);
+    let (file, rank) = (file as usize, rank as usize);
+    let own_pawns = board.get_pieces(Pieces::PAWN, side);
+    let enemy_pawns = board.get_pieces(Pieces::PAWN, side ^ 1);
+
+    // The rank of the enemy pawns that attack the stop square.
+    let attacker_rank = if side == Sides::WHITE {
+        rank + 2
+    } else {
+        rank.wrapping_sub(2)
+    };
+
+    if attacker_rank >= NrOf::RANKS {
+        return false;
+    }
+
+    let attackers = enemy_pawns & adjacent_files(file) & BB_RANKS[attacker_rank];
+    if attackers == 0 {
+        return false;
+    }
+
+    // Ranks level with the pawn, and behind it.
+    let support_ranks = BB_RANKS[rank] | ranks_behind(side, rank);
+
+    own_pawns & adjacent_files(file) & support_ranks == 0
+}
+
+// Returns the ranks behind the given rank, as seen from the given side.
+fn ranks_behind(side: Side, rank: usize) -> Bitboard {
+    let mut ranks: Bitboard = 0;
+
+    for (r, bb_rank) in BB_RANKS.iter().enumerate() {
+        let behind = if side == Sides::WHITE {
+            r < rank
+        } else {
+            r > rank
+        };
+
+        if behind {
+            ranks |= bb_rank;
+        }
+    }
+
+    ranks
+}
+
+// Determine which files are open (no pawns at all), and which files are
+// half-open for each side (no own pawns, but enemy pawns).
+fn files(board: &Board) -> (u8, [u8; Sides::BOTH]) {
+    let w_pawns = board.get_pieces(Pieces::PAWN, Sides::WHITE);
+    let b_pawns = board.get_pieces(Pieces::PAWN, Sides::BLACK);
+    let mut open_files = 0;
+    let mut half_open = [0; Sides::BOTH];
+
+    for (file, bb_file) in BB_FILES.iter().enumerate() {
+        let white = w_pawns & bb_file > 0;
+        let black = b_pawns & bb_file > 0;
+
+        match (white, black) {
+            (false, false) => open_files |= 1 << file,
+            (false, true) => half_open[Sides::WHITE] |= 1 << file,
+            (true, false) => half_open[Sides::BLACK] |= 1 << file,
+            (true, true) => (),
+        }
+    }
+
+    (open_files, half_open)
+}
+
+// Returns the files to the left and right of the given file.
+fn adjacent_files(file: usize) -> Bitboard {
+    let mut files: Bitboard = 0;
+
+    if file > 0 {
+        files |= BB_FILES[file - 1];
+    }
+
+    if file < 7 {
+        files |= BB_FILES[file + 1];
+    }
+
+    files
+}
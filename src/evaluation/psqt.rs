@@ -31,7 +31,7 @@ use crate::{
     misc::bits,
 };
 
-type Psqt = [i16; NrOf::SQUARES];
+pub type Psqt = [i16; NrOf::SQUARES];
 
 #[rustfmt::skip]
 const KING_MG: Psqt = [
@@ -192,6 +192,7 @@ pub fn apply(board: &Board) -> (i16, i16) {
     let mut b_psqt: i16 = 0;
     let bb_white = board.bb_pieces[Sides::WHITE]; // Array of white piece bitboards
     let bb_black = board.bb_pieces[Sides::BLACK]; // Array of black piece bitboards
+    let psqt = &board.params().psqt;
 
     // Iterate through the white and black bitboards (at the same time.)
     for (piece_type, (w, b)) in bb_white.iter().zip(bb_black.iter()).enumerate() {
@@ -201,13 +202,13 @@ pub fn apply(board: &Board) -> (i16, i16) {
         // Iterate over pieces of the current piece_type for white.
         while white_pieces > 0 {
             let square = bits::next(&mut white_pieces);
-            w_psqt += PSQT_MG[piece_type][FLIP[square]] as i16;
+            w_psqt += psqt[piece_type][FLIP[square]];
         }
 
         // Iterate over pieces of the current piece_type for black.
         while black_pieces > 0 {
             let square = bits::next(&mut black_pieces);
-            b_psqt += PSQT_MG[piece_type][square] as i16;
+            b_psqt += psqt[piece_type][square];
         }
     }
 
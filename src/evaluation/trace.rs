@@ -0,0 +1,131 @@
+/* =======================================================================
+Rustic is a chess playing engine.
+Copyright (C) 2019-2024, Marcel Vanthoor
+https://rustic-chess.org/
+
+Rustic is written in the Rust programming language. It is an original
+work, not derived from any engine that came before it. However, it does
+use a lot of concepts which are well-known and are in use by most if not
+all classical alpha/beta-based chess engines.
+
+Rustic is free software: you can redistribute it and/or modify it under
+the terms of the GNU General Public License version 3 as published by
+the Free Software Foundation.
+
+Rustic is distributed in the hope that it will be useful, but WITHOUT
+ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License
+for more details.
+
+You should have received a copy of the GNU General Public License along
+with this program.  If not, see <http://www.gnu.org/licenses/>.
+======================================================================= */
+
+// trace.rs breaks the evaluation of a position down into its terms, to
+// show where the score comes from. The terms are named after the keys in
+// an evaluation parameter file, so it is clear which weights to change.
+// All terms are from White's point of view, and add up to the evaluation
+// of the position, unless it is a material draw. With two knights against
+// a bare king, the total is scaled down just like the evaluation is.
+
+use super::{draw, has_bare_king, params::PSQT_NAMES, pawns, psqt::FLIP, rook_file_counts};
+use crate::{
+    board::Board,
+    defs::{Piece, Side, Sides},
+    misc::bits,
+    search::defs::DRAW,
+};
+
+pub struct EvalTrace {
+    pub terms: Vec<(String, i16)>,
+    pub material_draw: bool,
+    pub knn_v_k: bool,
+}
+
+impl EvalTrace {
+    // The evaluation from White's point of view.
+    pub fn total(&self) -> i16 {
+        let sum: i16 = self.terms.iter().map(|(_, value)| value).sum();
+        if self.material_draw {
+            DRAW
+        } else if self.knn_v_k {
+            sum / draw::KNN_V_K_SCALE
+        } else {
+            sum
+        }
+    }
+}
+
+// Evaluate the position, and keep each term separately. The board must
+// have a king for each side.
+pub fn trace(board: &Board) -> EvalTrace {
+    let params = board.params();
+    let mut terms = Vec::new();
+
+    // Material and piece placement, for each type of piece.
+    for (piece, name) in PSQT_NAMES.iter().enumerate() {
+        let value = psqt_sum(board, Sides::WHITE, piece) - psqt_sum(board, Sides::BLACK, piece);
+        terms.push((format!("psqt.{name}"), value));
+    }
+
+    let king_edge = if has_bare_king(board) {
+        let w = params.king_edge[board.king_square(Sides::WHITE)];
+        let b = params.king_edge[board.king_square(Sides::BLACK)];
+        w - b
+    } else {
+        0
+    };
+    terms.push((String::from("king_edge"), king_edge));
+
+    // The pawn structure, with the counts of each term weighted.
+    let pawn_terms = pawns::terms(board);
+    let passed = params
+        .passed
+        .iter()
+        .zip(pawn_terms.passed.iter())
+        .map(|(weight, count)| weight * count)
+        .sum();
+    terms.push((String::from("doubled"), params.doubled * pawn_terms.doubled));
+    terms.push((
+        String::from("isolated"),
+        params.isolated * pawn_terms.isolated,
+    ));
+    terms.push((
+        String::from("backward"),
+        params.backward * pawn_terms.backward,
+    ));
+    terms.push((String::from("passed"), passed));
+
+    // Rooks on open and half-open files.
+    let pawn_data = pawns::evaluate(board);
+    let (w_open, w_half_open) = rook_file_counts(board, Sides::WHITE, &pawn_data);
+    let (b_open, b_half_open) = rook_file_counts(board, Sides::BLACK, &pawn_data);
+    let open = (w_open - b_open) * params.rook_open_file;
+    let half_open = (w_half_open - b_half_open) * params.rook_half_open_file;
+    terms.push((String::from("rook_open_file"), open));
+    terms.push((String::from("rook_half_open_file"), half_open));
+
+    EvalTrace {
+        terms,
+        material_draw: draw::is_material_draw(board),
+        knn_v_k: draw::is_knn_v_k(board),
+    }
+}
+
+// The PSQT values of all pieces of one type and side, added up.
+fn psqt_sum(board: &Board, side: Side, piece: Piece) -> i16 {
+    let psqt = &board.params().psqt[piece];
+    let mut pieces = board.get_pieces(piece, side);
+    let mut sum = 0;
+
+    while pieces > 0 {
+        let square = bits::next(&mut pieces);
+        sum += if side == Sides::WHITE {
+            psqt[FLIP[square]]
+        } else {
+            psqt[square]
+        };
+    }
+
+    sum
+}
@@ -21,6 +21,12 @@ You should have received a copy of the GNU General Public License along
 with this program.  If not, see <http://www.gnu.org/licenses/>.
 ======================================================================= */
 
+pub mod audit;
 pub mod epds;
+pub mod fuzz;
+pub mod generate;
+pub mod selftest;
+pub mod tactics;
 pub mod testsuite;
+pub mod texel;
 pub mod wizardry;
@@ -0,0 +1,408 @@
+/* =======================================================================
+Rustic is a chess playing engine.
+Copyright (C) 2019-2024, Marcel Vanthoor
+https://rustic-chess.org/
+
+Rustic is written in the Rust programming language. It is an original
+work, not derived from any engine that came before it. However, it does
+use a lot of concepts which are well-known and are in use by most if not
+all classical alpha/beta-based chess engines.
+
+Rustic is free software: you can redistribute it and/or modify it under
+the terms of the GNU General Public License version 3 as published by
+the Free Software Foundation.
+
+Rustic is distributed in the hope that it will be useful, but WITHOUT
+ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License
+for more details.
+
+You should have received a copy of the GNU General Public License along
+with this program.  If not, see <http://www.gnu.org/licenses/>.
+======================================================================= */
+
+// audit.rs checks the castling and en-passant rules, which are the parts
+// of make() and the move generator that are the easiest to get wrong.
+// This is done in two ways:
+//
+// 1. A list of known edge cases. For each one, the move must be legal or
+//    illegal as expected. If it is legal, the board after the move must be
+//    the same as the expected position, including the Zobrist key. After
+//    unmake(), the board must be the same as before.
+// 2. A walk through all move sequences from a set of positions, where each
+//    move is checked against simple rules that don't depend on the way
+//    make() works: a castling permission is kept only if it was there
+//    before and the king and rook are both still on their home squares,
+//    and the en-passant square is set only after a pawn double step. The
+//    incrementally updated keys must also be the same as those created
+//    from scratch.
+
+use crate::{
+    board::{
+        defs::{Pieces, Squares, BB_SQUARES},
+        Board,
+    },
+    defs::{Castling, Side, Sides, Square},
+    misc::print,
+    movegen::{
+        defs::{MoveList, MoveType},
+        MoveGenerator,
+    },
+};
+
+struct EdgeCase {
+    name: &'static str,
+    fen: &'static str,
+    m: &'static str,
+    after: Option<&'static str>, // Position after the move, None if illegal
+}
+
+const EDGE_CASES: [EdgeCase; 14] = [
+    EdgeCase {
+        name: "ep capture exposes own king on the rank",
+        fen: "8/8/8/K2pP2r/8/8/8/7k w - d6 0 1",
+        m: "e5d6",
+        after: None,
+    },
+    EdgeCase {
+        name: "ep capture by a pawn pinned on the diagonal",
+        fen: "8/6K1/8/3pP3/3b4/8/8/k7 w - d6 0 1",
+        m: "e5d6",
+        after: None,
+    },
+    EdgeCase {
+        name: "ep capture along the pinning diagonal",
+        fen: "8/2b5/8/3pP3/8/8/7K/k7 w - d6 0 1",
+        m: "e5d6",
+        after: Some("8/2b5/3P4/8/8/8/7K/k7 b - - 0 1"),
+    },
+    EdgeCase {
+        name: "ep capture of the checking pawn",
+        fen: "8/8/8/3pP3/4K3/8/8/7k w - d6 0 1",
+        m: "e5d6",
+        after: Some("8/8/3P4/8/4K3/8/8/7k b - - 0 1"),
+    },
+    EdgeCase {
+        name: "ep capture by black exposes own king",
+        fen: "8/8/8/8/k2Pp2Q/8/8/7K b - d3 0 1",
+        m: "e4d3",
+        after: None,
+    },
+    EdgeCase {
+        name: "double step sets the ep square",
+        fen: "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        m: "e2e4",
+        after: Some("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1"),
+    },
+    EdgeCase {
+        name: "rook captured on its home square",
+        fen: "r3k2r/8/8/8/8/8/6b1/R3K2R b KQkq - 0 1",
+        m: "g2h1",
+        after: Some("r3k2r/8/8/8/8/8/8/R3K2b w Qkq - 0 2"),
+    },
+    EdgeCase {
+        name: "rooks capture each other on their home squares",
+        fen: "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1",
+        m: "a1a8",
+        after: Some("R3k2r/8/8/8/8/8/8/4K2R b Kk - 0 1"),
+    },
+    EdgeCase {
+        name: "promotion captures a rook on its home square",
+        fen: "r3k2r/1P6/8/8/8/8/8/R3K2R w KQkq - 0 1",
+        m: "b7a8q",
+        after: Some("Q3k2r/8/8/8/8/8/8/R3K2R b KQk - 0 1"),
+    },
+    EdgeCase {
+        name: "underpromotion captures a rook on its home square",
+        fen: "r3k2r/8/8/8/8/8/6p1/R3K2R b KQkq - 0 1",
+        m: "g2h1n",
+        after: Some("r3k2r/8/8/8/8/8/8/R3K2n w Qkq - 0 2"),
+    },
+    EdgeCase {
+        name: "king move loses both castling permissions",
+        fen: "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1",
+        m: "e1f1",
+        after: Some("r3k2r/8/8/8/8/8/8/R4K1R b kq - 1 1"),
+    },
+    EdgeCase {
+        name: "castling through an attacked square",
+        fen: "r3k2r/8/8/8/8/8/5r2/R3K2R w KQkq - 0 1",
+        m: "e1g1",
+        after: None,
+    },
+    EdgeCase {
+        name: "castling out of check",
+        fen: "r3k2r/8/8/8/8/8/4r3/R3K2R w KQkq - 0 1",
+        m: "e1g1",
+        after: None,
+    },
+    EdgeCase {
+        name: "queenside castling with b1 attacked",
+        fen: "r3k2r/8/8/8/8/8/1r6/R3K2R w KQkq - 0 1",
+        m: "e1c1",
+        after: Some("r3k2r/8/8/8/8/8/1r6/2KR3R b kq - 1 1"),
+    },
+];
+
+// Positions for the walk, with the depth to walk to. The castling
+// positions are created with every combination of permissions.
+const CASTLING_POSITION: &str = "r3k2r/8/8/8/8/8/8/R3K2R";
+const CASTLING_DEPTH: i8 = 3;
+const WALK_POSITIONS: [(&str, i8); 4] = [
+    (
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        3,
+    ),
+    ("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1", 4),
+    (
+        "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+        3,
+    ),
+    (
+        "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+        3,
+    ),
+];
+
+// The king and rook home squares for each castling permission.
+const HOME_SQUARES: [(u8, Side, Square, Square); 4] = [
+    (Castling::WK, Sides::WHITE, Squares::E1, Squares::H1),
+    (Castling::WQ, Sides::WHITE, Squares::E1, Squares::A1),
+    (Castling::BK, Sides::BLACK, Squares::E8, Squares::H8),
+    (Castling::BQ, Sides::BLACK, Squares::E8, Squares::A8),
+];
+
+pub fn run() {
+    let mg = MoveGenerator::new();
+    let mut failed = 0;
+
+    println!("Edge cases:");
+    for case in EDGE_CASES.iter() {
+        let result = check_edge_case(case, &mg);
+        if let Err(e) = &result {
+            failed += 1;
+            println!("FAIL {}: {e}", case.name);
+        } else {
+            println!("OK   {}", case.name);
+        }
+    }
+
+    println!("Move sequences:");
+    let positions = walk_positions();
+    let mut board = Board::new();
+    let mut moves = 0;
+    for (fen, depth) in positions.iter() {
+        if board.fen_read(Some(fen)).is_err() {
+            failed += 1;
+            println!("FAIL {fen}: cannot set up position");
+            continue;
+        }
+
+        let mut path = Vec::new();
+        if let Err(e) = walk(&mut board, &mg, *depth, &mut path, &mut moves) {
+            failed += 1;
+            println!("FAIL {fen} moves {}: {e}", path.join(" "));
+        }
+    }
+    println!("Checked {moves} moves from {} positions", positions.len());
+
+    if failed == 0 {
+        println!("Audit passed.");
+    } else {
+        println!("Audit failed: {failed} errors.");
+    }
+}
+
+// Walk from the castling position with each side to move and each
+// combination of castling permissions, and from the other positions.
+fn walk_positions() -> Vec<(String, i8)> {
+    let mut positions: Vec<(String, i8)> = Vec::new();
+    for side in ["w", "b"] {
+        for permissions in 0..=Castling::ALL {
+            let castling = print::castling_as_string(permissions);
+            let fen = format!("{CASTLING_POSITION} {side} {castling} - 0 1");
+            positions.push((fen, CASTLING_DEPTH));
+        }
+    }
+    positions.extend(WALK_POSITIONS.iter().map(|(f, d)| (f.to_string(), *d)));
+    positions
+}
+
+fn check_edge_case(case: &EdgeCase, mg: &MoveGenerator) -> Result<(), String> {
+    let mut board = Board::new();
+    board
+        .fen_read(Some(case.fen))
+        .map_err(|_| "cannot set up position")?;
+    let before = board.clone();
+
+    let is_legal = board.replay_moves(Some(case.fen), &[case.m], mg).is_ok();
+    let after = match (is_legal, case.after) {
+        (true, Some(after)) => after,
+        (false, None) => return Ok(()),
+        (true, None) => return Err(format!("{} is legal", case.m)),
+        (false, Some(_)) => return Err(format!("{} is not legal", case.m)),
+    };
+
+    let mut expected = Board::new();
+    expected
+        .fen_read(Some(after))
+        .map_err(|_| "cannot set up expected position")?;
+    if let Some(e) = compare(&board, &expected) {
+        return Err(format!("after {}: {e}", case.m));
+    }
+
+    board.unmake();
+    match compare(&board, &before) {
+        Some(e) => Err(format!("after unmake: {e}")),
+        None => Ok(()),
+    }
+}
+
+// Returns what is different between the two boards, if anything.
+fn compare(board: &Board, expected: &Board) -> Option<String> {
+    if board.bb_pieces != expected.bb_pieces || board.us() != expected.us() {
+        Some(String::from("pieces or side to move differ"))
+    } else if board.game_state.castling != expected.game_state.castling {
+        Some(format!(
+            "castling permissions {}, expected {}",
+            print::castling_as_string(board.game_state.castling),
+            print::castling_as_string(expected.game_state.castling)
+        ))
+    } else if board.game_state.en_passant != expected.game_state.en_passant {
+        Some(String::from("en-passant square differs"))
+    } else if board.game_state.zobrist_key != expected.game_state.zobrist_key {
+        Some(String::from("Zobrist key differs"))
+    } else if !board.keys_are_consistent() {
+        Some(String::from("keys differ from keys created from scratch"))
+    } else {
+        None
+    }
+}
+
+// Plays all move sequences up to the given depth and checks each move.
+// On an error, "path" holds the moves leading to it.
+fn walk(
+    board: &mut Board,
+    mg: &MoveGenerator,
+    depth: i8,
+    path: &mut Vec<String>,
+    moves: &mut usize,
+) -> Result<(), String> {
+    if depth == 0 {
+        return Ok(());
+    }
+
+    let mut move_list = MoveList::new();
+    mg.generate_moves(board, &mut move_list, MoveType::All);
+
+    for i in 0..move_list.len() {
+        let m = move_list.get_move(i);
+        let castling = board.game_state.castling;
+        let zobrist_key = board.game_state.zobrist_key;
+
+        if !board.make(m, mg) {
+            continue;
+        }
+
+        *moves += 1;
+        path.push(m.as_string());
+
+        let expected_castling = castling & castling_at_home(board);
+        let is_double_step =
+            m.piece() == Pieces::PAWN && (m.from() as i32 - m.to() as i32).abs() == 16;
+        let expected_ep = if is_double_step {
+            Some(((m.from() + m.to()) / 2) as u8)
+        } else {
+            None
+        };
+
+        if board.game_state.castling != expected_castling {
+            return Err(format!(
+                "castling permissions {}, expected {}",
+                print::castling_as_string(board.game_state.castling),
+                print::castling_as_string(expected_castling)
+            ));
+        }
+
+        if board.game_state.en_passant != expected_ep {
+            return Err(String::from("en-passant square is wrong"));
+        }
+
+        if !board.keys_are_consistent() {
+            return Err(String::from("keys differ from keys created from scratch"));
+        }
+
+        walk(board, mg, depth - 1, path, moves)?;
+
+        board.unmake();
+        if board.game_state.castling != castling || board.game_state.zobrist_key != zobrist_key {
+            return Err(String::from("unmake did not restore the position"));
+        }
+
+        path.pop();
+    }
+
+    Ok(())
+}
+
+// The castling permissions that are possible, judging by the kings and
+// rooks that are on their home squares.
+fn castling_at_home(board: &Board) -> u8 {
+    HOME_SQUARES
+        .iter()
+        .filter(|(_, side, king, rook)| {
+            board.get_pieces(Pieces::KING, *side) & BB_SQUARES[*king] != 0
+                && board.get_pieces(Pieces::ROOK, *side) & BB_SQUARES[*rook] != 0
+        })
+        .fold(0, |permissions, (p, ..)| permissions | p)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn edge_cases() {
+        let mg = MoveGenerator::new();
+        for case in EDGE_CASES.iter() {
+            let result = check_edge_case(case, &mg);
+            assert!(result.is_ok(), "{}: {:?}", case.name, result);
+        }
+    }
+
+    #[test]
+    fn edge_case_with_a_wrong_expectation_fails() {
+        let mg = MoveGenerator::new();
+        let case = EdgeCase {
+            name: "castling keeps the permissions",
+            fen: "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1",
+            m: "e1g1",
+            after: Some("r3k2r/8/8/8/8/8/8/R4RK1 b KQkq - 1 1"),
+        };
+        assert!(check_edge_case(&case, &mg).is_err());
+
+        let case = EdgeCase {
+            after: None,
+            ..case
+        };
+        assert_eq!(
+            check_edge_case(&case, &mg),
+            Err(String::from("e1g1 is legal"))
+        );
+    }
+
+    #[test]
+    fn move_sequences() {
+        let mg = MoveGenerator::new();
+        let mut board = Board::new();
+        let mut moves = 0;
+
+        for (fen, depth) in walk_positions() {
+            board.fen_read(Some(&fen)).unwrap();
+            let mut path = Vec::new();
+            let result = walk(&mut board, &mg, depth, &mut path, &mut moves);
+            assert!(result.is_ok(), "{fen} moves {}: {result:?}", path.join(" "));
+        }
+        assert!(moves > 0);
+    }
+}
@@ -0,0 +1,303 @@
+/* =======================================================================
+Rustic is a chess playing engine.
+Copyright (C) 2019-2024, Marcel Vanthoor
+https://rustic-chess.org/
+
+Rustic is written in the Rust programming language. It is an original
+work, not derived from any engine that came before it. However, it does
+use a lot of concepts which are well-known and are in use by most if not
+all classical alpha/beta-based chess engines.
+
+Rustic is free software: you can redistribute it and/or modify it under
+the terms of the GNU General Public License version 3 as published by
+the Free Software Foundation.
+
+Rustic is distributed in the hope that it will be useful, but WITHOUT
+ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License
+for more details.
+
+You should have received a copy of the GNU General Public License along
+with this program.  If not, see <http://www.gnu.org/licenses/>.
+======================================================================= */
+
+// fuzz.rs throws random input at the parts of the engine that have to deal
+// with input from the outside world: the FEN-reader, the UCI parser and the
+// move parser. It also plays random legal move sequences, to verify that
+// make() and unmake() keep the incrementally updated values (such as the
+// Zobrist key) correct. Each position the engine accepts is searched for
+// a few plies, because a position that can be set up but not searched
+// crashes the engine just as well. Inputs are created by mutating a corpus
+// of seed inputs. If an input makes the engine panic, it is printed before the
+// panic message, so it can be reproduced. The same seed always creates
+// the same inputs.
+
+use crate::{
+    board::Board,
+    comm::uci::Uci,
+    engine::defs::{info_channel, ErrFatal, InfoReceiver, Information, SearchData, TT},
+    extra::epds::LARGE_TEST_EPDS,
+    misc::parse,
+    movegen::{
+        defs::{MoveList, MoveType},
+        MoveGenerator,
+    },
+    search::{
+        defs::{SearchControl, SearchMode, SearchParams, SearchReport},
+        Search,
+    },
+};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaChaRng;
+use std::{
+    panic,
+    sync::{Arc, Mutex, RwLock},
+    time::Instant,
+};
+
+// Seed inputs for the FEN-reader, next to the FEN's in the EPD suite.
+const SEED_FENS: [&str; 8] = [
+    "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+    "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+    "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+    "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - -",
+    "4k3/8/8/8/8/8/8/4K3 b – – 99 120",
+    "8/P7/8/8/8/8/7p/K1k5 w - - 0 1",
+    "r3k2r/8/8/8/8/8/8/R3K2R b Kq - 0 1",
+    "",
+];
+
+// Seed inputs for the UCI parser.
+const SEED_COMMANDS: [&str; 14] = [
+    "uci",
+    "isready",
+    "ucinewgame",
+    "position startpos",
+    "position startpos moves e2e4 e7e5 g1f3 b8c6",
+    "position fen 8/P7/8/8/8/8/7p/K1k5 w - - 0 1 moves a7a8q h2h1n",
+    "go",
+    "go infinite",
+    "go depth 12",
+    "go movetime 5000",
+    "go nodes 100000",
+    "go wtime 60000 btime 60000 winc 1000 binc 1000 movestogo 40",
+    "go ponder wtime 1000 btime 1000",
+    "setoption name Hash value 128",
+];
+
+// Characters that mean something to one of the parsers, and a few that
+// don't. The em-dash is multi-byte, which catches byte/char index mixups.
+const ALPHABET: [char; 40] = [
+    'k', 'q', 'r', 'b', 'n', 'p', 'K', 'Q', 'R', 'B', 'N', 'P', 'w', 'a', 'c', 'd', 'e', 'f', 'g',
+    'h', '0', '1', '2', '3', '4', '5', '6', '7', '8', '9', '/', '-', ' ', ' ', '–', 'é', '\t', 'x',
+    '=', '+',
+];
+
+const MAX_MUTATIONS: usize = 4;
+const MAX_RANDOM_MOVES: usize = 64;
+const REPORT_INTERVAL: u64 = 100_000;
+const SEARCH_DEPTH: i8 = 2;
+const SEARCH_TT_SIZE: usize = 1;
+
+pub fn run(iterations: u64, seed: Option<u64>) {
+    let seed = seed.unwrap_or_else(rand::random);
+    let mut random = ChaChaRng::seed_from_u64(seed);
+    let mg = Arc::new(MoveGenerator::new());
+    let mut searcher = Searcher::new(Arc::clone(&mg));
+    let fens = fen_corpus();
+
+    // Keep the input that is being fuzzed, so it can be shown if the
+    // engine panics on it.
+    let current: Arc<Mutex<String>> = Arc::new(Mutex::new(String::new()));
+    let hook_input = Arc::clone(&current);
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        if let Ok(input) = hook_input.lock() {
+            println!("Fuzzer input causing panic: {:?}", *input);
+        }
+        default_hook(info);
+    }));
+
+    println!("Fuzzing {iterations} iterations with --seed {seed}");
+    let now = Instant::now();
+
+    for i in 1..=iterations {
+        let fen = mutate(fens[random.gen_range(0..fens.len())], &mut random);
+        set_current(&current, &fen);
+        fuzz_fen(&fen, &mg, &mut searcher);
+
+        let command = mutate(
+            SEED_COMMANDS[random.gen_range(0..SEED_COMMANDS.len())],
+            &mut random,
+        );
+        set_current(&current, &command);
+        Uci::parse(&command);
+
+        let fen = fens[random.gen_range(0..fens.len())];
+        set_current(&current, fen);
+        fuzz_make_unmake(fen, &mg, &mut random);
+
+        if i % REPORT_INTERVAL == 0 {
+            println!("{i} iterations ({} ms)", now.elapsed().as_millis());
+        }
+    }
+
+    searcher.quit();
+    let _ = panic::take_hook();
+    println!("Done. No panics in {iterations} iterations.");
+}
+
+// The corpus of FEN-strings: the seed FEN's, and all FEN's from the EPD
+// test suite.
+fn fen_corpus() -> Vec<&'static str> {
+    let mut fens: Vec<&'static str> = SEED_FENS.to_vec();
+    for epd in LARGE_TEST_EPDS.iter() {
+        if let Some(fen) = epd.split(';').next() {
+            fens.push(fen.trim());
+        }
+    }
+    fens
+}
+
+fn set_current(current: &Mutex<String>, input: &str) {
+    if let Ok(mut c) = current.lock() {
+        c.clear();
+        c.push_str(input);
+    }
+}
+
+// Creates a new input by applying a few random mutations to a seed.
+fn mutate(seed: &str, random: &mut ChaChaRng) -> String {
+    let mut chars: Vec<char> = seed.chars().collect();
+    let mutations = random.gen_range(0..=MAX_MUTATIONS);
+
+    for _ in 0..mutations {
+        let c = ALPHABET[random.gen_range(0..ALPHABET.len())];
+        let at = random.gen_range(0..=chars.len());
+
+        match random.gen_range(0..4) {
+            0 => chars.insert(at, c),
+            1 if at < chars.len() => chars[at] = c,
+            2 if at < chars.len() => {
+                chars.remove(at);
+            }
+            _ => chars.truncate(at),
+        }
+    }
+
+    chars.into_iter().collect()
+}
+
+// Set up the position the way the "position" command does. If it is
+// accepted, the position must be good enough to generate moves, to parse
+// and play a move on, and to be searched.
+fn fuzz_fen(fen: &str, mg: &MoveGenerator, searcher: &mut Searcher) {
+    let mut board = Board::new();
+
+    if board.replay_moves(Some(fen), &[], mg).is_ok() {
+        let mut move_list = MoveList::new();
+        mg.generate_moves(&board, &mut move_list, MoveType::All);
+
+        if move_list.len() > 0 {
+            let m = move_list.get_move(0);
+            if parse::algebraic_move_to_number(&m.as_string()).is_ok() && board.make(m, mg) {
+                board.unmake();
+            }
+        }
+
+        searcher.search(board);
+    }
+}
+
+// A search thread that is kept running during fuzzing, so each accepted
+// position can be searched without starting a new thread. If the search
+// panics, the panic hook shows the input.
+struct Searcher {
+    board: Arc<Mutex<Board>>,
+    tt: Arc<RwLock<TT<SearchData>>>,
+    search: Search,
+    info_rx: InfoReceiver,
+}
+
+impl Searcher {
+    fn new(mg: Arc<MoveGenerator>) -> Self {
+        let board = Arc::new(Mutex::new(Board::new()));
+        let tt = Arc::new(RwLock::new(TT::<SearchData>::new(SEARCH_TT_SIZE)));
+        let (info_tx, info_rx) = info_channel();
+        let mut search = Search::new();
+        search.init(info_tx, Arc::clone(&board), mg, Arc::clone(&tt), true);
+
+        Self {
+            board,
+            tt,
+            search,
+            info_rx,
+        }
+    }
+
+    fn search(&mut self, board: Board) {
+        *self.board.lock().expect(ErrFatal::LOCK) = board;
+        self.tt.write().expect(ErrFatal::LOCK).clear();
+
+        let mut sp = SearchParams::new();
+        sp.search_mode = SearchMode::Depth;
+        sp.depth = SEARCH_DEPTH;
+        sp.quiet = true;
+        self.search.send(SearchControl::Start(Box::new(sp)));
+
+        while !matches!(
+            self.info_rx.recv().expect(ErrFatal::CHANNEL),
+            Information::Search(SearchReport::Finished(..))
+        ) {}
+    }
+
+    fn quit(&mut self) {
+        self.search.send(SearchControl::Quit);
+        self.search.wait_for_shutdown();
+    }
+}
+
+// Play a random sequence of legal moves. After each move the incrementally
+// updated values must be equal to the values calculated from scratch.
+// After taking all the moves back, the board must be exactly as it was.
+fn fuzz_make_unmake(fen: &str, mg: &MoveGenerator, random: &mut ChaChaRng) {
+    let mut board = Board::new();
+    if board.fen_read(Some(fen)).is_err() {
+        return;
+    }
+
+    let original_key = board.game_state.zobrist_key;
+    let original_pieces = board.bb_pieces;
+    let mut played = 0;
+
+    for _ in 0..random.gen_range(0..=MAX_RANDOM_MOVES) {
+        let mut move_list = MoveList::new();
+        mg.generate_moves(&board, &mut move_list, MoveType::All);
+
+        let mut legal = Vec::new();
+        for i in 0..move_list.len() {
+            let m = move_list.get_move(i);
+            if board.make(m, mg) {
+                board.unmake();
+                legal.push(m);
+            }
+        }
+
+        if legal.is_empty() {
+            break;
+        }
+
+        board.make(legal[random.gen_range(0..legal.len())], mg);
+        played += 1;
+        assert!(board.incrementals_ok(), "Incremental update error");
+    }
+
+    for _ in 0..played {
+        board.unmake();
+    }
+
+    assert!(
+        board.game_state.zobrist_key == original_key && board.bb_pieces == original_pieces,
+        "Board not restored after unmake()"
+    );
+}
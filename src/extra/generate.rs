@@ -0,0 +1,152 @@
+/* =======================================================================
+Rustic is a chess playing engine.
+Copyright (C) 2019-2024, Marcel Vanthoor
+https://rustic-chess.org/
+
+Rustic is written in the Rust programming language. It is an original
+work, not derived from any engine that came before it. However, it does
+use a lot of concepts which are well-known and are in use by most if not
+all classical alpha/beta-based chess engines.
+
+Rustic is free software: you can redistribute it and/or modify it under
+the terms of the GNU General Public License version 3 as published by
+the Free Software Foundation.
+
+Rustic is distributed in the hope that it will be useful, but WITHOUT
+ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License
+for more details.
+
+You should have received a copy of the GNU General Public License along
+with this program.  If not, see <http://www.gnu.org/licenses/>.
+======================================================================= */
+
+// generate.rs regenerates the engine's precomputed data from a seed, and
+// prints it as Rust source code. Given the same seed, the output is always
+// the same. This way, anyone can verify where the numbers in the engine
+// come from, or rebuild them if they ever get corrupted.
+
+use crate::{
+    board::{
+        defs::{Pieces, RangeOf},
+        zobrist::{ZobristRandoms, RNG_SEED},
+    },
+    defs::{NrOf, Sides},
+    extra::wizardry,
+};
+use rand::SeedableRng;
+use rand_chacha::ChaChaRng;
+
+pub struct Artifacts;
+impl Artifacts {
+    pub const MAGICS: &'static str = "magics";
+    pub const ZOBRIST: &'static str = "zobrist";
+}
+
+const VALUES_PER_LINE: usize = 4;
+
+pub fn run(artifact: &str, seed: Option<u64>) {
+    let seed_bytes = seed_bytes(seed);
+    let seed_text = match seed {
+        Some(s) => format!("--seed {s}"),
+        None => String::from("the default seed"),
+    };
+
+    println!("// Generated with \"--generate {artifact}\" and {seed_text}.");
+    match artifact {
+        Artifacts::MAGICS => magics(seed_bytes),
+        Artifacts::ZOBRIST => zobrist(seed_bytes),
+        _ => println!("// Unknown artifact: {artifact}"),
+    }
+}
+
+// Turns the seed from the command line into the 256-bit seed ChaCha needs.
+// Without a seed, the engine's own Zobrist seed is used, so "--generate
+// zobrist" prints exactly the numbers the engine is using.
+fn seed_bytes(seed: Option<u64>) -> [u8; 32] {
+    match seed {
+        Some(s) => {
+            let mut bytes = [0u8; 32];
+            bytes[..8].copy_from_slice(&s.to_le_bytes());
+            bytes
+        }
+        None => RNG_SEED,
+    }
+}
+
+// Finds magic numbers for rooks and bishops. Both piece types are
+// searched with the same generator, one after the other.
+fn magics(seed: [u8; 32]) {
+    let mut random = ChaChaRng::from_seed(seed);
+    let rook = wizardry::search_magics(Pieces::ROOK, &mut random, false);
+    let bishop = wizardry::search_magics(Pieces::BISHOP, &mut random, false);
+
+    print_array("ROOK_MAGIC_NRS", "NrOf::SQUARES", &rook);
+    print_array("BISHOP_MAGIC_NRS", "NrOf::SQUARES", &bishop);
+}
+
+// Prints the Zobrist random numbers. The engine creates these at startup
+// instead of having them in the source, so these arrays are flattened, in
+// the order in which they are generated.
+fn zobrist(seed: [u8; 32]) {
+    let zr = ZobristRandoms::with_seed(seed);
+    let mut pieces: Vec<u64> = Vec::new();
+    let mut castling: Vec<u64> = Vec::new();
+    let mut en_passant: Vec<u64> = Vec::new();
+    let mut material: Vec<u64> = Vec::new();
+
+    for side in [Sides::WHITE, Sides::BLACK] {
+        for piece in 0..NrOf::PIECE_TYPES {
+            for square in RangeOf::SQUARES {
+                pieces.push(zr.piece(side, piece, square));
+            }
+        }
+    }
+
+    for permissions in 0..NrOf::CASTLING_PERMISSIONS {
+        castling.push(zr.castling(permissions as u8));
+    }
+
+    let sides = vec![zr.side(Sides::WHITE), zr.side(Sides::BLACK)];
+
+    for square in RangeOf::SQUARES {
+        en_passant.push(zr.en_passant(Some(square as u8)));
+    }
+    en_passant.push(zr.en_passant(None));
+
+    for side in [Sides::WHITE, Sides::BLACK] {
+        for piece in 0..NrOf::PIECE_TYPES {
+            for n in 0..NrOf::SQUARES {
+                material.push(zr.material(side, piece, n));
+            }
+        }
+    }
+
+    print_array(
+        "ZOBRIST_PIECES",
+        "Sides::BOTH * NrOf::PIECE_TYPES * NrOf::SQUARES",
+        &pieces,
+    );
+    print_array("ZOBRIST_CASTLING", "NrOf::CASTLING_PERMISSIONS", &castling);
+    print_array("ZOBRIST_SIDES", "Sides::BOTH", &sides);
+    print_array("ZOBRIST_EN_PASSANT", "NrOf::SQUARES + 1", &en_passant);
+    print_array(
+        "ZOBRIST_MATERIAL",
+        "Sides::BOTH * NrOf::PIECE_TYPES * NrOf::SQUARES",
+        &material,
+    );
+}
+
+// Prints an array of numbers in the same layout as the magic numbers in
+// movegen/magics.rs.
+fn print_array(name: &str, size: &str, values: &[u64]) {
+    println!();
+    println!("#[rustfmt::skip]");
+    println!("#[allow(clippy::unreadable_literal)]");
+    println!("pub const {name}: [u64; {size}] = [");
+    for line in values.chunks(VALUES_PER_LINE) {
+        let numbers: Vec<String> = line.iter().map(|v| format!("{v}u64")).collect();
+        println!("    {},", numbers.join(", "));
+    }
+    println!("];");
+}
@@ -0,0 +1,734 @@
+/* =======================================================================
+Rustic is a chess playing engine.
+Copyright (C) 2019-2024, Marcel Vanthoor
+https://rustic-chess.org/
+
+Rustic is written in the Rust programming language. It is an original
+work, not derived from any engine that came before it. However, it does
+use a lot of concepts which are well-known and are in use by most if not
+all classical alpha/beta-based chess engines.
+
+Rustic is free software: you can redistribute it and/or modify it under
+the terms of the GNU General Public License version 3 as published by
+the Free Software Foundation.
+
+Rustic is distributed in the hope that it will be useful, but WITHOUT
+ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License
+for more details.
+
+You should have received a copy of the GNU General Public License along
+with this program.  If not, see <http://www.gnu.org/licenses/>.
+======================================================================= */
+
+// selftest.rs checks three things that must hold for every position, on a
+// corpus made of the positions in the EPD test suite and the positions
+// reached from them within a few moves:
+//
+// 1. The evaluation is symmetrical: from White's point of view, the
+//    mirrored position (colors swapped, board flipped) has the opposite
+//    score of the original position.
+// 2. Hashing is consistent: after make(), the incrementally updated keys
+//    are the same as those created from scratch, and after unmake() the
+//    board is exactly as it was.
+// 3. The move generator is correct: the legal moves it creates are the
+//    same as those of a slow reference generator. The reference works on
+//    a plain array of squares, walks each piece's rays one square at a
+//    time, and has nothing in common with the engine's move generator.
+//    The legal move generator, which doesn't need make() to find out if
+//    a move is legal, must also create exactly those moves.
+//
+// Finally, a position with a long forced line is searched to a depth of
+// more than 100 plies, to check that the search survives going that deep,
+// and a mate is searched again after many searches on the same TT were
+// started and abandoned halfway. Results of the abandoned searches must
+// not end up in the TT, so the mate must be found just like before.
+
+use crate::{
+    board::{
+        defs::{Pieces, Squares, BB_SQUARES, SQUARE_NAME},
+        Board,
+    },
+    defs::{Castling, NrOf, Piece, Side, Sides, Square},
+    engine::defs::{info_channel, ErrFatal, InfoReceiver, Information, SearchData, TT},
+    evaluation::evaluate_position,
+    extra::epds::LARGE_TEST_EPDS,
+    movegen::{
+        defs::{MoveList, MoveType},
+        MoveGenerator,
+    },
+    search::{
+        defs::{SearchControl, SearchMode, SearchParams, SearchReport, SearchSummary},
+        Search,
+    },
+};
+use std::{
+    sync::{Arc, Mutex, RwLock},
+    time::Instant,
+};
+
+// Depth to walk from each position in the corpus.
+const WALK_DEPTH: i8 = 2;
+
+// Stop reporting after this many failures; the rest is probably the same.
+const MAX_REPORTED: usize = 20;
+
+// The black king can only shuffle around its pawn, which the white king
+// blockades. Each iteration is cheap, so the search gets very deep fast.
+const DEEP_FEN: &str = "8/8/8/8/8/k7/p7/K7 b - - 0 1";
+const DEEP_DEPTH: i8 = 110;
+const DEEP_TT_SIZE: usize = 16;
+
+// White mates in two: 1. Nf6+ gxf6 2. Bxf7#. The mate is searched after
+// ABANDONED searches, each stopped after a different number of nodes.
+const MATE_FEN: &str = "r2qkb1r/pp2nppp/3p4/2pNN1B1/2BnP3/3P4/PPP2PPP/R2bK2R w KQkq - 1 1";
+const MATE_DEPTH: i8 = 6;
+const ABANDONED: usize = 200;
+const ABANDON_NODES: usize = 3_000;
+
+// Mate in moves, and the best move.
+type MateResult = (i16, String);
+
+pub fn run() {
+    let mg = MoveGenerator::new();
+    let mut board = Board::new();
+    let mut result = SelfTestResult::new();
+    let now = Instant::now();
+
+    println!(
+        "Self-test on {} positions, depth {WALK_DEPTH}",
+        LARGE_TEST_EPDS.len()
+    );
+    for epd in LARGE_TEST_EPDS.iter() {
+        let fen = epd.split(';').next().unwrap_or("").trim();
+        if board.fen_read(Some(fen)).is_err() {
+            result.fail(fen, &[], "cannot set up position");
+            continue;
+        }
+
+        let mut path = Vec::new();
+        walk(&mut board, &mg, WALK_DEPTH, &mut path, fen, &mut result);
+    }
+
+    println!(
+        "Checked {} positions and {} moves ({} ms)",
+        result.positions,
+        result.moves,
+        now.elapsed().as_millis()
+    );
+
+    let (depth, seldepth) = deep_search(mg);
+    println!("Deep search: depth {depth}, seldepth {seldepth}");
+    if depth < DEEP_DEPTH {
+        result.fail(DEEP_FEN, &[], &format!("search stopped at depth {depth}"));
+    }
+
+    let (clean, polluted) = abandoned_searches(MoveGenerator::new());
+    println!("Mate after {ABANDONED} abandoned searches: {polluted:?} (clean TT: {clean:?})");
+    if clean.is_none() || polluted != clean {
+        result.fail(MATE_FEN, &[], "abandoned searches changed the result");
+    }
+
+    if result.failed == 0 {
+        println!("Self-test passed.");
+    } else {
+        println!("Self-test failed: {} errors.", result.failed);
+    }
+}
+
+struct SelfTestResult {
+    positions: usize,
+    moves: usize,
+    failed: usize,
+}
+
+impl SelfTestResult {
+    fn new() -> Self {
+        Self {
+            positions: 0,
+            moves: 0,
+            failed: 0,
+        }
+    }
+
+    fn fail(&mut self, fen: &str, path: &[String], error: &str) {
+        self.failed += 1;
+        if self.failed <= MAX_REPORTED {
+            println!("FAIL {fen} moves {}: {error}", path.join(" "));
+        }
+    }
+}
+
+// Checks the position on the board, and then all positions reached from
+// it up to the given depth. On an error, "path" holds the moves leading
+// to the position.
+fn walk(
+    board: &mut Board,
+    mg: &MoveGenerator,
+    depth: i8,
+    path: &mut Vec<String>,
+    fen: &str,
+    result: &mut SelfTestResult,
+) {
+    result.positions += 1;
+    if let Err(e) = check_position(board, mg) {
+        result.fail(fen, path, &e);
+    }
+
+    if depth == 0 {
+        return;
+    }
+
+    let mut move_list = MoveList::new();
+    mg.generate_moves(board, &mut move_list, MoveType::All);
+
+    for i in 0..move_list.len() {
+        let m = move_list.get_move(i);
+        let before = board.clone();
+
+        if !board.make(m, mg) {
+            continue;
+        }
+
+        result.moves += 1;
+        path.push(m.as_string());
+
+        if !board.keys_are_consistent() {
+            result.fail(fen, path, "keys differ from keys created from scratch");
+        }
+
+        walk(board, mg, depth - 1, path, fen, result);
+        board.unmake();
+
+        if !same_position(board, &before) {
+            result.fail(fen, path, "board differs after unmake");
+        }
+
+        path.pop();
+    }
+}
+
+// Search the deep position, and return the depth and seldepth of the last
+// completed iteration.
+fn deep_search(mg: MoveGenerator) -> (i8, i16) {
+    let mut position = Board::new();
+    if position.fen_read(Some(DEEP_FEN)).is_err() {
+        return (0, 0);
+    }
+
+    let board = Arc::new(Mutex::new(position));
+    let tt = Arc::new(RwLock::new(TT::<SearchData>::new(DEEP_TT_SIZE)));
+    let (info_tx, info_rx) = info_channel();
+    let mut search = Search::new();
+    search.init(info_tx, board, Arc::new(mg), tt, true);
+
+    let mut sp = SearchParams::new();
+    sp.search_mode = SearchMode::Depth;
+    sp.depth = DEEP_DEPTH;
+    sp.quiet = true;
+    search.send(SearchControl::Start(Box::new(sp)));
+
+    let reached = last_summary(&info_rx).map_or((0, 0), |s| (s.depth, s.seldepth));
+
+    search.send(SearchControl::Quit);
+    search.wait_for_shutdown();
+
+    reached
+}
+
+// Search the mate position with a fresh TT. Then start and abandon many
+// searches on another TT, and search the mate again on that TT. Returns
+// the mate (in moves) and best move found by both searches.
+fn abandoned_searches(mg: MoveGenerator) -> (Option<MateResult>, Option<MateResult>) {
+    let mut position = Board::new();
+    if position.fen_read(Some(MATE_FEN)).is_err() {
+        return (None, None);
+    }
+
+    let mg = Arc::new(mg);
+    let board = Arc::new(Mutex::new(position));
+    let mut results = [None, None];
+
+    for (i, abandoned) in [0, ABANDONED].into_iter().enumerate() {
+        let tt = Arc::new(RwLock::new(TT::<SearchData>::new(DEEP_TT_SIZE)));
+        let (info_tx, info_rx) = info_channel();
+        let mut search = Search::new();
+        search.init(info_tx, board.clone(), mg.clone(), tt, true);
+
+        // Each search is stopped after a different number of nodes, so
+        // they are abandoned at many different points in the tree.
+        for n in 0..abandoned {
+            let mut sp = SearchParams::new();
+            sp.search_mode = SearchMode::Nodes;
+            sp.nodes = ABANDON_NODES + n * 97;
+            sp.quiet = true;
+            search.send(SearchControl::Start(Box::new(sp)));
+            last_summary(&info_rx);
+        }
+
+        let mut sp = SearchParams::new();
+        sp.search_mode = SearchMode::Depth;
+        sp.depth = MATE_DEPTH;
+        sp.quiet = true;
+        search.send(SearchControl::Start(Box::new(sp)));
+        results[i] = last_summary(&info_rx).and_then(|s| {
+            let best_move = s.pv.first()?.as_string();
+            Some((s.mate_in()?, best_move))
+        });
+
+        search.send(SearchControl::Quit);
+        search.wait_for_shutdown();
+    }
+
+    let [clean, polluted] = results;
+    (clean, polluted)
+}
+
+// Waits for the search to finish, and returns its last summary.
+fn last_summary(info_rx: &InfoReceiver) -> Option<SearchSummary> {
+    let mut summary = None;
+    loop {
+        match info_rx.recv().expect(ErrFatal::CHANNEL) {
+            Information::Search(SearchReport::SearchSummary(s)) => summary = Some(s),
+            Information::Search(SearchReport::Finished(..)) => break,
+            _ => (),
+        }
+    }
+
+    summary
+}
+
+fn check_position(board: &Board, mg: &MoveGenerator) -> Result<(), String> {
+    let mirror = board.mirrored();
+    let score = white_score(board);
+    let mirror_score = white_score(&mirror);
+    if score != -mirror_score {
+        return Err(format!("eval {score}, mirrored eval {mirror_score}"));
+    }
+
+    if !same_position(&mirror.mirrored(), board) {
+        return Err(String::from("mirroring twice changes the position"));
+    }
+
+    let mut engine = engine_moves(board, mg);
+    let mut reference = Mailbox::new(board).legal_moves();
+    engine.sort();
+    reference.sort();
+    if engine != reference {
+        let missing: Vec<&String> = reference.iter().filter(|m| !engine.contains(m)).collect();
+        let extra: Vec<&String> = engine.iter().filter(|m| !reference.contains(m)).collect();
+        return Err(format!(
+            "move generator misses {missing:?}, has extra {extra:?}"
+        ));
+    }
+
+    let mut legal = legal_moves(board, mg);
+    legal.sort();
+    if legal != engine {
+        return Err(format!("legal moves {legal:?} differ from {engine:?}"));
+    }
+
+    Ok(())
+}
+
+// The evaluation is from the side to move's point of view.
+fn white_score(board: &Board) -> i16 {
+    let score = evaluate_position(board);
+    if board.us() == Sides::WHITE {
+        score
+    } else {
+        -score
+    }
+}
+
+fn same_position(board: &Board, expected: &Board) -> bool {
+    board.bb_pieces == expected.bb_pieces
+        && board.bb_side == expected.bb_side
+        && board.piece_list == expected.piece_list
+        && board.us() == expected.us()
+        && board.game_state.castling == expected.game_state.castling
+        && board.game_state.en_passant == expected.game_state.en_passant
+        && board.game_state.halfmove_clock == expected.game_state.halfmove_clock
+        && board.game_state.zobrist_key == expected.game_state.zobrist_key
+        && board.game_state.pawn_key == expected.game_state.pawn_key
+        && board.game_state.material_key == expected.game_state.material_key
+        && board.game_state.psqt == expected.game_state.psqt
+}
+
+fn engine_moves(board: &Board, mg: &MoveGenerator) -> Vec<String> {
+    let mut board = board.clone();
+    let mut move_list = MoveList::new();
+    let mut legal = Vec::new();
+    mg.generate_moves(&board, &mut move_list, MoveType::All);
+
+    for i in 0..move_list.len() {
+        let m = move_list.get_move(i);
+        if board.make(m, mg) {
+            board.unmake();
+            legal.push(m.as_string());
+        }
+    }
+
+    legal
+}
+
+// The moves of the legal move generator. Each of these must also pass
+// is_legal(), and all other pseudo-legal moves must fail it.
+fn legal_moves(board: &Board, mg: &MoveGenerator) -> Vec<String> {
+    let mut move_list = MoveList::new();
+    mg.generate_moves(board, &mut move_list, MoveType::Legal);
+    let legal: Vec<String> = (0..move_list.len())
+        .map(|i| move_list.get_move(i).as_string())
+        .collect();
+
+    let mut pseudo_legal = MoveList::new();
+    mg.generate_moves(board, &mut pseudo_legal, MoveType::All);
+    for i in 0..pseudo_legal.len() {
+        let m = pseudo_legal.get_move(i);
+        if mg.is_legal(board, m) != legal.contains(&m.as_string()) {
+            return vec![format!("is_legal({}) disagrees", m.as_string())];
+        }
+    }
+
+    legal
+}
+
+/*** ===== Reference move generator =============================== ***/
+
+type Step = (i8, i8); // (files, ranks)
+
+const KNIGHT_STEPS: [Step; 8] = [
+    (1, 2),
+    (2, 1),
+    (2, -1),
+    (1, -2),
+    (-1, -2),
+    (-2, -1),
+    (-2, 1),
+    (-1, 2),
+];
+const KING_STEPS: [Step; 8] = [
+    (1, 0),
+    (1, 1),
+    (0, 1),
+    (-1, 1),
+    (-1, 0),
+    (-1, -1),
+    (0, -1),
+    (1, -1),
+];
+const ROOK_RAYS: [Step; 4] = [(1, 0), (0, 1), (-1, 0), (0, -1)];
+const BISHOP_RAYS: [Step; 4] = [(1, 1), (-1, 1), (-1, -1), (1, -1)];
+const PROMOTIONS: [&str; 4] = ["q", "r", "b", "n"];
+
+// Castling permission, king from/to, rook from, the squares that must be
+// empty, and the squares that may not be attacked.
+type CastlingRule = (
+    u8,
+    Square,
+    Square,
+    Square,
+    &'static [Square],
+    &'static [Square],
+);
+const CASTLING_RULES: [CastlingRule; 4] = [
+    (
+        Castling::WK,
+        Squares::E1,
+        Squares::G1,
+        Squares::H1,
+        &[Squares::F1, Squares::G1],
+        &[Squares::E1, Squares::F1],
+    ),
+    (
+        Castling::WQ,
+        Squares::E1,
+        Squares::C1,
+        Squares::A1,
+        &[Squares::B1, Squares::C1, Squares::D1],
+        &[Squares::E1, Squares::D1],
+    ),
+    (
+        Castling::BK,
+        Squares::E8,
+        Squares::G8,
+        Squares::H8,
+        &[Squares::F8, Squares::G8],
+        &[Squares::E8, Squares::F8],
+    ),
+    (
+        Castling::BQ,
+        Squares::E8,
+        Squares::C8,
+        Squares::A8,
+        &[Squares::B8, Squares::C8, Squares::D8],
+        &[Squares::E8, Squares::D8],
+    ),
+];
+
+// The square reached by taking the step, if it is on the board.
+fn step(square: Square, (files, ranks): Step) -> Option<Square> {
+    let file = (square % 8) as i8 + files;
+    let rank = (square / 8) as i8 + ranks;
+
+    if (0..8).contains(&file) && (0..8).contains(&rank) {
+        Some((rank * 8 + file) as Square)
+    } else {
+        None
+    }
+}
+
+fn forward(side: Side) -> i8 {
+    if side == Sides::WHITE {
+        1
+    } else {
+        -1
+    }
+}
+
+#[derive(Clone)]
+struct Mailbox {
+    squares: [Option<(Side, Piece)>; NrOf::SQUARES],
+    us: Side,
+    castling: u8,
+    en_passant: Option<Square>,
+}
+
+impl Mailbox {
+    fn new(board: &Board) -> Self {
+        let mut squares = [None; NrOf::SQUARES];
+        for (square, piece) in board.piece_list.iter().enumerate() {
+            if *piece != Pieces::NONE {
+                let is_white = board.bb_side[Sides::WHITE] & BB_SQUARES[square] > 0;
+                let side = if is_white { Sides::WHITE } else { Sides::BLACK };
+                squares[square] = Some((side, *piece));
+            }
+        }
+
+        Self {
+            squares,
+            us: board.us(),
+            castling: board.game_state.castling,
+            en_passant: board.game_state.en_passant.map(|square| square as Square),
+        }
+    }
+
+    fn legal_moves(&self) -> Vec<String> {
+        let mut legal = Vec::new();
+
+        for (from, to, promotion) in self.pseudo_legal_moves() {
+            let after = self.play(from, to, promotion);
+            let king = after.king(self.us);
+            if king.is_some_and(|square| !after.attacked(square, self.us ^ 1)) {
+                let promotion = promotion.unwrap_or("");
+                legal.push(format!(
+                    "{}{}{promotion}",
+                    SQUARE_NAME[from], SQUARE_NAME[to]
+                ));
+            }
+        }
+
+        legal
+    }
+
+    fn pseudo_legal_moves(&self) -> Vec<(Square, Square, Option<&'static str>)> {
+        let mut moves = Vec::new();
+        let us = self.us;
+
+        for from in 0..NrOf::SQUARES {
+            let piece = match self.squares[from] {
+                Some((side, piece)) if side == us => piece,
+                _ => continue,
+            };
+
+            let targets = match piece {
+                Pieces::PAWN => self.pawn_targets(from),
+                Pieces::KNIGHT => self.step_targets(from, &KNIGHT_STEPS),
+                Pieces::KING => self.step_targets(from, &KING_STEPS),
+                Pieces::BISHOP => self.ray_targets(from, &BISHOP_RAYS),
+                Pieces::ROOK => self.ray_targets(from, &ROOK_RAYS),
+                _ => [
+                    self.ray_targets(from, &ROOK_RAYS),
+                    self.ray_targets(from, &BISHOP_RAYS),
+                ]
+                .concat(),
+            };
+
+            for to in targets {
+                let last_rank = to / 8 == 0 || to / 8 == 7;
+                if piece == Pieces::PAWN && last_rank {
+                    moves.extend(PROMOTIONS.iter().map(|p| (from, to, Some(*p))));
+                } else {
+                    moves.push((from, to, None));
+                }
+            }
+        }
+
+        for (permission, king_from, king_to, rook_from, empty, safe) in CASTLING_RULES.iter() {
+            let is_ours = (*permission & (Castling::WK | Castling::WQ) > 0) == (us == Sides::WHITE);
+            if is_ours
+                && self.castling & permission > 0
+                && self.squares[*king_from] == Some((us, Pieces::KING))
+                && self.squares[*rook_from] == Some((us, Pieces::ROOK))
+                && empty.iter().all(|square| self.squares[*square].is_none())
+                && safe.iter().all(|square| !self.attacked(*square, us ^ 1))
+            {
+                moves.push((*king_from, *king_to, None));
+            }
+        }
+
+        moves
+    }
+
+    fn is_free_for(&self, square: Square, side: Side) -> bool {
+        !matches!(self.squares[square], Some((s, _)) if s == side)
+    }
+
+    fn step_targets(&self, from: Square, steps: &[Step]) -> Vec<Square> {
+        steps
+            .iter()
+            .filter_map(|s| step(from, *s))
+            .filter(|to| self.is_free_for(*to, self.us))
+            .collect()
+    }
+
+    fn ray_targets(&self, from: Square, rays: &[Step]) -> Vec<Square> {
+        let mut targets = Vec::new();
+        for ray in rays {
+            let mut square = from;
+            while let Some(to) = step(square, *ray) {
+                if !self.is_free_for(to, self.us) {
+                    break;
+                }
+                targets.push(to);
+                if self.squares[to].is_some() {
+                    break;
+                }
+                square = to;
+            }
+        }
+        targets
+    }
+
+    fn pawn_targets(&self, from: Square) -> Vec<Square> {
+        let mut targets = Vec::new();
+        let dir = forward(self.us);
+        let start_rank = if self.us == Sides::WHITE { 1 } else { 6 };
+
+        if let Some(one) = step(from, (0, dir)).filter(|s| self.squares[*s].is_none()) {
+            targets.push(one);
+            if let Some(two) = step(one, (0, dir)).filter(|s| self.squares[*s].is_none()) {
+                if from / 8 == start_rank {
+                    targets.push(two);
+                }
+            }
+        }
+
+        for files in [-1, 1] {
+            if let Some(to) = step(from, (files, dir)) {
+                let is_capture = matches!(self.squares[to], Some((s, _)) if s != self.us);
+                if is_capture || self.en_passant == Some(to) {
+                    targets.push(to);
+                }
+            }
+        }
+
+        targets
+    }
+
+    // Plays the move on a copy. Only the pieces are updated: that is all
+    // the legality check needs.
+    fn play(&self, from: Square, to: Square, promotion: Option<&str>) -> Mailbox {
+        let mut after = self.clone();
+        let (side, piece) = self.squares[from].expect("piece on from-square");
+
+        // A pawn moving diagonally to an empty square captures en-passant.
+        let is_diagonal = from % 8 != to % 8;
+        if piece == Pieces::PAWN && is_diagonal && self.squares[to].is_none() {
+            let captured = step(to, (0, -forward(side))).expect("en-passant square");
+            after.squares[captured] = None;
+        }
+
+        // A king moving two files is castling: move the rook as well.
+        if piece == Pieces::KING && (from % 8).abs_diff(to % 8) == 2 {
+            let (rook_from, rook_to) = if to % 8 == 6 {
+                (to + 1, to - 1)
+            } else {
+                (to - 2, to + 1)
+            };
+            after.squares[rook_to] = after.squares[rook_from].take();
+        }
+
+        let piece = match promotion {
+            Some("q") => Pieces::QUEEN,
+            Some("r") => Pieces::ROOK,
+            Some("b") => Pieces::BISHOP,
+            Some("n") => Pieces::KNIGHT,
+            _ => piece,
+        };
+
+        after.squares[from] = None;
+        after.squares[to] = Some((side, piece));
+        after
+    }
+
+    fn king(&self, side: Side) -> Option<Square> {
+        (0..NrOf::SQUARES).find(|square| self.squares[*square] == Some((side, Pieces::KING)))
+    }
+
+    // Is the square attacked by the given side? Look from the square in
+    // every direction a piece could attack it from.
+    fn attacked(&self, square: Square, by: Side) -> bool {
+        let is = |s: Option<Square>, piece: Piece| {
+            s.is_some_and(|s| self.squares[s] == Some((by, piece)))
+        };
+
+        let pawns = [-1, 1].iter().any(|files| {
+            let from = step(square, (*files, -forward(by)));
+            is(from, Pieces::PAWN)
+        });
+        let knights = KNIGHT_STEPS
+            .iter()
+            .any(|s| is(step(square, *s), Pieces::KNIGHT));
+        let king = KING_STEPS
+            .iter()
+            .any(|s| is(step(square, *s), Pieces::KING));
+        let straight = self.slider_attacks(square, &ROOK_RAYS, by, Pieces::ROOK);
+        let diagonal = self.slider_attacks(square, &BISHOP_RAYS, by, Pieces::BISHOP);
+
+        pawns || knights || king || straight || diagonal
+    }
+
+    // A queen attacks along the rays of both the rook and the bishop.
+    fn slider_attacks(&self, square: Square, rays: &[Step], by: Side, piece: Piece) -> bool {
+        rays.iter().any(|ray| match self.first_piece(square, *ray) {
+            Some((side, p)) => side == by && (p == piece || p == Pieces::QUEEN),
+            None => false,
+        })
+    }
+
+    fn first_piece(&self, from: Square, ray: Step) -> Option<(Side, Piece)> {
+        let mut square = from;
+        while let Some(next) = step(square, ray) {
+            if self.squares[next].is_some() {
+                return self.squares[next];
+            }
+            square = next;
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Searches that were stopped halfway must not leave results in the
+    // TT that change the outcome of a later search.
+    #[test]
+    fn abandoned_searches_leave_no_trace() {
+        let (clean, polluted) = abandoned_searches(MoveGenerator::new());
+        assert_eq!(clean, Some((2, String::from("d5f6"))));
+        assert_eq!(polluted, clean);
+    }
+}
@@ -0,0 +1,214 @@
+/* =======================================================================
+Rustic is a chess playing engine.
+Copyright (C) 2019-2024, Marcel Vanthoor
+https://rustic-chess.org/
+
+Rustic is written in the Rust programming language. It is an original
+work, not derived from any engine that came before it. However, it does
+use a lot of concepts which are well-known and are in use by most if not
+all classical alpha/beta-based chess engines.
+
+Rustic is free software: you can redistribute it and/or modify it under
+the terms of the GNU General Public License version 3 as published by
+the Free Software Foundation.
+
+Rustic is distributed in the hope that it will be useful, but WITHOUT
+ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License
+for more details.
+
+You should have received a copy of the GNU General Public License along
+with this program.  If not, see <http://www.gnu.org/licenses/>.
+======================================================================= */
+
+// tactics.rs runs a tactical test suite such as WAC or STS. Each position
+// in the EPD file is searched for a fixed time. The position is solved if
+// the engine plays one of the "bm" (best move) moves, and none of the
+// "am" (avoid move) moves. STS files also give points for the best few
+// moves in the "c0" opcode, as in c0 "Nd5=10, Bb5=3"; if these are
+// present, they are used for the score. Otherwise, a solved position
+// scores one point.
+
+use crate::{
+    board::Board,
+    engine::defs::{info_channel, ErrFatal, Information, SearchData, TT},
+    misc::{epd::EpdRecord, san},
+    movegen::{
+        defs::{Move, MoveList, MoveType},
+        MoveGenerator,
+    },
+    search::{
+        defs::{SearchControl, SearchMode, SearchParams, SearchReport},
+        Search,
+    },
+};
+use std::{
+    fs,
+    sync::{Arc, Mutex, RwLock},
+};
+
+pub fn run(
+    file_name: &str,
+    move_time: u128,
+    threads: usize,
+    board: Arc<Mutex<Board>>,
+    mg: Arc<MoveGenerator>,
+    tt: Arc<RwLock<TT<SearchData>>>,
+) {
+    let text = match fs::read_to_string(file_name) {
+        Ok(t) => t,
+        Err(e) => {
+            println!("Cannot read {file_name}: {e}");
+            return;
+        }
+    };
+
+    let (info_tx, info_rx) = info_channel();
+    let mut search = Search::new();
+    search.init(
+        info_tx,
+        Arc::clone(&board),
+        Arc::clone(&mg),
+        Arc::clone(&tt),
+        true,
+    );
+
+    let mut tested = 0;
+    let mut solved = 0;
+    let mut score = 0;
+    let mut max_score = 0;
+
+    println!("Running {file_name} at {move_time} ms per position");
+
+    for (nr, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let record = match EpdRecord::parse(line) {
+            Some(r) => r,
+            None => {
+                println!("Line {}: cannot parse EPD record", nr + 1);
+                continue;
+            }
+        };
+        let id = record.operand("id").unwrap_or("-").to_string();
+
+        // Set up the position and find the moves the test expects.
+        let (best, avoid, points) = {
+            let mut board = board.lock().expect(ErrFatal::LOCK);
+            if board.fen_read(Some(&record.fen)).is_err() || !board.is_legal_position(&mg) {
+                println!("{id}: cannot set up position {}", record.fen);
+                continue;
+            }
+
+            let mut find = |opcode: &str| -> Option<Vec<Move>> {
+                let operands = record.operands(opcode).unwrap_or(&[]);
+                operands
+                    .iter()
+                    .map(|text| find_move(&mut board, &mg, text))
+                    .collect()
+            };
+
+            match (find("bm"), find("am")) {
+                (Some(best), Some(avoid)) => {
+                    let points = record
+                        .operand("c0")
+                        .and_then(|c0| parse_points(c0, |text| find_move(&mut board, &mg, text)));
+                    (best, avoid, points)
+                }
+                _ => {
+                    println!("{id}: bm or am contains an illegal move");
+                    continue;
+                }
+            }
+        };
+
+        if best.is_empty() && avoid.is_empty() {
+            println!("{id}: no bm or am to test");
+            continue;
+        }
+
+        // Each position is searched from scratch.
+        tt.write().expect(ErrFatal::LOCK).clear();
+        let mut sp = SearchParams::new();
+        sp.search_mode = SearchMode::MoveTime;
+        sp.move_time = move_time;
+        sp.threads = threads;
+        sp.quiet = true;
+        search.send(SearchControl::Start(Box::new(sp)));
+
+        let played = loop {
+            let information = info_rx.recv().expect(ErrFatal::CHANNEL);
+            if let Information::Search(SearchReport::Finished(m, _)) = information {
+                break m;
+            }
+        };
+
+        let is_move = |m: &Move| m.to_short_move() == played.to_short_move();
+        let is_solved = (best.is_empty() || best.iter().any(is_move)) && !avoid.iter().any(is_move);
+        let (position_score, position_max) = match &points {
+            Some(p) => (
+                p.iter().find(|(m, _)| is_move(m)).map_or(0, |(_, s)| *s),
+                p.iter().map(|(_, s)| *s).max().unwrap_or(0),
+            ),
+            None => (u32::from(is_solved), 1),
+        };
+
+        tested += 1;
+        solved += usize::from(is_solved);
+        score += position_score;
+        max_score += position_max;
+
+        println!(
+            "{id}: {} played {} (score {position_score}/{position_max})",
+            if is_solved { "solved" } else { "failed" },
+            played.as_string()
+        );
+    }
+
+    search.send(SearchControl::Quit);
+    search.wait_for_shutdown();
+
+    let percentage = if tested > 0 {
+        solved as f64 * 100.0 / tested as f64
+    } else {
+        0.0
+    };
+    println!(
+        "Solved {solved} of {tested} ({percentage:.1}%), failed {}, score {score} of {max_score}",
+        tested - solved
+    );
+}
+
+// Finds the legal move written in either SAN or long algebraic notation.
+fn find_move(board: &mut Board, mg: &MoveGenerator, text: &str) -> Option<Move> {
+    if let Some(m) = san::san_to_move(board, mg, text) {
+        return Some(m);
+    }
+
+    let mut move_list = MoveList::new();
+    mg.generate_moves(board, &mut move_list, MoveType::All);
+    (0..move_list.len())
+        .map(|i| move_list.get_move(i))
+        .find(|m| {
+            m.as_string() == text && board.make(*m, mg) && {
+                board.unmake();
+                true
+            }
+        })
+}
+
+// Parses the points for each move, such as "Nd5=10, Bb5=3, Nf5=2".
+fn parse_points(
+    text: &str,
+    mut find: impl FnMut(&str) -> Option<Move>,
+) -> Option<Vec<(Move, u32)>> {
+    text.split(',')
+        .map(|entry| {
+            let (m, points) = entry.trim().split_once('=')?;
+            Some((find(m.trim())?, points.trim().parse::<u32>().ok()?))
+        })
+        .collect()
+}
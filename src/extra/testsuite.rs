@@ -23,15 +23,14 @@ with this program.  If not, see <http://www.gnu.org/licenses/>.
 
 use crate::{
     board::Board,
-    engine::defs::{PerftData, TT},
     extra::epds::LARGE_TEST_EPDS,
-    misc::{perft, print},
+    misc::{
+        perft::{self, PerftCache},
+        print::{self, BoardStyle},
+    },
     movegen::MoveGenerator,
 };
-use std::{
-    sync::{Arc, Mutex},
-    time::Instant,
-};
+use std::time::Instant;
 
 const SEMI_COLON: char = ';';
 const SPACE: char = ' ';
@@ -52,10 +51,11 @@ const TEST_RESULTS: [&str; 5] = [
 
 // This private function is the one actually running tests.
 // This can be the entire suite, or a single test.
-pub fn run(tt: Arc<Mutex<TT<PerftData>>>, tt_enabled: bool) {
+pub fn run(hash: usize) {
     let number_of_tests = LARGE_TEST_EPDS.len();
     let move_generator = MoveGenerator::new();
     let mut board: Board = Board::new();
+    let mut cache = PerftCache::new(hash);
     let mut result: usize = ERR_NONE;
 
     // Run all the tests.
@@ -75,7 +75,7 @@ pub fn run(tt: Arc<Mutex<TT<PerftData>>>, tt_enabled: bool) {
 
         // If setup ok, then print position. Else, print error and continue to the next test.
         match setup_result {
-            Ok(()) => print::position(&board, None),
+            Ok(()) => print::position(&board, None, BoardStyle::Ascii),
             Err(_) => result = ERR_FEN,
         };
 
@@ -103,7 +103,7 @@ pub fn run(tt: Arc<Mutex<TT<PerftData>>>, tt_enabled: bool) {
 
                 // This is the actual perft run for this test and depth.
                 let now = Instant::now();
-                let found_ln = perft::perft(&mut board, depth, &move_generator, &tt, tt_enabled);
+                let found_ln = perft::perft(&mut board, depth, &move_generator, &mut cache);
                 let elapsed = now.elapsed().as_millis();
                 let moves_per_second = ((found_ln * 1000) as f64 / elapsed as f64).floor();
                 let is_ok = expected_ln == found_ln;
@@ -0,0 +1,750 @@
+/* =======================================================================
+Rustic is a chess playing engine.
+Copyright (C) 2019-2024, Marcel Vanthoor
+https://rustic-chess.org/
+
+Rustic is written in the Rust programming language. It is an original
+work, not derived from any engine that came before it. However, it does
+use a lot of concepts which are well-known and are in use by most if not
+all classical alpha/beta-based chess engines.
+
+Rustic is free software: you can redistribute it and/or modify it under
+the terms of the GNU General Public License version 3 as published by
+the Free Software Foundation.
+
+Rustic is distributed in the hope that it will be useful, but WITHOUT
+ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License
+for more details.
+
+You should have received a copy of the GNU General Public License along
+with this program.  If not, see <http://www.gnu.org/licenses/>.
+======================================================================= */
+
+// texel.rs tunes evaluation weights using Texel's tuning method. The
+// evaluation of each position in a dataset is converted into an expected
+// game result with a sigmoid function. The tuner changes the weights so
+// the mean squared error between the expected and the actual results
+// becomes as small as possible.
+//
+// The dataset is either a PGN file, or a text file with one position per
+// line. From a PGN file, every position of every finished game is used,
+// except the first OPENING_PLIES of each game. Supported lines are:
+//
+// fen; result          (also with a comma instead of a semicolon)
+// fen [result]         (Zurichess style, result as 1.0, 0.5 or 0.0)
+// fen c9 "result";     (EPD with the result in the c9 opcode)
+//
+// The result is seen from white's point of view, and is either written as
+// "1-0", "1/2-1/2" and "0-1", or as 1.0, 0.5 and 0.0. The FEN may omit the
+// halfmove clock and fullmove number.
+//
+// All the evaluation parameters that are linear in the evaluation are
+// tuned: the piece square tables (which include the material values), the
+// pawn structure terms and the rook file bonuses. What is left of the
+// evaluation is taken as a fixed base value for each position. Weights
+// are optimized with Adam, using the gradient of the error, which is
+// calculated on all available CPU threads. The result is written as TOML
+// and as Rust source code.
+//
+// Every VALIDATION_EVERY-th game (or line, for a text file) is kept apart
+// as a validation set. The tuner only learns from the training set, and
+// stops when the error on the validation set has not improved for PATIENCE
+// reports: from then on, the weights only fit the training positions
+// better. The weights with the lowest validation error are written.
+//
+// Tuning can take hours. Every REPORT_EVERY epochs, the tuner prints the
+// error, the time spent and the estimated time left, and writes the weights
+// and the optimizer's state to a checkpoint file. If the run is interrupted,
+// it can be continued from there with --resume, on the same dataset.
+
+use crate::{
+    board::Board,
+    defs::{NrOf, Sides},
+    evaluation::{self, params::EvalParams, pawns, psqt::FLIP},
+    misc::{
+        bits,
+        pgn::{self, GameResult},
+    },
+    movegen::MoveGenerator,
+};
+use std::{
+    fs::{self, File},
+    io::{BufRead, BufReader},
+    thread,
+    time::Instant,
+};
+
+// Layout of the weights: first all the PSQT's, then the other terms.
+const PSQT_WEIGHTS: usize = NrOf::PIECE_TYPES * NrOf::SQUARES;
+const DOUBLED: usize = PSQT_WEIGHTS;
+const ISOLATED: usize = DOUBLED + 1;
+const BACKWARD: usize = ISOLATED + 1;
+const PASSED: usize = BACKWARD + 1;
+const ROOK_OPEN: usize = PASSED + NrOf::RANKS;
+const ROOK_HALF_OPEN: usize = ROOK_OPEN + 1;
+const NR_OF_WEIGHTS: usize = ROOK_HALF_OPEN + 1;
+
+// Adam optimizer settings.
+const EPOCHS: usize = 5000;
+const LEARNING_RATE: f64 = 0.5;
+const BETA1: f64 = 0.9;
+const BETA2: f64 = 0.999;
+const EPSILON: f64 = 1e-8;
+const REPORT_EVERY: usize = 100;
+
+// Validation set and early stopping.
+const VALIDATION_EVERY: usize = 10;
+const PATIENCE: usize = 10;
+
+// Positions at the start of a game are mostly book moves; skip them.
+const OPENING_PLIES: usize = 8;
+
+// Files the tuned parameters are written to.
+const OUTPUT_TOML: &str = "tuned.toml";
+const OUTPUT_RUST: &str = "tuned.rs";
+
+// File the tuner's state is saved in, and the line it starts with.
+const CHECKPOINT: &str = "tuner.checkpoint";
+const CHECKPOINT_HEADER: &str = "rustic texel checkpoint";
+
+type Weights = Vec<f64>;
+
+// A position from the dataset. Only what the tuner needs is kept: the
+// part of the evaluation that is not tuned, the number of times each
+// tuned term occurs (only the terms that do), and the game result.
+// Everything is from white's point of view.
+struct TunerPosition {
+    base: f32,
+    terms: Vec<(u16, i8)>,
+    result: f32,
+}
+
+// The weights with the lowest validation error so far, and the epoch
+// after which they were found.
+struct Best {
+    epoch: usize,
+    error: f64,
+    weights: Weights,
+}
+
+// The state of the tuner after an epoch: everything needed to continue
+// as if the run had not been interrupted.
+struct Checkpoint {
+    dataset: String,
+    epoch: usize,
+    k: f64,
+    weights: Weights,
+    m: Weights,
+    v: Weights,
+    best: Best,
+}
+
+pub struct Tuner {
+    training: Vec<TunerPosition>,
+    validation: Vec<TunerPosition>,
+    params: EvalParams,
+    weights: Weights,
+    k: f64,
+    threads: usize,
+}
+
+impl Tuner {
+    pub fn new() -> Self {
+        let threads = thread::available_parallelism().map_or(1, |n| n.get());
+        let params = EvalParams::DEFAULT;
+
+        Self {
+            training: Vec::new(),
+            validation: Vec::new(),
+            weights: Tuner::to_weights(&params),
+            params,
+            k: 1.0,
+            threads,
+        }
+    }
+
+    // Load the dataset, then tune the weights and write them. If "resume"
+    // is set, tuning continues from the checkpoint of an earlier run.
+    pub fn run(&mut self, file_name: &str, resume: bool) {
+        println!("Loading dataset: {file_name}");
+        if let Err(e) = self.load(file_name) {
+            println!("Cannot read {file_name}: {e}");
+            return;
+        }
+
+        if self.training.is_empty() || self.validation.is_empty() {
+            println!("Not enough usable positions found.");
+            return;
+        }
+
+        println!(
+            "Positions: {} training, {} validation, weights: {NR_OF_WEIGHTS}, threads: {}",
+            self.training.len(),
+            self.validation.len(),
+            self.threads
+        );
+
+        // The positions must be loaded with the starting weights, so their
+        // base values are correct. The checkpoint's weights replace them
+        // afterward.
+        let checkpoint = if resume {
+            match Checkpoint::read(CHECKPOINT, file_name) {
+                Ok(c) => Some(c),
+                Err(e) => {
+                    println!("Cannot resume: {e}");
+                    return;
+                }
+            }
+        } else {
+            None
+        };
+
+        match &checkpoint {
+            Some(c) => {
+                println!("Resuming after epoch {} from {CHECKPOINT}", c.epoch);
+                self.weights = c.weights.clone();
+                self.k = c.k;
+            }
+            None => self.k = self.find_k(),
+        }
+
+        println!("Scaling constant K: {:.4}", self.k);
+        println!(
+            "Starting error: training {:.8}, validation {:.8}",
+            self.error(&self.training, &self.weights),
+            self.error(&self.validation, &self.weights)
+        );
+
+        self.optimize(file_name, checkpoint);
+        self.write_params();
+    }
+}
+
+// Saving and loading the tuner's state. Each line of the file holds one
+// value, or the values of all weights separated by spaces. Floating point
+// numbers are written with as many digits as it takes to read back the
+// exact same number.
+impl Checkpoint {
+    fn write(&self, file_name: &str) -> std::io::Result<()> {
+        let list = |values: &Weights| {
+            let values: Vec<String> = values.iter().map(|v| v.to_string()).collect();
+            values.join(" ")
+        };
+        let contents = [
+            String::from(CHECKPOINT_HEADER),
+            self.dataset.clone(),
+            self.epoch.to_string(),
+            self.k.to_string(),
+            list(&self.weights),
+            list(&self.m),
+            list(&self.v),
+            self.best.epoch.to_string(),
+            self.best.error.to_string(),
+            list(&self.best.weights),
+        ]
+        .join("\n");
+
+        // If the tuner is stopped while writing, the previous checkpoint
+        // is still there.
+        let temporary = format!("{file_name}.tmp");
+        fs::write(&temporary, contents + "\n")?;
+        fs::rename(&temporary, file_name)
+    }
+
+    fn read(file_name: &str, dataset: &str) -> Result<Self, String> {
+        let text = fs::read_to_string(file_name).map_err(|e| format!("{file_name}: {e}"))?;
+        let invalid = || format!("{file_name} is not a valid checkpoint");
+        let mut lines = text.lines();
+
+        if lines.next() != Some(CHECKPOINT_HEADER) {
+            return Err(invalid());
+        }
+
+        let checkpoint = Checkpoint {
+            dataset: lines.next().ok_or_else(invalid)?.to_string(),
+            epoch: lines
+                .next()
+                .and_then(|l| l.parse().ok())
+                .ok_or_else(invalid)?,
+            k: lines
+                .next()
+                .and_then(|l| l.parse().ok())
+                .ok_or_else(invalid)?,
+            weights: Checkpoint::list(lines.next()).ok_or_else(invalid)?,
+            m: Checkpoint::list(lines.next()).ok_or_else(invalid)?,
+            v: Checkpoint::list(lines.next()).ok_or_else(invalid)?,
+            best: Best {
+                epoch: lines
+                    .next()
+                    .and_then(|l| l.parse().ok())
+                    .ok_or_else(invalid)?,
+                error: lines
+                    .next()
+                    .and_then(|l| l.parse().ok())
+                    .ok_or_else(invalid)?,
+                weights: Checkpoint::list(lines.next()).ok_or_else(invalid)?,
+            },
+        };
+
+        if checkpoint.dataset != dataset {
+            let msg = format!("{file_name} was made with {}", checkpoint.dataset);
+            return Err(msg);
+        }
+
+        Ok(checkpoint)
+    }
+
+    // A list of values, if there is one for each weight.
+    fn list(line: Option<&str>) -> Option<Weights> {
+        let values: Option<Weights> = line?.split_whitespace().map(|v| v.parse().ok()).collect();
+        values.filter(|v| v.len() == NR_OF_WEIGHTS)
+    }
+}
+
+// Loading the dataset.
+impl Tuner {
+    fn load(&mut self, file_name: &str) -> std::io::Result<()> {
+        if file_name.to_lowercase().ends_with(".pgn") {
+            return self.load_pgn(file_name);
+        }
+
+        let reader = BufReader::new(File::open(file_name)?);
+        let mut board = Board::new();
+        let mut skipped = 0;
+        let mut count = 0;
+
+        for line in reader.lines() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let position = Tuner::parse_line(line).and_then(|(fen, result)| {
+                board.fen_read(Some(&fen)).ok()?;
+                Some(self.create_position(&board, result))
+            });
+
+            match position {
+                Some(p) => {
+                    count += 1;
+                    if count % VALIDATION_EVERY == 0 {
+                        self.validation.push(p);
+                    } else {
+                        self.training.push(p);
+                    }
+                }
+                None => skipped += 1,
+            }
+        }
+
+        if skipped > 0 {
+            println!("Skipped {skipped} lines that could not be read.");
+        }
+
+        Ok(())
+    }
+
+    // Use all positions from all finished games in the PGN file. All
+    // positions of a game go into the same set, so the validation set
+    // doesn't contain positions the tuner has nearly seen already.
+    fn load_pgn(&mut self, file_name: &str) -> std::io::Result<()> {
+        let text = fs::read_to_string(file_name)?;
+        let mg = MoveGenerator::new();
+        let mut skipped = 0;
+        let mut count = 0;
+
+        for game in pgn::read(&text, &mg) {
+            let game = match game {
+                Ok(g) => g,
+                Err(_) => {
+                    skipped += 1;
+                    continue;
+                }
+            };
+
+            // Games without a result can't be used.
+            if let Some(result) = game.result.score() {
+                count += 1;
+                let validation = count % VALIDATION_EVERY == 0;
+                let mut ply = 0;
+                let replayed = game.replay(&mg, |board| {
+                    if ply >= OPENING_PLIES {
+                        let p = self.create_position(board, result);
+                        if validation {
+                            self.validation.push(p);
+                        } else {
+                            self.training.push(p);
+                        }
+                    }
+                    ply += 1;
+                });
+
+                if replayed.is_err() {
+                    skipped += 1;
+                }
+            }
+        }
+
+        if skipped > 0 {
+            println!("Skipped {skipped} games that could not be read.");
+        }
+
+        Ok(())
+    }
+
+    // Split a line into a FEN-string and the game result.
+    fn parse_line(line: &str) -> Option<(String, f32)> {
+        let (fen, result) = if let Some(i) = line.find("c9") {
+            let result = line[i + 2..].trim().trim_end_matches(';').trim_matches('"');
+            (&line[..i], result)
+        } else if let (Some(open), Some(close)) = (line.find('['), line.rfind(']')) {
+            (&line[..open], &line[open + 1..close])
+        } else {
+            line.rsplit_once([';', ','])?
+        };
+
+        // Keep the FEN's board, color, castling and en-passant parts, and
+        // the clocks if they are there.
+        let fen = fen
+            .split_whitespace()
+            .take(6)
+            .collect::<Vec<&str>>()
+            .join(" ");
+        let result = GameResult::parse(result)?.score()?;
+
+        Some((fen, result))
+    }
+
+    fn create_position(&self, board: &Board, result: f32) -> TunerPosition {
+        let terms = Tuner::terms(board);
+
+        // The engine evaluates from the side to move's point of view.
+        let mut eval = evaluation::evaluate_position(board) as f64;
+        if board.us() == Sides::BLACK {
+            eval = -eval;
+        }
+
+        let tuned: f64 = terms
+            .iter()
+            .map(|&(i, t)| t as f64 * self.weights[i as usize])
+            .sum();
+
+        TunerPosition {
+            base: (eval - tuned) as f32,
+            terms,
+            result,
+        }
+    }
+
+    // Count how many times each tuned term occurs in the position. White's
+    // terms count as positive, black's as negative.
+    fn terms(board: &Board) -> Vec<(u16, i8)> {
+        let mut counts = vec![0i8; NR_OF_WEIGHTS];
+
+        for piece in 0..NrOf::PIECE_TYPES {
+            let mut white = board.get_pieces(piece, Sides::WHITE);
+            let mut black = board.get_pieces(piece, Sides::BLACK);
+
+            while white > 0 {
+                let square = bits::next(&mut white);
+                counts[piece * NrOf::SQUARES + FLIP[square]] += 1;
+            }
+
+            while black > 0 {
+                let square = bits::next(&mut black);
+                counts[piece * NrOf::SQUARES + square] -= 1;
+            }
+        }
+
+        let p = pawns::terms(board);
+        let pawn_data = pawns::evaluate(board);
+        let (w_open, w_half_open) = evaluation::rook_file_counts(board, Sides::WHITE, &pawn_data);
+        let (b_open, b_half_open) = evaluation::rook_file_counts(board, Sides::BLACK, &pawn_data);
+
+        counts[DOUBLED] = p.doubled as i8;
+        counts[ISOLATED] = p.isolated as i8;
+        counts[BACKWARD] = p.backward as i8;
+        for (rank, count) in p.passed.iter().enumerate() {
+            counts[PASSED + rank] = *count as i8;
+        }
+        counts[ROOK_OPEN] = (w_open - b_open) as i8;
+        counts[ROOK_HALF_OPEN] = (w_half_open - b_half_open) as i8;
+
+        // Keep only the terms that occur.
+        counts
+            .iter()
+            .enumerate()
+            .filter(|(_, &c)| c != 0)
+            .map(|(i, &c)| (i as u16, c))
+            .collect()
+    }
+}
+
+// Conversion between the evaluation parameters and the weights.
+impl Tuner {
+    fn to_weights(params: &EvalParams) -> Weights {
+        let mut weights = vec![0.0; NR_OF_WEIGHTS];
+
+        for (i, value) in params.psqt.iter().flatten().enumerate() {
+            weights[i] = *value as f64;
+        }
+
+        weights[DOUBLED] = params.doubled as f64;
+        weights[ISOLATED] = params.isolated as f64;
+        weights[BACKWARD] = params.backward as f64;
+        for (rank, value) in params.passed.iter().enumerate() {
+            weights[PASSED + rank] = *value as f64;
+        }
+        weights[ROOK_OPEN] = params.rook_open_file as f64;
+        weights[ROOK_HALF_OPEN] = params.rook_half_open_file as f64;
+
+        weights
+    }
+
+    // Parameters that are not tuned are taken from the starting
+    // parameters.
+    fn to_params(&self, weights: &Weights) -> EvalParams {
+        let w = |i: usize| weights[i].round() as i16;
+        let mut params = self.params.clone();
+
+        for (i, value) in params.psqt.iter_mut().flatten().enumerate() {
+            *value = w(i);
+        }
+
+        params.doubled = w(DOUBLED);
+        params.isolated = w(ISOLATED);
+        params.backward = w(BACKWARD);
+        for (rank, value) in params.passed.iter_mut().enumerate() {
+            *value = w(PASSED + rank);
+        }
+        params.rook_open_file = w(ROOK_OPEN);
+        params.rook_half_open_file = w(ROOK_HALF_OPEN);
+
+        params
+    }
+
+    // Write the tuned parameters as TOML and as Rust source.
+    fn write_params(&self) {
+        let params = self.to_params(&self.weights);
+
+        for (file_name, contents) in [
+            (OUTPUT_TOML, params.to_toml()),
+            (OUTPUT_RUST, params.to_rust()),
+        ] {
+            match fs::write(file_name, contents) {
+                Ok(()) => println!("Tuned parameters written to {file_name}"),
+                Err(e) => println!("Cannot write {file_name}: {e}"),
+            }
+        }
+    }
+}
+
+// Error and gradient calculation. Both are split over all threads.
+impl Tuner {
+    fn sigmoid(k: f64, eval: f64) -> f64 {
+        1.0 / (1.0 + 10f64.powf(-k * eval / 400.0))
+    }
+
+    fn evaluate(p: &TunerPosition, weights: &Weights) -> f64 {
+        let tuned: f64 = p
+            .terms
+            .iter()
+            .map(|&(i, t)| t as f64 * weights[i as usize])
+            .sum();
+
+        p.base as f64 + tuned
+    }
+
+    // Mean squared error over a set of positions.
+    fn error(&self, positions: &[TunerPosition], weights: &Weights) -> f64 {
+        self.error_with_k(positions, self.k, weights)
+    }
+
+    fn error_with_k(&self, positions: &[TunerPosition], k: f64, weights: &Weights) -> f64 {
+        let chunk_size = positions.len().div_ceil(self.threads);
+
+        let total: f64 = thread::scope(|s| {
+            let handles: Vec<_> = positions
+                .chunks(chunk_size)
+                .map(|chunk| {
+                    s.spawn(move || {
+                        chunk
+                            .iter()
+                            .map(|p| {
+                                let sig = Tuner::sigmoid(k, Tuner::evaluate(p, weights));
+                                let e = p.result as f64 - sig;
+                                e * e
+                            })
+                            .sum::<f64>()
+                    })
+                })
+                .collect();
+
+            handles.into_iter().map(|h| h.join().unwrap_or(0.0)).sum()
+        });
+
+        total / positions.len() as f64
+    }
+
+    // Gradient of the training set's mean squared error with regard to
+    // each weight.
+    fn gradient(&self, weights: &Weights) -> Weights {
+        let chunk_size = self.training.len().div_ceil(self.threads);
+        let k = self.k;
+
+        let total: Weights = thread::scope(|s| {
+            let handles: Vec<_> = self
+                .training
+                .chunks(chunk_size)
+                .map(|chunk| {
+                    s.spawn(move || {
+                        let mut gradient = vec![0.0; NR_OF_WEIGHTS];
+                        for p in chunk {
+                            let sig = Tuner::sigmoid(k, Tuner::evaluate(p, weights));
+                            let slope = (p.result as f64 - sig) * sig * (1.0 - sig);
+                            for &(i, t) in p.terms.iter() {
+                                gradient[i as usize] += slope * t as f64;
+                            }
+                        }
+                        gradient
+                    })
+                })
+                .collect();
+
+            let mut total = vec![0.0; NR_OF_WEIGHTS];
+            for h in handles {
+                if let Ok(gradient) = h.join() {
+                    for (t, g) in total.iter_mut().zip(gradient.iter()) {
+                        *t += g;
+                    }
+                }
+            }
+            total
+        });
+
+        let factor = -2.0 * k * 10f64.ln() / 400.0 / self.training.len() as f64;
+        total.iter().map(|g| factor * g).collect()
+    }
+}
+
+// Optimization.
+impl Tuner {
+    // Find the scaling constant K for which the current weights give the
+    // smallest error on the training set. First search in large steps,
+    // then refine.
+    fn find_k(&self) -> f64 {
+        let mut best_k = 1.0;
+        let mut best_error = self.error_with_k(&self.training, best_k, &self.weights);
+        let mut step = 0.1;
+        let (mut low, mut high) = (0.0, 3.0);
+
+        for _ in 0..3 {
+            let mut k = low;
+            while k <= high {
+                let error = self.error_with_k(&self.training, k, &self.weights);
+                if error < best_error {
+                    best_error = error;
+                    best_k = k;
+                }
+                k += step;
+            }
+
+            low = best_k - step;
+            high = best_k + step;
+            step /= 10.0;
+        }
+
+        best_k
+    }
+
+    // Adam: every weight gets its own learning rate, which adapts to the
+    // size and stability of its gradient. The moments m and v are part of
+    // the checkpoint, so a resumed run continues with the same steps. When
+    // done, the weights are set to the best ones found.
+    fn optimize(&mut self, dataset: &str, checkpoint: Option<Checkpoint>) {
+        let (first, mut m, mut v, mut best) = match checkpoint {
+            Some(c) => (c.epoch + 1, c.m, c.v, c.best),
+            None => {
+                let best = Best {
+                    epoch: 0,
+                    error: self.error(&self.validation, &self.weights),
+                    weights: self.weights.clone(),
+                };
+                (1, vec![0.0; NR_OF_WEIGHTS], vec![0.0; NR_OF_WEIGHTS], best)
+            }
+        };
+        let now = Instant::now();
+
+        for epoch in first..=EPOCHS {
+            let gradient = self.gradient(&self.weights);
+
+            for i in 0..NR_OF_WEIGHTS {
+                m[i] = BETA1 * m[i] + (1.0 - BETA1) * gradient[i];
+                v[i] = BETA2 * v[i] + (1.0 - BETA2) * gradient[i] * gradient[i];
+
+                let m_hat = m[i] / (1.0 - BETA1.powi(epoch as i32));
+                let v_hat = v[i] / (1.0 - BETA2.powi(epoch as i32));
+                self.weights[i] -= LEARNING_RATE * m_hat / (v_hat.sqrt() + EPSILON);
+            }
+
+            if epoch % REPORT_EVERY == 0 {
+                // The time left is estimated from the epochs done since
+                // this run started.
+                let elapsed = now.elapsed().as_secs_f64();
+                let left = elapsed / (epoch + 1 - first) as f64 * (EPOCHS - epoch) as f64;
+                let validation_error = self.error(&self.validation, &self.weights);
+                println!(
+                    "Epoch {epoch}/{EPOCHS}: training {:.8}, validation {:.8}, elapsed {}, ETA {}",
+                    self.error(&self.training, &self.weights),
+                    validation_error,
+                    Tuner::hms(elapsed),
+                    Tuner::hms(left)
+                );
+
+                if validation_error < best.error {
+                    best = Best {
+                        epoch,
+                        error: validation_error,
+                        weights: self.weights.clone(),
+                    };
+                }
+
+                let checkpoint = Checkpoint {
+                    dataset: dataset.to_string(),
+                    epoch,
+                    k: self.k,
+                    weights: self.weights.clone(),
+                    m: m.clone(),
+                    v: v.clone(),
+                    best: Best {
+                        weights: best.weights.clone(),
+                        ..best
+                    },
+                };
+                if let Err(e) = checkpoint.write(CHECKPOINT) {
+                    println!("Cannot write {CHECKPOINT}: {e}");
+                }
+
+                if epoch - best.epoch >= PATIENCE * REPORT_EVERY {
+                    println!(
+                        "No improvement on the validation set in {PATIENCE} reports. Stopping."
+                    );
+                    break;
+                }
+            }
+        }
+
+        println!(
+            "Best validation error {:.8} after epoch {}",
+            best.error, best.epoch
+        );
+        self.weights = best.weights;
+    }
+
+    fn hms(seconds: f64) -> String {
+        let s = seconds.round() as u64;
+        format!("{}:{:02}:{:02}", s / 3600, s / 60 % 60, s % 60)
+    }
+}
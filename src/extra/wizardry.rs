@@ -37,6 +37,17 @@ use rand_chacha::ChaChaRng;
 // is a combination of a rook and a bishop, so she does not have her own
 // magic numbers.
 pub fn find_magics(piece: Piece) {
+    let mut random = ChaChaRng::from_entropy();
+
+    println!("Finding magics for: {}", PIECE_NAME[piece]);
+    search_magics(piece, &mut random, true);
+}
+
+// This function does the actual work of finding the magic numbers, using
+// the given random number generator. With a seeded generator, the same
+// magics will be found every time. If "report" is set, each magic number
+// is printed as soon as it is found.
+pub fn search_magics(piece: Piece, random: &mut ChaChaRng, report: bool) -> Vec<u64> {
     // First check if we're actually dealing with a rook or a bishop.
     let ok = piece == Pieces::ROOK || piece == Pieces::BISHOP;
     assert!(ok, "Illegal piece: {piece}");
@@ -45,10 +56,9 @@ pub fn find_magics(piece: Piece) {
     let is_rook = piece == Pieces::ROOK;
     let mut rook_table: Vec<Bitboard> = vec![EMPTY; ROOK_TABLE_SIZE];
     let mut bishop_table: Vec<Bitboard> = vec![EMPTY; BISHOP_TABLE_SIZE];
-    let mut random = ChaChaRng::from_entropy();
+    let mut magic_nrs: Vec<u64> = Vec::new();
     let mut offset = 0;
 
-    println!("Finding magics for: {}", PIECE_NAME[piece]);
     for sq in RangeOf::SQUARES {
         // Create the mask for either the rook or bishop.
         let r_mask = MoveGenerator::rook_mask(sq);
@@ -124,7 +134,10 @@ pub fn find_magics(piece: Piece) {
         // We got out of the loop and found a random magic number that can
         // index all the attack boards for a rook/bishop for a single
         // square without a collision. Report this number.
-        found_magic(sq, try_this, offset, end, attempts);
+        if report {
+            found_magic(sq, try_this, offset, end, attempts);
+        }
+        magic_nrs.push(try_this.nr);
 
         // Set table offset for next magic.
         offset += permutations;
@@ -139,6 +152,8 @@ pub fn find_magics(piece: Piece) {
     const ERROR: &str = "Creating magics failed. Permutations were skipped.";
 
     assert!(offset == expected, "{}", ERROR);
+
+    magic_nrs
 }
 
 // Print the magic number.
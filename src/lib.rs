@@ -0,0 +1,72 @@
+/* =======================================================================
+Rustic is a chess playing engine.
+Copyright (C) 2019-2024, Marcel Vanthoor
+https://rustic-chess.org/
+
+Rustic is written in the Rust programming language. It is an original
+work, not derived from any engine that came before it. However, it does
+use a lot of concepts which are well-known and are in use by most if not
+all classical alpha/beta-based chess engines.
+
+Rustic is free software: you can redistribute it and/or modify it under
+the terms of the GNU General Public License version 3 as published by
+the Free Software Foundation.
+
+Rustic is distributed in the hope that it will be useful, but WITHOUT
+ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License
+for more details.
+
+You should have received a copy of the GNU General Public License along
+with this program.  If not, see <http://www.gnu.org/licenses/>.
+======================================================================= */
+
+// Rustic can be used as a library. The modules making up the engine are
+// internal; the "api" module is the public face of the library. It offers
+// positions, a search engine and search limits, and keeps everything
+// else (threads, channels, the board representation) out of sight. The
+// engine binary itself is a thin wrapper around run().
+//
+// In WebAssembly, only the parts of the API that work without threads are
+// available. Most of the engine (the engine thread, the protocols) is
+// then never used, but compiling it anyway is simpler than sprinkling
+// cfg attributes all over the tree.
+#![cfg_attr(target_arch = "wasm32", allow(dead_code))]
+
+mod board;
+mod comm;
+mod defs;
+mod engine;
+mod evaluation;
+mod misc;
+mod movegen;
+mod search;
+
+#[cfg(feature = "extra")]
+mod extra;
+
+pub mod api;
+pub use api::{evaluate_fen, perft_fen};
+
+#[cfg(all(target_arch = "wasm32", feature = "wasm"))]
+pub mod wasm;
+
+#[cfg(not(target_arch = "wasm32"))]
+use defs::ENGINE_RUN_ERRORS;
+#[cfg(not(target_arch = "wasm32"))]
+use engine::Engine;
+
+// Runs Rustic as a UCI or XBoard engine, as the "rustic" binary does. It
+// reads the command line, and returns when the engine quits. The engine
+// needs threads, so it is not available in WebAssembly.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn run() {
+    misc::cpu::init();
+    let mut engine = Engine::new();
+    let result = engine.run();
+
+    match result {
+        Ok(()) => (),
+        Err(e) => println!("Error code {}: {}", e, ENGINE_RUN_ERRORS[e as usize]),
+    };
+}
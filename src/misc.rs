@@ -21,8 +21,19 @@ You should have received a copy of the GNU General Public License along
 with this program.  If not, see <http://www.gnu.org/licenses/>.
 ======================================================================= */
 
+pub mod bench;
+pub mod bitboard_expr;
 pub mod bits;
+pub mod build_info;
 pub mod cmdline;
+pub mod cpu;
+pub mod eco;
+pub mod epd;
 pub mod parse;
 pub mod perft;
+pub mod pgn;
 pub mod print;
+pub mod protocol_log;
+pub mod san;
+pub mod stats_csv;
+pub mod system;
@@ -0,0 +1,181 @@
+/* =======================================================================
+Rustic is a chess playing engine.
+Copyright (C) 2019-2024, Marcel Vanthoor
+https://rustic-chess.org/
+
+Rustic is written in the Rust programming language. It is an original
+work, not derived from any engine that came before it. However, it does
+use a lot of concepts which are well-known and are in use by most if not
+all classical alpha/beta-based chess engines.
+
+Rustic is free software: you can redistribute it and/or modify it under
+the terms of the GNU General Public License version 3 as published by
+the Free Software Foundation.
+
+Rustic is distributed in the hope that it will be useful, but WITHOUT
+ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License
+for more details.
+
+You should have received a copy of the GNU General Public License along
+with this program.  If not, see <http://www.gnu.org/licenses/>.
+======================================================================= */
+
+// bench.rs searches a fixed set of positions to a fixed depth, and
+// reports the total number of nodes and the speed. The search is done on
+// one thread, with a new TT and move ordering tables, so the node count
+// is the same on every run and every computer. The built-in evaluation
+// is used, even if the engine has loaded another one. If a change isn't meant
+// to change the search (such as a speed optimization), the node count
+// must stay the same. Testing frameworks such as OpenBench use the
+// count as the signature of a build.
+
+use crate::{
+    board::Board,
+    engine::defs::{info_channel, ErrFatal, Information, SearchData, TT},
+    misc::stats_csv::StatsCsv,
+    movegen::MoveGenerator,
+    search::{
+        defs::{SearchControl, SearchMode, SearchParams, SearchReport},
+        Search,
+    },
+};
+use std::{
+    sync::{Arc, Mutex, RwLock},
+    time::Instant,
+};
+
+pub const BENCH_DEPTH: i8 = 7;
+const BENCH_TT_SIZE: usize = 16;
+
+// The warm-up search uses one of the middle game positions, and is cut
+// off after this many milliseconds.
+pub const WARM_UP_TIME: u128 = 100;
+const WARM_UP_POSITION: usize = 5;
+
+const BENCH_POSITIONS: [&str; 12] = [
+    "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+    "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+    "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+    "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+    "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+    "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+    "r1bqkb1r/pp3ppp/2np1n2/4p3/2B1P3/2N2N2/PPP2PPP/R1BQK2R w KQkq - 0 7",
+    "2r2rk1/pp1bqppp/2n1pn2/3p4/2PP4/P1NBPN2/1P3PPP/R2Q1RK1 b - - 2 13",
+    "r1bq1rk1/pp2ppbp/2np1np1/8/3NP3/2N1BP2/PPPQ2PP/R3KB1R w KQ - 3 9",
+    "8/8/1p1k4/p1p1p3/P1P1P1p1/1P1K2P1/8/8 w - - 0 40",
+    "6k1/5pp1/4p2p/8/3R4/6P1/r4P1P/6K1 b - - 0 35",
+    "7k/8/8/3Q4/8/8/5K2/3q4 w - - 0 60",
+];
+
+pub struct BenchResult {
+    pub nodes: usize,
+    pub time: u128,
+}
+
+impl BenchResult {
+    pub fn nps(&self) -> usize {
+        ((self.nodes as u128 * 1000) / self.time.max(1)) as usize
+    }
+
+    // The totals, in the same format as Stockfish's bench.
+    pub fn summary(&self) -> Vec<String> {
+        vec![
+            format!("Total time (ms) : {}", self.time),
+            format!("Nodes searched  : {}", self.nodes),
+            format!("Nodes/second    : {}", self.nps()),
+        ]
+    }
+}
+
+// Search each of the positions to the given depth. After each position,
+// a line with its results is passed to "output". The iterations are also
+// written to the search statistics file, if there is one.
+pub fn run(
+    depth: i8,
+    mg: Arc<MoveGenerator>,
+    csv: &StatsCsv,
+    mut output: impl FnMut(String),
+) -> BenchResult {
+    let board = Arc::new(Mutex::new(Board::new()));
+    let tt = Arc::new(RwLock::new(TT::<SearchData>::new(BENCH_TT_SIZE)));
+    let (info_tx, info_rx) = info_channel();
+    let mut search = Search::new();
+    search.init(info_tx, Arc::clone(&board), mg, Arc::clone(&tt), true);
+
+    let mut result = BenchResult { nodes: 0, time: 0 };
+    let now = Instant::now();
+
+    for (i, fen) in BENCH_POSITIONS.iter().enumerate() {
+        board
+            .lock()
+            .expect(ErrFatal::LOCK)
+            .fen_read(Some(fen))
+            .expect(ErrFatal::BENCH_FEN);
+        tt.write().expect(ErrFatal::LOCK).clear();
+        csv.begin(fen);
+
+        let mut sp = SearchParams::new();
+        sp.search_mode = SearchMode::Depth;
+        sp.depth = depth;
+        sp.quiet = true;
+        search.send(SearchControl::Start(Box::new(sp)));
+
+        // The node count is kept over all depths, so the last summary
+        // holds the nodes for the entire search.
+        let mut nodes = 0;
+        loop {
+            match info_rx.recv().expect(ErrFatal::CHANNEL) {
+                Information::Search(SearchReport::SearchSummary(s)) => {
+                    csv.record(&s);
+                    nodes = s.nodes;
+                }
+                Information::Search(SearchReport::Finished(..)) => break,
+                _ => (),
+            }
+        }
+
+        result.nodes += nodes;
+        output(format!("Position {:>2}: {nodes} nodes", i + 1));
+    }
+
+    result.time = now.elapsed().as_millis();
+    search.send(SearchControl::Quit);
+    search.wait_for_shutdown();
+
+    result
+}
+
+// Run a short search that nobody gets to see, using the engine's own TT.
+// The first real search then doesn't have to pay for touching the TT and
+// the move generator's tables for the first time. The search ends after
+// WARM_UP_TIME, so commands such as "isready" are never held up for
+// longer than that. The entries it leaves in the TT are from an older
+// generation by the time the first real search starts, so they are
+// replaced first.
+pub fn warm_up(mg: Arc<MoveGenerator>, tt: Arc<RwLock<TT<SearchData>>>, tt_enabled: bool) {
+    let board = Arc::new(Mutex::new(Board::new()));
+    let (info_tx, info_rx) = info_channel();
+    let mut search = Search::new();
+
+    board
+        .lock()
+        .expect(ErrFatal::LOCK)
+        .fen_read(Some(BENCH_POSITIONS[WARM_UP_POSITION]))
+        .expect(ErrFatal::BENCH_FEN);
+    search.init(info_tx, board, mg, tt, tt_enabled);
+
+    let mut sp = SearchParams::new();
+    sp.search_mode = SearchMode::MoveTime;
+    sp.move_time = WARM_UP_TIME;
+    sp.quiet = true;
+    search.send(SearchControl::Start(Box::new(sp)));
+
+    while !matches!(
+        info_rx.recv().expect(ErrFatal::CHANNEL),
+        Information::Search(SearchReport::Finished(..))
+    ) {}
+
+    search.send(SearchControl::Quit);
+    search.wait_for_shutdown();
+}
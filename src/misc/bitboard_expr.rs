@@ -0,0 +1,269 @@
+/* =======================================================================
+Rustic is a chess playing engine.
+Copyright (C) 2019-2024, Marcel Vanthoor
+https://rustic-chess.org/
+
+Rustic is written in the Rust programming language. It is an original
+work, not derived from any engine that came before it. However, it does
+use a lot of concepts which are well-known and are in use by most if not
+all classical alpha/beta-based chess engines.
+
+Rustic is free software: you can redistribute it and/or modify it under
+the terms of the GNU General Public License version 3 as published by
+the Free Software Foundation.
+
+Rustic is distributed in the hope that it will be useful, but WITHOUT
+ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License
+for more details.
+
+You should have received a copy of the GNU General Public License along
+with this program.  If not, see <http://www.gnu.org/licenses/>.
+======================================================================= */
+
+// bitboard_expr.rs evaluates small expressions that describe a bitboard in
+// the current position, such as "attacks(d1) & black". The "showbb"
+// console command uses this to show how the move generator and the
+// evaluation see the board.
+//
+// Terms:
+//   K Q R B N P       white pieces
+//   k q r b n p       black pieces
+//   white black       all pieces of one side
+//   occupied empty    all occupied or empty squares
+//   e4                a single square
+//   file_e rank_4     a file or a rank
+//   attacks(e4)       squares attacked by the piece on e4
+//   0xff00 65280      a number
+//
+// Operators are | (or), & (and), ^ (xor) and - (and not). They are
+// evaluated from left to right; there is no precedence. A ~ in front of
+// a term inverts it.
+
+use std::slice::Iter;
+
+use crate::{
+    board::{
+        defs::{Pieces, BB_FILES, BB_RANKS, BB_SQUARES},
+        Board,
+    },
+    defs::{Bitboard, Sides},
+    misc::parse,
+    movegen::MoveGenerator,
+};
+
+const OPERATORS: &str = "|&^-";
+const INVERT: char = '~';
+
+// Evaluates the expression in the given position.
+pub fn evaluate(expr: &str, board: &Board, mg: &MoveGenerator) -> Result<Bitboard, String> {
+    let tokens = tokenize(expr);
+    let mut tokens = tokens.iter();
+    let mut result = term(&mut tokens, board, mg)?;
+
+    while let Some(operator) = tokens.next() {
+        if !(operator.len() == 1 && OPERATORS.contains(&operator[..])) {
+            return Err(format!("Expected an operator instead of '{operator}'"));
+        }
+
+        let value = term(&mut tokens, board, mg)?;
+        result = match &operator[..] {
+            "|" => result | value,
+            "&" => result & value,
+            "^" => result ^ value,
+            _ => result & !value,
+        };
+    }
+
+    Ok(result)
+}
+
+// Splits the expression into operators, inversions and terms.
+fn tokenize(expr: &str) -> Vec<String> {
+    let mut tokens: Vec<String> = Vec::new();
+    let mut current = String::new();
+
+    for c in expr.chars() {
+        if c.is_whitespace() || OPERATORS.contains(c) || c == INVERT {
+            if !current.is_empty() {
+                tokens.push(std::mem::take(&mut current));
+            }
+            if !c.is_whitespace() {
+                tokens.push(c.to_string());
+            }
+        } else {
+            current.push(c);
+        }
+    }
+
+    if !current.is_empty() {
+        tokens.push(current);
+    }
+
+    tokens
+}
+
+// Evaluates a single term. Each ~ in front of it inverts the result.
+fn term(tokens: &mut Iter<String>, board: &Board, mg: &MoveGenerator) -> Result<Bitboard, String> {
+    match tokens.next() {
+        Some(token) if token.starts_with(INVERT) => Ok(!term(tokens, board, mg)?),
+        Some(token) => value(token, board, mg),
+        None => Err(String::from("The expression is incomplete")),
+    }
+}
+
+fn value(token: &str, board: &Board, mg: &MoveGenerator) -> Result<Bitboard, String> {
+    let bitboard = match token {
+        "white" => board.bb_side[Sides::WHITE],
+        "black" => board.bb_side[Sides::BLACK],
+        "occupied" => board.occupancy(),
+        "empty" => !board.occupancy(),
+        t if t.starts_with("file_") => line(&t[5..], "abcdefgh", &BB_FILES)?,
+        t if t.starts_with("rank_") => line(&t[5..], "12345678", &BB_RANKS)?,
+        t if t.starts_with("attacks(") && t.ends_with(')') => {
+            attacks(&t[8..t.len() - 1], board, mg)?
+        }
+        t if t.starts_with("0x") => {
+            Bitboard::from_str_radix(&t[2..], 16).map_err(|_| unknown(t))?
+        }
+        t if t.starts_with(|c: char| c.is_ascii_digit()) => {
+            t.parse::<Bitboard>().map_err(|_| unknown(t))?
+        }
+        t if t.len() == 1 => piece(t, board)?,
+        t => BB_SQUARES[parse::algebraic_square_to_number(t).ok_or_else(|| unknown(t))?],
+    };
+
+    Ok(bitboard)
+}
+
+fn piece(token: &str, board: &Board) -> Result<Bitboard, String> {
+    let c = token.chars().next().unwrap_or(' ');
+    let side = if c.is_ascii_uppercase() {
+        Sides::WHITE
+    } else {
+        Sides::BLACK
+    };
+    let piece = match c.to_ascii_lowercase() {
+        'k' => Pieces::KING,
+        'q' => Pieces::QUEEN,
+        'r' => Pieces::ROOK,
+        'b' => Pieces::BISHOP,
+        'n' => Pieces::KNIGHT,
+        'p' => Pieces::PAWN,
+        _ => return Err(unknown(token)),
+    };
+
+    Ok(board.get_pieces(piece, side))
+}
+
+// A file (a-h) or rank (1-8), selected by its name.
+fn line(name: &str, names: &str, lines: &[Bitboard; 8]) -> Result<Bitboard, String> {
+    match names.find(name) {
+        Some(index) if name.len() == 1 => Ok(lines[index]),
+        _ => Err(unknown(name)),
+    }
+}
+
+// The squares attacked by the piece on the given square, with the pieces
+// that are currently on the board blocking the sliders.
+fn attacks(square_name: &str, board: &Board, mg: &MoveGenerator) -> Result<Bitboard, String> {
+    let square =
+        parse::algebraic_square_to_number(square_name).ok_or_else(|| unknown(square_name))?;
+    let piece = board.piece_list[square];
+    let side = if board.bb_side[Sides::WHITE] & BB_SQUARES[square] > 0 {
+        Sides::WHITE
+    } else {
+        Sides::BLACK
+    };
+
+    match piece {
+        Pieces::KING | Pieces::KNIGHT => Ok(mg.get_non_slider_attacks(piece, square)),
+        Pieces::QUEEN | Pieces::ROOK | Pieces::BISHOP => {
+            Ok(mg.get_slider_attacks(piece, square, board.occupancy()))
+        }
+        Pieces::PAWN => Ok(mg.get_pawn_attacks(side, square)),
+        _ => Err(format!("There is no piece on {square_name}")),
+    }
+}
+
+fn unknown(token: &str) -> String {
+    format!("Unknown term '{token}'")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::defs::FEN_START_POSITION;
+
+    fn eval(expr: &str) -> Result<Bitboard, String> {
+        let mut board = Board::new();
+        board.fen_read(Some(FEN_START_POSITION)).unwrap();
+        evaluate(expr, &board, &MoveGenerator::new())
+    }
+
+    fn squares(names: &[&str]) -> Bitboard {
+        names
+            .iter()
+            .map(|n| BB_SQUARES[parse::algebraic_square_to_number(n).unwrap()])
+            .fold(0, |bb, square| bb | square)
+    }
+
+    #[test]
+    fn terms() {
+        assert_eq!(eval("P"), Ok(0xff00));
+        assert_eq!(eval("white"), Ok(0xffff));
+        assert_eq!(eval("k"), Ok(squares(&["e8"])));
+        assert_eq!(eval("e4"), Ok(squares(&["e4"])));
+        assert_eq!(eval("rank_1"), Ok(0xff));
+        assert_eq!(eval("0xff00"), Ok(0xff00));
+        assert_eq!(eval("65280"), Ok(0xff00));
+        assert_eq!(eval("empty"), Ok(0x0000_ffff_ffff_0000));
+    }
+
+    #[test]
+    fn attacks_are_blocked_by_pieces() {
+        assert_eq!(eval("attacks(g1)"), Ok(squares(&["e2", "f3", "h3"])));
+        assert_eq!(eval("attacks(e2)"), Ok(squares(&["d3", "f3"])));
+        assert_eq!(eval("attacks(e7)"), Ok(squares(&["d6", "f6"])));
+        assert_eq!(
+            eval("attacks(d1)"),
+            Ok(squares(&["c1", "e1", "c2", "d2", "e2"]))
+        );
+    }
+
+    #[test]
+    fn operators_work_from_left_to_right() {
+        assert_eq!(eval("file_e & rank_2"), Ok(squares(&["e2"])));
+        assert_eq!(eval("occupied - white"), eval("black"));
+        assert_eq!(eval("~occupied"), eval("empty"));
+        assert_eq!(eval("~~white"), eval("white"));
+        assert_eq!(
+            eval("rank_1 ^ R"),
+            Ok(squares(&["b1", "c1", "d1", "e1", "f1", "g1"]))
+        );
+        assert_eq!(eval("0xff|256"), Ok(0x1ff));
+
+        // Without precedence, the | is done before the &.
+        assert_eq!(eval("white | black & rank_8"), eval("rank_8"));
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(eval("x"), Err(String::from("Unknown term 'x'")));
+        assert_eq!(eval("file_i"), Err(String::from("Unknown term 'i'")));
+        assert_eq!(eval("e9"), Err(String::from("Unknown term 'e9'")));
+        assert_eq!(eval("0xfg"), Err(String::from("Unknown term '0xfg'")));
+        assert_eq!(
+            eval("attacks(e4)"),
+            Err(String::from("There is no piece on e4"))
+        );
+        assert_eq!(
+            eval("e4 |"),
+            Err(String::from("The expression is incomplete"))
+        );
+        assert_eq!(
+            eval("e4 e5"),
+            Err(String::from("Expected an operator instead of 'e5'"))
+        );
+    }
+}
@@ -0,0 +1,107 @@
+/* =======================================================================
+Rustic is a chess playing engine.
+Copyright (C) 2019-2024, Marcel Vanthoor
+https://rustic-chess.org/
+
+Rustic is written in the Rust programming language. It is an original
+work, not derived from any engine that came before it. However, it does
+use a lot of concepts which are well-known and are in use by most if not
+all classical alpha/beta-based chess engines.
+
+Rustic is free software: you can redistribute it and/or modify it under
+the terms of the GNU General Public License version 3 as published by
+the Free Software Foundation.
+
+Rustic is distributed in the hope that it will be useful, but WITHOUT
+ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License
+for more details.
+
+You should have received a copy of the GNU General Public License along
+with this program.  If not, see <http://www.gnu.org/licenses/>.
+======================================================================= */
+
+// build_info.rs describes the binary that is running: where and how it
+// was built (from the constants generated by build.rs), and which CPU
+// features it can use on this computer.
+
+use crate::misc::cpu;
+
+include!(concat!(env!("OUT_DIR"), "/build_info.rs"));
+
+// Returns the Cargo features this binary was compiled with.
+pub fn features() -> Vec<&'static str> {
+    let mut features = Vec::new();
+
+    if cfg!(feature = "extra") {
+        features.push("extra");
+    }
+
+    features
+}
+
+// Returns the CPU features, relevant to the engine, that are available on
+// the computer the engine is running on. ("compiled" means the compiler
+// was allowed to use the feature; "detected" means the CPU has it.)
+pub fn cpu_features() -> Vec<String> {
+    #[cfg_attr(not(target_arch = "x86_64"), allow(unused_mut))]
+    let mut features = Vec::new();
+
+    #[cfg(target_arch = "x86_64")]
+    {
+        let detected = [
+            (
+                "popcnt",
+                is_x86_feature_detected!("popcnt"),
+                cfg!(target_feature = "popcnt"),
+            ),
+            (
+                "bmi2",
+                is_x86_feature_detected!("bmi2"),
+                cfg!(target_feature = "bmi2"),
+            ),
+            (
+                "avx2",
+                is_x86_feature_detected!("avx2"),
+                cfg!(target_feature = "avx2"),
+            ),
+        ];
+
+        for (name, is_detected, is_compiled) in detected {
+            let state = match (is_detected, is_compiled) {
+                (true, true) => "detected, compiled",
+                (true, false) => "detected",
+                (false, true) => "compiled, not detected",
+                (false, false) => "no",
+            };
+            features.push(format!("{name} ({state})"));
+        }
+    }
+
+    features
+}
+
+// Lines of build information, to be shown in the "about" output.
+pub fn lines() -> Vec<String> {
+    let features = features();
+    let features = if features.is_empty() {
+        String::from("none")
+    } else {
+        features.join(", ")
+    };
+
+    let cpu = cpu_features();
+    let cpu = if cpu.is_empty() {
+        String::from("not checked on this architecture")
+    } else {
+        cpu.join(", ")
+    };
+
+    vec![
+        format!("{:<10} {} ({})", "Build:", GIT_HASH, BUILD_DATE),
+        format!("{:<10} {}", "Compiler:", RUSTC_VERSION),
+        format!("{:<10} {features}", "Features:"),
+        format!("{:<10} {cpu}", "CPU:"),
+        format!("{:<10} {}", "Dispatch:", cpu::dispatch_level()),
+    ]
+}
@@ -22,15 +22,22 @@ with this program.  If not, see <http://www.gnu.org/licenses/>.
 ======================================================================= */
 
 use crate::{
+    comm::CommType,
     defs::{About, FEN_START_POSITION},
     engine::defs::EngineOptionDefaults,
+    misc::{bench::BENCH_DEPTH, print::BoardStyle},
 };
 use clap::{value_parser, Arg, ArgAction, ArgMatches};
+use std::io::{self, IsTerminal};
 
 // Consts for command line options, flags and arguments
 
 struct CmdLineArgs {}
 impl CmdLineArgs {
+    // Help
+    const HELP_LONG: &'static str = "help";
+    const HELP_HELP: &'static str = "Print help";
+
     // FEN
     const FEN_LONG: &'static str = "fen";
     const FEN_SHORT: char = 'f';
@@ -45,26 +52,66 @@ impl CmdLineArgs {
     // Interface
     const COMM_LONG: &'static str = "comm";
     const COMM_SHORT: char = 'c';
-    const COMM_HELP: &'static str = "Select communication protocol to use";
-    const COMM_VALUES: [&'static str; 2] = ["uci", "xboard"];
+    const COMM_HELP: &'static str =
+        "Select communication protocol to use [default: console in a terminal, uci otherwise]";
+    const COMM_VALUES: [&'static str; 3] = ["uci", "xboard", "console"];
     const COMM_DEFAULT: &'static str = "uci";
 
     // Threads
     const THREADS_LONG: &'static str = "threads";
     const THREADS_SHORT: char = 't';
     const THREADS_HELP: &'static str = "Number of CPU-threads to use";
-    const THREADS_DEFAULT: usize = 1;
+    const THREADS_DEFAULT: usize = EngineOptionDefaults::THREADS_DEFAULT;
 
     const HASH_LONG: &'static str = "hash";
     const HASH_SHORT: char = 'h';
     const HASH_HELP: &'static str = "Transposition Table size in MB";
     const HASH_DEFAULT: usize = EngineOptionDefaults::HASH_DEFAULT;
 
+    // Evaluation parameters
+    const EVAL_FILE_LONG: &'static str = "eval-file";
+    const EVAL_FILE_SHORT: char = 'v';
+    const EVAL_FILE_HELP: &'static str = "Load evaluation parameters from a TOML or JSON file";
+
+    // Limit strength
+    const ELO_LONG: &'static str = "elo";
+    const ELO_SHORT: char = 'l';
+    const ELO_HELP: &'static str = "Limit playing strength to the given Elo rating";
+
     // Quiet (no search stats updates except on depth change)
     const QUIET_LONG: &'static str = "quiet";
     const QUIET_SHORT: char = 'q';
     const QUIET_HELP: &'static str = "No intermediate search stats updates";
 
+    // No strict mode (keep custom commands when a GUI is connected)
+    const NO_STRICT_LONG: &'static str = "nostrict";
+    const NO_STRICT_SHORT: char = 'n';
+    const NO_STRICT_HELP: &'static str = "Keep custom commands available in UCI mode";
+
+    // No warm-up search on startup
+    const NO_WARM_UP_LONG: &'static str = "nowarmup";
+    const NO_WARM_UP_SHORT: char = 'o';
+    const NO_WARM_UP_HELP: &'static str = "Skip the short warm-up search on startup";
+
+    // Bench
+    const BENCH_LONG: &'static str = "bench";
+    const BENCH_SHORT: char = 'b';
+    const BENCH_HELP: &'static str = "Search the bench positions and print nodes and speed";
+
+    // Script
+    const SCRIPT_LONG: &'static str = "script";
+    const SCRIPT_SHORT: char = 'r';
+    const SCRIPT_HELP: &'static str = "Run the commands in the file before reading input";
+
+    // Pretty board printing
+    const PRETTY_LONG: &'static str = "pretty";
+    const PRETTY_HELP: &'static str = "Print the board with unicode pieces, in a color theme";
+    const PRETTY_DEFAULT: &'static str = "brown";
+
+    // Log file
+    const LOG_LONG: &'static str = "log";
+    const LOG_HELP: &'static str = "Write all input and output to the file, with timestamps";
+
     // Kiwipete
     const KIWI_LONG: &'static str = "kiwipete";
     const KIWI_SHORT: char = 'k';
@@ -75,10 +122,57 @@ impl CmdLineArgs {
     const WIZARDRY_SHORT: char = 'w';
     const WIZARDRY_HELP: &'static str = "Generate magic numbers";
 
+    // Generate
+    const GENERATE_LONG: &'static str = "generate";
+    const GENERATE_SHORT: char = 'g';
+    const GENERATE_HELP: &'static str = "Regenerate precomputed data as Rust source";
+    const GENERATE_VALUES: [&'static str; 2] = ["magics", "zobrist"];
+
+    // Seed
+    const SEED_LONG: &'static str = "seed";
+    const SEED_SHORT: char = 's';
+    const SEED_HELP: &'static str = "Random seed for --generate and --fuzz";
+
     // Test
     const EPD_TEST_LONG: &'static str = "epdtest";
     const EPD_TEST_SHORT: char = 'e';
     const EPD_TEST_HELP: &'static str = "Run EPD Test Suite";
+
+    // Tactical test suite
+    const TACTICS_LONG: &'static str = "tactics";
+    const TACTICS_SHORT: char = 'a';
+    const TACTICS_HELP: &'static str = "Run a tactical EPD suite with bm/am moves (WAC, STS)";
+
+    // Move time for the tactical test suite
+    const MOVE_TIME_LONG: &'static str = "movetime";
+    const MOVE_TIME_SHORT: char = 'm';
+    const MOVE_TIME_HELP: &'static str = "Milliseconds per position for --tactics";
+    #[cfg(feature = "extra")]
+    const MOVE_TIME_DEFAULT: u128 = 1000;
+
+    // Castling and en-passant audit
+    const AUDIT_LONG: &'static str = "audit";
+    const AUDIT_SHORT: char = 'd';
+    const AUDIT_HELP: &'static str = "Check castling and en-passant edge cases";
+
+    // Fuzzer
+    const FUZZ_LONG: &'static str = "fuzz";
+    const FUZZ_SHORT: char = 'z';
+    const FUZZ_HELP: &'static str = "Fuzz the input parsers and make/unmake N times";
+
+    // Texel tuner
+    const TUNE_LONG: &'static str = "tune";
+    const TUNE_SHORT: char = 'u';
+    const TUNE_HELP: &'static str = "Tune evaluation weights on a dataset of FEN's and results";
+
+    // Continue tuning from the last checkpoint
+    const RESUME_LONG: &'static str = "resume";
+    const RESUME_HELP: &'static str = "Continue --tune from the checkpoint of an earlier run";
+
+    // Evaluation symmetry, hashing and move generator self-test
+    const SELFTEST_LONG: &'static str = "selftest";
+    const SELFTEST_SHORT: char = 'y';
+    const SELFTEST_HELP: &'static str = "Check eval symmetry, hashing and move generation";
 }
 
 pub struct CmdLine {
@@ -88,15 +182,27 @@ pub struct CmdLine {
 impl CmdLine {
     pub fn new() -> Self {
         Self {
-            arguments: Self::get(),
+            arguments: Self::get().get_matches(),
+        }
+    }
+
+    // A command line without any arguments, so all settings have their
+    // default values. This is used when the engine is embedded in another
+    // program, which has its own command line.
+    pub fn empty() -> Self {
+        Self {
+            arguments: Self::get().get_matches_from([About::ENGINE]),
         }
     }
 
+    // Without a protocol on the command line, a user typing in a terminal
+    // gets the console; a GUI, which connects through a pipe, gets UCI.
     pub fn comm(&self) -> String {
-        self.arguments
-            .get_one::<String>(CmdLineArgs::COMM_LONG)
-            .unwrap_or(&CmdLineArgs::COMM_DEFAULT.to_string())
-            .clone()
+        match self.arguments.get_one::<String>(CmdLineArgs::COMM_LONG) {
+            Some(comm) => comm.clone(),
+            None if io::stdin().is_terminal() => String::from(CommType::CONSOLE),
+            None => CmdLineArgs::COMM_DEFAULT.to_string(),
+        }
     }
 
     pub fn fen(&self) -> String {
@@ -127,6 +233,47 @@ impl CmdLine {
             .unwrap_or(&CmdLineArgs::HASH_DEFAULT)
     }
 
+    pub fn eval_file(&self) -> Option<String> {
+        self.arguments
+            .get_one::<String>(CmdLineArgs::EVAL_FILE_LONG)
+            .cloned()
+    }
+
+    pub fn elo(&self) -> Option<u16> {
+        self.arguments
+            .get_one::<u16>(CmdLineArgs::ELO_LONG)
+            .copied()
+    }
+
+    // The depth is optional, so --bench may be given without a value.
+    pub fn bench(&self) -> Option<i8> {
+        if self.arguments.contains_id(CmdLineArgs::BENCH_LONG) {
+            let depth = self.arguments.get_one::<i8>(CmdLineArgs::BENCH_LONG);
+            Some(*depth.unwrap_or(&BENCH_DEPTH))
+        } else {
+            None
+        }
+    }
+
+    // The theme is optional, so --pretty may be given without a value.
+    pub fn pretty(&self) -> Option<String> {
+        self.arguments
+            .get_one::<String>(CmdLineArgs::PRETTY_LONG)
+            .cloned()
+    }
+
+    pub fn script(&self) -> Option<String> {
+        self.arguments
+            .get_one::<String>(CmdLineArgs::SCRIPT_LONG)
+            .cloned()
+    }
+
+    pub fn log(&self) -> Option<String> {
+        self.arguments
+            .get_one::<String>(CmdLineArgs::LOG_LONG)
+            .cloned()
+    }
+
     pub fn has_kiwipete(&self) -> bool {
         self.arguments.get_flag(CmdLineArgs::KIWI_LONG)
     }
@@ -135,28 +282,102 @@ impl CmdLine {
         self.arguments.get_flag(CmdLineArgs::QUIET_LONG)
     }
 
+    pub fn has_no_strict(&self) -> bool {
+        self.arguments.get_flag(CmdLineArgs::NO_STRICT_LONG)
+    }
+
+    pub fn has_no_warm_up(&self) -> bool {
+        self.arguments.get_flag(CmdLineArgs::NO_WARM_UP_LONG)
+    }
+
     #[cfg(feature = "extra")]
     pub fn has_wizardry(&self) -> bool {
         self.arguments.get_flag(CmdLineArgs::WIZARDRY_LONG)
     }
 
+    #[cfg(feature = "extra")]
+    pub fn generate(&self) -> Option<String> {
+        self.arguments
+            .get_one::<String>(CmdLineArgs::GENERATE_LONG)
+            .cloned()
+    }
+
+    #[cfg(feature = "extra")]
+    pub fn seed(&self) -> Option<u64> {
+        self.arguments
+            .get_one::<u64>(CmdLineArgs::SEED_LONG)
+            .copied()
+    }
+
     #[cfg(feature = "extra")]
     pub fn has_test(&self) -> bool {
         self.arguments.get_flag(CmdLineArgs::EPD_TEST_LONG)
     }
 
-    fn get() -> ArgMatches {
+    #[cfg(feature = "extra")]
+    pub fn tactics(&self) -> Option<String> {
+        self.arguments
+            .get_one::<String>(CmdLineArgs::TACTICS_LONG)
+            .cloned()
+    }
+
+    #[cfg(feature = "extra")]
+    pub fn move_time(&self) -> u128 {
+        *self
+            .arguments
+            .get_one::<u128>(CmdLineArgs::MOVE_TIME_LONG)
+            .unwrap_or(&CmdLineArgs::MOVE_TIME_DEFAULT)
+    }
+
+    #[cfg(feature = "extra")]
+    pub fn has_audit(&self) -> bool {
+        self.arguments.get_flag(CmdLineArgs::AUDIT_LONG)
+    }
+
+    #[cfg(feature = "extra")]
+    pub fn fuzz(&self) -> Option<u64> {
+        self.arguments
+            .get_one::<u64>(CmdLineArgs::FUZZ_LONG)
+            .copied()
+    }
+
+    #[cfg(feature = "extra")]
+    pub fn tune(&self) -> Option<String> {
+        self.arguments
+            .get_one::<String>(CmdLineArgs::TUNE_LONG)
+            .cloned()
+    }
+
+    #[cfg(feature = "extra")]
+    pub fn has_resume(&self) -> bool {
+        self.arguments.get_flag(CmdLineArgs::RESUME_LONG)
+    }
+
+    #[cfg(feature = "extra")]
+    pub fn has_selftest(&self) -> bool {
+        self.arguments.get_flag(CmdLineArgs::SELFTEST_LONG)
+    }
+
+    fn get() -> clap::Command {
         let mut cmd_line = clap::Command::new(About::ENGINE)
             .version(About::VERSION)
             .author(About::AUTHOR)
             .about(About::WEBSITE)
+            // "-h" sets the hash size, so help is only available as
+            // "--help". Clap refuses to build the command otherwise.
+            .disable_help_flag(true)
+            .arg(
+                Arg::new(CmdLineArgs::HELP_LONG)
+                    .long(CmdLineArgs::HELP_LONG)
+                    .help(CmdLineArgs::HELP_HELP)
+                    .action(ArgAction::Help),
+            )
             .arg(
                 Arg::new(CmdLineArgs::COMM_LONG)
                     .short(CmdLineArgs::COMM_SHORT)
                     .long(CmdLineArgs::COMM_LONG)
                     .help(CmdLineArgs::COMM_HELP)
                     .num_args(1)
-                    .default_value(CmdLineArgs::COMM_DEFAULT)
                     .value_parser(CmdLineArgs::COMM_VALUES),
             )
             .arg(
@@ -192,6 +413,55 @@ impl CmdLine {
                     .value_parser(value_parser!(usize))
                     .num_args(1),
             )
+            .arg(
+                Arg::new(CmdLineArgs::EVAL_FILE_LONG)
+                    .short(CmdLineArgs::EVAL_FILE_SHORT)
+                    .long(CmdLineArgs::EVAL_FILE_LONG)
+                    .help(CmdLineArgs::EVAL_FILE_HELP)
+                    .value_parser(value_parser!(String))
+                    .num_args(1),
+            )
+            .arg(
+                Arg::new(CmdLineArgs::ELO_LONG)
+                    .short(CmdLineArgs::ELO_SHORT)
+                    .long(CmdLineArgs::ELO_LONG)
+                    .help(CmdLineArgs::ELO_HELP)
+                    .value_parser(value_parser!(u16).range(
+                        EngineOptionDefaults::ELO_MIN as i64..=EngineOptionDefaults::ELO_MAX as i64,
+                    ))
+                    .num_args(1),
+            )
+            .arg(
+                Arg::new(CmdLineArgs::BENCH_LONG)
+                    .short(CmdLineArgs::BENCH_SHORT)
+                    .long(CmdLineArgs::BENCH_LONG)
+                    .help(CmdLineArgs::BENCH_HELP)
+                    .value_parser(value_parser!(i8).range(1..))
+                    .num_args(0..=1),
+            )
+            .arg(
+                Arg::new(CmdLineArgs::SCRIPT_LONG)
+                    .short(CmdLineArgs::SCRIPT_SHORT)
+                    .long(CmdLineArgs::SCRIPT_LONG)
+                    .help(CmdLineArgs::SCRIPT_HELP)
+                    .value_parser(value_parser!(String))
+                    .num_args(1),
+            )
+            .arg(
+                Arg::new(CmdLineArgs::PRETTY_LONG)
+                    .long(CmdLineArgs::PRETTY_LONG)
+                    .help(CmdLineArgs::PRETTY_HELP)
+                    .value_parser(BoardStyle::names())
+                    .num_args(0..=1)
+                    .default_missing_value(CmdLineArgs::PRETTY_DEFAULT),
+            )
+            .arg(
+                Arg::new(CmdLineArgs::LOG_LONG)
+                    .long(CmdLineArgs::LOG_LONG)
+                    .help(CmdLineArgs::LOG_HELP)
+                    .value_parser(value_parser!(String))
+                    .num_args(1),
+            )
             .arg(
                 Arg::new(CmdLineArgs::KIWI_LONG)
                     .long(CmdLineArgs::KIWI_LONG)
@@ -205,6 +475,20 @@ impl CmdLine {
                     .short(CmdLineArgs::QUIET_SHORT)
                     .help(CmdLineArgs::QUIET_HELP)
                     .action(ArgAction::SetTrue),
+            )
+            .arg(
+                Arg::new(CmdLineArgs::NO_STRICT_LONG)
+                    .long(CmdLineArgs::NO_STRICT_LONG)
+                    .short(CmdLineArgs::NO_STRICT_SHORT)
+                    .help(CmdLineArgs::NO_STRICT_HELP)
+                    .action(ArgAction::SetTrue),
+            )
+            .arg(
+                Arg::new(CmdLineArgs::NO_WARM_UP_LONG)
+                    .long(CmdLineArgs::NO_WARM_UP_LONG)
+                    .short(CmdLineArgs::NO_WARM_UP_SHORT)
+                    .help(CmdLineArgs::NO_WARM_UP_HELP)
+                    .action(ArgAction::SetTrue),
             );
 
         if cfg!(feature = "extra") {
@@ -216,15 +500,84 @@ impl CmdLine {
                         .help(CmdLineArgs::WIZARDRY_HELP)
                         .action(ArgAction::SetTrue),
                 )
+                .arg(
+                    Arg::new(CmdLineArgs::GENERATE_LONG)
+                        .short(CmdLineArgs::GENERATE_SHORT)
+                        .long(CmdLineArgs::GENERATE_LONG)
+                        .help(CmdLineArgs::GENERATE_HELP)
+                        .num_args(1)
+                        .value_parser(CmdLineArgs::GENERATE_VALUES),
+                )
+                .arg(
+                    Arg::new(CmdLineArgs::SEED_LONG)
+                        .short(CmdLineArgs::SEED_SHORT)
+                        .long(CmdLineArgs::SEED_LONG)
+                        .help(CmdLineArgs::SEED_HELP)
+                        .value_parser(value_parser!(u64))
+                        .num_args(1),
+                )
                 .arg(
                     Arg::new(CmdLineArgs::EPD_TEST_LONG)
                         .short(CmdLineArgs::EPD_TEST_SHORT)
                         .long(CmdLineArgs::EPD_TEST_LONG)
                         .help(CmdLineArgs::EPD_TEST_HELP)
                         .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new(CmdLineArgs::TACTICS_LONG)
+                        .short(CmdLineArgs::TACTICS_SHORT)
+                        .long(CmdLineArgs::TACTICS_LONG)
+                        .help(CmdLineArgs::TACTICS_HELP)
+                        .value_parser(value_parser!(String))
+                        .num_args(1),
+                )
+                .arg(
+                    Arg::new(CmdLineArgs::MOVE_TIME_LONG)
+                        .short(CmdLineArgs::MOVE_TIME_SHORT)
+                        .long(CmdLineArgs::MOVE_TIME_LONG)
+                        .help(CmdLineArgs::MOVE_TIME_HELP)
+                        .value_parser(value_parser!(u128))
+                        .num_args(1),
+                )
+                .arg(
+                    Arg::new(CmdLineArgs::AUDIT_LONG)
+                        .short(CmdLineArgs::AUDIT_SHORT)
+                        .long(CmdLineArgs::AUDIT_LONG)
+                        .help(CmdLineArgs::AUDIT_HELP)
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new(CmdLineArgs::FUZZ_LONG)
+                        .short(CmdLineArgs::FUZZ_SHORT)
+                        .long(CmdLineArgs::FUZZ_LONG)
+                        .help(CmdLineArgs::FUZZ_HELP)
+                        .value_parser(value_parser!(u64))
+                        .num_args(1),
+                )
+                .arg(
+                    Arg::new(CmdLineArgs::TUNE_LONG)
+                        .short(CmdLineArgs::TUNE_SHORT)
+                        .long(CmdLineArgs::TUNE_LONG)
+                        .help(CmdLineArgs::TUNE_HELP)
+                        .value_parser(value_parser!(String))
+                        .num_args(1),
+                )
+                .arg(
+                    Arg::new(CmdLineArgs::RESUME_LONG)
+                        .long(CmdLineArgs::RESUME_LONG)
+                        .help(CmdLineArgs::RESUME_HELP)
+                        .requires(CmdLineArgs::TUNE_LONG)
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new(CmdLineArgs::SELFTEST_LONG)
+                        .short(CmdLineArgs::SELFTEST_SHORT)
+                        .long(CmdLineArgs::SELFTEST_LONG)
+                        .help(CmdLineArgs::SELFTEST_HELP)
+                        .action(ArgAction::SetTrue),
                 );
         }
 
-        cmd_line.get_matches()
+        cmd_line
     }
 }
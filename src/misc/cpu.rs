@@ -0,0 +1,55 @@
+/* =======================================================================
+Rustic is a chess playing engine.
+Copyright (C) 2019-2024, Marcel Vanthoor
+https://rustic-chess.org/
+
+Rustic is written in the Rust programming language. It is an original
+work, not derived from any engine that came before it. However, it does
+use a lot of concepts which are well-known and are in use by most if not
+all classical alpha/beta-based chess engines.
+
+Rustic is free software: you can redistribute it and/or modify it under
+the terms of the GNU General Public License version 3 as published by
+the Free Software Foundation.
+
+Rustic is distributed in the hope that it will be useful, but WITHOUT
+ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License
+for more details.
+
+You should have received a copy of the GNU General Public License along
+with this program.  If not, see <http://www.gnu.org/licenses/>.
+======================================================================= */
+
+// cpu.rs is the engine's dispatch layer. It detects once, at startup,
+// which CPU features the engine can use on this computer. Hot functions
+// check the result and use the faster instruction if the CPU has it, and
+// a scalar fallback otherwise. This way a single binary, built for a
+// generic x86-64 CPU, still runs at full speed on a modern one, without
+// having to be compiled with target-cpu=native.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static POPCNT: AtomicBool = AtomicBool::new(false);
+
+// Detect the CPU features. This must be called before the engine starts.
+// If it isn't, the engine still works, but only uses the fallbacks.
+pub fn init() {
+    #[cfg(target_arch = "x86_64")]
+    POPCNT.store(is_x86_feature_detected!("popcnt"), Ordering::Relaxed);
+}
+
+// If the compiler was allowed to use popcnt, there is nothing to detect.
+#[inline(always)]
+pub fn has_popcnt() -> bool {
+    cfg!(target_feature = "popcnt") || POPCNT.load(Ordering::Relaxed)
+}
+
+// The instruction set the hot functions are running on, for reporting.
+pub fn dispatch_level() -> &'static str {
+    if has_popcnt() {
+        "popcnt"
+    } else {
+        "scalar"
+    }
+}
@@ -0,0 +1,206 @@
+/* =======================================================================
+Rustic is a chess playing engine.
+Copyright (C) 2019-2024, Marcel Vanthoor
+https://rustic-chess.org/
+
+Rustic is written in the Rust programming language. It is an original
+work, not derived from any engine that came before it. However, it does
+use a lot of concepts which are well-known and are in use by most if not
+all classical alpha/beta-based chess engines.
+
+Rustic is free software: you can redistribute it and/or modify it under
+the terms of the GNU General Public License version 3 as published by
+the Free Software Foundation.
+
+Rustic is distributed in the hope that it will be useful, but WITHOUT
+ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License
+for more details.
+
+You should have received a copy of the GNU General Public License along
+with this program.  If not, see <http://www.gnu.org/licenses/>.
+======================================================================= */
+
+// eco.rs classifies the opening of a game, using a small subset of the
+// Encyclopaedia of Chess Openings. Each entry in the table is the move
+// sequence leading to the canonical position of that opening. When the
+// table is first used, these sequences are played out on a board, and
+// the Zobrist key of the resulting position is stored. Classifying a game
+// then means looking up the keys of the positions in the game history.
+// Because the lookup is done by position instead of by move order, the
+// opening is also recognized when it was reached by transposition.
+
+use crate::{
+    board::{zobrist::ZobristKey, Board},
+    misc::parse,
+    movegen::{
+        defs::{MoveList, MoveType},
+        MoveGenerator,
+    },
+};
+use std::{collections::HashMap, sync::OnceLock};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Opening {
+    pub code: &'static str,
+    pub name: &'static str,
+    pub plies: usize,
+}
+
+impl std::fmt::Display for Opening {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{} {}", self.code, self.name)
+    }
+}
+
+// ECO code, opening name, moves from the starting position.
+#[rustfmt::skip]
+const ECO_TABLE: [(&str, &str, &str); 80] = [
+    ("A00", "Polish Opening", "b2b4"),
+    ("A00", "Grob Opening", "g2g4"),
+    ("A00", "Van 't Kruijs Opening", "e2e3"),
+    ("A01", "Nimzo-Larsen Attack", "b2b3"),
+    ("A02", "Bird's Opening", "f2f4"),
+    ("A04", "Reti Opening", "g1f3"),
+    ("A05", "Reti Opening", "g1f3 g8f6"),
+    ("A06", "Reti Opening", "g1f3 d7d5"),
+    ("A07", "King's Indian Attack", "g1f3 d7d5 g2g3"),
+    ("A10", "English Opening", "c2c4"),
+    ("A15", "English Opening: Anglo-Indian Defense", "c2c4 g8f6"),
+    ("A20", "English Opening: King's English", "c2c4 e7e5"),
+    ("A30", "English Opening: Symmetrical Variation", "c2c4 c7c5"),
+    ("A40", "Queen's Pawn Game", "d2d4"),
+    ("A45", "Indian Defense", "d2d4 g8f6"),
+    ("A46", "Indian Defense", "d2d4 g8f6 g1f3"),
+    ("A48", "London System", "d2d4 g8f6 g1f3 g7g6 c1f4"),
+    ("A50", "Indian Defense: Normal Variation", "d2d4 g8f6 c2c4"),
+    ("A51", "Budapest Gambit", "d2d4 g8f6 c2c4 e7e5"),
+    ("A56", "Benoni Defense", "d2d4 g8f6 c2c4 c7c5"),
+    ("A57", "Benko Gambit", "d2d4 g8f6 c2c4 c7c5 d4d5 b7b5"),
+    ("A60", "Modern Benoni", "d2d4 g8f6 c2c4 c7c5 d4d5 e7e6"),
+    ("A80", "Dutch Defense", "d2d4 f7f5"),
+    ("B00", "King's Pawn Game", "e2e4"),
+    ("B00", "Nimzowitsch Defense", "e2e4 b8c6"),
+    ("B01", "Scandinavian Defense", "e2e4 d7d5"),
+    ("B02", "Alekhine's Defense", "e2e4 g8f6"),
+    ("B06", "Modern Defense", "e2e4 g7g6"),
+    ("B07", "Pirc Defense", "e2e4 d7d6 d2d4 g8f6"),
+    ("B10", "Caro-Kann Defense", "e2e4 c7c6"),
+    ("B12", "Caro-Kann Defense", "e2e4 c7c6 d2d4 d7d5"),
+    ("B12", "Caro-Kann Defense: Advance Variation", "e2e4 c7c6 d2d4 d7d5 e4e5"),
+    ("B15", "Caro-Kann Defense", "e2e4 c7c6 d2d4 d7d5 b1c3"),
+    ("B20", "Sicilian Defense", "e2e4 c7c5"),
+    ("B22", "Sicilian Defense: Alapin Variation", "e2e4 c7c5 c2c3"),
+    ("B23", "Sicilian Defense: Closed", "e2e4 c7c5 b1c3"),
+    ("B27", "Sicilian Defense", "e2e4 c7c5 g1f3"),
+    ("B30", "Sicilian Defense: Old Sicilian", "e2e4 c7c5 g1f3 b8c6"),
+    ("B40", "Sicilian Defense: French Variation", "e2e4 c7c5 g1f3 e7e6"),
+    ("B50", "Sicilian Defense", "e2e4 c7c5 g1f3 d7d6"),
+    ("B54", "Sicilian Defense: Open", "e2e4 c7c5 g1f3 d7d6 d2d4 c5d4 f3d4"),
+    ("B70", "Sicilian Defense: Dragon Variation", "e2e4 c7c5 g1f3 d7d6 d2d4 c5d4 f3d4 g8f6 b1c3 g7g6"),
+    ("B90", "Sicilian Defense: Najdorf Variation", "e2e4 c7c5 g1f3 d7d6 d2d4 c5d4 f3d4 g8f6 b1c3 a7a6"),
+    ("C00", "French Defense", "e2e4 e7e6"),
+    ("C01", "French Defense: Exchange Variation", "e2e4 e7e6 d2d4 d7d5 e4d5"),
+    ("C02", "French Defense: Advance Variation", "e2e4 e7e6 d2d4 d7d5 e4e5"),
+    ("C03", "French Defense: Tarrasch Variation", "e2e4 e7e6 d2d4 d7d5 b1d2"),
+    ("C10", "French Defense: Paulsen Variation", "e2e4 e7e6 d2d4 d7d5 b1c3"),
+    ("C15", "French Defense: Winawer Variation", "e2e4 e7e6 d2d4 d7d5 b1c3 f8b4"),
+    ("C20", "King's Pawn Game", "e2e4 e7e5"),
+    ("C21", "Center Game", "e2e4 e7e5 d2d4 e5d4"),
+    ("C23", "Bishop's Opening", "e2e4 e7e5 f1c4"),
+    ("C25", "Vienna Game", "e2e4 e7e5 b1c3"),
+    ("C30", "King's Gambit", "e2e4 e7e5 f2f4"),
+    ("C33", "King's Gambit Accepted", "e2e4 e7e5 f2f4 e5f4"),
+    ("C40", "King's Knight Opening", "e2e4 e7e5 g1f3"),
+    ("C41", "Philidor Defense", "e2e4 e7e5 g1f3 d7d6"),
+    ("C42", "Petrov's Defense", "e2e4 e7e5 g1f3 g8f6"),
+    ("C44", "King's Pawn Game: Knight Opening", "e2e4 e7e5 g1f3 b8c6"),
+    ("C45", "Scotch Game", "e2e4 e7e5 g1f3 b8c6 d2d4 e5d4 f3d4"),
+    ("C46", "Three Knights Opening", "e2e4 e7e5 g1f3 b8c6 b1c3"),
+    ("C47", "Four Knights Game", "e2e4 e7e5 g1f3 b8c6 b1c3 g8f6"),
+    ("C50", "Italian Game", "e2e4 e7e5 g1f3 b8c6 f1c4"),
+    ("C51", "Evans Gambit", "e2e4 e7e5 g1f3 b8c6 f1c4 f8c5 b2b4"),
+    ("C53", "Italian Game: Giuoco Piano", "e2e4 e7e5 g1f3 b8c6 f1c4 f8c5 c2c3"),
+    ("C55", "Italian Game: Two Knights Defense", "e2e4 e7e5 g1f3 b8c6 f1c4 g8f6"),
+    ("C60", "Ruy Lopez", "e2e4 e7e5 g1f3 b8c6 f1b5"),
+    ("C65", "Ruy Lopez: Berlin Defense", "e2e4 e7e5 g1f3 b8c6 f1b5 g8f6"),
+    ("C68", "Ruy Lopez: Exchange Variation", "e2e4 e7e5 g1f3 b8c6 f1b5 a7a6 b5c6"),
+    ("C70", "Ruy Lopez: Morphy Defense", "e2e4 e7e5 g1f3 b8c6 f1b5 a7a6"),
+    ("C84", "Ruy Lopez: Closed", "e2e4 e7e5 g1f3 b8c6 f1b5 a7a6 b5a4 g8f6 e1g1 f8e7"),
+    ("D00", "Queen's Pawn Game", "d2d4 d7d5"),
+    ("D02", "Queen's Pawn Game", "d2d4 d7d5 g1f3"),
+    ("D06", "Queen's Gambit", "d2d4 d7d5 c2c4"),
+    ("D10", "Slav Defense", "d2d4 d7d5 c2c4 c7c6"),
+    ("D20", "Queen's Gambit Accepted", "d2d4 d7d5 c2c4 d5c4"),
+    ("D30", "Queen's Gambit Declined", "d2d4 d7d5 c2c4 e7e6"),
+    ("D80", "Grunfeld Defense", "d2d4 g8f6 c2c4 g7g6 b1c3 d7d5"),
+    ("E20", "Nimzo-Indian Defense", "d2d4 g8f6 c2c4 e7e6 b1c3 f8b4"),
+    ("E60", "King's Indian Defense", "d2d4 g8f6 c2c4 g7g6"),
+];
+
+// Maps the Zobrist key of each canonical position onto its opening.
+type EcoMap = HashMap<ZobristKey, Opening>;
+
+static ECO_MAP: OnceLock<EcoMap> = OnceLock::new();
+
+// Returns the opening of the deepest position in the game (including the
+// current position) that is in the table, or None if no position is.
+pub fn classify(board: &Board, mg: &MoveGenerator) -> Option<Opening> {
+    let map = ECO_MAP.get_or_init(|| create_map(mg));
+    let current = board.game_state.zobrist_key;
+    let history = (0..board.history.len()).map(|i| board.history.get_ref(i).zobrist_key);
+
+    history
+        .chain(std::iter::once(current))
+        .filter_map(|key| map.get(&key))
+        .max_by_key(|opening| opening.plies)
+        .copied()
+}
+
+fn create_map(mg: &MoveGenerator) -> EcoMap {
+    let mut map = EcoMap::new();
+
+    for (code, name, moves) in ECO_TABLE {
+        let mut board = Board::new();
+        let _ = board.fen_read(None);
+        let mut plies = 0;
+
+        for m in moves.split_whitespace() {
+            if play_move(&mut board, m, mg) {
+                plies += 1;
+            } else {
+                break;
+            }
+        }
+
+        // An entry with an illegal move can't be in any game.
+        if plies == moves.split_whitespace().count() {
+            let opening = Opening { code, name, plies };
+            map.insert(board.game_state.zobrist_key, opening);
+        }
+    }
+
+    map
+}
+
+fn play_move(board: &mut Board, m: &str, mg: &MoveGenerator) -> bool {
+    let potential_move = match parse::algebraic_move_to_number(m) {
+        Ok(pm) => pm,
+        Err(_) => return false,
+    };
+
+    let mut ml = MoveList::new();
+    mg.generate_moves(board, &mut ml, MoveType::All);
+
+    for i in 0..ml.len() {
+        let current = ml.get_move(i);
+        if potential_move.0 == current.from()
+            && potential_move.1 == current.to()
+            && potential_move.2 == current.promoted()
+        {
+            return board.make(current, mg);
+        }
+    }
+
+    false
+}
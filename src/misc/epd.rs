@@ -0,0 +1,137 @@
+/* =======================================================================
+Rustic is a chess playing engine.
+Copyright (C) 2019-2024, Marcel Vanthoor
+https://rustic-chess.org/
+
+Rustic is written in the Rust programming language. It is an original
+work, not derived from any engine that came before it. However, it does
+use a lot of concepts which are well-known and are in use by most if not
+all classical alpha/beta-based chess engines.
+
+Rustic is free software: you can redistribute it and/or modify it under
+the terms of the GNU General Public License version 3 as published by
+the Free Software Foundation.
+
+Rustic is distributed in the hope that it will be useful, but WITHOUT
+ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License
+for more details.
+
+You should have received a copy of the GNU General Public License along
+with this program.  If not, see <http://www.gnu.org/licenses/>.
+======================================================================= */
+
+// epd.rs parses EPD records. A record is a position, followed by
+// operations such as:
+//
+// 2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - bm Qg6; id "WAC.001";
+//
+// The position has the first four fields of a FEN-string. The halfmove
+// clock and fullmove number are given by the "hmvc" and "fmvn" opcodes,
+// if at all. Some files use a complete FEN-string instead; this is also
+// accepted. Each operation is an opcode, followed by zero or more
+// operands, and ends with a semicolon. Quoted operands may contain spaces
+// and semicolons.
+
+const POSITION_FIELDS: usize = 4;
+
+/// An EPD record: a position with operations such as "bm" (best move)
+/// and "id".
+pub struct EpdRecord {
+    /// The position as a complete FEN-string.
+    pub fen: String,
+    operations: Vec<(String, Vec<String>)>,
+}
+
+impl EpdRecord {
+    /// Reads a record from a line of text. Returns None if the line
+    /// doesn't start with a position.
+    pub fn parse(line: &str) -> Option<EpdRecord> {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() < POSITION_FIELDS {
+            return None;
+        }
+
+        // Find out if the halfmove clock and fullmove number follow the
+        // position, as they do in a FEN-string.
+        let is_clock = |i: usize| fields.get(i).is_some_and(|f| f.parse::<u16>().is_ok());
+        let position_fields = if is_clock(4) && is_clock(5) {
+            POSITION_FIELDS + 2
+        } else {
+            POSITION_FIELDS
+        };
+
+        // Find the start of the operations by skipping the position.
+        let mut rest = line.trim_start();
+        for _ in 0..position_fields {
+            let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            rest = rest[end..].trim_start();
+        }
+
+        let mut record = EpdRecord {
+            fen: fields[..position_fields].join(" "),
+            operations: parse_operations(rest),
+        };
+
+        if position_fields == POSITION_FIELDS {
+            let hmvc = record.operand("hmvc").unwrap_or("0").to_string();
+            let fmvn = record.operand("fmvn").unwrap_or("1").to_string();
+            record.fen = format!("{} {hmvc} {fmvn}", record.fen);
+        }
+
+        Some(record)
+    }
+
+    /// All the operands of the operation with this opcode.
+    pub fn operands(&self, opcode: &str) -> Option<&[String]> {
+        self.operations
+            .iter()
+            .find(|(op, _)| op == opcode)
+            .map(|(_, operands)| &operands[..])
+    }
+
+    /// The first operand of the operation with this opcode.
+    pub fn operand(&self, opcode: &str) -> Option<&str> {
+        self.operands(opcode)
+            .and_then(|operands| operands.first())
+            .map(|operand| &operand[..])
+    }
+}
+
+// Splits the operations into opcodes and their operands.
+fn parse_operations(text: &str) -> Vec<(String, Vec<String>)> {
+    let mut operations: Vec<(String, Vec<String>)> = Vec::new();
+    let mut tokens: Vec<String> = Vec::new();
+    let mut token = String::new();
+    let mut in_quotes = false;
+
+    // A semicolon ends the operation. The last one may lack it.
+    let mut end_operation = |tokens: &mut Vec<String>| {
+        if !tokens.is_empty() {
+            let opcode = tokens.remove(0);
+            operations.push((opcode, std::mem::take(tokens)));
+        }
+    };
+
+    for c in text.chars().chain(std::iter::once(';')) {
+        match c {
+            '"' if in_quotes => {
+                tokens.push(std::mem::take(&mut token));
+                in_quotes = false;
+            }
+            _ if in_quotes => token.push(c),
+            '"' => in_quotes = true,
+            ';' | ' ' | '\t' => {
+                if !token.is_empty() {
+                    tokens.push(std::mem::take(&mut token));
+                }
+                if c == ';' {
+                    end_operation(&mut tokens);
+                }
+            }
+            _ => token.push(c),
+        }
+    }
+
+    operations
+}
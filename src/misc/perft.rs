@@ -22,16 +22,20 @@ with this program.  If not, see <http://www.gnu.org/licenses/>.
 ======================================================================= */
 
 use crate::{
-    board::Board,
-    engine::defs::{ErrFatal, PerftData, TT},
-    misc::print,
+    board::{defs::ZobristKey, Board},
+    engine::defs::ErrFatal,
+    misc::print::{self, BoardStyle},
     movegen::{
-        defs::{MoveList, MoveType},
+        defs::{Move, MoveList, MoveType},
         MoveGenerator,
     },
 };
 use std::{
-    sync::{Arc, Mutex},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    thread,
     time::Instant,
 };
 
@@ -42,12 +46,13 @@ pub fn run(
     board: Arc<Mutex<Board>>,
     depth: i8,
     mg: Arc<MoveGenerator>,
-    tt: Arc<Mutex<TT<PerftData>>>,
-    tt_enabled: bool,
+    hash: usize,
+    threads: usize,
+    style: BoardStyle,
 ) {
     let mut total_time: u128 = 0;
     let mut total_nodes: u64 = 0;
-    let mut hash_full = String::from("");
+    let mut cache_usage = String::from("");
 
     // Create a mutex guard for the board, so it can be safely cloned.
     // Panic if the guard can't be created, because something is wrong with
@@ -55,23 +60,27 @@ pub fn run(
     let mtx_board = board.lock().expect(ErrFatal::LOCK);
 
     // Clone the locked board for local use.
-    let mut local_board = mtx_board.clone();
+    let local_board = mtx_board.clone();
 
     // The function now has its own local board. Drop the guard. It is not
     // necessary to keep the lock until perft runs out.
     std::mem::drop(mtx_board);
 
-    println!("Benchmarking perft 1-{depth}:");
+    println!("Benchmarking perft 1-{depth} ({threads} threads):");
+
+    // The caches are kept from one depth to the next.
+    let mut caches = PerftCache::per_thread(hash, threads);
 
-    print::position(&local_board, None);
+    print::position(&local_board, None, style);
 
     // Perform all perfts for depths 1 up to and including "depth"
     for d in 1..=depth {
         // Current time
         let now = Instant::now();
-        let mut leaf_nodes = 0;
-
-        leaf_nodes += perft(&mut local_board, d, &mg, &tt, tt_enabled);
+        let leaf_nodes: u64 = divide(&local_board, d, &mg, &mut caches)
+            .iter()
+            .map(|(_, nodes)| nodes)
+            .sum();
 
         // Measure time and speed
         let elapsed = now.elapsed().as_millis();
@@ -81,18 +90,27 @@ pub fn run(
         total_time += elapsed;
         total_nodes += leaf_nodes;
 
-        // Request TT usage. (This is provided permille as per UCI
-        // spec, so divide by 10 to get the usage in percents.)
-        if tt_enabled {
-            hash_full = format!(
-                ", hash full: {}%",
-                tt.lock().expect(ErrFatal::LOCK).hash_full() as f64 / 10f64
+        // Request cache usage and hit rate for this depth, over the
+        // caches of all threads together.
+        if caches.iter().any(|c| c.is_enabled()) {
+            let mut stats = PerftCacheStats::default();
+            let mut hash_full = 0;
+            for cache in caches.iter_mut() {
+                stats.probes += cache.stats().probes;
+                stats.hits += cache.stats().hits;
+                hash_full += cache.hash_full() as usize;
+                cache.reset_stats();
+            }
+            cache_usage = format!(
+                ", hash full: {}%, hits: {}%",
+                (hash_full / caches.len()) as f64 / 10f64,
+                stats.hit_rate() as f64 / 10f64
             );
         }
 
         // Print the results.
         println!(
-            "Perft {d}: {leaf_nodes} ({elapsed} ms, {leaves_per_second} leaves/sec{hash_full})"
+            "Perft {d}: {leaf_nodes} ({elapsed} ms, {leaves_per_second} leaves/sec{cache_usage})"
         );
     }
 
@@ -102,15 +120,69 @@ pub fn run(
     println!("Execution speed: {final_lnps} leaves/second");
 }
 
-// This is the actual Perft function. It is public, because it is used by
-// the "testsuite" module.
-pub fn perft(
-    board: &mut Board,
+// Divide runs perft for each of the legal root moves, and returns the
+// leaf node count per move, sorted by move. Comparing these counts with
+// those of another engine shows which move is counted incorrectly. The
+// root moves are divided over one thread per cache; each thread takes
+// the next move that hasn't been counted yet. A thread only uses its own
+// cache, so the threads never wait for each other.
+pub fn divide(
+    board: &Board,
     depth: i8,
     mg: &MoveGenerator,
-    tt: &Mutex<TT<PerftData>>,
-    tt_enabled: bool,
-) -> u64 {
+    caches: &mut [PerftCache],
+) -> Vec<(Move, u64)> {
+    let mut local_board = board.clone();
+    let mut move_list: MoveList = MoveList::new();
+    let mut root_moves: Vec<Move> = Vec::new();
+
+    if depth < 1 {
+        return Vec::new();
+    }
+
+    // Only keep the legal moves.
+    mg.generate_moves(&local_board, &mut move_list, MoveType::All);
+    for i in 0..move_list.len() {
+        let m = move_list.get_move(i);
+        if local_board.make(m, mg) {
+            local_board.unmake();
+            root_moves.push(m);
+        }
+    }
+
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new(Vec::with_capacity(root_moves.len()));
+
+    thread::scope(|s| {
+        let (next, results, root_moves) = (&next, &results, &root_moves);
+        for cache in caches.iter_mut().take(root_moves.len()) {
+            s.spawn(move || {
+                let mut thread_board = board.clone();
+                loop {
+                    let i = next.fetch_add(1, Ordering::Relaxed);
+                    if i >= root_moves.len() {
+                        break;
+                    }
+
+                    let m = root_moves[i];
+                    thread_board.make(m, mg);
+                    let leaf_nodes = perft(&mut thread_board, depth - 1, mg, cache);
+                    thread_board.unmake();
+
+                    results.lock().expect(ErrFatal::LOCK).push((m, leaf_nodes));
+                }
+            });
+        }
+    });
+
+    let mut results = results.into_inner().expect(ErrFatal::LOCK);
+    results.sort_by_key(|(m, _)| m.as_string());
+    results
+}
+
+// This is the actual Perft function. It is public, because it is used by
+// the "testsuite" module.
+pub fn perft(board: &mut Board, depth: i8, mg: &MoveGenerator, cache: &mut PerftCache) -> u64 {
     let mut leaf_nodes: u64 = 0;
     let mut move_list: MoveList = MoveList::new();
 
@@ -119,21 +191,9 @@ pub fn perft(
         return 1;
     }
 
-    // See if the current position is in the TT, and if so, get the
-    // number of leaf nodes that were previously calculated for it.
-    let mut leaf_nodes_tt: Option<u64> = None;
-    if tt_enabled {
-        if let Some(data) = tt
-            .lock()
-            .expect(ErrFatal::LOCK)
-            .probe(board.game_state.zobrist_key)
-        {
-            leaf_nodes_tt = data.get(depth);
-        };
-    }
-
-    // If we found a leaf node count, return it immediately.
-    if let Some(leaf_nodes) = leaf_nodes_tt {
+    // See if the current position is in the cache, and if so, return
+    // the number of leaf nodes that was previously calculated for it.
+    if let Some(leaf_nodes) = cache.probe(board.game_state.zobrist_key, depth) {
         return leaf_nodes;
     }
 
@@ -147,7 +207,7 @@ pub fn perft(
         // If the move is legal...
         if board.make(m, mg) {
             // Then count the number of leaf nodes it generates...
-            leaf_nodes += perft(board, depth - 1, mg, tt, tt_enabled);
+            leaf_nodes += perft(board, depth - 1, mg, cache);
 
             // Then unmake the move so the next one can be counted.
             board.unmake();
@@ -155,14 +215,159 @@ pub fn perft(
     }
 
     // We have calculated the number of leaf nodes for this position.
-    // Store this in the TT for later use.
-    if tt_enabled {
-        tt.lock().expect(ErrFatal::LOCK).insert(
-            board.game_state.zobrist_key,
-            PerftData::create(depth, leaf_nodes),
-        )
-    }
+    // Store this in the cache for later use.
+    cache.insert(board.game_state.zobrist_key, depth, leaf_nodes);
 
     // Return the number of leaf nodes for the given position and depth.
     leaf_nodes
 }
+
+/* ===== Perft cache ================================================== */
+
+// The perft cache stores leaf node counts of positions that were already
+// counted. It is independent of the search TT, so both can be changed
+// without affecting the other. Each slot holds two entries: the first
+// one keeps the deepest count (which saves the most work), the second
+// one is always replaced. A cache of 0 MB is disabled.
+
+const MEGABYTE: usize = 1024 * 1024;
+
+#[derive(Copy, Clone, Default)]
+struct PerftEntry {
+    zobrist_key: ZobristKey,
+    depth: i8,
+    leaf_nodes: u64,
+}
+
+#[derive(Copy, Clone, Default)]
+struct PerftSlot {
+    deepest: PerftEntry,
+    latest: PerftEntry,
+}
+
+// Counters to see how well the cache is doing. The number of used entries
+// is kept over the cache's lifetime; the others can be reset.
+#[derive(Copy, Clone, Default)]
+pub struct PerftCacheStats {
+    pub used_entries: usize, // Entries that are in use
+    pub probes: usize,       // Total number of probes
+    pub hits: usize,         // Probes that found a leaf node count
+}
+
+impl PerftCacheStats {
+    // Hit rate in permille, in the same way as hash_full().
+    pub fn hit_rate(&self) -> u16 {
+        if self.probes > 0 {
+            ((self.hits as f64 / self.probes as f64) * 1000f64).floor() as u16
+        } else {
+            0
+        }
+    }
+}
+
+pub struct PerftCache {
+    slots: Vec<PerftSlot>,
+    stats: PerftCacheStats,
+}
+
+impl PerftCache {
+    pub fn new(megabytes: usize) -> Self {
+        Self::with_slots(Self::total_slots(megabytes))
+    }
+
+    // Creates a cache for each of the given number of threads (at least
+    // one), dividing the memory over them.
+    pub fn per_thread(megabytes: usize, threads: usize) -> Vec<PerftCache> {
+        let threads = threads.max(1);
+        let slots = Self::total_slots(megabytes) / threads;
+        (0..threads).map(|_| Self::with_slots(slots)).collect()
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.slots.is_empty()
+    }
+
+    // Returns the leaf node count for the position at the given depth, if
+    // it is in the cache.
+    pub fn probe(&mut self, zobrist_key: ZobristKey, depth: i8) -> Option<u64> {
+        if !self.is_enabled() {
+            return None;
+        }
+
+        let slot = &self.slots[self.index(zobrist_key)];
+        let found = [slot.deepest, slot.latest]
+            .into_iter()
+            .find(|e| e.zobrist_key == zobrist_key && e.depth == depth)
+            .map(|e| e.leaf_nodes);
+
+        self.stats.probes += 1;
+        if found.is_some() {
+            self.stats.hits += 1;
+        }
+
+        found
+    }
+
+    pub fn insert(&mut self, zobrist_key: ZobristKey, depth: i8, leaf_nodes: u64) {
+        if !self.is_enabled() {
+            return;
+        }
+
+        let index = self.index(zobrist_key);
+        let slot = &mut self.slots[index];
+        let entry = PerftEntry {
+            zobrist_key,
+            depth,
+            leaf_nodes,
+        };
+
+        // Depth 0 is never stored, so an entry with depth 0 is unused.
+        let target = if depth >= slot.deepest.depth {
+            &mut slot.deepest
+        } else {
+            &mut slot.latest
+        };
+
+        if target.depth == 0 {
+            self.stats.used_entries += 1;
+        }
+        *target = entry;
+    }
+
+    // Provides cache usage in permille.
+    pub fn hash_full(&self) -> u16 {
+        let total_entries = self.slots.len() * 2;
+        if total_entries > 0 {
+            ((self.stats.used_entries as f64 / total_entries as f64) * 1000f64).floor() as u16
+        } else {
+            0
+        }
+    }
+
+    pub fn stats(&self) -> PerftCacheStats {
+        self.stats
+    }
+
+    // Reset the probe counters, but keep counting used entries.
+    pub fn reset_stats(&mut self) {
+        self.stats = PerftCacheStats {
+            used_entries: self.stats.used_entries,
+            ..PerftCacheStats::default()
+        };
+    }
+
+    fn with_slots(slots: usize) -> Self {
+        Self {
+            slots: vec![PerftSlot::default(); slots],
+            stats: PerftCacheStats::default(),
+        }
+    }
+
+    fn total_slots(megabytes: usize) -> usize {
+        megabytes * MEGABYTE / std::mem::size_of::<PerftSlot>()
+    }
+
+    fn index(&self, zobrist_key: ZobristKey) -> usize {
+        (zobrist_key % self.slots.len() as u64) as usize
+    }
+}
@@ -0,0 +1,502 @@
+/* =======================================================================
+Rustic is a chess playing engine.
+Copyright (C) 2019-2024, Marcel Vanthoor
+https://rustic-chess.org/
+
+Rustic is written in the Rust programming language. It is an original
+work, not derived from any engine that came before it. However, it does
+use a lot of concepts which are well-known and are in use by most if not
+all classical alpha/beta-based chess engines.
+
+Rustic is free software: you can redistribute it and/or modify it under
+the terms of the GNU General Public License version 3 as published by
+the Free Software Foundation.
+
+Rustic is distributed in the hope that it will be useful, but WITHOUT
+ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License
+for more details.
+
+You should have received a copy of the GNU General Public License along
+with this program.  If not, see <http://www.gnu.org/licenses/>.
+======================================================================= */
+
+// pgn.rs reads and writes games in Portable Game Notation. A game is kept
+// as its starting position and the list of moves played from there, with
+// the tag pairs (headers), comments and NAGs (numeric annotation glyphs).
+// Moves are read as SAN and checked against the move generator, so every
+// game that is read successfully is a legal game. Variations are skipped;
+// only the main line is kept.
+
+use crate::{
+    board::Board,
+    defs::{Sides, FEN_START_POSITION},
+    misc::{eco, san},
+    movegen::{defs::Move, MoveGenerator},
+};
+
+// These tags are always written, in this order.
+const SEVEN_TAG_ROSTER: [&str; 7] = ["Event", "Site", "Date", "Round", "White", "Black", "Result"];
+const TAG_UNKNOWN: &str = "?";
+const DATE_UNKNOWN: &str = "????.??.??";
+const LINE_LENGTH: usize = 80;
+
+// Annotation symbols that can be written after a move, with their NAG.
+const SUFFIX_NAGS: [(&str, u8); 6] = [
+    ("!", 1),
+    ("?", 2),
+    ("!!", 3),
+    ("??", 4),
+    ("!?", 5),
+    ("?!", 6),
+];
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum GameResult {
+    WhiteWins,
+    BlackWins,
+    Draw,
+    Unknown,
+}
+
+impl GameResult {
+    // Accepts the results used in PGN ("1-0", "0-1", "1/2-1/2" and "*"),
+    // and the numeric results used in datasets (1.0, 0.0 and 0.5).
+    pub fn parse(result: &str) -> Option<GameResult> {
+        match result.trim().trim_matches('"') {
+            "1-0" | "1" | "1.0" => Some(GameResult::WhiteWins),
+            "0-1" | "0" | "0.0" => Some(GameResult::BlackWins),
+            "1/2-1/2" | "1/2" | "0.5" => Some(GameResult::Draw),
+            "*" => Some(GameResult::Unknown),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            GameResult::WhiteWins => "1-0",
+            GameResult::BlackWins => "0-1",
+            GameResult::Draw => "1/2-1/2",
+            GameResult::Unknown => "*",
+        }
+    }
+
+    // The result as a score from white's point of view, if the game has
+    // finished.
+    #[cfg(feature = "extra")]
+    pub fn score(&self) -> Option<f32> {
+        match self {
+            GameResult::WhiteWins => Some(1.0),
+            GameResult::BlackWins => Some(0.0),
+            GameResult::Draw => Some(0.5),
+            GameResult::Unknown => None,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct PgnMove {
+    pub m: Move,
+    pub nags: Vec<u8>,
+    pub comment: Option<String>,
+}
+
+#[derive(Clone)]
+pub struct PgnGame {
+    headers: Vec<(String, String)>,
+    fen: Option<String>,
+    pub comment: Option<String>, // Comment before the first move
+    pub moves: Vec<PgnMove>,
+    pub result: GameResult,
+}
+
+impl PgnGame {
+    // Creates an empty game, starting from the given position. Without a
+    // FEN-string, the game starts from the normal starting position.
+    pub fn new(fen: Option<&str>) -> Self {
+        let fen = fen
+            .map(|f| f.trim().to_string())
+            .filter(|f| f != FEN_START_POSITION);
+
+        Self {
+            headers: Vec::new(),
+            fen,
+            comment: None,
+            moves: Vec::new(),
+            result: GameResult::Unknown,
+        }
+    }
+
+    // Creates a game from the moves that were played on the board since
+    // it was set up from the given FEN-string.
+    pub fn from_history(fen: Option<&str>, board: &Board) -> Self {
+        let mut game = PgnGame::new(fen);
+        for i in 0..board.history.len() {
+            game.push(board.history.get_ref(i).next_move);
+        }
+
+        game
+    }
+
+    pub fn fen(&self) -> Option<&str> {
+        self.fen.as_deref()
+    }
+
+    pub fn header(&self, key: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| &v[..])
+    }
+
+    pub fn set_header(&mut self, key: &str, value: &str) {
+        match self.headers.iter_mut().find(|(k, _)| k == key) {
+            Some(header) => header.1 = value.to_string(),
+            None => self.headers.push((key.to_string(), value.to_string())),
+        }
+    }
+
+    pub fn push(&mut self, m: Move) {
+        self.moves.push(PgnMove {
+            m,
+            nags: Vec::new(),
+            comment: None,
+        });
+    }
+
+    // Sets up the starting position of the game.
+    pub fn start_board(&self) -> Result<Board, String> {
+        let mut board = Board::new();
+        match board.fen_read(self.fen()) {
+            Ok(()) => Ok(board),
+            Err(_) => Err(format!("invalid FEN: {}", self.fen().unwrap_or(""))),
+        }
+    }
+
+    // Plays through the game, and calls the given function with every
+    // position in it, from the start to the end. Returns the final
+    // position.
+    pub fn replay(&self, mg: &MoveGenerator, mut f: impl FnMut(&Board)) -> Result<Board, String> {
+        let mut board = self.start_board()?;
+
+        f(&board);
+        for pm in self.moves.iter() {
+            if !board.make(pm.m, mg) {
+                return Err(format!("illegal move: {}", pm.m.as_string()));
+            }
+            f(&board);
+        }
+
+        Ok(board)
+    }
+
+    // Adds the ECO code and name of the opening to the headers, if the
+    // opening is known.
+    pub fn classify(&mut self, mg: &MoveGenerator) {
+        if let Ok(board) = self.replay(mg, |_| ()) {
+            if let Some(opening) = eco::classify(&board, mg) {
+                self.set_header("ECO", opening.code);
+                self.set_header("Opening", opening.name);
+            }
+        }
+    }
+
+    // Writes the game as PGN. The Seven Tag Roster comes first, followed
+    // by the starting position (if needed) and all other headers.
+    pub fn write(&self, mg: &MoveGenerator) -> Result<String, String> {
+        let mut pgn = String::from("");
+
+        for tag in SEVEN_TAG_ROSTER {
+            let value = match tag {
+                "Result" => self.result.as_str(),
+                "Date" => self.header(tag).unwrap_or(DATE_UNKNOWN),
+                _ => self.header(tag).unwrap_or(TAG_UNKNOWN),
+            };
+            pgn.push_str(&tag_pair(tag, value));
+        }
+
+        if let Some(fen) = self.fen() {
+            pgn.push_str(&tag_pair("SetUp", "1"));
+            pgn.push_str(&tag_pair("FEN", fen));
+        }
+
+        let is_extra = |k: &str| !SEVEN_TAG_ROSTER.contains(&k) && k != "SetUp" && k != "FEN";
+        for (key, value) in self.headers.iter().filter(|(k, _)| is_extra(k)) {
+            pgn.push_str(&tag_pair(key, value));
+        }
+
+        pgn.push('\n');
+        pgn.push_str(&wrap(&self.movetext(mg)?));
+        pgn.push('\n');
+
+        Ok(pgn)
+    }
+
+    // Creates the tokens of the movetext: move numbers, moves, NAGs,
+    // comments and the result.
+    fn movetext(&self, mg: &MoveGenerator) -> Result<Vec<String>, String> {
+        let mut board = self.start_board()?;
+        let mut tokens: Vec<String> = Vec::new();
+        let mut needs_number = true;
+
+        if let Some(comment) = &self.comment {
+            tokens.push(format!("{{{comment}}}"));
+        }
+
+        for pm in self.moves.iter() {
+            // A move by black needs its number at the start of the game,
+            // and after a comment or NAG interrupted the movetext.
+            let move_number = board.game_state.fullmove_number;
+            if board.us() == Sides::WHITE {
+                tokens.push(format!("{move_number}."));
+            } else if needs_number {
+                tokens.push(format!("{move_number}..."));
+            }
+
+            tokens.push(san::move_to_san(&mut board, mg, pm.m));
+            if !board.make(pm.m, mg) {
+                return Err(format!("illegal move: {}", pm.m.as_string()));
+            }
+
+            tokens.extend(pm.nags.iter().map(|nag| format!("${nag}")));
+            if let Some(comment) = &pm.comment {
+                tokens.push(format!("{{{comment}}}"));
+            }
+            needs_number = !pm.nags.is_empty() || pm.comment.is_some();
+        }
+
+        tokens.push(self.result.as_str().to_string());
+
+        Ok(tokens)
+    }
+}
+
+// Reads all the games in the text. Each game is either read completely, or
+// an error describes why it could not be read; one bad game doesn't stop
+// the others from being read.
+pub fn read(text: &str, mg: &MoveGenerator) -> Vec<Result<PgnGame, String>> {
+    split_games(text)
+        .iter()
+        .map(|(headers, movetext)| read_game(headers, movetext, mg))
+        .collect()
+}
+
+// Splits the text into games, each with its tag pair lines and its
+// movetext. A game ends where the tag pairs of the next game begin.
+fn split_games(text: &str) -> Vec<(Vec<&str>, String)> {
+    let mut games: Vec<(Vec<&str>, String)> = Vec::new();
+    let mut headers: Vec<&str> = Vec::new();
+    let mut movetext = String::from("");
+    let mut in_comment = false;
+
+    for line in text.lines() {
+        let trimmed = line.trim();
+
+        // Lines starting with '%' are escaped, and must be skipped.
+        if line.starts_with('%') {
+            continue;
+        }
+
+        if !in_comment && trimmed.starts_with('[') {
+            if !movetext.trim().is_empty() {
+                games.push((headers, movetext));
+                headers = Vec::new();
+                movetext = String::from("");
+            }
+            headers.push(trimmed);
+            continue;
+        }
+
+        // A comment in braces can span lines; tag pairs can't be in it.
+        for c in trimmed.chars() {
+            match c {
+                '{' => in_comment = true,
+                '}' => in_comment = false,
+                _ => (),
+            }
+        }
+        movetext.push_str(line);
+        movetext.push('\n');
+    }
+
+    if !headers.is_empty() || !movetext.trim().is_empty() {
+        games.push((headers, movetext));
+    }
+
+    games
+}
+
+fn read_game(headers: &[&str], movetext: &str, mg: &MoveGenerator) -> Result<PgnGame, String> {
+    let mut pairs: Vec<(String, String)> = Vec::new();
+    for line in headers {
+        pairs.push(parse_tag_pair(line).ok_or(format!("invalid tag pair: {line}"))?);
+    }
+
+    let fen = pairs
+        .iter()
+        .find(|(k, _)| k == "FEN")
+        .map(|(_, v)| v.clone());
+    let mut game = PgnGame::new(fen.as_deref());
+    for (key, value) in pairs.iter() {
+        match &key[..] {
+            "SetUp" | "FEN" => (),
+            "Result" => game.result = GameResult::parse(value).unwrap_or(GameResult::Unknown),
+            _ => game.set_header(key, value),
+        }
+    }
+
+    let mut board = game.start_board()?;
+    let mut variation_depth: usize = 0;
+
+    for token in tokenize(movetext) {
+        // Everything inside a variation is skipped.
+        match token {
+            Token::VariationStart => variation_depth += 1,
+            Token::VariationEnd => variation_depth = variation_depth.saturating_sub(1),
+            _ if variation_depth > 0 => (),
+            Token::Comment(comment) => match game.moves.last_mut() {
+                Some(pm) => pm.comment = Some(comment),
+                None => game.comment = Some(comment),
+            },
+            Token::Nag(nag) => {
+                if let Some(pm) = game.moves.last_mut() {
+                    pm.nags.push(nag);
+                }
+            }
+            Token::Word(word) => {
+                if let Some(result) = GameResult::parse(&word) {
+                    game.result = result;
+                    break;
+                }
+
+                // Move numbers, such as "12." or "12...", are skipped.
+                if word
+                    .trim_end_matches('.')
+                    .chars()
+                    .all(|c| c.is_ascii_digit())
+                {
+                    continue;
+                }
+
+                match san::san_to_move(&mut board, mg, &word) {
+                    Some(m) if board.make(m, mg) => game.push(m),
+                    _ => return Err(format!("illegal move: {word}")),
+                }
+            }
+        }
+    }
+
+    Ok(game)
+}
+
+enum Token {
+    Comment(String),
+    Nag(u8),
+    VariationStart,
+    VariationEnd,
+    Word(String),
+}
+
+// Splits the movetext into tokens. Annotation symbols written after a
+// move ("e4!?") become NAGs, and move numbers are split from the move
+// that follows them ("12.e4").
+fn tokenize(movetext: &str) -> Vec<Token> {
+    let mut tokens: Vec<Token> = Vec::new();
+    let mut chars = movetext.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '{' => {
+                let comment: String = chars.by_ref().take_while(|&c| c != '}').collect();
+                tokens.push(Token::Comment(
+                    comment.split_whitespace().collect::<Vec<_>>().join(" "),
+                ));
+            }
+            ';' => {
+                let comment: String = chars.by_ref().take_while(|&c| c != '\n').collect();
+                tokens.push(Token::Comment(comment.trim().to_string()));
+            }
+            '(' => tokens.push(Token::VariationStart),
+            ')' => tokens.push(Token::VariationEnd),
+            '$' => {
+                let mut nag = String::from("");
+                while let Some(d) = chars.next_if(|d| d.is_ascii_digit()) {
+                    nag.push(d);
+                }
+                if let Ok(n) = nag.parse::<u8>() {
+                    tokens.push(Token::Nag(n));
+                }
+            }
+            c if c.is_whitespace() => (),
+            c => {
+                let mut word = String::from(c);
+                while let Some(w) = chars.next_if(|w| !w.is_whitespace() && !"{}();$".contains(*w))
+                {
+                    word.push(w);
+                }
+                split_word(&word, &mut tokens);
+            }
+        }
+    }
+
+    tokens
+}
+
+// Splits a word from the movetext into a move number, a move, and an
+// annotation symbol, each of which may be missing.
+fn split_word(word: &str, tokens: &mut Vec<Token>) {
+    let mut word = word;
+
+    // A result such as "1-0" also starts with a digit.
+    if GameResult::parse(word).is_none() {
+        if let Some(i) = word.rfind('.') {
+            tokens.push(Token::Word(word[..=i].to_string()));
+            word = &word[i + 1..];
+        }
+    }
+
+    let symbol_start = word.find(['!', '?']).unwrap_or(word.len());
+    let (m, symbol) = word.split_at(symbol_start);
+    if !m.is_empty() {
+        tokens.push(Token::Word(m.to_string()));
+    }
+    if let Some((_, nag)) = SUFFIX_NAGS.iter().find(|(s, _)| *s == symbol) {
+        tokens.push(Token::Nag(*nag));
+    }
+}
+
+// Parses a tag pair such as [Event "Casual Game"] into its key and value.
+fn parse_tag_pair(line: &str) -> Option<(String, String)> {
+    let inner = line.strip_prefix('[')?.strip_suffix(']')?.trim();
+    let (key, value) = inner.split_once(char::is_whitespace)?;
+    let value = value.trim().strip_prefix('"')?.strip_suffix('"')?;
+    let value = value.replace("\\\"", "\"").replace("\\\\", "\\");
+
+    Some((key.to_string(), value))
+}
+
+fn tag_pair(key: &str, value: &str) -> String {
+    let value = value.replace('\\', "\\\\").replace('"', "\\\"");
+    format!("[{key} \"{value}\"]\n")
+}
+
+// Joins the tokens with spaces, into lines of at most LINE_LENGTH
+// characters (unless a single token is longer).
+fn wrap(tokens: &[String]) -> String {
+    let mut text = String::from("");
+    let mut line_length = 0;
+
+    for token in tokens {
+        if line_length > 0 && line_length + 1 + token.len() > LINE_LENGTH {
+            text.push('\n');
+            line_length = 0;
+        } else if line_length > 0 {
+            text.push(' ');
+            line_length += 1;
+        }
+        text.push_str(token);
+        line_length += token.len();
+    }
+
+    text
+}
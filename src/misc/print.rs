@@ -27,6 +27,7 @@ use crate::{
         Board,
     },
     defs::{Bitboard, Castling, NrOf, Sides},
+    evaluation::heatmap::EvalMap,
     movegen::defs::{Move, MoveList},
 };
 
@@ -46,15 +47,149 @@ const CHAR_BB: char = 'b';
 const CHAR_BN: char = 'n';
 const CHAR_BP: char = 'i';
 
+// The colors of a board theme, as codes of the 256-color ANSI palette.
+// The pieces of both sides are drawn in the same solid shape, and told
+// apart by their color.
+pub struct Theme {
+    pub name: &'static str,
+    light: u8,     // Background of the light squares.
+    dark: u8,      // Background of the dark squares.
+    highlight: u8, // Background of the squares of the last move.
+}
+
+pub const THEMES: [Theme; 3] = [
+    Theme {
+        name: "brown",
+        light: 180,
+        dark: 137,
+        highlight: 143,
+    },
+    Theme {
+        name: "blue",
+        light: 153,
+        dark: 67,
+        highlight: 186,
+    },
+    Theme {
+        name: "green",
+        light: 187,
+        dark: 65,
+        highlight: 179,
+    },
+];
+
+const THEME_MONO: &str = "mono"; // Unicode pieces without colors.
+const COLOR_WHITE_PIECE: u8 = 231;
+const COLOR_BLACK_PIECE: u8 = 16;
+
+// How the board is drawn. ASCII works in every terminal; unicode pieces
+// and colors need a terminal that can show them.
+#[derive(Clone, Copy)]
+pub enum BoardStyle {
+    Ascii,
+    Unicode,
+    Color(&'static Theme),
+}
+
+impl BoardStyle {
+    // The style of the theme with the given name, or the default style
+    // without one. A style the terminal can't show falls back to a
+    // simpler one: a dumb terminal or a legacy Windows console gets
+    // ASCII, and NO_COLOR turns the colors off.
+    pub fn select(theme: Option<&str>, default: BoardStyle) -> BoardStyle {
+        let style = match theme {
+            None => default,
+            Some(THEME_MONO) => BoardStyle::Unicode,
+            Some(name) => THEMES
+                .iter()
+                .find(|t| t.name == name)
+                .map_or(default, BoardStyle::Color),
+        };
+
+        let term = std::env::var("TERM").unwrap_or_default();
+        let is_dumb =
+            term == "dumb" || (cfg!(windows) && term.is_empty() && !is_windows_terminal());
+        let no_color = std::env::var_os("NO_COLOR").is_some();
+
+        match style {
+            _ if is_dumb => BoardStyle::Ascii,
+            BoardStyle::Color(_) if no_color => BoardStyle::Unicode,
+            style => style,
+        }
+    }
+
+    // The names that can be given to select().
+    pub fn names() -> Vec<&'static str> {
+        let mut names = vec![THEME_MONO];
+        names.extend(THEMES.iter().map(|t| t.name));
+        names
+    }
+}
+
+// Windows Terminal sets this variable. The legacy console doesn't.
+fn is_windows_terminal() -> bool {
+    std::env::var_os("WT_SESSION").is_some()
+}
+
 // Prints the current position to the screen.
-pub fn position(board: &Board, mark_square: Option<u8>) {
+pub fn position(board: &Board, mark_square: Option<u8>, style: BoardStyle) {
     let mut ascii_board: AsciiBoard = [CHAR_ES; NrOf::SQUARES];
 
     bitboards_to_ascii(board, &mut ascii_board);
-    to_console(&ascii_board, mark_square);
+    to_console(&ascii_board, mark_square, last_move(board), style);
     metadata(board);
 }
 
+// Prints only the board and the side to move, for a user playing in a
+// terminal window.
+pub fn position_compact(board: &Board, style: BoardStyle) {
+    let mut ascii_board: AsciiBoard = [CHAR_ES; NrOf::SQUARES];
+
+    bitboards_to_ascii(board, &mut ascii_board);
+    to_console(&ascii_board, None, last_move(board), style);
+
+    let is_white = board.us() == Sides::WHITE;
+    let active_color = if is_white { "White" } else { "Black" };
+    println!("{active_color} to move.");
+    println!();
+}
+
+// The move that was played to reach the position, if any.
+fn last_move(board: &Board) -> Option<Move> {
+    match board.history.len() {
+        0 => None,
+        n => Some(board.history.get_ref(n - 1).next_move),
+    }
+}
+
+// The unicode chess piece for a character of the ASCII-board.
+fn to_unicode(character: char) -> char {
+    match character {
+        CHAR_WK => '\u{2654}',
+        CHAR_WQ => '\u{2655}',
+        CHAR_WR => '\u{2656}',
+        CHAR_WB => '\u{2657}',
+        CHAR_WN => '\u{2658}',
+        CHAR_WP => '\u{2659}',
+        CHAR_BK => '\u{265A}',
+        CHAR_BQ => '\u{265B}',
+        CHAR_BR => '\u{265C}',
+        CHAR_BB => '\u{265D}',
+        CHAR_BN => '\u{265E}',
+        CHAR_BP => '\u{265F}',
+        _ => '\u{00B7}',
+    }
+}
+
+// The solid unicode chess piece for a character of the ASCII-board, to
+// be drawn in the color of its side.
+fn to_solid_unicode(character: char) -> char {
+    match character.to_ascii_lowercase() {
+        CHAR_ES => ' ',
+        c => to_unicode(c),
+    }
+}
+
 // Create a printable ASCII-board out of bitboards.
 fn bitboards_to_ascii(board: &Board, ascii_board: &mut AsciiBoard) {
     let bb_w = board.bb_pieces[Sides::WHITE];
@@ -100,10 +235,19 @@ fn put_character_on_square(bitboard: Bitboard, ascii_board: &mut AsciiBoard, cha
     }
 }
 
-// Print the generated ASCII-board to the console. Optionally mark one square.
-fn to_console(ascii_board: &AsciiBoard, mark_square: Option<u8>) {
+// Print the generated ASCII-board to the console. Optionally mark one
+// square. With colors, the squares of the last move are highlighted.
+fn to_console(
+    ascii_board: &AsciiBoard,
+    mark_square: Option<u8>,
+    last_move: Option<Move>,
+    style: BoardStyle,
+) {
     let coordinate_alpha: &str = "ABCDEFGH";
     let mut coordinate_digit = NrOf::FILES;
+    let highlight = |square: usize| {
+        last_move.is_some_and(|m| m.get_move() != 0 && (m.from() == square || m.to() == square))
+    };
 
     println!();
     for current_rank in RangeOf::RANKS.rev() {
@@ -111,15 +255,37 @@ fn to_console(ascii_board: &AsciiBoard, mark_square: Option<u8>) {
         for current_file in RangeOf::FILES {
             let square = (current_rank as usize * NrOf::FILES) + current_file as usize;
             let character = ascii_board[square];
-            if let Some(m) = mark_square {
-                if m == (square as u8) {
-                    // \x1b[0;35m is magenta
-                    print!("\x1b[0;35m{character} \x1b[0m");
-                } else {
-                    print!("{character} ");
+            let marked = mark_square == Some(square as u8);
+            match style {
+                BoardStyle::Ascii | BoardStyle::Unicode => {
+                    let character = match style {
+                        BoardStyle::Unicode => to_unicode(character),
+                        _ => character,
+                    };
+                    if marked {
+                        // \x1b[0;35m is magenta
+                        print!("\x1b[0;35m{character} \x1b[0m");
+                    } else {
+                        print!("{character} ");
+                    }
+                }
+                BoardStyle::Color(theme) => {
+                    let is_light = (current_rank + current_file) % 2 == 1;
+                    let background = if marked || highlight(square) {
+                        theme.highlight
+                    } else if is_light {
+                        theme.light
+                    } else {
+                        theme.dark
+                    };
+                    let foreground = if character.is_ascii_uppercase() {
+                        COLOR_WHITE_PIECE
+                    } else {
+                        COLOR_BLACK_PIECE
+                    };
+                    let piece = to_solid_unicode(character);
+                    print!("\x1b[48;5;{background}m\x1b[38;5;{foreground}m {piece} \x1b[0m");
                 }
-            } else {
-                print!("{character} ");
             }
         }
         println!();
@@ -128,7 +294,10 @@ fn to_console(ascii_board: &AsciiBoard, mark_square: Option<u8>) {
     println!();
     print!("    ");
     for c in coordinate_alpha.chars() {
-        print!("{c} ");
+        match style {
+            BoardStyle::Color(_) => print!(" {c} "),
+            _ => print!("{c} "),
+        }
     }
     println!();
     println!();
@@ -172,6 +341,69 @@ pub fn castling_as_string(permissions: u8) -> String {
     castling_as_string
 }
 
+// Prints one or more bitboards on top of each other, below a caption.
+// Each layer puts its own character on the squares that are set in its
+// bitboard. Later layers are drawn over earlier ones, so it is possible
+// to show, for example, the attacks of a piece over the occupancy.
+pub fn bitboards(caption: &str, layers: &[(Bitboard, char)]) {
+    let mut ascii_board: AsciiBoard = [CHAR_ES; NrOf::SQUARES];
+
+    for &(bitboard, character) in layers {
+        put_character_on_square(bitboard, &mut ascii_board, character);
+    }
+
+    println!();
+    println!("{caption}");
+    to_console(&ascii_board, None, None, BoardStyle::Ascii);
+}
+
+// Prints the evaluation contribution of each square as a heatmap, with
+// the piece on the square in front of the value. Values that are good
+// for White are green, and values that are good for Black are red.
+pub fn eval_map(board: &Board, map: &EvalMap) {
+    let mut ascii_board: AsciiBoard = [CHAR_ES; NrOf::SQUARES];
+    bitboards_to_ascii(board, &mut ascii_board);
+
+    println!();
+    for current_rank in RangeOf::RANKS.rev() {
+        print!("{}   ", current_rank + 1);
+        for current_file in RangeOf::FILES {
+            let square = (current_rank as usize * NrOf::FILES) + current_file as usize;
+            let character = ascii_board[square];
+            match map[square] {
+                0 if character == CHAR_ES => print!("{character}{:>5} ", ""),
+                // \x1b[0;32m is green, \x1b[0;31m is red
+                v if v > 0 => print!("\x1b[0;32m{character}{v:>+5}\x1b[0m "),
+                v if v < 0 => print!("\x1b[0;31m{character}{v:>+5}\x1b[0m "),
+                v => print!("{character}{v:>+5} "),
+            }
+        }
+        println!();
+    }
+    println!();
+    print!("    ");
+    for c in "ABCDEFGH".chars() {
+        print!("{c:<6} ");
+    }
+    println!();
+    println!();
+}
+
+// Converts the squares that are set in a bitboard to a list of square
+// names, such as "a1 c3 h8".
+pub fn squares_as_string(bitboard: Bitboard) -> String {
+    let squares: Vec<&str> = (0..NrOf::SQUARES)
+        .filter(|&sq| (bitboard >> sq) & 1 == 1)
+        .map(|sq| SQUARE_NAME[sq])
+        .collect();
+
+    if squares.is_empty() {
+        String::from("-")
+    } else {
+        squares.join(" ")
+    }
+}
+
 // ===== Printing used for development purposes only =====
 
 // This prints a bitboard (64-bit number) to the screen in an 8x8 grid.
@@ -180,7 +412,7 @@ pub fn bitboard(bitboard: Bitboard, mark_square: Option<u8>) {
     const SQUARE_OCCUPIED: char = '1';
     let mut ascii_board: AsciiBoard = [CHAR_ES; 64];
     put_character_on_square(bitboard, &mut ascii_board, SQUARE_OCCUPIED);
-    to_console(&ascii_board, mark_square);
+    to_console(&ascii_board, mark_square, None, BoardStyle::Ascii);
 }
 
 // Prints a given movelist to the screen.
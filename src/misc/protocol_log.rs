@@ -0,0 +1,276 @@
+/* =======================================================================
+Rustic is a chess playing engine.
+Copyright (C) 2019-2024, Marcel Vanthoor
+https://rustic-chess.org/
+
+Rustic is written in the Rust programming language. It is an original
+work, not derived from any engine that came before it. However, it does
+use a lot of concepts which are well-known and are in use by most if not
+all classical alpha/beta-based chess engines.
+
+Rustic is free software: you can redistribute it and/or modify it under
+the terms of the GNU General Public License version 3 as published by
+the Free Software Foundation.
+
+Rustic is distributed in the hope that it will be useful, but WITHOUT
+ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License
+for more details.
+
+You should have received a copy of the GNU General Public License along
+with this program.  If not, see <http://www.gnu.org/licenses/>.
+======================================================================= */
+
+// protocol_log.rs writes every line the engine receives from the GUI, and
+// every line it sends back, to a file. Each line gets a timestamp (UTC,
+// with milliseconds) and a marker for its direction: ">" for input and
+// "<" for output. This shows exactly what was said, and when, if the
+// engine and a GUI don't understand each other. When the file grows too
+// large, it is renamed to "<file>.1" and a new file is started. Older
+// files move up to "<file>.2" and so on; only a few of them are kept.
+
+use crate::engine::defs::ErrFatal;
+use std::{
+    fs::{self, File, OpenOptions},
+    io::Write,
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+const MAX_FILE_SIZE: u64 = 10 * 1024 * 1024;
+const OLD_FILES: usize = 3;
+
+const INCOMING: char = '>';
+const OUTGOING: char = '<';
+
+// The open file, with its path for rotating it.
+struct LogFile {
+    file: File,
+    path: String,
+    size: u64,
+}
+
+impl LogFile {
+    // Move the current file and the old ones up by one, dropping the
+    // oldest, and start a new, empty file.
+    fn rotate(&mut self) -> std::io::Result<()> {
+        for n in (1..OLD_FILES).rev() {
+            let from = format!("{}.{n}", self.path);
+            if fs::metadata(&from).is_ok() {
+                fs::rename(&from, format!("{}.{}", self.path, n + 1))?;
+            }
+        }
+        fs::rename(&self.path, format!("{}.1", self.path))?;
+
+        self.file = File::create(&self.path)?;
+        self.size = 0;
+
+        Ok(())
+    }
+}
+
+// The log is shared: the engine opens and closes it, and the threads of
+// the Comm module write to it. Without a file, all functions do nothing.
+#[derive(Clone)]
+pub struct ProtocolLog {
+    file: Arc<Mutex<Option<LogFile>>>,
+}
+
+impl ProtocolLog {
+    pub fn new() -> Self {
+        Self {
+            file: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    // Write to the given file from now on, or stop writing if the path is
+    // empty. Lines are appended to an existing file.
+    pub fn open(&self, path: &str) -> Result<(), String> {
+        let mut file = self.file.lock().expect(ErrFatal::LOCK);
+        *file = None;
+
+        if path.is_empty() {
+            return Ok(());
+        }
+
+        let f = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| format!("{path}: {e}"))?;
+        let size = f.metadata().map(|m| m.len()).unwrap_or(0);
+
+        *file = Some(LogFile {
+            file: f,
+            path: path.to_string(),
+            size,
+        });
+
+        Ok(())
+    }
+
+    // A line received from the GUI.
+    pub fn incoming(&self, line: &str) {
+        self.write(INCOMING, line);
+    }
+
+    // Output sent to the GUI. This can be several lines at once, which
+    // are logged one by one.
+    pub fn outgoing(&self, output: &str) {
+        for line in output.lines() {
+            self.write(OUTGOING, line);
+        }
+    }
+
+    // Lines are written right away, so the log is complete even if the
+    // engine is killed by the GUI. A write error closes the file; it is
+    // not worth stopping a game for.
+    fn write(&self, direction: char, line: &str) {
+        let mut file = self.file.lock().expect(ErrFatal::LOCK);
+        let Some(f) = file.as_mut() else {
+            return;
+        };
+
+        let entry = format!("{} {direction} {line}\n", timestamp());
+        let length = entry.len() as u64;
+
+        if f.size > 0 && f.size + length > MAX_FILE_SIZE && f.rotate().is_err() {
+            *file = None;
+            return;
+        }
+
+        match f.file.write_all(entry.as_bytes()) {
+            Ok(()) => f.size += length,
+            Err(_) => *file = None,
+        }
+    }
+}
+
+// The current time in UTC, as "2024-01-21 13:45:07.123".
+fn timestamp() -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let seconds = now.as_secs();
+    let (year, month, day) = date_from_days((seconds / 86_400) as i64);
+    let time = seconds % 86_400;
+
+    format!(
+        "{year:04}-{month:02}-{day:02} {:02}:{:02}:{:02}.{:03}",
+        time / 3600,
+        time % 3600 / 60,
+        time % 60,
+        now.subsec_millis()
+    )
+}
+
+// Converts a number of days since 1970-01-01 into a date in the
+// Gregorian calendar. The calendar repeats every 400 years (an "era");
+// within an era, years are counted from March, so the leap day is the
+// last day of the year. (This is Howard Hinnant's "civil_from_days".)
+fn date_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    // A path in a fresh directory of its own, so tests running at the
+    // same time don't share files.
+    fn log_path(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("rustic-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir.join("protocol.log")
+    }
+
+    #[test]
+    fn dates_from_days() {
+        assert_eq!(date_from_days(0), (1970, 1, 1));
+        assert_eq!(date_from_days(-1), (1969, 12, 31));
+        assert_eq!(date_from_days(11_016), (2000, 2, 29));
+        assert_eq!(date_from_days(11_017), (2000, 3, 1));
+        assert_eq!(date_from_days(19_782), (2024, 2, 29));
+        assert_eq!(date_from_days(47_541), (2100, 3, 1));
+    }
+
+    #[test]
+    fn timestamp_format() {
+        let t = timestamp();
+        let digits = [0, 1, 2, 3, 5, 6, 8, 9, 11, 12, 14, 15, 17, 18, 20, 21, 22];
+        let separators = [
+            (4, '-'),
+            (7, '-'),
+            (10, ' '),
+            (13, ':'),
+            (16, ':'),
+            (19, '.'),
+        ];
+
+        assert_eq!(t.len(), 23, "{t}");
+        for i in digits {
+            assert!(t.as_bytes()[i].is_ascii_digit(), "{t}");
+        }
+        for (i, c) in separators {
+            assert_eq!(t.as_bytes()[i] as char, c, "{t}");
+        }
+    }
+
+    #[test]
+    fn lines_are_marked_with_their_direction() {
+        let path = log_path("direction");
+        let log = ProtocolLog::new();
+        log.open(path.to_str().unwrap()).unwrap();
+        log.incoming("isready");
+        log.outgoing("readyok\nbestmove e2e4");
+        log.open("").unwrap();
+        log.incoming("not logged");
+
+        let text = fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = text.lines().map(|l| &l[24..]).collect();
+        assert_eq!(lines, ["> isready", "< readyok", "< bestmove e2e4"]);
+
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn rotate_keeps_old_files() {
+        let path = log_path("rotate");
+        let name = path.to_str().unwrap().to_string();
+        let log = ProtocolLog::new();
+        log.open(&name).unwrap();
+
+        // Each file holds the number of the rotation that started it.
+        for n in 0..=OLD_FILES + 1 {
+            let mut file = log.file.lock().unwrap();
+            let f = file.as_mut().unwrap();
+            if n > 0 {
+                f.rotate().unwrap();
+            }
+            write!(f.file, "{n}").unwrap();
+        }
+
+        let newest = OLD_FILES + 1;
+        assert_eq!(fs::read_to_string(&path).unwrap(), newest.to_string());
+        for old in 1..=OLD_FILES {
+            let text = fs::read_to_string(format!("{name}.{old}")).unwrap();
+            assert_eq!(text, (newest - old).to_string());
+        }
+        assert!(fs::metadata(format!("{name}.{}", OLD_FILES + 1)).is_err());
+
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+    }
+}
@@ -0,0 +1,198 @@
+/* =======================================================================
+Rustic is a chess playing engine.
+Copyright (C) 2019-2024, Marcel Vanthoor
+https://rustic-chess.org/
+
+Rustic is written in the Rust programming language. It is an original
+work, not derived from any engine that came before it. However, it does
+use a lot of concepts which are well-known and are in use by most if not
+all classical alpha/beta-based chess engines.
+
+Rustic is free software: you can redistribute it and/or modify it under
+the terms of the GNU General Public License version 3 as published by
+the Free Software Foundation.
+
+Rustic is distributed in the hope that it will be useful, but WITHOUT
+ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License
+for more details.
+
+You should have received a copy of the GNU General Public License along
+with this program.  If not, see <http://www.gnu.org/licenses/>.
+======================================================================= */
+
+// san.rs converts moves into Standard Algebraic Notation (SAN), such as
+// "Nbd2", "exd5", "O-O" or "e8=Q+", and back. The engine itself works with
+// long algebraic notation (e2e4) because that is what UCI uses; SAN is
+// used to present moves to humans, and to read and write PGN.
+
+use crate::{
+    board::{
+        defs::{Pieces, PIECE_CHAR_CAPS, SQUARE_NAME},
+        Board,
+    },
+    defs::Sides,
+    movegen::{
+        defs::{Move, MoveList, MoveType},
+        MoveGenerator,
+    },
+};
+
+// Converts a single move into SAN. The move must be legal in the given
+// position. The board is used to make and unmake moves while determining
+// disambiguation and check/mate suffixes; it is unchanged afterwards.
+pub fn move_to_san(board: &mut Board, mg: &MoveGenerator, m: Move) -> String {
+    let mut san = san_without_suffix(board, mg, m);
+
+    // Play the move to see if it gives check or mate.
+    if board.make(m, mg) {
+        let in_check = mg.square_attacked(board, board.opponent(), board.king_square(board.us()));
+        if in_check {
+            san.push(if has_legal_move(board, mg) { '+' } else { '#' });
+        }
+        board.unmake();
+    }
+
+    san
+}
+
+// Finds the legal move in the position that is written as the given SAN.
+// Check and mate markers, annotations such as "!?", and the "=" of a
+// promotion are optional; castling may also be written with zeroes.
+// Returns None if no legal move matches.
+pub fn san_to_move(board: &mut Board, mg: &MoveGenerator, san: &str) -> Option<Move> {
+    let wanted = normalize(san);
+    let mut move_list = MoveList::new();
+    mg.generate_moves(board, &mut move_list, MoveType::All);
+
+    for i in 0..move_list.len() {
+        let m = move_list.get_move(i);
+        if normalize(&san_without_suffix(board, mg, m)) == wanted && board.make(m, mg) {
+            board.unmake();
+            return Some(m);
+        }
+    }
+
+    None
+}
+
+// The SAN of a move, without the check or mate marker.
+fn san_without_suffix(board: &mut Board, mg: &MoveGenerator, m: Move) -> String {
+    let piece = m.piece();
+    let from = m.from();
+    let to = m.to();
+    let is_capture = m.captured() != Pieces::NONE || m.en_passant();
+    let mut san = String::from("");
+
+    if m.castling() {
+        // Castling to the G-file is kingside, to the C-file queenside.
+        san.push_str(if to % 8 == 6 { "O-O" } else { "O-O-O" });
+    } else if piece == Pieces::PAWN {
+        // Pawn captures are noted with the file the pawn came from.
+        if is_capture {
+            san.push_str(&SQUARE_NAME[from][0..1]);
+            san.push('x');
+        }
+        san.push_str(SQUARE_NAME[to]);
+
+        if m.promoted() != Pieces::NONE {
+            san.push('=');
+            san.push_str(PIECE_CHAR_CAPS[m.promoted()]);
+        }
+    } else {
+        san.push_str(PIECE_CHAR_CAPS[piece]);
+        san.push_str(&disambiguation(board, mg, m));
+        if is_capture {
+            san.push('x');
+        }
+        san.push_str(SQUARE_NAME[to]);
+    }
+
+    san
+}
+
+// Strips everything from a SAN that is optional when reading it.
+fn normalize(san: &str) -> String {
+    san.replace('0', "O")
+        .chars()
+        .filter(|c| !matches!(c, '+' | '#' | '!' | '?' | '='))
+        .collect()
+}
+
+// Converts a list of moves (such as a PV) into SAN with move numbers, for
+// example "12. Qh5+ Kf8 13. Qxf7#" or "12... Kf8 13. Qxf7#". Conversion
+// stops at the first move that is not legal in the position it is played
+// in.
+pub fn moves_to_san(board: &Board, mg: &MoveGenerator, moves: &[Move]) -> String {
+    let mut local_board = board.clone();
+    let mut line = String::from("");
+
+    for (i, m) in moves.iter().enumerate() {
+        let is_white = local_board.us() == Sides::WHITE;
+        let move_number = local_board.game_state.fullmove_number;
+
+        if is_white {
+            line.push_str(&format!("{move_number}. "));
+        } else if i == 0 {
+            line.push_str(&format!("{move_number}... "));
+        }
+
+        let san = move_to_san(&mut local_board, mg, *m);
+        if !local_board.make(*m, mg) {
+            break;
+        }
+
+        line.push_str(&san);
+        line.push(' ');
+    }
+
+    line.trim().to_string()
+}
+
+// Returns the file, the rank, or the full square of the from-square if
+// another piece of the same type could also legally move to the to-square.
+fn disambiguation(board: &mut Board, mg: &MoveGenerator, m: Move) -> String {
+    let mut move_list = MoveList::new();
+    let mut same_file = false;
+    let mut same_rank = false;
+    let mut ambiguous = false;
+
+    mg.generate_moves(board, &mut move_list, MoveType::All);
+
+    for i in 0..move_list.len() {
+        let other = move_list.get_move(i);
+        let is_candidate =
+            other.piece() == m.piece() && other.to() == m.to() && other.from() != m.from();
+
+        // Only legal moves can cause ambiguity.
+        if is_candidate && board.make(other, mg) {
+            board.unmake();
+            ambiguous = true;
+            same_file |= other.from() % 8 == m.from() % 8;
+            same_rank |= other.from() / 8 == m.from() / 8;
+        }
+    }
+
+    let square = SQUARE_NAME[m.from()];
+    match (ambiguous, same_file, same_rank) {
+        (false, _, _) => String::from(""),
+        (true, false, _) => square[0..1].to_string(),
+        (true, true, false) => square[1..2].to_string(),
+        (true, true, true) => square.to_string(),
+    }
+}
+
+// Determines if the side to move has at least one legal move.
+fn has_legal_move(board: &mut Board, mg: &MoveGenerator) -> bool {
+    let mut move_list = MoveList::new();
+    mg.generate_moves(board, &mut move_list, MoveType::All);
+
+    for i in 0..move_list.len() {
+        if board.make(move_list.get_move(i), mg) {
+            board.unmake();
+            return true;
+        }
+    }
+
+    false
+}
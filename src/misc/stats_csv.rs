@@ -0,0 +1,172 @@
+/* =======================================================================
+Rustic is a chess playing engine.
+Copyright (C) 2019-2024, Marcel Vanthoor
+https://rustic-chess.org/
+
+Rustic is written in the Rust programming language. It is an original
+work, not derived from any engine that came before it. However, it does
+use a lot of concepts which are well-known and are in use by most if not
+all classical alpha/beta-based chess engines.
+
+Rustic is free software: you can redistribute it and/or modify it under
+the terms of the GNU General Public License version 3 as published by
+the Free Software Foundation.
+
+Rustic is distributed in the hope that it will be useful, but WITHOUT
+ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License
+for more details.
+
+You should have received a copy of the GNU General Public License along
+with this program.  If not, see <http://www.gnu.org/licenses/>.
+======================================================================= */
+
+// stats_csv.rs writes the result of each iteration of each search to a
+// CSV file, so the behavior of the search over a whole match or bench run
+// can be analyzed in a spreadsheet or a script. Every row holds the
+// position the search started in (as FEN), so rows can be grouped per
+// position. The effective branching factor (EBF) is the number of nodes
+// of an iteration divided by that of the iteration before it; "pv
+// changes" counts how often the best move changed during the search.
+// Only the main line is written; MultiPV lines are skipped.
+
+use crate::{
+    engine::defs::ErrFatal,
+    movegen::defs::Move,
+    search::defs::{ScoreBound, SearchSummary},
+};
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufWriter, Write},
+    sync::{Arc, Mutex},
+};
+
+const HEADER: &str =
+    "search,position,depth,seldepth,cp,mate,bound,nodes,time,nps,ebf,pv_changes,hashfull,pv";
+
+// The open file, and what is needed to fill in the columns that depend
+// on earlier iterations of the same search.
+struct CsvFile {
+    writer: BufWriter<File>,
+    search: usize,
+    position: String,
+    last_nodes: usize,
+    best_move: Option<Move>,
+    pv_changes: usize,
+}
+
+// The writer is shared: the engine and the benchmark can both write to the
+// same file. Without a file, all functions do nothing.
+#[derive(Clone)]
+pub struct StatsCsv {
+    file: Arc<Mutex<Option<CsvFile>>>,
+}
+
+impl StatsCsv {
+    pub fn new() -> Self {
+        Self {
+            file: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    // Write to the given file from now on, or stop writing if the path is
+    // empty. Rows are appended to an existing file, so the statistics of
+    // several engine runs can be collected into one. The header is only
+    // written into a new (or empty) file.
+    pub fn open(&self, path: &str) -> Result<(), String> {
+        let mut file = self.file.lock().expect(ErrFatal::LOCK);
+        *file = None;
+
+        if path.is_empty() {
+            return Ok(());
+        }
+
+        let f = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| format!("{path}: {e}"))?;
+        let is_empty = f.metadata().map(|m| m.len() == 0).unwrap_or(true);
+        let mut writer = BufWriter::new(f);
+
+        if is_empty {
+            writeln!(writer, "{HEADER}").map_err(|e| format!("{path}: {e}"))?;
+        }
+
+        *file = Some(CsvFile {
+            writer,
+            search: 0,
+            position: String::new(),
+            last_nodes: 0,
+            best_move: None,
+            pv_changes: 0,
+        });
+
+        Ok(())
+    }
+
+    // A new search starts in the given position.
+    pub fn begin(&self, fen: &str) {
+        if let Some(f) = self.file.lock().expect(ErrFatal::LOCK).as_mut() {
+            f.search += 1;
+            f.position = fen.to_string();
+            f.last_nodes = 0;
+            f.best_move = None;
+            f.pv_changes = 0;
+        }
+    }
+
+    // Write one row for a finished iteration. Writing is done right away,
+    // so the file is complete even if the engine is killed by the GUI.
+    // A write error closes the file; it is not worth stopping a game for.
+    pub fn record(&self, summary: &SearchSummary) {
+        let mut file = self.file.lock().expect(ErrFatal::LOCK);
+        let Some(f) = file.as_mut() else {
+            return;
+        };
+
+        if summary.multi_pv > 1 {
+            return;
+        }
+
+        let best_move = summary.pv.first().copied();
+        if f.best_move.is_some() && best_move != f.best_move {
+            f.pv_changes += 1;
+        }
+        f.best_move = best_move;
+
+        let ebf = if f.last_nodes > 0 {
+            format!("{:.2}", summary.nodes as f64 / f.last_nodes as f64)
+        } else {
+            String::new()
+        };
+        f.last_nodes = summary.nodes;
+
+        let bound = match summary.bound {
+            ScoreBound::Exact => "exact",
+            ScoreBound::Lower => "lower",
+            ScoreBound::Upper => "upper",
+        };
+
+        let row = format!(
+            "{},{},{},{},{},{},{bound},{},{},{},{ebf},{},{},{}",
+            f.search,
+            f.position,
+            summary.depth,
+            summary.seldepth,
+            summary.cp,
+            summary.mate_in().unwrap_or(0),
+            summary.nodes,
+            summary.time,
+            summary.nps,
+            f.pv_changes,
+            summary.hash_full,
+            summary.pv_as_string().trim()
+        );
+
+        let written = writeln!(f.writer, "{row}").and_then(|_| f.writer.flush());
+        if written.is_err() {
+            *file = None;
+        }
+    }
+}
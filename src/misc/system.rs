@@ -0,0 +1,59 @@
+/* =======================================================================
+Rustic is a chess playing engine.
+Copyright (C) 2019-2024, Marcel Vanthoor
+https://rustic-chess.org/
+
+Rustic is written in the Rust programming language. It is an original
+work, not derived from any engine that came before it. However, it does
+use a lot of concepts which are well-known and are in use by most if not
+all classical alpha/beta-based chess engines.
+
+Rustic is free software: you can redistribute it and/or modify it under
+the terms of the GNU General Public License version 3 as published by
+the Free Software Foundation.
+
+Rustic is distributed in the hope that it will be useful, but WITHOUT
+ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License
+for more details.
+
+You should have received a copy of the GNU General Public License along
+with this program.  If not, see <http://www.gnu.org/licenses/>.
+======================================================================= */
+
+// system.rs queries the platform the engine is running on. These queries
+// are used to warn the user about settings the computer can't support.
+// If a query is not supported on the current platform, it returns None.
+
+// The clock of the standard library is not available in a browser. There,
+// the time is taken from JavaScript instead.
+#[cfg(not(target_arch = "wasm32"))]
+pub use std::time::Instant;
+#[cfg(target_arch = "wasm32")]
+pub use web_time::Instant;
+
+// Returns the number of logical CPU cores.
+pub fn logical_cores() -> Option<usize> {
+    std::thread::available_parallelism().ok().map(|n| n.get())
+}
+
+// Returns the total amount of physical memory in megabytes. On Linux this
+// is read from /proc/meminfo; other platforms are not supported yet.
+pub fn total_memory_mb() -> Option<usize> {
+    #[cfg(target_os = "linux")]
+    {
+        const KILOBYTES_PER_MB: usize = 1024;
+
+        // The line we're looking for is: "MemTotal:   16318276 kB"
+        let meminfo = std::fs::read_to_string("/proc/meminfo").ok()?;
+        let line = meminfo.lines().find(|l| l.starts_with("MemTotal:"))?;
+        let kilobytes = line.split_whitespace().nth(1)?.parse::<usize>().ok()?;
+
+        Some(kilobytes / KILOBYTES_PER_MB)
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    {
+        None
+    }
+}
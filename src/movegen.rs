@@ -24,6 +24,7 @@ with this program.  If not, see <http://www.gnu.org/licenses/>.
 mod create;
 pub mod defs;
 mod init;
+mod legal;
 mod magics;
 mod movelist;
 
@@ -82,8 +83,15 @@ impl MoveGenerator {
     }
 
     // Generates moves for the side that is to move. The MoveType parameter
-    // determines if all moves, or only captures need to be generated.
+    // determines if all moves, or only captures need to be generated. All
+    // of these are pseudo-legal, except for MoveType::Legal: those moves
+    // don't leave the king in check.
     pub fn generate_moves(&self, board: &Board, ml: &mut MoveList, mt: MoveType) {
+        if mt == MoveType::Legal {
+            self.legal_moves(board, ml);
+            return;
+        }
+
         self.piece(board, Pieces::KING, ml, mt);
         self.piece(board, Pieces::KNIGHT, ml, mt);
         self.piece(board, Pieces::ROOK, ml, mt);
@@ -164,7 +172,7 @@ impl MoveGenerator {
 
             // Generate moves according to requested move type.
             let bb_moves = match mt {
-                MoveType::All => bb_target & !bb_own_pieces,
+                MoveType::All | MoveType::Legal => bb_target & !bb_own_pieces,
                 MoveType::Quiet => bb_target & bb_empty,
                 MoveType::Capture => bb_target & bb_opponent_pieces,
             };
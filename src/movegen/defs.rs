@@ -71,7 +71,7 @@ use crate::{
 #[cfg(feature = "extra")]
 pub use super::magics::Magic;
 
-const MOVE_ONLY: usize = 0x00_00_00_00_00_FF_FF_FF;
+const MOVE_ONLY: u64 = 0x00_00_00_00_00_FF_FF_FF;
 
 /* "Shift" is an enum which contains the number of bits that needed to be shifted to store
  * move data in a specific place within the u64 integer. This makes sure that, should the
@@ -94,19 +94,21 @@ pub enum MoveType {
     Quiet,
     Capture,
     All,
+    Legal,
 }
 
 /* This struct contains the move data. It's a struct so it can be instantiated, and then
- * it can provide all of the methods associated with it to easily decode the move data. */
+ * it can provide all of the methods associated with it to easily decode the move data.
+ * The data is a u64 even on 32-bit platforms, because the sort score needs the upper bits. */
 #[derive(Copy, Clone, PartialEq)]
 pub struct Move {
-    data: usize,
+    data: u64,
 }
 
 // These functions decode the move data.
 impl Move {
     pub fn new(data: usize) -> Self {
-        Self { data }
+        Self { data: data as u64 }
     }
 
     pub fn piece(&self) -> Piece {
@@ -146,8 +148,8 @@ impl Move {
     }
 
     pub fn set_sort_score(&mut self, value: u32) {
-        let mask: usize = 0xFFFFFFFF << Shift::SORTSCORE;
-        let v: usize = (value as usize) << Shift::SORTSCORE;
+        let mask: u64 = 0xFFFFFFFF << Shift::SORTSCORE;
+        let v: u64 = (value as u64) << Shift::SORTSCORE;
         self.data = (self.data & !mask) | v;
     }
 
@@ -0,0 +1,213 @@
+/* =======================================================================
+Rustic is a chess playing engine.
+Copyright (C) 2019-2024, Marcel Vanthoor
+https://rustic-chess.org/
+
+Rustic is written in the Rust programming language. It is an original
+work, not derived from any engine that came before it. However, it does
+use a lot of concepts which are well-known and are in use by most if not
+all classical alpha/beta-based chess engines.
+
+Rustic is free software: you can redistribute it and/or modify it under
+the terms of the GNU General Public License version 3 as published by
+the Free Software Foundation.
+
+Rustic is distributed in the hope that it will be useful, but WITHOUT
+ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License
+for more details.
+
+You should have received a copy of the GNU General Public License along
+with this program.  If not, see <http://www.gnu.org/licenses/>.
+======================================================================= */
+
+// legal.rs generates strictly legal moves. Normally, the move generator
+// creates pseudo-legal moves, and make() finds out if a move leaves the
+// king in check. Here, the pieces giving check and the pinned pieces are
+// determined up front, with bitboards, so each move can be checked without
+// making it:
+//
+// - In double check, only the king can move.
+// - In single check, a move must capture the checker or block the check.
+// - A pinned piece may only move along the line through the king and
+//   itself.
+// - The king may not move to an attacked square. The king is taken off
+//   the board first, so it can't hide behind itself from a slider.
+// - En passant removes two pawns from a rank at once, which can uncover a
+//   check in a way the pins don't show. It is checked separately.
+
+use super::{
+    defs::{Move, MoveType},
+    movelist::MoveList,
+    MoveGenerator,
+};
+use crate::{
+    board::{
+        defs::{Pieces, BB_SQUARES},
+        Board,
+    },
+    defs::{Bitboard, Square, EMPTY},
+    misc::bits,
+};
+
+// Everything needed to check moves for legality in one position.
+struct LegalInfo {
+    king: Square,
+    checkers: Bitboard,
+    check_mask: Bitboard,
+    pinned: Bitboard,
+}
+
+impl MoveGenerator {
+    // Generates the legal moves for the side to move.
+    pub fn legal_moves(&self, board: &Board, list: &mut MoveList) {
+        let mut pseudo_legal = MoveList::new();
+        self.generate_moves(board, &mut pseudo_legal, MoveType::All);
+
+        let info = self.legal_info(board);
+        for i in 0..pseudo_legal.len() {
+            let m = pseudo_legal.get_move(i);
+            if self.is_legal_with(board, m, &info) {
+                list.push(m);
+            }
+        }
+    }
+
+    // Determines if a move is legal, without making it. The move must be
+    // pseudo-legal in this position, as created by generate_moves().
+    pub fn is_legal(&self, board: &Board, m: Move) -> bool {
+        let info = self.legal_info(board);
+        self.is_legal_with(board, m, &info)
+    }
+
+    fn is_legal_with(&self, board: &Board, m: Move, info: &LegalInfo) -> bool {
+        let from = m.from();
+        let to = m.to();
+
+        if m.piece() == Pieces::KING {
+            let occupancy = board.occupancy() & !BB_SQUARES[from];
+            if m.castling() {
+                let passing = (from + to) / 2;
+                return info.checkers == EMPTY
+                    && self.attackers(board, passing, occupancy) == EMPTY
+                    && self.attackers(board, to, occupancy) == EMPTY;
+            }
+            return self.attackers(board, to, occupancy) == EMPTY;
+        }
+
+        // In double check, only a king move can help.
+        if info.checkers.count_ones() > 1 {
+            return false;
+        }
+
+        if m.en_passant() {
+            return self.en_passant_is_legal(board, from, to, info.king);
+        }
+
+        // Capture the checker, or step in between.
+        if BB_SQUARES[to] & info.check_mask == EMPTY {
+            return false;
+        }
+
+        // A pinned piece stays on the line through the king.
+        BB_SQUARES[from] & info.pinned == EMPTY
+            || BB_SQUARES[to] & self.line(info.king, from) != EMPTY
+    }
+
+    // Play the en passant capture on the occupancy, and see if the king is
+    // attacked afterwards. The captured pawn can't attack anymore.
+    fn en_passant_is_legal(&self, board: &Board, from: Square, to: Square, king: Square) -> bool {
+        let captured = if to > from { to - 8 } else { to + 8 };
+        let bb_captured = BB_SQUARES[captured];
+        let occupancy = (board.occupancy() & !BB_SQUARES[from] & !bb_captured) | BB_SQUARES[to];
+
+        self.attackers(board, king, occupancy) & !bb_captured == EMPTY
+    }
+
+    // Finds the checkers, the squares where a check can be stopped, and
+    // the pinned pieces.
+    fn legal_info(&self, board: &Board) -> LegalInfo {
+        let us = board.us();
+        let opponent = board.opponent();
+        let king = board.king_square(us);
+        let checkers = self.attackers(board, king, board.occupancy());
+
+        // Without check, every square is fine. In single check, it's the
+        // checker and the squares between it and the king.
+        let check_mask = match checkers.count_ones() {
+            0 => !EMPTY,
+            1 => checkers | self.between(king, checkers.trailing_zeros() as Square),
+            _ => EMPTY,
+        };
+
+        // Look for opponent sliders attacking the king as if our own
+        // pieces weren't there. If exactly one piece stands in between,
+        // and it is ours, it is pinned.
+        let them = board.bb_pieces[opponent];
+        let bb_opponent = board.bb_side[opponent];
+        let rook_like = them[Pieces::ROOK] | them[Pieces::QUEEN];
+        let bishop_like = them[Pieces::BISHOP] | them[Pieces::QUEEN];
+        let mut pinners = (self.get_slider_attacks(Pieces::ROOK, king, bb_opponent) & rook_like)
+            | (self.get_slider_attacks(Pieces::BISHOP, king, bb_opponent) & bishop_like);
+
+        let mut pinned = EMPTY;
+        while pinners > 0 {
+            let pinner = bits::next(&mut pinners);
+            let blockers = self.between(king, pinner) & board.occupancy();
+            if blockers.count_ones() == 1 {
+                pinned |= blockers & board.bb_side[us];
+            }
+        }
+
+        LegalInfo {
+            king,
+            checkers,
+            check_mask,
+            pinned,
+        }
+    }
+
+    // Returns the opponent's pieces attacking the square, with the given
+    // occupancy.
+    fn attackers(&self, board: &Board, square: Square, occupancy: Bitboard) -> Bitboard {
+        let us = board.us();
+        let them = board.bb_pieces[board.opponent()];
+        let bb_rook = self.get_slider_attacks(Pieces::ROOK, square, occupancy);
+        let bb_bishop = self.get_slider_attacks(Pieces::BISHOP, square, occupancy);
+
+        (self.get_non_slider_attacks(Pieces::KING, square) & them[Pieces::KING])
+            | (self.get_non_slider_attacks(Pieces::KNIGHT, square) & them[Pieces::KNIGHT])
+            | (self.get_pawn_attacks(us, square) & them[Pieces::PAWN])
+            | (bb_rook & (them[Pieces::ROOK] | them[Pieces::QUEEN]))
+            | (bb_bishop & (them[Pieces::BISHOP] | them[Pieces::QUEEN]))
+    }
+
+    // Returns the squares strictly between two squares on the same rank,
+    // file or diagonal. If the squares are not on one line, this is empty.
+    // A slider on either square, with only the other square occupied,
+    // reaches the other square; the squares both reach lie in between.
+    fn between(&self, a: Square, b: Square) -> Bitboard {
+        for piece in [Pieces::ROOK, Pieces::BISHOP] {
+            let from_a = self.get_slider_attacks(piece, a, BB_SQUARES[b]);
+            if from_a & BB_SQUARES[b] != EMPTY {
+                return from_a & self.get_slider_attacks(piece, b, BB_SQUARES[a]);
+            }
+        }
+
+        EMPTY
+    }
+
+    // Returns the entire line through two squares, from one edge of the
+    // board to the other. The squares must be on one line.
+    fn line(&self, a: Square, b: Square) -> Bitboard {
+        for piece in [Pieces::ROOK, Pieces::BISHOP] {
+            let from_a = self.get_slider_attacks(piece, a, EMPTY);
+            if from_a & BB_SQUARES[b] != EMPTY {
+                let from_b = self.get_slider_attacks(piece, b, EMPTY);
+                return (from_a & from_b) | BB_SQUARES[a] | BB_SQUARES[b];
+            }
+        }
+
+        EMPTY
+    }
+}
@@ -25,25 +25,32 @@ with this program.  If not, see <http://www.gnu.org/licenses/>.
 
 mod alpha_beta;
 pub mod defs;
+mod extensions;
 mod iter_deep;
 mod qsearch;
+mod see;
+mod smp;
 mod sorting;
 mod time;
 mod utils;
 
 use crate::{
     board::Board,
-    engine::defs::{ErrFatal, Information},
-    engine::defs::{SearchData, TT},
-    movegen::MoveGenerator,
+    engine::defs::{info_channel, ErrFatal, InfoSender, Information},
+    engine::defs::{PawnData, SearchData, TT},
+    evaluation::params::EvalParams,
+    movegen::{defs::Move, MoveGenerator},
 };
 use crossbeam_channel::Sender;
 use defs::{
-    SearchControl, SearchInfo, SearchParams, SearchRefs, SearchReport, SearchSummary,
-    SearchTerminate,
+    HistoryTable, LmrTable, PvArena, SearchControl, SearchInfo, SearchParams, SearchRefs,
+    SearchReport, SearchSummary, SearchTerminate, SharedHistory, STACK_SIZE,
 };
 use std::{
-    sync::{Arc, Mutex},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize},
+        Arc, Mutex, RwLock,
+    },
     thread::{self, JoinHandle},
 };
 
@@ -62,10 +69,10 @@ impl Search {
 
     pub fn init(
         &mut self,
-        report_tx: Sender<Information>, // Used to send information to engine.
-        board: Arc<Mutex<Board>>,       // Arc pointer to engine's board.
-        mg: Arc<MoveGenerator>,         // Arc pointer to engine's move generator.
-        tt: Arc<Mutex<TT<SearchData>>>,
+        report_tx: InfoSender,    // Used to send information to engine.
+        board: Arc<Mutex<Board>>, // Arc pointer to engine's board.
+        mg: Arc<MoveGenerator>,   // Arc pointer to engine's move generator.
+        tt: Arc<RwLock<TT<SearchData>>>,
         tt_enabled: bool,
     ) {
         // Set up a channel for incoming commands
@@ -74,13 +81,21 @@ impl Search {
         // Create thread-local variables.
         let t_report_tx = report_tx;
 
-        // Create the search thread.
-        let h = thread::spawn(move || {
+        // Create the search thread. Each ply of the search puts a move
+        // list on the stack, so the thread gets more than the default.
+        let builder = thread::Builder::new().stack_size(STACK_SIZE);
+        let spawned = builder.spawn(move || {
             // Create thread-local variables.
             let arc_board = Arc::clone(&board);
             let arc_mg = Arc::clone(&mg);
             let arc_tt = Arc::clone(&tt);
             let mut search_params = SearchParams::new();
+            let lmr = LmrTable::new();
+            let mut history = HistoryTable::new();
+            let shared_history = Mutex::new(SharedHistory::new());
+            let mut pawn_tt: TT<PawnData> = TT::new(0);
+            let mut pawn_params = EvalParams::DEFAULT;
+            let mut pv_arena = PvArena::new();
 
             let mut quit = false;
             let mut halt = true;
@@ -93,12 +108,23 @@ impl Search {
                 // And react accordingly.
                 match cmd {
                     SearchControl::Start(sp) => {
-                        search_params = sp;
+                        // The pawn hash table is kept between searches; it
+                        // is only recreated if its size was changed.
+                        if sp.pawn_hash != search_params.pawn_hash {
+                            pawn_tt.resize(sp.pawn_hash);
+                        }
+                        search_params = *sp;
                         halt = false; // This will start the search.
                     }
                     SearchControl::Stop => halt = true,
                     SearchControl::Quit => quit = true,
-                    SearchControl::Nothing => (),
+                    // What was learned about move ordering belongs to
+                    // the previous game.
+                    SearchControl::NewGame => history = HistoryTable::new(),
+                    SearchControl::PonderHit
+                    | SearchControl::Pause
+                    | SearchControl::Resume
+                    | SearchControl::Nothing => (),
                 }
 
                 // Search isn't halted and not going to quit.
@@ -108,8 +134,36 @@ impl Search {
                     let mut board = mtx_board.clone();
                     std::mem::drop(mtx_board);
 
+                    // A board without a king for each side can't be
+                    // searched. Report the null move instead of crashing.
+                    if !board.has_valid_kings() {
+                        let report = SearchReport::Finished(Move::new(0), None);
+                        let information = Information::Search(report);
+                        t_report_tx.send(information).expect(ErrFatal::CHANNEL);
+                        halt = true;
+                        continue;
+                    }
+
+                    // Pawn scores depend on the evaluation parameters, so
+                    // the pawn hash is cleared if those were replaced.
+                    if *board.params() != pawn_params {
+                        pawn_tt.clear();
+                        pawn_params = board.params().clone();
+                    }
+
                     // Create a place to put search information
                     let mut search_info = SearchInfo::new();
+                    let helper_nodes = AtomicUsize::new(0);
+                    let paused = AtomicBool::new(false);
+
+                    // What was learned in the previous search counts for
+                    // less in this one.
+                    history.age();
+                    let mut mtx_tt = arc_tt.write().expect(ErrFatal::LOCK);
+                    mtx_tt.new_search();
+                    search_info.tt_generation = mtx_tt.generation();
+                    let tt_prefetch = mtx_tt.prefetcher();
+                    std::mem::drop(mtx_tt);
 
                     // Create references to all needed information and structures.
                     let mut search_refs = SearchRefs {
@@ -117,40 +171,144 @@ impl Search {
                         mg: &arc_mg,
                         tt: &arc_tt,
                         tt_enabled,
+                        tt_prefetch,
                         search_params: &mut search_params,
                         search_info: &mut search_info,
                         control_rx: &control_rx,
                         report_tx: &t_report_tx,
+                        on_report: None,
+                        helper_nodes: &helper_nodes,
+                        paused: &paused,
+                        lmr: &lmr,
+                        history: &mut history,
+                        shared_history: &shared_history,
+                        pawn_tt: &mut pawn_tt,
+                        pv_arena: &mut pv_arena,
                     };
 
-                    // Start the search using Iterative Deepening.
-                    let (best_move, terminate) = Search::iterative_deepening(&mut search_refs);
+                    // Start the search using Iterative Deepening, on as
+                    // many threads as requested.
+                    let (best_move, ponder_move, terminate) = Search::lazy_smp(&mut search_refs);
+                    Search::publish_tt_stats(&mut search_refs);
+
+                    // Report how often the main thread razored a node.
+                    let tries = search_info.razor_tries;
+                    if tries > 0 && !search_params.quiet {
+                        let rescues = search_info.razor_rescues;
+                        let msg = format!(
+                            "Razoring: {tries} nodes verified, {} pruned, {rescues} rescued",
+                            tries - rescues
+                        );
+                        let information = Information::Search(SearchReport::InfoString(msg));
+                        t_report_tx.send(information).expect(ErrFatal::CHANNEL);
+                    }
+
+                    // Report how the soft time limit moved during the
+                    // search, and why.
+                    if search_params.is_game_time() && !search_params.quiet {
+                        let msg = format!(
+                            "Time: best move changed {} times, stable for {} depths, soft limit {} ms, used {} ms",
+                            search_info.best_move_changes,
+                            search_info.best_move_stable,
+                            search_info.soft_time,
+                            search_info.time_used()
+                        );
+                        let information = Information::Search(SearchReport::InfoString(msg));
+                        t_report_tx.send(information).expect(ErrFatal::CHANNEL);
+                    }
 
                     // Inform the engine that the search has finished.
-                    let information = Information::Search(SearchReport::Finished(best_move));
+                    let report = SearchReport::Finished(best_move, ponder_move);
+                    let information = Information::Search(report);
                     t_report_tx.send(information).expect(ErrFatal::CHANNEL);
 
-                    // If the search was finished due to a Stop or Quit
-                    // command then either halt or quit the search.
-                    match terminate {
-                        SearchTerminate::Stop => {
-                            halt = true;
-                        }
-                        SearchTerminate::Quit => {
-                            halt = true;
-                            quit = true;
-                        }
-                        SearchTerminate::Nothing => (),
+                    // The search is done, however it finished. Only a new
+                    // Start command begins the next one; without halting,
+                    // any other command would rerun this search. If it was
+                    // finished due to a Quit command, also quit the thread.
+                    halt = true;
+                    if terminate == SearchTerminate::Quit {
+                        quit = true;
                     }
                 }
             }
         });
 
         // Store the thread's handle and command sender.
-        self.handle = Some(h);
+        self.handle = Some(spawned.expect(ErrFatal::THREAD));
         self.control_tx = Some(control_tx);
     }
 
+    // Searches the board on the calling thread, without starting the
+    // search thread, and returns the best move and the ponder move. This
+    // is meant for platforms without threads, such as WebAssembly in a
+    // browser. Nothing can stop this search from the outside, so the
+    // search parameters must limit it. Reports are handed to on_report
+    // while searching.
+    pub fn run_sync(
+        board: &Board,
+        mg: &Arc<MoveGenerator>,
+        tt: &Arc<RwLock<TT<SearchData>>>,
+        tt_enabled: bool,
+        mut search_params: SearchParams,
+        on_report: &dyn Fn(SearchReport),
+    ) -> (Move, Option<Move>) {
+        let mut board = board.clone();
+        if !board.has_valid_kings() {
+            return (Move::new(0), None);
+        }
+
+        // Nobody sends commands to this search, and reports don't go to
+        // the engine, but the search still needs both channels.
+        let (_control_tx, control_rx) = crossbeam_channel::unbounded::<SearchControl>();
+        let (report_tx, _report_rx) = info_channel();
+
+        // There is only the calling thread, and nobody to answer
+        // "ponderhit".
+        search_params.threads = 1;
+        search_params.ponder = false;
+
+        let lmr = LmrTable::new();
+        let mut history = HistoryTable::new();
+        let shared_history = Mutex::new(SharedHistory::new());
+        let mut pawn_tt: TT<PawnData> = TT::new(search_params.pawn_hash);
+        let mut pv_arena = PvArena::new();
+        let mut search_info = SearchInfo::new();
+        let helper_nodes = AtomicUsize::new(0);
+        let paused = AtomicBool::new(false);
+
+        let mut mtx_tt = tt.write().expect(ErrFatal::LOCK);
+        mtx_tt.new_search();
+        search_info.tt_generation = mtx_tt.generation();
+        let tt_prefetch = mtx_tt.prefetcher();
+        std::mem::drop(mtx_tt);
+
+        let mut search_refs = SearchRefs {
+            board: &mut board,
+            mg,
+            tt,
+            tt_enabled,
+            tt_prefetch,
+            search_params: &mut search_params,
+            search_info: &mut search_info,
+            control_rx: &control_rx,
+            report_tx: &report_tx,
+            on_report: Some(on_report),
+            helper_nodes: &helper_nodes,
+            paused: &paused,
+            lmr: &lmr,
+            history: &mut history,
+            shared_history: &shared_history,
+            pawn_tt: &mut pawn_tt,
+            pv_arena: &mut pv_arena,
+        };
+
+        let (best_move, ponder_move, _) = Search::iterative_deepening(&mut search_refs);
+        Search::publish_tt_stats(&mut search_refs);
+
+        (best_move, ponder_move)
+    }
+
     // This function is used to send commands into the search thread.
     pub fn send(&self, cmd: SearchControl) {
         if let Some(tx) = &self.control_tx {
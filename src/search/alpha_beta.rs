@@ -22,7 +22,10 @@ with this program.  If not, see <http://www.gnu.org/licenses/>.
 ======================================================================= */
 
 use super::{
-    defs::{SearchTerminate, CHECKMATE, CHECK_TERMINATION, DRAW, INF, SEND_STATS, STALEMATE},
+    defs::{
+        ScoreBound, SearchTerminate, CHECKMATE, CHECKMATE_THRESHOLD, CHECK_TERMINATION, DRAW, INF,
+        LMR_FULL_MOVES, LMR_MIN_DEPTH, SEND_STATS, STALEMATE,
+    },
     Search, SearchRefs,
 };
 use crate::{
@@ -45,6 +48,24 @@ impl Search {
         let is_root = refs.search_info.ply == 0; // At root if no moves were played.
         let mut do_pvs = false; // Used for PVS (Principal Variation Search)
 
+        // Stop going deeper if we hit MAX_PLY.
+        if refs.search_info.ply >= MAX_PLY {
+            return evaluation::evaluate_position_cached(refs.board, refs.pawn_tt);
+        }
+
+        // In a singular extension search, the TT move is excluded at
+        // this ply.
+        let excluded_move = refs.search_info.excluded_moves[refs.search_info.ply as usize];
+        let is_exclusion = excluded_move.get_move() != 0;
+
+        // When searching the second or later MultiPV line, or only some
+        // of the root moves, the root move list is incomplete, and so is
+        // the move list in a singular extension search. The result must
+        // then not go into the TT.
+        let is_restricted = !refs.search_info.root_excluded.is_empty()
+            || !refs.search_params.search_moves.is_empty();
+        let is_excluding = (is_root && is_restricted) || is_exclusion;
+
         // Check if termination condition is met.
         if refs.search_info.nodes & CHECK_TERMINATION == 0 {
             Search::check_termination(refs);
@@ -56,9 +77,9 @@ impl Search {
             return 0;
         }
 
-        // Stop going deeper if we hit MAX_PLY.
-        if refs.search_info.ply >= MAX_PLY {
-            return evaluation::evaluate_position(refs.board);
+        // Abandon the root move if it used up its node budget.
+        if Search::root_move_exhausted(refs) {
+            return 0;
         }
 
         // Determine if we are in check.
@@ -77,7 +98,7 @@ impl Search {
         // We have arrived at the leaf node. Evaluate the position and
         // return the result.
         if depth <= 0 {
-            return Search::quiescence(alpha, beta, pv, refs);
+            return Search::quiescence(alpha, beta, 0, pv, refs);
         }
 
         // Count this node, as it is not aborted or searched by QSearch.
@@ -86,28 +107,71 @@ impl Search {
         // Variables to hold TT value and move if any.
         let mut tt_value: Option<i16> = None;
         let mut tt_move: ShortMove = ShortMove::new(0);
+        let mut tt_bound: Option<(i8, i16)> = None;
+
+        // The static evaluation is only calculated when it is needed, if
+        // it wasn't already stored in the TT.
+        let mut static_eval: Option<i16> = None;
 
         // Probe the TT for information.
         if refs.tt_enabled {
             if let Some(data) = refs
                 .tt
-                .lock()
+                .read()
                 .expect(ErrFatal::LOCK)
                 .probe(refs.board.game_state.zobrist_key)
             {
                 let tt_result = data.get(depth, refs.search_info.ply, alpha, beta);
                 tt_value = tt_result.0;
                 tt_move = tt_result.1;
+                tt_bound = data.lower_bound(refs.search_info.ply);
+                static_eval = data.static_eval();
             }
         }
 
-        // If we have a value from the TT, then return immediately.
+        // If we have a value from the TT, then return immediately. (Not
+        // when excluding the TT move: the value is probably from that move.)
         if let Some(v) = tt_value {
-            if !is_root {
+            if !is_root && !is_exclusion {
                 return v;
             }
         }
 
+        // Razoring. If the position is far below alpha at low depth, a
+        // quiet move is unlikely to save it. Unless a quiescence search
+        // finds a tactic that does, the node is pruned.
+        if !is_root
+            && !is_check
+            && !is_exclusion
+            && Search::razoring_allowed(depth, alpha, beta, refs)
+        {
+            let eval = *static_eval.get_or_insert_with(|| {
+                evaluation::evaluate_position_cached(refs.board, refs.pawn_tt)
+            });
+            if let Some(score) = Search::razoring(depth, alpha, eval, refs) {
+                return score;
+            }
+        }
+
+        // Null move pruning. If we can pass, and a reduced search still
+        // gives a score of at least beta, then this position is so good
+        // that making an actual move will also fail high.
+        let try_null = !is_root && !is_check && !is_exclusion;
+        if try_null && Search::null_move_allowed(depth, alpha, beta, refs) {
+            let eval = *static_eval.get_or_insert_with(|| {
+                evaluation::evaluate_position_cached(refs.board, refs.pawn_tt)
+            });
+            if let Some(score) = Search::null_move(depth, beta, eval, refs) {
+                return score;
+            }
+        }
+
+        // Find out if the TT move is singular, so it can be extended.
+        let mut singular_move = ShortMove::new(0);
+        if !is_root && !is_exclusion && Search::is_singular(depth, tt_move, tt_bound, refs) {
+            singular_move = tt_move;
+        }
+
         /*=== Actual searching starts here ===*/
 
         // Generate the moves in this position
@@ -140,9 +204,39 @@ impl Search {
             // This function finds the best move to test according to the
             // move scoring, and puts it at the current index of the move
             // list, so get_move() will get this next.
-            Search::pick_move(&mut move_list, i);
+            if is_root {
+                Search::pick_root_move(&mut move_list, i);
+            } else {
+                Search::pick_move(&mut move_list, i);
+            }
 
             let current_move = move_list.get_move(i);
+
+            // In MultiPV mode, skip root moves which are already the
+            // first move of a line found earlier at this depth.
+            let excluded = &refs.search_info.root_excluded;
+            if is_root && excluded.contains(&current_move.to_short_move()) {
+                continue;
+            }
+
+            // Only search the root moves given by "go searchmoves".
+            if is_root && !refs.search_params.search_moves.allows(current_move) {
+                continue;
+            }
+
+            // Skip promotions the user doesn't want the engine to play.
+            if !refs.search_params.promotions.allows(current_move, is_root) {
+                continue;
+            }
+
+            // In a singular extension search, skip the TT move.
+            if is_exclusion && current_move.get_move() == excluded_move.get_move() {
+                continue;
+            }
+
+            // Determine the extension while the move is not yet made.
+            let extension = Search::extension(current_move, singular_move, refs);
+
             let is_legal = refs.board.make(current_move, refs.mg);
 
             // If not legal, skip the move and the rest of the function.
@@ -150,6 +244,12 @@ impl Search {
                 continue;
             }
 
+            // The child node probes the TT first thing. Start loading its
+            // bucket now, so it is in the cache by then.
+            if refs.tt_enabled {
+                refs.tt_prefetch.prefetch(refs.board.game_state.zobrist_key);
+            }
+
             // We found a legal move.
             legal_moves_found += 1;
             refs.search_info.ply += 1;
@@ -159,13 +259,18 @@ impl Search {
                 refs.search_info.seldepth = refs.search_info.ply;
             }
 
-            // Send currently searched move to GUI.
-            if !quiet && is_root {
-                Search::send_move_to_gui(refs, current_move, legal_moves_found);
+            // Send currently searched move to GUI, and set the node
+            // budget for this move.
+            if is_root {
+                refs.search_info.root_move_number = legal_moves_found;
+                if !quiet {
+                    Search::send_move_to_gui(refs, current_move, legal_moves_found);
+                }
+                refs.search_info.root_move_limit = Search::root_move_limit(legal_moves_found, refs);
             }
 
-            // Create a node PV for this move.
-            let mut node_pv: Vec<Move> = Vec::new();
+            // Take the PV for this move from the arena.
+            let mut node_pv = refs.pv_arena.take(refs.search_info.ply);
 
             // We just made a move. We are not yet at one of the leaf
             // nodes, so if the position is not a draw, we must search
@@ -174,25 +279,96 @@ impl Search {
 
             // If it isn't a draw, we must search.
             if !Search::is_draw(refs) {
-                // Try a PVS if applicable.
-                if do_pvs {
-                    eval_score =
-                        -Search::alpha_beta(depth - 1, -alpha - 1, -alpha, &mut node_pv, refs);
+                // Extended moves are searched deeper; they are not reduced.
+                // Otherwise, determine if this move can be searched at
+                // reduced depth.
+                let new_depth = depth + extension;
+                let r = if extension > 0 {
+                    0
+                } else {
+                    Search::late_move_reduction(
+                        depth,
+                        is_check,
+                        current_move,
+                        legal_moves_found,
+                        refs,
+                    )
+                };
+                refs.search_info.extensions += extension;
+
+                // Try a PVS if applicable. A reduced move is always
+                // searched with a zero window first.
+                if do_pvs || r > 0 {
+                    eval_score = -Search::alpha_beta(
+                        new_depth - 1 - r,
+                        -alpha - 1,
+                        -alpha,
+                        &mut node_pv,
+                        refs,
+                    );
+
+                    // The reduced move unexpectedly beats alpha. Search it
+                    // again at full depth to see if that is true.
+                    if r > 0 && eval_score > alpha {
+                        eval_score = -Search::alpha_beta(
+                            new_depth - 1,
+                            -alpha - 1,
+                            -alpha,
+                            &mut node_pv,
+                            refs,
+                        );
+                    }
 
                     // Check if we failed the PVS.
                     if (eval_score > alpha) && (eval_score < beta) {
                         eval_score =
-                            -Search::alpha_beta(depth - 1, -beta, -alpha, &mut node_pv, refs);
+                            -Search::alpha_beta(new_depth - 1, -beta, -alpha, &mut node_pv, refs);
                     }
                 } else {
-                    eval_score = -Search::alpha_beta(depth - 1, -beta, -alpha, &mut node_pv, refs);
+                    eval_score =
+                        -Search::alpha_beta(new_depth - 1, -beta, -alpha, &mut node_pv, refs);
                 }
+
+                refs.search_info.extensions -= extension;
             }
 
-            // Take back the move, and decrease ply accordingly.
+            // Take back the move, and decrease ply accordingly. The node
+            // PV goes back into the arena, where it can still be read.
             refs.board.unmake();
+            refs.pv_arena.put_back(refs.search_info.ply, node_pv);
             refs.search_info.ply -= 1;
 
+            // The root move was abandoned. Its score means nothing, so
+            // unwind to the root and continue with the next root move.
+            if refs.search_info.root_move_capped {
+                if !is_root {
+                    return 0;
+                }
+
+                refs.search_info.root_move_capped = false;
+                refs.search_info.root_moves_capped += 1;
+                continue;
+            }
+
+            // The search was stopped while this move was searched. Its
+            // score is meaningless, and must not end up in the TT, the
+            // killers, the history or the PV. Unwind to the root, which
+            // keeps the move it has found so far.
+            if refs.search_info.interrupted() && !is_root {
+                return 0;
+            }
+
+            // A root move that failed low is refuted. Tell the GUI which
+            // line refutes it, if it asked for that.
+            if is_root
+                && !quiet
+                && refs.search_params.show_refutations
+                && eval_score <= alpha
+                && !refs.search_info.interrupted()
+            {
+                Search::send_refutation_to_gui(refs, current_move, depth);
+            }
+
             // eval_score is better than the best we found so far, so we
             // save a new best_move that'll go into the hash table.
             if eval_score > best_eval_score {
@@ -203,23 +379,31 @@ impl Search {
             // Beta cutoff: this move is so good for our opponent, that we
             // do not search any further. Insert into TT and return beta.
             if eval_score >= beta {
-                refs.tt.lock().expect(ErrFatal::LOCK).insert(
-                    refs.board.game_state.zobrist_key,
-                    SearchData::create(
-                        depth,
-                        refs.search_info.ply,
-                        HashFlag::Beta,
-                        beta,
-                        best_move,
-                    ),
-                );
+                if !is_excluding {
+                    Search::tt_store(
+                        SearchData::create(
+                            depth,
+                            refs.search_info.ply,
+                            HashFlag::Beta,
+                            beta,
+                            static_eval,
+                            best_move,
+                        ),
+                        refs,
+                    );
+                }
 
                 // If the move is not a capture but still causes a
                 // beta-cutoff, then store it as a killer move and update
                 // the history heuristics.
                 if current_move.captured() == Pieces::NONE {
                     Search::store_killer_move(current_move, refs);
-                    // Search::update_history_heuristic(current_move, depth, refs);
+                    Search::update_history_heuristic(current_move, depth, refs);
+                }
+
+                // At the root, beta is only a lower bound of the score.
+                if is_root {
+                    refs.search_info.root_bound = ScoreBound::Lower;
                 }
 
                 return beta;
@@ -237,17 +421,28 @@ impl Search {
                 do_pvs = true;
                 pv.clear();
                 pv.push(current_move);
-                pv.append(&mut node_pv);
+                pv.extend_from_slice(refs.pv_arena.get(refs.search_info.ply + 1));
             }
         }
 
+        // The node budget only applies while searching a root move.
+        if is_root {
+            refs.search_info.root_move_limit = None;
+        }
+
         // If we exit the loop without legal moves being found, the
         // side to move is either in checkmate or stalemate.
         if legal_moves_found == 0 {
+            // In a singular extension search, the excluded TT move was the
+            // only legal move. It is singular for sure.
+            if is_exclusion {
+                return alpha;
+            }
+
             if is_check {
                 // The return value is minus CHECKMATE, because if we have
                 // no legal moves and are in check, it's game over.
-                return -CHECKMATE + (refs.search_info.ply as i16);
+                return -CHECKMATE + refs.search_info.ply;
             } else {
                 return STALEMATE;
             }
@@ -255,13 +450,270 @@ impl Search {
 
         // We save the best move we found for us; with an ALPHA flag if we
         // didn't improve alpha, or EXACT if we did raise alpha.
-        refs.tt.lock().expect(ErrFatal::LOCK).insert(
-            refs.board.game_state.zobrist_key,
-            SearchData::create(depth, refs.search_info.ply, hash_flag, alpha, best_move),
-        );
+        if !is_excluding {
+            Search::tt_store(
+                SearchData::create(
+                    depth,
+                    refs.search_info.ply,
+                    hash_flag,
+                    alpha,
+                    static_eval,
+                    best_move,
+                ),
+                refs,
+            );
+        }
+
+        // If no move raised alpha at the root, alpha is only an upper
+        // bound of the score.
+        if is_root {
+            refs.search_info.root_bound = match hash_flag {
+                HashFlag::Exact => ScoreBound::Exact,
+                _ => ScoreBound::Upper,
+            };
+        }
 
         // We have traversed the entire move list and found the best
         // possible move/eval_score for us.
         alpha
     }
 }
+
+// Late Move Reductions.
+impl Search {
+    // Late, quiet moves are reduced, as long as we are not in check and the
+    // move doesn't give check: those moves are too forcing to be reduced.
+    // This function is called after the move has been made.
+    fn late_move_reduction(
+        depth: i8,
+        is_check: bool,
+        current_move: Move,
+        move_nr: u8,
+        refs: &SearchRefs,
+    ) -> i8 {
+        let is_quiet =
+            current_move.captured() == Pieces::NONE && current_move.promoted() == Pieces::NONE;
+
+        if depth < LMR_MIN_DEPTH || move_nr <= LMR_FULL_MOVES || is_check || !is_quiet {
+            return 0;
+        }
+
+        let gives_check = refs.mg.square_attacked(
+            refs.board,
+            refs.board.opponent(),
+            refs.board.king_square(refs.board.us()),
+        );
+
+        if gives_check {
+            return 0;
+        }
+
+        // Never reduce the move straight into quiescence search.
+        refs.lmr.reduction(depth, move_nr).min(depth - 2)
+    }
+}
+
+// Null move pruning.
+impl Search {
+    // The null move is not tried in PV-nodes, in positions where the side
+    // to move only has pawns left (zugzwang is likely), or directly after
+    // another null move.
+    fn null_move_allowed(depth: i8, alpha: i16, beta: i16, refs: &SearchRefs) -> bool {
+        let is_pv = beta > alpha + 1;
+        let us = refs.board.us();
+        let pieces = refs.board.bb_side[us]
+            ^ refs.board.get_pieces(Pieces::PAWN, us)
+            ^ refs.board.get_pieces(Pieces::KING, us);
+
+        !is_pv
+            && depth >= refs.search_params.null_move.min_depth
+            && refs.search_info.ply >= refs.search_info.nmp_min_ply
+            && pieces > 0
+            && !refs.board.last_move_was_null()
+    }
+
+    fn razoring_allowed(depth: i8, alpha: i16, beta: i16, refs: &SearchRefs) -> bool {
+        let is_pv = beta > alpha + 1;
+
+        !is_pv
+            && depth <= refs.search_params.razoring.max_depth
+            && alpha.abs() < CHECKMATE_THRESHOLD
+    }
+
+    // Verifies a razoring candidate with a quiescence search. Returns a
+    // score if the node can be pruned.
+    fn razoring(depth: i8, alpha: i16, static_eval: i16, refs: &mut SearchRefs) -> Option<i16> {
+        let margin = refs.search_params.razoring.margin(depth);
+        if static_eval as i32 + margin as i32 > alpha as i32 {
+            return None;
+        }
+
+        // The quiescence search is done with a zero window at alpha. It
+        // only has to find out if a capture gets the score above it.
+        refs.search_info.razor_tries += 1;
+        let mut razor_pv: Vec<Move> = Vec::new();
+        let score = Search::quiescence(alpha, alpha + 1, 0, &mut razor_pv, refs);
+
+        if refs.search_info.interrupted() {
+            return None;
+        }
+
+        if score <= alpha {
+            Some(score)
+        } else {
+            refs.search_info.razor_rescues += 1;
+            None
+        }
+    }
+
+    // Tries the null move. Returns a score if the node can be pruned.
+    fn null_move(depth: i8, beta: i16, static_eval: i16, refs: &mut SearchRefs) -> Option<i16> {
+        let params = refs.search_params.null_move;
+
+        // If we are not already above beta, passing won't get us there.
+        if static_eval < beta {
+            return None;
+        }
+
+        // The reduction increases with depth, and with the margin by which
+        // the evaluation is above beta.
+        let margin = static_eval as i32 - beta as i32;
+        let eval_bonus = (margin / params.eval_divisor as i32).min(params.max_eval_bonus as i32);
+        let r = params.reduction + depth / params.depth_divisor + eval_bonus as i8;
+        let reduced_depth = (depth - 1 - r).max(0);
+
+        // A zero-window search never finds a PV, so this vector stays
+        // empty, and doesn't allocate memory.
+        let mut null_pv: Vec<Move> = Vec::new();
+        refs.board.make_null();
+        refs.search_info.ply += 1;
+        let score = -Search::alpha_beta(reduced_depth, -beta, -beta + 1, &mut null_pv, refs);
+        refs.board.unmake_null();
+        refs.search_info.ply -= 1;
+
+        if refs.search_info.interrupted() || score < beta {
+            return None;
+        }
+
+        // Don't return a mate score found by passing; that mate is not real.
+        let score = if score >= CHECKMATE_THRESHOLD {
+            beta
+        } else {
+            score
+        };
+
+        // At low depth, trust the result.
+        if depth < params.verify_depth {
+            return Some(score);
+        }
+
+        // At high depth, zugzwang can make the null move result wrong, so
+        // it is verified by a reduced search without null moves, for the
+        // first part of the remaining plies. This can happen inside the
+        // verification of a null move higher up in the tree, so that
+        // restriction is restored afterwards instead of lifted.
+        let ply = refs.search_info.ply;
+        let outer_min_ply = refs.search_info.nmp_min_ply;
+        refs.search_info.nmp_min_ply = ply + 3 * reduced_depth as i16 / 4;
+        let verified = Search::alpha_beta(reduced_depth, beta - 1, beta, &mut null_pv, refs);
+        refs.search_info.nmp_min_ply = outer_min_ply;
+
+        if !refs.search_info.interrupted() && verified >= beta {
+            Some(score)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        board::Board,
+        engine::defs::{info_channel, ErrFatal, Information, SearchData, TT},
+        movegen::MoveGenerator,
+        search::{
+            defs::{SearchControl, SearchMode, SearchParams, SearchReport},
+            Search,
+        },
+    };
+    use std::sync::{Arc, Mutex, RwLock};
+
+    const DEPTH: i8 = 8;
+    const TT_SIZE: usize = 16;
+
+    // WAC positions in which the best move is quiet, with that move if
+    // the search is expected to find it at this depth.
+    const POSITIONS: [(&str, Option<&str>); 4] = [
+        (
+            "2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - 0 1",
+            None,
+        ),
+        (
+            "5rk1/1ppb3p/p1pb4/6q1/3P1p1r/2P1R2P/PP1BQ1P1/5RKN w - - 0 1",
+            Some("e3g3"),
+        ),
+        (
+            "3r1k2/4npp1/1ppr3p/p6P/P2PPPP1/1NR5/5K2/2R5 w - - 0 1",
+            None,
+        ),
+        (
+            "2r1nrk1/p2q1ppp/bp1p4/n1pPp3/P1P1P3/2PBB1N1/4QPPP/R4RK1 w - - 0 1",
+            Some("f2f4"),
+        ),
+    ];
+
+    // With late move reductions, these searches take about 1.3 million
+    // nodes; without them, about 6.7 million. If the reductions stop
+    // working, or become much smaller, the count leaves this range.
+    const NODES: std::ops::RangeInclusive<usize> = 900_000..=1_800_000;
+
+    // Searches the position on one thread with a new TT, as the bench does,
+    // and returns the best move and the number of nodes.
+    fn search(fen: &str, mg: &Arc<MoveGenerator>) -> (String, usize) {
+        let mut position = Board::new();
+        position.fen_read(Some(fen)).unwrap();
+
+        let board = Arc::new(Mutex::new(position));
+        let tt = Arc::new(RwLock::new(TT::<SearchData>::new(TT_SIZE)));
+        let (info_tx, info_rx) = info_channel();
+        let mut search = Search::new();
+        search.init(info_tx, board, Arc::clone(mg), tt, true);
+
+        let mut sp = SearchParams::new();
+        sp.search_mode = SearchMode::Depth;
+        sp.depth = DEPTH;
+        sp.quiet = true;
+        search.send(SearchControl::Start(Box::new(sp)));
+
+        let mut nodes = 0;
+        let best_move = loop {
+            match info_rx.recv().expect(ErrFatal::CHANNEL) {
+                Information::Search(SearchReport::SearchSummary(s)) => nodes = s.nodes,
+                Information::Search(SearchReport::Finished(m, _)) => break m.as_string(),
+                _ => (),
+            }
+        };
+
+        search.send(SearchControl::Quit);
+        search.wait_for_shutdown();
+
+        (best_move, nodes)
+    }
+
+    #[test]
+    fn late_move_reductions_keep_quiet_key_moves() {
+        let mg = Arc::new(MoveGenerator::new());
+        let mut total = 0;
+
+        for (fen, expected) in POSITIONS {
+            let (best_move, nodes) = search(fen, &mg);
+            if let Some(m) = expected {
+                assert_eq!(best_move, m, "{fen}");
+            }
+            total += nodes;
+        }
+
+        assert!(NODES.contains(&total), "{total} nodes");
+    }
+}
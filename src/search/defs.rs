@@ -1,16 +1,17 @@
 use crate::{
-    board::Board,
-    defs::MAX_PLY,
-    engine::defs::{Information, SearchData, TT},
+    board::{defs::Pieces, Board},
+    defs::{NrOf, Side, Sides, MAX_DEPTH, MAX_LEGAL_MOVES, MAX_PLY},
+    engine::defs::{InfoSender, PawnData, SearchData, TTPrefetcher, TTStats, TT},
+    misc::system::Instant,
     movegen::{
         defs::{Move, ShortMove},
         MoveGenerator,
     },
 };
-use crossbeam_channel::{Receiver, Sender};
-use std::{
-    sync::{Arc, Mutex},
-    time::Instant,
+use crossbeam_channel::Receiver;
+use std::sync::{
+    atomic::{AtomicBool, AtomicUsize},
+    Arc, Mutex, RwLock,
 };
 
 pub use super::time::OVERHEAD;
@@ -18,25 +19,52 @@ pub use super::time::OVERHEAD;
 pub const INF: i16 = 25_000;
 // pub const ASPIRATION_WINDOW: i16 = 50;
 pub const CHECKMATE: i16 = 24_000;
-pub const CHECKMATE_THRESHOLD: i16 = 23_900;
+pub const CHECKMATE_THRESHOLD: i16 = CHECKMATE - MAX_PLY; // Mate found at any ply
 pub const STALEMATE: i16 = 0;
 pub const DRAW: i16 = 0;
 pub const CHECK_TERMINATION: usize = 0x7FF; // 2.047 nodes
 pub const SEND_STATS: usize = 0x7FFFF; // 524.287 nodes
 pub const MIN_TIME_STATS: u128 = 2_000; // Minimum time for sending stats
 pub const MIN_TIME_CURR_MOVE: u128 = 1_000; // Minimum time for sending curr_move
+pub const STACK_SIZE: usize = 64 * 1024 * 1024; // Search threads recurse up to MAX_PLY deep
 pub const MAX_KILLER_MOVES: usize = 2;
+pub const HISTORY_MAX: u32 = 1 << 20; // Scale history down when reaching this
+pub const CONT_HISTORY_PLIES: usize = 2; // Previous moves used by continuation history
+pub const SE_MIN_DEPTH: i8 = 8; // Minimum depth for singular extensions
+pub const SE_TT_DEPTH: i8 = 3; // TT entry may be this much shallower
+pub const SE_MARGIN: i16 = 2; // Singular margin in cp per ply of depth
+pub const LMR_MIN_DEPTH: i8 = 3; // Don't reduce moves below this depth
+pub const LMR_FULL_MOVES: u8 = 3; // Always search this many moves fully
+pub const HISTORY_MERGE_MIN_DEPTH: i8 = 4; // SMP threads merge history from this depth
+pub const HISTORY_MERGE_WEIGHT_MAX: u32 = 64; // Cap on the weight of merged history
+pub const SHARPNESS_LINES: usize = 5; // Root lines searched to rate sharpness
+pub const SHARPNESS_MARGIN: i16 = 30; // A good move scores within this of the best
 
-pub type SearchResult = (Move, SearchTerminate);
+pub type SearchResult = (Move, Option<Move>, SearchTerminate);
+pub type RootMove = (Move, Option<Move>, i16); // Move, ponder move, score
 type KillerMoves = [[ShortMove; MAX_KILLER_MOVES]; MAX_PLY as usize];
-// type HistoryHeuristic = [[[u32; NrOf::SQUARES]; NrOf::PIECE_TYPES]; Sides::BOTH];
+type ExcludedMoves = [ShortMove; MAX_PLY as usize];
+type Reductions = [[i8; MAX_LEGAL_MOVES as usize]; MAX_DEPTH as usize + 1];
+type HistoryHeuristic = [[[u32; NrOf::SQUARES]; NrOf::SQUARES]; Sides::BOTH];
+type CounterMoves = [[[ShortMove; NrOf::SQUARES]; NrOf::PIECE_TYPES]; Sides::BOTH];
+
+// Continuation history has an entry for each combination of a previous
+// move (piece, to-square) and the current move (piece, to-square), per
+// side, for the move one ply and two plies back.
+const CONT_HISTORY_PAIRS: usize = NrOf::PIECE_TYPES * NrOf::SQUARES;
+const CONT_HISTORY_SIZE: usize =
+    CONT_HISTORY_PLIES * Sides::BOTH * CONT_HISTORY_PAIRS * CONT_HISTORY_PAIRS;
 
 #[derive(PartialEq)]
 // These commands can be used by the engine thread to control the search.
 pub enum SearchControl {
-    Start(SearchParams),
+    Start(Box<SearchParams>),
+    PonderHit,
+    Pause,
+    Resume,
     Stop,
     Quit,
+    NewGame,
     Nothing,
 }
 
@@ -48,6 +76,16 @@ pub enum SearchTerminate {
     Nothing, // No command received yet.
 }
 
+// The score found at the root is exact, or only a bound: if the search
+// failed high, the real score is at least this high (lower bound); if it
+// failed low, the real score is at most this high (upper bound).
+#[derive(PartialEq, Copy, Clone)]
+pub enum ScoreBound {
+    Exact,
+    Lower,
+    Upper,
+}
+
 // SearchMode lists how the search termination criteria will be evaluated,
 // to see if the search has to be stopped.
 #[derive(PartialEq, Copy, Clone)]
@@ -57,6 +95,7 @@ pub enum SearchMode {
     Nodes,    // Run until the number of requested nodes was reached.
     GameTime, // Search determines when to quit, depending on available time.
     Infinite, // Run forever, until the 'stop' command is received.
+    Mate(u8), // Run until a mate in at most this many moves is found.
     Nothing,  // No search mode has been defined.
 }
 
@@ -90,25 +129,57 @@ impl GameTime {
 // This struct holds all the search parameters as set by the engine thread.
 // (These parameters are either default, or provided by the user interface
 // before the game starts.)
-#[derive(PartialEq, Copy, Clone)]
+#[derive(PartialEq, Clone)]
 pub struct SearchParams {
-    pub depth: i8,               // Maximum depth to search to
-    pub move_time: u128,         // Maximum time per move to search
-    pub nodes: usize,            // Maximum number of nodes to search
-    pub game_time: GameTime,     // Time available for entire game
-    pub search_mode: SearchMode, // Defines the mode to search in
-    pub quiet: bool,             // No intermediate search stats updates
+    pub depth: i8,                       // Maximum depth to search to
+    pub move_time: u128,                 // Maximum time per move to search
+    pub nodes: usize,                    // Maximum number of nodes to search
+    pub game_time: GameTime,             // Time available for entire game
+    pub search_mode: SearchMode,         // Defines the mode to search in
+    pub quiet: bool,                     // No intermediate search stats updates
+    pub multi_pv: usize,                 // Number of root lines to report
+    pub ponder: bool,                    // Searching on the opponent's time
+    pub threads: usize,                  // Number of search threads (Lazy SMP)
+    pub null_move: NullMoveParams,       // Null move pruning parameters
+    pub razoring: RazoringParams,        // Razoring parameters
+    pub pawn_hash: usize,                // Pawn hash table size in MB
+    pub promotions: PromotionFilter,     // Promotions the engine may play
+    pub root_move_nodes: usize,          // Node budget per root move in analysis
+    pub search_moves: SearchMoves,       // Root moves to search ("searchmoves")
+    pub strength: Option<StrengthLimit>, // Play weaker than full strength
+    pub show_refutations: bool,          // Report refutations of root moves
+    pub show_curr_line: bool,            // Report the line being searched
+    pub qsearch_checks: bool,            // Search quiet checks in QSearch
+    pub show_sharpness: bool,            // Report how sharp the position is
+    pub opening_time: OpeningTimeParams, // Time shaping in the opening
+    pub moves_played: Option<usize>,     // Moves since the start position
 }
 
 impl SearchParams {
     pub fn new() -> Self {
         Self {
-            depth: MAX_PLY,
+            depth: MAX_DEPTH,
             move_time: 0,
             nodes: 0,
             game_time: GameTime::new(0, 0, 0, 0, None),
             search_mode: SearchMode::Nothing,
             quiet: false,
+            multi_pv: 1,
+            ponder: false,
+            threads: 1,
+            null_move: NullMoveParams::new(),
+            razoring: RazoringParams::new(),
+            pawn_hash: 0,
+            promotions: PromotionFilter::new(),
+            root_move_nodes: 0,
+            search_moves: SearchMoves::new(),
+            strength: None,
+            show_refutations: false,
+            show_curr_line: false,
+            qsearch_checks: false,
+            show_sharpness: false,
+            opening_time: OpeningTimeParams::new(),
+            moves_played: None,
         }
     }
 
@@ -117,35 +188,678 @@ impl SearchParams {
     }
 }
 
+// The root moves the search is restricted to, as given by "go searchmoves".
+// If the list is empty, all root moves are searched.
+#[derive(PartialEq, Clone)]
+pub struct SearchMoves {
+    moves: Vec<ShortMove>,
+}
+
+impl SearchMoves {
+    pub fn new() -> Self {
+        Self { moves: Vec::new() }
+    }
+
+    pub fn push(&mut self, m: ShortMove) {
+        if !self.moves.contains(&m) {
+            self.moves.push(m);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.moves.is_empty()
+    }
+
+    pub fn allows(&self, m: Move) -> bool {
+        self.is_empty() || self.moves.contains(&m.to_short_move())
+    }
+}
+
+// Restricts the pieces the engine promotes to, which keeps positions
+// simple in training games. The restriction is applied to the root moves,
+// and optionally in the entire search. A queen promotion is always
+// allowed, so a position with a legal promotion never loses all of them.
+#[derive(PartialEq, Copy, Clone)]
+pub struct PromotionFilter {
+    allowed: [bool; NrOf::PIECE_TYPES],
+    pub in_search: bool,
+}
+
+impl PromotionFilter {
+    pub fn new() -> Self {
+        Self {
+            allowed: [true; NrOf::PIECE_TYPES],
+            in_search: false,
+        }
+    }
+
+    // Set the allowed pieces from a string of piece letters, such as "QN".
+    // The value "Off" lifts the restriction.
+    pub fn set(&mut self, value: &str) {
+        let value = value.to_lowercase();
+
+        if value == "off" {
+            self.allowed = [true; NrOf::PIECE_TYPES];
+            return;
+        }
+
+        self.allowed = [false; NrOf::PIECE_TYPES];
+        self.allowed[Pieces::QUEEN] = true;
+        for c in value.chars() {
+            match c {
+                'r' => self.allowed[Pieces::ROOK] = true,
+                'b' => self.allowed[Pieces::BISHOP] = true,
+                'n' => self.allowed[Pieces::KNIGHT] = true,
+                _ => (),
+            }
+        }
+    }
+
+    // The current restriction, in the same form as set() accepts it.
+    pub fn value(&self) -> String {
+        if self.allowed.iter().all(|&a| a) {
+            return String::from("Off");
+        }
+
+        let mut value = String::from("Q");
+        for (piece, letter) in [
+            (Pieces::ROOK, 'R'),
+            (Pieces::BISHOP, 'B'),
+            (Pieces::KNIGHT, 'N'),
+        ] {
+            if self.allowed[piece] {
+                value.push(letter);
+            }
+        }
+        value
+    }
+
+    // Returns true if the engine may consider this move.
+    pub fn allows(&self, m: Move, is_root: bool) -> bool {
+        let promoted = m.promoted();
+        let applies = is_root || self.in_search;
+
+        !applies || promoted == Pieces::NONE || self.allowed[promoted]
+    }
+}
+
+// Weakens the engine for casual games. The search is capped in depth and
+// nodes, and instead of always playing the best move, the engine picks a
+// random one among the root moves that score within a margin of the best.
+// The caps and margin for each Elo band are rough estimates.
+#[derive(PartialEq, Copy, Clone)]
+pub struct StrengthLimit {
+    elo: u16,
+}
+
+impl StrengthLimit {
+    pub const ELO_MIN: u16 = 800;
+    pub const ELO_MAX: u16 = 2400;
+    const CANDIDATES: usize = 4; // Root moves to choose from
+
+    // (Elo, depth, nodes, margin). Each band holds up to the next one.
+    const BANDS: [(u16, i8, usize, i16); 9] = [
+        (800, 1, 500, 300),
+        (1000, 2, 2_000, 200),
+        (1200, 3, 5_000, 120),
+        (1400, 4, 15_000, 80),
+        (1600, 5, 50_000, 50),
+        (1800, 6, 150_000, 30),
+        (2000, 8, 500_000, 15),
+        (2200, 10, 1_500_000, 5),
+        (2400, 12, 5_000_000, 0),
+    ];
+
+    pub fn new(elo: u16) -> Self {
+        Self {
+            elo: elo.clamp(Self::ELO_MIN, Self::ELO_MAX),
+        }
+    }
+
+    // Maximum search depth.
+    pub fn depth(&self) -> i8 {
+        self.band().1
+    }
+
+    // Maximum number of nodes per move.
+    pub fn nodes(&self) -> usize {
+        self.band().2
+    }
+
+    // Candidate moves score within this many centipawns of the best.
+    pub fn margin(&self) -> i16 {
+        self.band().3
+    }
+
+    // Number of root moves to choose from.
+    pub fn candidates(&self) -> usize {
+        if self.margin() > 0 {
+            Self::CANDIDATES
+        } else {
+            1
+        }
+    }
+
+    fn band(&self) -> (u16, i8, usize, i16) {
+        Self::BANDS
+            .iter()
+            .rev()
+            .find(|band| band.0 <= self.elo)
+            .copied()
+            .unwrap_or(Self::BANDS[0])
+    }
+}
+
+// Parameters for null move pruning. They can be changed with UCI options
+// that are not announced to the GUI, so they can be tuned (with SPSA, for
+// example) without cluttering the GUI's option screen. Each parameter has
+// a default, minimum, and maximum value.
+#[derive(PartialEq, Copy, Clone)]
+pub struct NullMoveParams {
+    pub min_depth: i8,      // Minimum depth to try a null move
+    pub reduction: i8,      // Base depth reduction (R)
+    pub depth_divisor: i8,  // R increases by 1 every this many plies
+    pub eval_divisor: i16,  // R increases by 1 per this many cp above beta
+    pub max_eval_bonus: i8, // Maximum increase of R due to the evaluation
+    pub verify_depth: i8,   // Verify the null move result from this depth
+}
+
+// Bounds of the parameters: (default, minimum, maximum).
+impl NullMoveParams {
+    pub const MIN_DEPTH: (i16, i16, i16) = (3, 1, 10);
+    pub const REDUCTION: (i16, i16, i16) = (2, 1, 5);
+    pub const DEPTH_DIVISOR: (i16, i16, i16) = (6, 1, 20);
+    pub const EVAL_DIVISOR: (i16, i16, i16) = (200, 50, 1000);
+    pub const MAX_EVAL_BONUS: (i16, i16, i16) = (3, 0, 6);
+    pub const VERIFY_DEPTH: (i16, i16, i16) = (8, 1, MAX_DEPTH as i16);
+
+    pub fn new() -> Self {
+        Self {
+            min_depth: NullMoveParams::MIN_DEPTH.0 as i8,
+            reduction: NullMoveParams::REDUCTION.0 as i8,
+            depth_divisor: NullMoveParams::DEPTH_DIVISOR.0 as i8,
+            eval_divisor: NullMoveParams::EVAL_DIVISOR.0,
+            max_eval_bonus: NullMoveParams::MAX_EVAL_BONUS.0 as i8,
+            verify_depth: NullMoveParams::VERIFY_DEPTH.0 as i8,
+        }
+    }
+
+    // Sets the parameter with the given (lowercase) option name. The value
+    // is kept within the parameter's bounds. Unknown names are ignored.
+    pub fn set(&mut self, name: &str, value: i16) {
+        let clamp = |bounds: (i16, i16, i16)| value.clamp(bounds.1, bounds.2);
+
+        match name {
+            "nullmovemindepth" => self.min_depth = clamp(NullMoveParams::MIN_DEPTH) as i8,
+            "nullmovereduction" => self.reduction = clamp(NullMoveParams::REDUCTION) as i8,
+            "nullmovedepthdivisor" => {
+                self.depth_divisor = clamp(NullMoveParams::DEPTH_DIVISOR) as i8
+            }
+            "nullmoveevaldivisor" => self.eval_divisor = clamp(NullMoveParams::EVAL_DIVISOR),
+            "nullmovemaxevalbonus" => {
+                self.max_eval_bonus = clamp(NullMoveParams::MAX_EVAL_BONUS) as i8
+            }
+            "nullmoveverifydepth" => self.verify_depth = clamp(NullMoveParams::VERIFY_DEPTH) as i8,
+            _ => (),
+        }
+    }
+
+    // The option names and current values of all parameters.
+    pub fn values(&self) -> Vec<(&'static str, i16)> {
+        vec![
+            ("NullMoveMinDepth", self.min_depth as i16),
+            ("NullMoveReduction", self.reduction as i16),
+            ("NullMoveDepthDivisor", self.depth_divisor as i16),
+            ("NullMoveEvalDivisor", self.eval_divisor),
+            ("NullMoveMaxEvalBonus", self.max_eval_bonus as i16),
+            ("NullMoveVerifyDepth", self.verify_depth as i16),
+        ]
+    }
+}
+
+// Parameters for razoring. Like the null move parameters, they can be
+// changed with UCI options that are not announced to the GUI. A node is
+// razored if its static evaluation is below alpha by more than the
+// margin, which grows with the remaining depth.
+#[derive(PartialEq, Copy, Clone)]
+pub struct RazoringParams {
+    pub max_depth: i8,     // Maximum remaining depth to razor at
+    pub margin: i16,       // Base margin below alpha
+    pub depth_margin: i16, // Added to the margin per ply of depth
+}
+
+// Bounds of the parameters: (default, minimum, maximum).
+impl RazoringParams {
+    pub const MAX_DEPTH: (i16, i16, i16) = (3, 0, 6);
+    pub const MARGIN: (i16, i16, i16) = (150, 0, 1000);
+    pub const DEPTH_MARGIN: (i16, i16, i16) = (100, 0, 500);
+
+    pub fn new() -> Self {
+        Self {
+            max_depth: RazoringParams::MAX_DEPTH.0 as i8,
+            margin: RazoringParams::MARGIN.0,
+            depth_margin: RazoringParams::DEPTH_MARGIN.0,
+        }
+    }
+
+    // Sets the parameter with the given (lowercase) option name. The value
+    // is kept within the parameter's bounds. Unknown names are ignored.
+    pub fn set(&mut self, name: &str, value: i16) {
+        let clamp = |bounds: (i16, i16, i16)| value.clamp(bounds.1, bounds.2);
+
+        match name {
+            "razoringmaxdepth" => self.max_depth = clamp(RazoringParams::MAX_DEPTH) as i8,
+            "razoringmargin" => self.margin = clamp(RazoringParams::MARGIN),
+            "razoringdepthmargin" => self.depth_margin = clamp(RazoringParams::DEPTH_MARGIN),
+            _ => (),
+        }
+    }
+
+    // The option names and current values of all parameters.
+    pub fn values(&self) -> Vec<(&'static str, i16)> {
+        vec![
+            ("RazoringMaxDepth", self.max_depth as i16),
+            ("RazoringMargin", self.margin),
+            ("RazoringDepthMargin", self.depth_margin),
+        ]
+    }
+
+    pub fn margin(&self, depth: i8) -> i16 {
+        self.margin + self.depth_margin * depth as i16
+    }
+}
+
+// Parameters for spending less time in the opening. Like the null move
+// parameters, they can be changed with UCI options that are not announced
+// to the GUI. On the first move, the engine uses the start percentage of
+// its time slice. This rises linearly to the full time slice on the last
+// opening move.
+#[derive(PartialEq, Copy, Clone)]
+pub struct OpeningTimeParams {
+    pub moves: i16,         // Moves that count as the opening
+    pub start_percent: i16, // Percentage of the time slice on move 1
+}
+
+// Bounds of the parameters: (default, minimum, maximum).
+impl OpeningTimeParams {
+    pub const MOVES: (i16, i16, i16) = (10, 1, 40);
+    pub const START_PERCENT: (i16, i16, i16) = (50, 10, 100);
+
+    pub fn new() -> Self {
+        Self {
+            moves: OpeningTimeParams::MOVES.0,
+            start_percent: OpeningTimeParams::START_PERCENT.0,
+        }
+    }
+
+    // Sets the parameter with the given (lowercase) option name. The value
+    // is kept within the parameter's bounds. Unknown names are ignored.
+    pub fn set(&mut self, name: &str, value: i16) {
+        let clamp = |bounds: (i16, i16, i16)| value.clamp(bounds.1, bounds.2);
+
+        match name {
+            "timeopeningmoves" => self.moves = clamp(OpeningTimeParams::MOVES),
+            "timeopeningstartpercent" => {
+                self.start_percent = clamp(OpeningTimeParams::START_PERCENT)
+            }
+            _ => (),
+        }
+    }
+
+    // The option names and current values of all parameters.
+    pub fn values(&self) -> Vec<(&'static str, i16)> {
+        vec![
+            ("TimeOpeningMoves", self.moves),
+            ("TimeOpeningStartPercent", self.start_percent),
+        ]
+    }
+
+    // The fraction of the time slice to use on the given move, counting
+    // from 0 for the first move.
+    pub fn factor(&self, moves_played: usize) -> f64 {
+        let moves = self.moves as usize;
+        let start = self.start_percent as f64 / 100.0;
+
+        if moves_played + 1 < moves {
+            let progress = moves_played as f64 / (moves - 1) as f64;
+            start + (1.0 - start) * progress
+        } else {
+            1.0
+        }
+    }
+}
+
+// The history heuristic keeps track of how often a quiet move (by side,
+// from-square and to-square) caused a beta-cutoff, weighted by depth. Moves
+// that did so often are likely to do it again, so they are sorted first.
+// The table is kept from one search to the next, because most of it is
+// still valid after a move has been played; but it is aged at the start
+// of each search, so older results count for less.
+//
+// Two more tables look at the moves that led to the position. The counter
+// move is the quiet move that last refuted the opponent's previous move
+// (by piece and to-square). The continuation history is like the normal
+// history, but it scores the current move in combination with the moves
+// played one and two plies before, so it learns which moves follow up on
+// which others.
+#[derive(Clone)]
+pub struct HistoryTable {
+    table: HistoryHeuristic,
+    counter_moves: CounterMoves,
+    continuation: Vec<u32>,
+}
+
+impl HistoryTable {
+    pub fn new() -> Self {
+        Self {
+            table: [[[0; NrOf::SQUARES]; NrOf::SQUARES]; Sides::BOTH],
+            counter_moves: [[[ShortMove::new(0); NrOf::SQUARES]; NrOf::PIECE_TYPES]; Sides::BOTH],
+            continuation: vec![0; CONT_HISTORY_SIZE],
+        }
+    }
+
+    // The history score of a move, including the continuation history
+    // for the previous moves. A null move in "previous" (after a null
+    // move, or at the start of the game) adds nothing.
+    pub fn get(&self, side: Side, m: Move, previous: &[Move; CONT_HISTORY_PLIES]) -> u32 {
+        let mut value = self.table[side][m.from()][m.to()];
+
+        for (plies_back, p) in previous.iter().enumerate() {
+            if p.get_move() != 0 {
+                value += self.continuation[cont_index(plies_back, side, *p, m)];
+            }
+        }
+
+        value
+    }
+
+    // The quiet move that refuted the opponent's previous move the last
+    // time it was played.
+    pub fn counter_move(&self, side: Side, previous: Move) -> ShortMove {
+        if previous.get_move() == 0 {
+            return ShortMove::new(0);
+        }
+
+        self.counter_moves[side][previous.piece()][previous.to()]
+    }
+
+    // Rewards a move that caused a beta-cutoff. A cutoff at a higher depth
+    // saves more work, so it counts for more. If an entry becomes too
+    // large, the whole table is scaled down so the ordering is kept, but
+    // there is room for new results. The move also becomes the counter
+    // move of the opponent's previous move.
+    pub fn update(
+        &mut self,
+        side: Side,
+        m: Move,
+        previous: &[Move; CONT_HISTORY_PLIES],
+        depth: i8,
+    ) {
+        let d = depth.max(1) as u32;
+        let entry = &mut self.table[side][m.from()][m.to()];
+        *entry += d * d;
+        let mut largest = *entry;
+
+        for (plies_back, p) in previous.iter().enumerate() {
+            if p.get_move() != 0 {
+                let entry = &mut self.continuation[cont_index(plies_back, side, *p, m)];
+                *entry += d * d;
+                largest = largest.max(*entry);
+            }
+        }
+
+        let p = previous[0];
+        if p.get_move() != 0 {
+            self.counter_moves[side][p.piece()][p.to()] = m.to_short_move();
+        }
+
+        if largest >= HISTORY_MAX {
+            self.age();
+        }
+    }
+
+    // Halves all entries. Counter moves are kept; they are replaced
+    // whenever a newer refutation is found.
+    pub fn age(&mut self) {
+        for value in self.table.iter_mut().flatten().flatten() {
+            *value /= 2;
+        }
+
+        for value in self.continuation.iter_mut() {
+            *value /= 2;
+        }
+    }
+
+    // Replaces each entry by the weighted average of itself and the
+    // same entry in the other table. The other table's counter moves
+    // replace these where it has one.
+    pub fn blend(&mut self, other: &HistoryTable, weight: u32, other_weight: u32) {
+        let total = (weight + other_weight).max(1) as u64;
+        let mix = |value: &mut u32, other_value: u32| {
+            let sum = *value as u64 * weight as u64 + other_value as u64 * other_weight as u64;
+            *value = (sum / total) as u32;
+        };
+
+        let own = self.table.iter_mut().flatten().flatten();
+        let theirs = other.table.iter().flatten().flatten();
+        for (value, other_value) in own.zip(theirs) {
+            mix(value, *other_value);
+        }
+
+        for (value, other_value) in self.continuation.iter_mut().zip(other.continuation.iter()) {
+            mix(value, *other_value);
+        }
+
+        let own = self.counter_moves.iter_mut().flatten().flatten();
+        let theirs = other.counter_moves.iter().flatten().flatten();
+        for (counter, other_counter) in own.zip(theirs) {
+            if other_counter.get_move() != 0 {
+                *counter = *other_counter;
+            }
+        }
+    }
+}
+
+// Index into the continuation history for the current move "m" by "side",
+// after the move "previous" that was played "plies_back" + 1 plies ago.
+fn cont_index(plies_back: usize, side: Side, previous: Move, m: Move) -> usize {
+    let previous_pair = previous.piece() * NrOf::SQUARES + previous.to();
+    let pair = m.piece() * NrOf::SQUARES + m.to();
+
+    ((plies_back * Sides::BOTH + side) * CONT_HISTORY_PAIRS + previous_pair) * CONT_HISTORY_PAIRS
+        + pair
+}
+
+// The PV of each node is collected in a vector, which is passed up to the
+// parent node when it is better than what was found before. Creating a
+// new vector in every node means allocating memory whenever a PV is
+// found. Instead, each search thread has one vector per ply, created at
+// the start with room for a full-length PV. A node takes the vector of
+// the next ply for its child nodes, and puts it back when done, so the
+// search doesn't allocate any memory while it runs. (The move lists are
+// not in here: they are arrays on the stack, which don't allocate.)
+pub struct PvArena {
+    lines: Vec<Vec<Move>>,
+}
+
+impl PvArena {
+    pub fn new() -> Self {
+        let plies = MAX_PLY as usize + 1;
+        Self {
+            lines: (0..plies).map(|_| Vec::with_capacity(plies)).collect(),
+        }
+    }
+
+    // Takes the (emptied) vector for the given ply out of the arena.
+    pub fn take(&mut self, ply: i16) -> Vec<Move> {
+        let mut pv = std::mem::take(&mut self.lines[ply as usize]);
+        pv.clear();
+        pv
+    }
+
+    // Puts the vector back, so the next node at this ply can use it.
+    pub fn put_back(&mut self, ply: i16, pv: Vec<Move>) {
+        self.lines[ply as usize] = pv;
+    }
+
+    // The PV that was last put back for the given ply.
+    pub fn get(&self, ply: i16) -> &[Move] {
+        &self.lines[ply as usize]
+    }
+}
+
+// When searching with more than one thread, each thread orders its moves
+// using its own history table and killer moves, so threads don't have to
+// wait for one another. After completing an iteration, a thread merges
+// what it learned into this shared copy and continues with the result.
+// History is averaged, weighted by the depth each thread completed; the
+// killer moves are taken from the deepest iteration.
+pub struct SharedHistory {
+    history: HistoryTable,
+    killer_moves: KillerMoves,
+    weight: u32,      // Sum of the depths merged into the history
+    killer_depth: i8, // Depth the killer moves were found at
+}
+
+impl SharedHistory {
+    pub fn new() -> Self {
+        Self {
+            history: HistoryTable::new(),
+            killer_moves: [[ShortMove::new(0); MAX_KILLER_MOVES]; MAX_PLY as usize],
+            weight: 0,
+            killer_depth: 0,
+        }
+    }
+
+    // Start a new search from the main thread's history.
+    pub fn reset(&mut self, history: &HistoryTable) {
+        *self = Self::new();
+        self.history = history.clone();
+    }
+
+    // Merge a thread's tables into the shared ones, and give the thread
+    // the merged result. The weight of what was merged before is capped,
+    // so results of later iterations keep having an effect.
+    pub fn merge(&mut self, history: &mut HistoryTable, killers: &mut KillerMoves, depth: i8) {
+        let d = depth.max(1) as u32;
+        self.history.blend(history, self.weight, d);
+        self.weight = (self.weight + d).min(HISTORY_MERGE_WEIGHT_MAX);
+        *history = self.history.clone();
+
+        if depth >= self.killer_depth {
+            self.killer_moves = *killers;
+            self.killer_depth = depth;
+        }
+        *killers = self.killer_moves;
+    }
+}
+
+// Late Move Reductions. The later a move comes in the move ordering, the
+// less likely it is to be the best move, so it is searched with a reduced
+// depth. The reduction grows with the logarithm of both the depth and the
+// move number. The table is calculated once, when the search thread starts.
+pub struct LmrTable {
+    reductions: Reductions,
+}
+
+impl LmrTable {
+    pub fn new() -> Self {
+        let mut reductions: Reductions = [[0; MAX_LEGAL_MOVES as usize]; MAX_DEPTH as usize + 1];
+
+        for (depth, row) in reductions.iter_mut().enumerate().skip(1) {
+            for (move_nr, r) in row.iter_mut().enumerate().skip(1) {
+                let reduction = (depth as f64).ln() * (move_nr as f64).ln() / 2.0;
+                *r = reduction.floor() as i8;
+            }
+        }
+
+        Self { reductions }
+    }
+
+    // Returns the reduction for the given depth and legal move number.
+    pub fn reduction(&self, depth: i8, move_nr: u8) -> i8 {
+        let d = depth.max(0) as usize;
+        self.reductions[d][move_nr as usize]
+    }
+}
+
 // The search function will put all findings collected during the running
 // search into this struct.
 #[derive(PartialEq)]
 pub struct SearchInfo {
-    start_time: Option<Instant>,    // Time the search started
-    pub depth: i8,                  // Depth currently being searched
-    pub seldepth: i8,               // Maximum selective depth reached
-    pub nodes: usize,               // Nodes searched
-    pub ply: i8,                    // Number of plys from the root
-    pub killer_moves: KillerMoves,  // Killer moves (array; see "type" above)
-    pub last_stats_sent: u128,      // When last stats update was sent
-    pub last_curr_move_sent: u128,  // When last current move was sent
-    pub allocated_time: u128,       // Allotted msecs to spend on move
-    pub terminate: SearchTerminate, // Terminate flag
+    start_time: Option<Instant>,        // Time the search started
+    pub thread_id: usize,               // 0 = main thread, others are helpers
+    pub depth: i8,                      // Depth currently being searched
+    pub completed_depth: i8,            // Last depth that was fully searched
+    pub seldepth: i16,                  // Maximum selective depth reached
+    pub nodes: usize,                   // Nodes searched
+    pub nodes_published: usize,         // Nodes a helper reported as searched
+    pub tb_hits: usize,                 // Tablebase probes that hit
+    pub ply: i16,                       // Number of plys from the root
+    pub killer_moves: KillerMoves,      // Killer moves (array; see "type" above)
+    pub last_stats_sent: u128,          // When last stats update was sent
+    pub last_curr_move_sent: u128,      // When last current move was sent
+    pub allocated_time: u128,           // Allotted msecs to spend on move
+    pub soft_time: u128,                // Msecs after which no new depth is started
+    pub hard_time: u128,                // Msecs after which the search is aborted
+    pub best_move_changes: usize,       // Completed depths that changed the best move
+    pub best_move_stable: usize,        // Completed depths since the last change
+    pub score_drop: i16,                // Score lost since the previous depth
+    pub ponder_time: u128,              // Msecs spent pondering before ponderhit
+    pub paused_time: u128,              // Msecs the search was paused
+    pub terminate: SearchTerminate,     // Terminate flag
+    pub tt_generation: u8,              // TT generation this search stores into
+    pub tt_stats: TTStats,              // What this thread's TT stores did
+    pub root_excluded: Vec<ShortMove>,  // Root moves already in a MultiPV line
+    pub nmp_min_ply: i16,               // No null moves before this ply
+    pub root_bound: ScoreBound,         // Bound type of the root's score
+    pub extensions: i8,                 // Extensions in the current line
+    pub excluded_moves: ExcludedMoves,  // Per ply: move excluded by singular search
+    pub root_move_limit: Option<usize>, // Node count at which the root move is abandoned
+    pub root_move_capped: bool,         // The root move ran out of nodes
+    pub root_moves_capped: usize,       // Root moves abandoned at this depth
+    pub root_move_number: u8,           // Number of the root move being searched
+    pub razor_tries: usize,             // Nodes verified for razoring
+    pub razor_rescues: usize,           // Razoring attempts the verification refused
 }
 
 impl SearchInfo {
     pub fn new() -> Self {
         Self {
             start_time: None,
+            thread_id: 0,
             depth: 0,
+            completed_depth: 0,
             seldepth: 0,
             nodes: 0,
+            nodes_published: 0,
+            tb_hits: 0,
             ply: 0,
             killer_moves: [[ShortMove::new(0); MAX_KILLER_MOVES]; MAX_PLY as usize],
             last_stats_sent: 0,
             last_curr_move_sent: 0,
             allocated_time: 0,
+            soft_time: 0,
+            hard_time: 0,
+            best_move_changes: 0,
+            best_move_stable: 0,
+            score_drop: 0,
+            ponder_time: 0,
+            paused_time: 0,
             terminate: SearchTerminate::Nothing,
+            tt_generation: 0,
+            tt_stats: TTStats::default(),
+            root_excluded: Vec::new(),
+            nmp_min_ply: 0,
+            root_bound: ScoreBound::Exact,
+            extensions: 0,
+            excluded_moves: [ShortMove::new(0); MAX_PLY as usize],
+            root_move_limit: None,
+            root_move_capped: false,
+            root_moves_capped: 0,
+            root_move_number: 0,
+            razor_tries: 0,
+            razor_rescues: 0,
         }
     }
 
@@ -153,14 +867,24 @@ impl SearchInfo {
         self.start_time = Some(Instant::now());
     }
 
+    // Time spent searching. The clock stops while the search is paused.
     pub fn timer_elapsed(&self) -> u128 {
         if let Some(x) = self.start_time {
-            x.elapsed().as_millis()
+            x.elapsed().as_millis().saturating_sub(self.paused_time)
         } else {
             0
         }
     }
 
+    // Time spent on our own clock. This excludes time spent pondering.
+    pub fn time_used(&self) -> u128 {
+        self.timer_elapsed().saturating_sub(self.ponder_time)
+    }
+
+    pub fn is_helper(&self) -> bool {
+        self.thread_id > 0
+    }
+
     pub fn interrupted(&self) -> bool {
         self.terminate != SearchTerminate::Nothing
     }
@@ -172,15 +896,20 @@ impl SearchInfo {
 // information into UCI/XBoard/Console output and print it to STDOUT.
 #[derive(PartialEq, Clone)]
 pub struct SearchSummary {
-    pub depth: i8,      // depth reached during search
-    pub seldepth: i8,   // Maximum selective depth reached
-    pub time: u128,     // milliseconds
-    pub cp: i16,        // centipawns score
-    pub mate: u8,       // mate in X moves
-    pub nodes: usize,   // nodes searched
-    pub nps: usize,     // nodes per second
-    pub hash_full: u16, // TT use in permille
-    pub pv: Vec<Move>,  // Principal Variation
+    pub depth: i8,         // depth reached during search
+    pub seldepth: i16,     // Maximum selective depth reached
+    pub time: u128,        // milliseconds
+    pub cp: i16,           // centipawns score
+    pub bound: ScoreBound, // The score is exact, or a bound
+    pub mate: u8,          // mate in X moves
+    pub nodes: usize,      // nodes searched
+    pub nps: usize,        // nodes per second
+    pub hash_full: u16,    // TT use in permille
+    pub tb_hits: usize,    // tablebase hits
+    pub cpu_load: u16,     // CPU use in permille
+    pub multi_pv: usize,   // MultiPV line number, starting at 1
+    pub pv: Vec<Move>,     // Principal Variation
+    pub side: Side,        // Side to move in the searched position
 }
 
 impl SearchSummary {
@@ -192,22 +921,43 @@ impl SearchSummary {
         }
         pv.trim().to_string()
     }
+
+    // If the score is a mate score, this returns the number of moves to
+    // mate. The number is positive if the side to move is mating, and
+    // negative if it is being mated.
+    pub fn mate_in(&self) -> Option<i16> {
+        let is_mate = (self.cp.abs() >= CHECKMATE_THRESHOLD) && (self.cp.abs() < CHECKMATE);
+
+        if is_mate {
+            // Number of plies to mate, rounded up to full moves.
+            let ply = CHECKMATE - self.cp.abs();
+            let moves = (ply + 1) / 2;
+
+            // If the engine is being mated itself, flip the score.
+            Some(if self.cp < 0 { -moves } else { moves })
+        } else {
+            None
+        }
+    }
 }
 
-#[derive(PartialEq, Copy, Clone)]
+#[derive(PartialEq, Clone)]
 // This struct holds the currently searched move, and its move number in
-// the list of legal moves. This struct is sent through the engine thread
-// to Comm, to be transmitted to the (G)UI.
+// the list of legal moves. If requested, it also holds the line that is
+// being searched, starting with the root move. This struct is sent
+// through the engine thread to Comm, to be transmitted to the (G)UI.
 pub struct SearchCurrentMove {
     pub curr_move: Move,
     pub curr_move_number: u8,
+    pub curr_line: Vec<Move>,
 }
 
 impl SearchCurrentMove {
-    pub fn new(curr_move: Move, curr_move_number: u8) -> Self {
+    pub fn new(curr_move: Move, curr_move_number: u8, curr_line: Vec<Move>) -> Self {
         Self {
             curr_move,
             curr_move_number,
+            curr_line,
         }
     }
 }
@@ -220,15 +970,26 @@ pub struct SearchStats {
     pub nodes: usize,   // Number of nodes searched
     pub nps: usize,     // Speed in nodes per second
     pub hash_full: u16, // TT full in permille
+    pub tb_hits: usize, // Number of tablebase hits
+    pub cpu_load: u16,  // CPU use in permille
 }
 
 impl SearchStats {
-    pub fn new(time: u128, nodes: usize, nps: usize, hash_full: u16) -> Self {
+    pub fn new(
+        time: u128,
+        nodes: usize,
+        nps: usize,
+        hash_full: u16,
+        tb_hits: usize,
+        cpu_load: u16,
+    ) -> Self {
         Self {
             time,
             nodes,
             nps,
             hash_full,
+            tb_hits,
+            cpu_load,
         }
     }
 }
@@ -237,25 +998,128 @@ impl SearchStats {
 // the current board to make moves on, the move generator, search paramters
 // (depth, time available, etc...), SearchInfo to put the results. It also
 // needs references to the control receiver and report sender so it can
-// receive commands from the engine and send reports back. These references
-// are grouped in SearchRefs, so they don't have to be passed one by one as
-// function arguments.
+// receive commands from the engine and send reports back. (A synchronous
+// search has no engine thread listening; it hands its reports to the
+// caller through on_report instead.) These references are grouped in
+// SearchRefs, so they don't have to be passed one by one as function
+// arguments.
 pub struct SearchRefs<'a> {
     pub board: &'a mut Board,
     pub mg: &'a Arc<MoveGenerator>,
-    pub tt: &'a Arc<Mutex<TT<SearchData>>>,
+    pub tt: &'a Arc<RwLock<TT<SearchData>>>,
     pub tt_enabled: bool,
+    pub tt_prefetch: TTPrefetcher,
     pub search_params: &'a mut SearchParams,
     pub search_info: &'a mut SearchInfo,
     pub control_rx: &'a Receiver<SearchControl>,
-    pub report_tx: &'a Sender<Information>,
+    pub report_tx: &'a InfoSender,
+    pub on_report: Option<&'a dyn Fn(SearchReport)>,
+    pub helper_nodes: &'a AtomicUsize,
+    pub paused: &'a AtomicBool,
+    pub lmr: &'a LmrTable,
+    pub history: &'a mut HistoryTable,
+    pub shared_history: &'a Mutex<SharedHistory>,
+    pub pawn_tt: &'a mut TT<PawnData>,
+    pub pv_arena: &'a mut PvArena,
 }
 
 // This struct holds all the reports a search can send to the engine.
 #[derive(PartialEq)]
 pub enum SearchReport {
-    Finished(Move),                       // Search done. Contains the best move.
-    SearchSummary(SearchSummary),         // Periodic intermediate results.
+    Finished(Move, Option<Move>), // Search done. Best move and ponder move.
+    SearchSummary(SearchSummary), // Periodic intermediate results.
+    SearchSummaries(Vec<SearchSummary>), // Results of all MultiPV lines.
     SearchCurrentMove(SearchCurrentMove), // Move currently searched.
-    SearchStats(SearchStats),             // General search statistics
+    SearchRefutation(Vec<Move>),  // Root move and the reply refuting it.
+    SearchStats(SearchStats),     // General search statistics
+    InfoString(String),           // Information for the user
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        alloc::{GlobalAlloc, Layout, System},
+        cell::Cell,
+    };
+
+    // Counts the allocations made by each thread, so tests running on
+    // other threads at the same time don't disturb the count.
+    struct CountingAllocator;
+
+    thread_local! {
+        static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+    }
+
+    fn count_allocation() {
+        let _ = ALLOCATIONS.try_with(|a| a.set(a.get() + 1));
+    }
+
+    fn allocations() -> usize {
+        ALLOCATIONS.with(|a| a.get())
+    }
+
+    unsafe impl GlobalAlloc for CountingAllocator {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            count_allocation();
+            System.alloc(layout)
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            System.dealloc(ptr, layout)
+        }
+
+        unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+            count_allocation();
+            System.realloc(ptr, layout, new_size)
+        }
+    }
+
+    #[global_allocator]
+    static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+    // Uses the arena the way alpha/beta does: each node takes the vector
+    // of the next ply for its child, puts it back, and copies the child's
+    // PV into its own after the move that led to it.
+    fn search(arena: &mut PvArena, ply: i16, depth: i16, moves: usize, pv: &mut Vec<Move>) {
+        for i in 0..moves {
+            let child = ply + 1;
+            let mut node_pv = arena.take(child);
+            if depth > 1 {
+                search(arena, child, depth - 1, moves, &mut node_pv);
+            }
+            arena.put_back(child, node_pv);
+
+            pv.clear();
+            pv.push(Move::new(i + 1));
+            pv.extend_from_slice(arena.get(child));
+        }
+    }
+
+    #[test]
+    fn allocations_are_counted() {
+        let before = allocations();
+        let v: Vec<u8> = Vec::with_capacity(1);
+        assert_eq!(allocations(), before + 1);
+        std::mem::drop(v);
+    }
+
+    #[test]
+    fn pv_arena_does_not_allocate_while_searching() {
+        let mut arena = PvArena::new();
+        let before = allocations();
+
+        // A wide tree, and a single line as long as a PV can be.
+        let mut pv = arena.take(0);
+        search(&mut arena, 0, 10, 3, &mut pv);
+        assert_eq!(pv.len(), 10);
+        arena.put_back(0, pv);
+
+        let mut pv = arena.take(0);
+        search(&mut arena, 0, MAX_PLY, 1, &mut pv);
+        assert_eq!(pv.len(), MAX_PLY as usize);
+        arena.put_back(0, pv);
+
+        assert_eq!(allocations(), before);
+    }
 }
@@ -0,0 +1,133 @@
+/* =======================================================================
+Rustic is a chess playing engine.
+Copyright (C) 2019-2024, Marcel Vanthoor
+https://rustic-chess.org/
+
+Rustic is written in the Rust programming language. It is an original
+work, not derived from any engine that came before it. However, it does
+use a lot of concepts which are well-known and are in use by most if not
+all classical alpha/beta-based chess engines.
+
+Rustic is free software: you can redistribute it and/or modify it under
+the terms of the GNU General Public License version 3 as published by
+the Free Software Foundation.
+
+Rustic is distributed in the hope that it will be useful, but WITHOUT
+ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License
+for more details.
+
+You should have received a copy of the GNU General Public License along
+with this program.  If not, see <http://www.gnu.org/licenses/>.
+======================================================================= */
+
+// extensions.rs decides which moves are searched one ply deeper than the
+// others. (Being in check is handled directly in alpha_beta, because a
+// position in check must never be handed to quiescence search.) All other
+// extensions are counted for the line being searched; once a line has as
+// many extensions as the iteration's depth, it isn't extended any further,
+// so the search can't explode.
+
+use super::{
+    defs::{SearchRefs, CHECKMATE_THRESHOLD, SE_MARGIN, SE_MIN_DEPTH, SE_TT_DEPTH},
+    Search,
+};
+use crate::{
+    board::{
+        defs::{Pieces, Ranks},
+        Board,
+    },
+    defs::Sides,
+    movegen::defs::{Move, ShortMove},
+};
+
+impl Search {
+    // Returns the number of plies by which the move should be extended.
+    // This function must be called before the move is made.
+    pub fn extension(current_move: Move, singular_move: ShortMove, refs: &SearchRefs) -> i8 {
+        if refs.search_info.extensions >= refs.search_info.depth {
+            return 0;
+        }
+
+        let is_singular =
+            singular_move.get_move() != 0 && current_move.get_move() == singular_move.get_move();
+
+        let extend = is_singular
+            || Search::is_recapture(current_move, refs.board)
+            || Search::is_passed_pawn_push(current_move, refs.board);
+
+        if extend {
+            1
+        } else {
+            0
+        }
+    }
+
+    // A move is singular if it is much better than all the other moves in
+    // the position. To find out, the position is searched at reduced depth
+    // without the TT move. If none of the other moves gets close to the TT
+    // value, the TT move is singular: it's the only move that holds the
+    // position, so it is worth searching it more deeply.
+    pub fn is_singular(
+        depth: i8,
+        tt_move: ShortMove,
+        tt_bound: Option<(i8, i16)>,
+        refs: &mut SearchRefs,
+    ) -> bool {
+        let (tt_depth, tt_value) = match tt_bound {
+            Some(bound) => bound,
+            None => return false,
+        };
+
+        if depth < SE_MIN_DEPTH
+            || tt_move.get_move() == 0
+            || tt_depth < depth - SE_TT_DEPTH
+            || tt_value.abs() >= CHECKMATE_THRESHOLD
+        {
+            return false;
+        }
+
+        let singular_beta = tt_value - SE_MARGIN * depth as i16;
+        let ply = refs.search_info.ply as usize;
+        let mut pv: Vec<Move> = Vec::new();
+
+        refs.search_info.excluded_moves[ply] = tt_move;
+        let score = Search::alpha_beta(
+            (depth - 1) / 2,
+            singular_beta - 1,
+            singular_beta,
+            &mut pv,
+            refs,
+        );
+        refs.search_info.excluded_moves[ply] = ShortMove::new(0);
+
+        !refs.search_info.interrupted() && score < singular_beta
+    }
+
+    // Recapturing on the square where the opponent just captured usually
+    // only restores the material balance; the exchange must be searched to
+    // the end to see if it really does.
+    fn is_recapture(current_move: Move, board: &Board) -> bool {
+        let count = board.history.len();
+        if count == 0 || current_move.captured() == Pieces::NONE {
+            return false;
+        }
+
+        let last_move = board.history.get_ref(count - 1).next_move;
+        last_move.captured() != Pieces::NONE && last_move.to() == current_move.to()
+    }
+
+    // A passed pawn moving to the seventh rank is about to promote.
+    fn is_passed_pawn_push(current_move: Move, board: &Board) -> bool {
+        let us = board.us();
+        let seventh = if us == Sides::WHITE {
+            Ranks::R7
+        } else {
+            Ranks::R2
+        };
+
+        current_move.piece() == Pieces::PAWN
+            && Board::square_on_rank(current_move.to(), seventh)
+            && board.is_passed_pawn(us, current_move.to())
+    }
+}
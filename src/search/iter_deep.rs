@@ -22,21 +22,51 @@ with this program.  If not, see <http://www.gnu.org/licenses/>.
 ======================================================================= */
 
 use super::{
-    defs::{SearchMode, SearchRefs, SearchResult, INF},
-    ErrFatal, Information, Search, SearchReport, SearchSummary,
+    defs::{
+        RootMove, ScoreBound, SearchMode, SearchRefs, SearchResult, CHECKMATE_THRESHOLD, INF,
+        SHARPNESS_LINES,
+    },
+    ErrFatal, Search, SearchReport, SearchSummary,
 };
-use crate::{defs::MAX_PLY, movegen::defs::Move};
+use crate::{defs::MAX_DEPTH, movegen::defs::Move};
 
 // Actual search routines.
 impl Search {
     pub fn iterative_deepening(refs: &mut SearchRefs) -> SearchResult {
-        // Working variables
-        let mut depth = 1;
+        // Working variables. Every other helper thread starts one ply
+        // deeper, so not all threads search the same depth.
+        let mut depth = 1 + (refs.search_info.thread_id % 2) as i8;
         let mut best_move = Move::new(0);
-        let mut root_pv: Vec<Move> = Vec::new();
+        let mut ponder_move: Option<Move> = None;
         let mut stop = false;
+        let mut best_eval = -INF;
+        let mut last_best: Option<(Move, i16)> = None;
         let is_game_time = refs.search_params.is_game_time();
 
+        // Can't report more lines than there are legal moves.
+        let legal_root_moves = Search::legal_root_moves(refs);
+        let legal_moves = legal_root_moves.len();
+        let multi_pv = refs.search_params.multi_pv.min(legal_moves).max(1);
+
+        // A weakened engine searches extra lines to have root moves to
+        // choose from, but it only reports the lines that were asked for.
+        // Rating the sharpness of the position also needs extra lines.
+        let strength = refs.search_params.strength;
+        let candidates = strength.map_or(1, |s| s.candidates()).min(legal_moves);
+        let sharpness = refs.search_params.show_sharpness;
+        let sharpness_lines = if sharpness { SHARPNESS_LINES } else { 1 };
+        let mut lines = multi_pv
+            .max(candidates)
+            .max(sharpness_lines.min(legal_moves));
+
+        // Helpers don't exclude the root moves of earlier lines, so every
+        // extra line, also a weakened engine's candidate line, would only
+        // search the first line again.
+        if refs.search_info.is_helper() {
+            lines = 1;
+        }
+        let mut root_moves: Vec<RootMove> = Vec::new();
+
         // Determine available time in case of GameTime search mode.
         if is_game_time {
             // Determine the maximum time slice available for this move.
@@ -47,10 +77,26 @@ impl Search {
             // don't allocated more than 40% of the calculated move time.
             let factor = 0.40;
 
+            // Spend less time in the opening, and save it for later.
+            let shaping = Search::time_shaping(refs);
+
             // If we have time, do a normal search in GameTime mode.
             if time_slice > 0 {
                 // Determine the actual time to allot for this search.
-                refs.search_info.allocated_time = (time_slice as f64 * factor).round() as u128;
+                let allocated = (time_slice as f64 * factor * shaping).round() as u128;
+                let hard = Search::hard_time_limit(allocated);
+                refs.search_info.allocated_time = allocated;
+                refs.search_info.soft_time = allocated;
+                refs.search_info.hard_time = hard;
+
+                // Report the time allocation, so the shaping can be
+                // verified in real games.
+                if !refs.search_params.quiet {
+                    let msg = format!(
+                        "Time: slice {time_slice} ms, shaping {shaping:.2}, allocated {allocated} ms, hard limit {hard} ms"
+                    );
+                    Search::report(refs, SearchReport::InfoString(msg));
+                }
             } else {
                 // We have no time. Send the best move from ply 1 to avoid
                 // killing ourselves by sending no move at all. Change mode
@@ -60,6 +106,13 @@ impl Search {
             }
         }
 
+        // A fixed move time is a hard limit. There's no soft limit, as the
+        // engine is expected to use all of the time it was given.
+        if refs.search_params.search_mode == SearchMode::MoveTime {
+            refs.search_info.soft_time = refs.search_params.move_time;
+            refs.search_info.hard_time = refs.search_params.move_time;
+        }
+
         // Set the starting values for alpha and beta, for use with the
         // aspiration window. We always start with a fully open window.
         let alpha: i16 = -INF;
@@ -67,58 +120,174 @@ impl Search {
 
         // Start the search
         refs.search_info.timer_start();
-        while (depth <= MAX_PLY) && (depth <= refs.search_params.depth) && !stop {
+        while (depth <= refs.search_params.depth) && !stop {
             // Set the current depth
             refs.search_info.depth = depth;
 
-            // Get the evaluation for this depth.
-            let eval = Search::alpha_beta(depth, alpha, beta, &mut root_pv, refs);
+            // Search each MultiPV line in turn. Every line excludes the
+            // root moves that started the lines before it.
+            refs.search_info.root_excluded.clear();
+            refs.search_info.root_moves_capped = 0;
+            let mut depth_moves: Vec<RootMove> = Vec::new();
+            let mut summaries: Vec<SearchSummary> = Vec::new();
+            for line in 1..=lines {
+                // Get the evaluation for this depth.
+                let mut root_pv: Vec<Move> = Vec::new();
+                let eval = Search::alpha_beta(depth, alpha, beta, &mut root_pv, refs);
 
-            // Create summary if search was not interrupted.
-            if !refs.search_info.interrupted() {
-                // Save the best move until now.
-                if !root_pv.is_empty() {
+                // Don't report lines from an interrupted search. If the
+                // search is stopped before it finished the first depth,
+                // the best root move found so far is still better than
+                // having no move at all.
+                if refs.search_info.interrupted() || root_pv.is_empty() {
+                    if best_move.get_move() == 0 && line == 1 && !root_pv.is_empty() {
+                        best_move = root_pv[0];
+                    }
+                    break;
+                }
+
+                // Complete a PV that was cut short by a TT cutoff.
+                if !refs.search_info.is_helper() {
+                    Search::extend_pv_from_tt(&mut root_pv, depth, eval, refs);
+                }
+
+                // Only the main thread reports to the GUI.
+                if refs.search_info.is_helper() {
+                    best_eval = eval;
+                    best_move = root_pv[0];
+                    ponder_move = root_pv.get(1).copied();
+                    continue;
+                }
+
+                // Save the best move until now. This is always the first
+                // move of the first line.
+                if line == 1 {
+                    best_eval = eval;
                     best_move = root_pv[0];
+                    ponder_move = root_pv.get(1).copied();
+                }
+                refs.search_info
+                    .root_excluded
+                    .push(root_pv[0].to_short_move());
+                depth_moves.push((root_pv[0], root_pv.get(1).copied(), eval));
+
+                if line > multi_pv {
+                    continue;
                 }
 
                 // Create search summary for this depth.
                 let elapsed = refs.search_info.timer_elapsed();
-                let nodes = refs.search_info.nodes;
-                let hash_full = refs.tt.lock().expect(ErrFatal::LOCK).hash_full();
+                let nodes = Search::total_nodes(refs);
+                let hash_full = refs.tt.read().expect(ErrFatal::LOCK).hash_full();
                 let summary = SearchSummary {
                     depth,
                     seldepth: refs.search_info.seldepth,
                     time: elapsed,
                     cp: eval,
+                    bound: refs.search_info.root_bound,
                     mate: 0,
                     nodes,
                     nps: Search::nodes_per_second(nodes, elapsed),
                     hash_full,
-                    pv: root_pv.clone(),
+                    tb_hits: refs.search_info.tb_hits,
+                    cpu_load: Search::cpu_load(refs.search_params.threads),
+                    multi_pv: line,
+                    pv: root_pv,
+                    side: refs.board.us(),
                 };
 
-                // Create information for the engine
-                let report = SearchReport::SearchSummary(summary);
-                let information = Information::Search(report);
-                refs.report_tx.send(information).expect(ErrFatal::CHANNEL);
+                summaries.push(summary);
+            }
 
-                // Search one ply deepr.
+            // Report the lines of this depth. MultiPV lines are reported
+            // together, so the GUI receives them as one block.
+            if summaries.len() > 1 {
+                Search::report(refs, SearchReport::SearchSummaries(summaries));
+            } else if let Some(summary) = summaries.pop() {
+                Search::report(refs, SearchReport::SearchSummary(summary));
+            }
+
+            // Let the user know that not all root moves were searched
+            // completely at this depth.
+            let capped = refs.search_info.root_moves_capped;
+            if capped > 0 && !refs.search_info.is_helper() && !refs.search_params.quiet {
+                let msg = format!("Depth {depth}: {capped} root move(s) exceeded the node budget");
+                Search::report(refs, SearchReport::InfoString(msg));
+            }
+
+            // Search one ply deeper if this depth was completed.
+            if !refs.search_info.interrupted() {
+                refs.search_info.completed_depth = depth;
+                root_moves = depth_moves;
+                Search::merge_history(refs, depth);
+                Search::update_time_limits(refs, (best_move, best_eval), last_best);
+                last_best = Some((best_move, best_eval));
+
+                // There's no deeper iteration than MAX_DEPTH.
+                if depth == MAX_DEPTH {
+                    break;
+                }
                 depth += 1;
             }
 
-            // Determine if time is up, when in GameTime mode.
-            let time_up = if is_game_time {
-                refs.search_info.timer_elapsed() > refs.search_info.allocated_time
+            // Determine if time is up, when in GameTime mode. If the root
+            // score is only a bound, it can't be trusted; keep searching
+            // until the search runs out of time completely. The same goes
+            // for being mated: keep looking for a swindle.
+            let is_exact = refs.search_info.root_bound == ScoreBound::Exact;
+            let is_clock = is_game_time && is_exact && !refs.search_params.ponder;
+            let is_mated = best_eval <= -CHECKMATE_THRESHOLD;
+            let time_up = if is_clock && !is_mated {
+                Search::soft_time_up(refs)
             } else {
                 false
             };
 
+            // If a mate was found and time is short, play it instead of
+            // deepening the search to find a faster one.
+            let mate_short =
+                is_clock && best_eval >= CHECKMATE_THRESHOLD && Search::is_time_short(refs);
+
+            // In Mate mode, the search is done when it has proven a mate
+            // in the requested number of moves (or less).
+            let mate_found = match refs.search_params.search_mode {
+                SearchMode::Mate(moves) => Search::is_mate_within(best_eval, moves, refs),
+                _ => false,
+            };
+
             // Stop deepening the search if the current depth was
-            // interrupted, or if the time is up.
-            stop = refs.search_info.interrupted() || time_up;
+            // interrupted, if the time is up, or the mate was found.
+            stop = refs.search_info.interrupted() || time_up || mate_found || mate_short;
         }
 
+        // Tell the user how many good moves there are.
+        if sharpness && !refs.search_info.is_helper() && !refs.search_params.quiet {
+            Search::send_sharpness_to_gui(&root_moves, legal_moves, refs);
+        }
+
+        // A weakened engine doesn't always play the best move.
+        if let Some(s) = strength {
+            if let Some((m, p)) = Search::weakened_move(&root_moves, s.margin()) {
+                best_move = m;
+                ponder_move = p;
+            }
+        }
+
+        // If even the root moves weren't searched yet, play the first
+        // legal one. Without legal moves, the game is over and the null
+        // move is sent.
+        if best_move.get_move() == 0 {
+            if let Some(m) = legal_root_moves.first() {
+                best_move = *m;
+                ponder_move = None;
+            }
+        }
+
+        // When pondering or in infinite analysis, the best move can't be
+        // sent yet.
+        Search::wait_for_search_end(refs);
+
         // Search is done. Report best move and reason to terminate.
-        (best_move, refs.search_info.terminate)
+        (best_move, ponder_move, refs.search_info.terminate)
     }
 }
@@ -22,17 +22,32 @@ with this program.  If not, see <http://www.gnu.org/licenses/>.
 ======================================================================= */
 
 use super::{
-    defs::{SearchTerminate, CHECK_TERMINATION, SEND_STATS},
+    defs::{SearchTerminate, CHECKMATE, CHECK_TERMINATION, SEND_STATS},
     Search, SearchRefs,
 };
 use crate::{
+    board::defs::Pieces,
     defs::MAX_PLY,
     evaluation,
     movegen::defs::{Move, MoveList, MoveType, ShortMove},
 };
 
 impl Search {
-    pub fn quiescence(mut alpha: i16, beta: i16, pv: &mut Vec<Move>, refs: &mut SearchRefs) -> i16 {
+    // Quiescence search only searches captures, until the position is
+    // quiet. There are two exceptions. When in check, standing pat is not
+    // allowed, so all moves are searched to find an evasion (or to find
+    // that there is none, which is checkmate). At the first ply, quiet
+    // moves that give check can also be searched, if they don't lose
+    // material according to SEE; this finds short tactical shots that
+    // captures alone would miss. "qply" is the number of plies searched
+    // in quiescence so far.
+    pub fn quiescence(
+        mut alpha: i16,
+        beta: i16,
+        qply: i16,
+        pv: &mut Vec<Move>,
+        refs: &mut SearchRefs,
+    ) -> i16 {
         // We created a new node which we'll search, so count it.
         refs.search_info.nodes += 1;
 
@@ -51,23 +66,29 @@ impl Search {
 
         // Immediately evaluate and return on reaching MAX_PLY
         if refs.search_info.ply >= MAX_PLY {
-            return evaluation::evaluate_position(refs.board);
+            return evaluation::evaluate_position_cached(refs.board, refs.pawn_tt);
         }
 
+        // Determine if we are in check.
+        let is_check = Search::side_to_move_in_check(refs);
+
         // Do a stand-pat here: Check how we're doing, even before we make
         // a move. If the evaluation score is larger than beta, then we're
         // already so bad we don't need to search any further. Just return
-        // the beta score.
-        let eval_score = evaluation::evaluate_position(refs.board);
-        if eval_score >= beta {
-            return beta;
-        }
+        // the beta score. When in check, there is no standing pat: one of
+        // the evasions must be played.
+        if !is_check {
+            let eval_score = evaluation::evaluate_position_cached(refs.board, refs.pawn_tt);
+            if eval_score >= beta {
+                return beta;
+            }
 
-        // If the evaluation score is bigger than alpha, then we can
-        // improve our position. So set alpha to this score and keep
-        // searching until there are no more captures.
-        if eval_score > alpha {
-            alpha = eval_score
+            // If the evaluation score is bigger than alpha, then we can
+            // improve our position. So set alpha to this score and keep
+            // searching until there are no more captures.
+            if eval_score > alpha {
+                alpha = eval_score
+            }
         }
 
         // Stand-pat is done. Start searching the captures in our position.
@@ -76,9 +97,14 @@ impl Search {
         // the recursion, or until there are no more captures available.
         // Then the function will return after looping the move list.
 
-        // Generate only capture moves.
+        // Generate only capture moves, unless we need evasions or checks.
+        let add_checks = !is_check && qply == 0 && refs.search_params.qsearch_checks;
         let mut move_list = MoveList::new();
-        let mtc = MoveType::Capture;
+        let mtc = if is_check || add_checks {
+            MoveType::All
+        } else {
+            MoveType::Capture
+        };
         refs.mg.generate_moves(refs.board, &mut move_list, mtc);
 
         // Do move scoring, so the best move will be searched first.
@@ -90,12 +116,28 @@ impl Search {
             Search::send_stats_to_gui(refs);
         }
 
-        // Iterate over the capture moves.
+        // Count the legal moves. In check without any, we are mated.
+        let mut legal_moves_found = 0;
+
+        // Iterate over the moves.
         for i in 0..move_list.len() {
             // Pick the next moves with the higest score.
             Search::pick_move(&mut move_list, i);
 
             let current_move = move_list.get_move(i);
+
+            // Skip promotions the user doesn't want the engine to play.
+            if !refs.search_params.promotions.allows(current_move, false) {
+                continue;
+            }
+
+            // A quiet move that isn't an evasion must give check without
+            // losing material. Checking SEE first is cheaper.
+            let is_quiet = current_move.captured() == Pieces::NONE;
+            if add_checks && is_quiet && Search::see(refs.board, refs.mg, current_move) < 0 {
+                continue;
+            }
+
             let is_legal = refs.board.make(current_move, refs.mg);
 
             // If not legal, skip the move and the rest of the function.
@@ -103,7 +145,14 @@ impl Search {
                 continue;
             }
 
+            // After making the move, the opponent is the side to move.
+            if add_checks && is_quiet && !Search::side_to_move_in_check(refs) {
+                refs.board.unmake();
+                continue;
+            }
+
             // Move is legal; increase the ply count.
+            legal_moves_found += 1;
             refs.search_info.ply += 1;
 
             // Update seldepth if we're searching deeper than requested.
@@ -111,14 +160,16 @@ impl Search {
                 refs.search_info.seldepth = refs.search_info.ply;
             }
 
-            // Create a PV for this node.
-            let mut node_pv: Vec<Move> = Vec::new();
+            // Take the PV for this node from the arena.
+            let mut node_pv = refs.pv_arena.take(refs.search_info.ply);
 
             // The position is not yet quiet. Go one ply deeper.
-            let eval_score = -Search::quiescence(-beta, -alpha, &mut node_pv, refs);
+            let eval_score = -Search::quiescence(-beta, -alpha, qply + 1, &mut node_pv, refs);
 
-            // Take back the move, and decrease ply accordingly.
+            // Take back the move, and decrease ply accordingly. The node
+            // PV goes back into the arena, where it can still be read.
             refs.board.unmake();
+            refs.pv_arena.put_back(refs.search_info.ply, node_pv);
             refs.search_info.ply -= 1;
 
             // If we are worse than beta (the opponent), then stop
@@ -135,12 +186,26 @@ impl Search {
                 // Update the Principal Variation.
                 pv.clear();
                 pv.push(current_move);
-                pv.append(&mut node_pv);
+                pv.extend_from_slice(refs.pv_arena.get(refs.search_info.ply + 1));
             }
         }
 
+        // In check without a legal move: checkmate.
+        if is_check && legal_moves_found == 0 {
+            return -CHECKMATE + refs.search_info.ply;
+        }
+
         // We have traversed the entire move list and found the best score for us,
         // so we return this.
         alpha
     }
+
+    // Returns true if the side to move is in check.
+    fn side_to_move_in_check(refs: &SearchRefs) -> bool {
+        refs.mg.square_attacked(
+            refs.board,
+            refs.board.opponent(),
+            refs.board.king_square(refs.board.us()),
+        )
+    }
 }
@@ -0,0 +1,143 @@
+/* =======================================================================
+Rustic is a chess playing engine.
+Copyright (C) 2019-2024, Marcel Vanthoor
+https://rustic-chess.org/
+
+Rustic is written in the Rust programming language. It is an original
+work, not derived from any engine that came before it. However, it does
+use a lot of concepts which are well-known and are in use by most if not
+all classical alpha/beta-based chess engines.
+
+Rustic is free software: you can redistribute it and/or modify it under
+the terms of the GNU General Public License version 3 as published by
+the Free Software Foundation.
+
+Rustic is distributed in the hope that it will be useful, but WITHOUT
+ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License
+for more details.
+
+You should have received a copy of the GNU General Public License along
+with this program.  If not, see <http://www.gnu.org/licenses/>.
+======================================================================= */
+
+// see.rs contains the Static Exchange Evaluation. It calculates what a
+// move wins or loses in material if both sides keep capturing on the
+// move's to-square, each time with their least valuable piece, and each
+// side stops capturing as soon as that would lose material. No moves are
+// made on the board: captured pieces are removed from an occupancy
+// bitboard, so sliders behind them (x-rays) join in.
+
+use super::Search;
+use crate::{
+    board::{
+        defs::{Pieces, BB_SQUARES},
+        Board,
+    },
+    defs::{Bitboard, NrOf, Piece, Side, Sides, Square},
+    misc::bits,
+    movegen::{defs::Move, MoveGenerator},
+};
+
+// Piece values used by SEE, in the order K, Q, R, B, N, P, None. The king
+// can never be captured, so it is worth more than everything else.
+const SEE_VALUES: [i16; NrOf::PIECE_TYPES + 1] = [5000, 900, 500, 330, 320, 100, 0];
+
+// Attackers in the order SEE uses them: least valuable first.
+const SEE_ORDER: [Piece; NrOf::PIECE_TYPES] = [
+    Pieces::PAWN,
+    Pieces::KNIGHT,
+    Pieces::BISHOP,
+    Pieces::ROOK,
+    Pieces::QUEEN,
+    Pieces::KING,
+];
+
+// Maximum number of captures in an exchange: there are only 32 pieces.
+const MAX_EXCHANGE: usize = 32;
+
+impl Search {
+    // Returns the material balance of the exchange started by the move,
+    // from the point of view of the side making it. A result of 0 or more
+    // means the move doesn't lose material.
+    pub fn see(board: &Board, mg: &MoveGenerator, m: Move) -> i16 {
+        let to = m.to();
+        let mut gain = [0i16; MAX_EXCHANGE];
+        let mut occupancy = board.occupancy() & !BB_SQUARES[m.from()];
+        let mut on_square = m.piece();
+        let mut side = board.us() ^ 1;
+        let mut d = 0;
+
+        gain[0] = SEE_VALUES[m.captured()];
+        if m.promoted() != Pieces::NONE {
+            gain[0] += SEE_VALUES[m.promoted()] - SEE_VALUES[Pieces::PAWN];
+            on_square = m.promoted();
+        }
+
+        loop {
+            let attackers = attackers_to(board, mg, to, occupancy) & occupancy;
+            let own = attackers & board.bb_side[side];
+            let Some((piece, square)) = least_valuable(board, side, own) else {
+                break;
+            };
+
+            // A king can't capture on a square the opponent still attacks.
+            if piece == Pieces::KING && (attackers & board.bb_side[side ^ 1]) > 0 {
+                break;
+            }
+
+            // Store the balance after this side captures the piece on the
+            // square, as seen by this side.
+            d += 1;
+            gain[d] = SEE_VALUES[on_square] - gain[d - 1];
+            if d == MAX_EXCHANGE - 1 {
+                break;
+            }
+
+            occupancy &= !BB_SQUARES[square];
+            on_square = piece;
+            side ^= 1;
+        }
+
+        // Each side may stop capturing if that's better than going on.
+        while d > 0 {
+            gain[d - 1] = -((-gain[d - 1]).max(gain[d]));
+            d -= 1;
+        }
+
+        gain[0]
+    }
+}
+
+// All pieces of both sides that attack the given square, when the pieces
+// on the board are those in "occupancy".
+fn attackers_to(
+    board: &Board,
+    mg: &MoveGenerator,
+    square: Square,
+    occupancy: Bitboard,
+) -> Bitboard {
+    let w = &board.bb_pieces[Sides::WHITE];
+    let b = &board.bb_pieces[Sides::BLACK];
+    let bb_rook = mg.get_slider_attacks(Pieces::ROOK, square, occupancy);
+    let bb_bishop = mg.get_slider_attacks(Pieces::BISHOP, square, occupancy);
+    let bb_knight = mg.get_non_slider_attacks(Pieces::KNIGHT, square);
+    let bb_king = mg.get_non_slider_attacks(Pieces::KING, square);
+    let rooks = w[Pieces::ROOK] | b[Pieces::ROOK] | w[Pieces::QUEEN] | b[Pieces::QUEEN];
+    let bishops = w[Pieces::BISHOP] | b[Pieces::BISHOP] | w[Pieces::QUEEN] | b[Pieces::QUEEN];
+
+    (bb_rook & rooks)
+        | (bb_bishop & bishops)
+        | (bb_knight & (w[Pieces::KNIGHT] | b[Pieces::KNIGHT]))
+        | (bb_king & (w[Pieces::KING] | b[Pieces::KING]))
+        | (mg.get_pawn_attacks(Sides::BLACK, square) & w[Pieces::PAWN])
+        | (mg.get_pawn_attacks(Sides::WHITE, square) & b[Pieces::PAWN])
+}
+
+// The least valuable piece of "side" among the attackers, and its square.
+fn least_valuable(board: &Board, side: Side, attackers: Bitboard) -> Option<(Piece, Square)> {
+    SEE_ORDER.iter().find_map(|&piece| {
+        let mut bb = attackers & board.bb_pieces[side][piece];
+        (bb > 0).then(|| (piece, bits::next(&mut bb)))
+    })
+}
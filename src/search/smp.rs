@@ -0,0 +1,194 @@
+/* =======================================================================
+Rustic is a chess playing engine.
+Copyright (C) 2019-2024, Marcel Vanthoor
+https://rustic-chess.org/
+
+Rustic is written in the Rust programming language. It is an original
+work, not derived from any engine that came before it. However, it does
+use a lot of concepts which are well-known and are in use by most if not
+all classical alpha/beta-based chess engines.
+
+Rustic is free software: you can redistribute it and/or modify it under
+the terms of the GNU General Public License version 3 as published by
+the Free Software Foundation.
+
+Rustic is distributed in the hope that it will be useful, but WITHOUT
+ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License
+for more details.
+
+You should have received a copy of the GNU General Public License along
+with this program.  If not, see <http://www.gnu.org/licenses/>.
+======================================================================= */
+
+// smp.rs implements Lazy SMP. The main search thread starts a number of
+// helper threads, which all search the same position. They don't talk to
+// each other; the only thing they share is the transposition table, and
+// through that table they speed each other up. Helpers start at different
+// depths and order their quiet moves slightly differently, so they don't
+// all search exactly the same tree at the same time. Between iterations,
+// each thread merges its history table and killer moves with those of the
+// other threads, so move ordering doesn't drift too far apart.
+
+use super::{
+    defs::{
+        PvArena, SearchControl, SearchInfo, SearchMode, SearchRefs, SearchResult,
+        HISTORY_MERGE_MIN_DEPTH, STACK_SIZE,
+    },
+    Search,
+};
+use crate::{
+    engine::defs::{ErrFatal, PawnData, TT},
+    movegen::defs::Move,
+};
+use std::{sync::atomic::Ordering, thread};
+
+// Deepest depth a helper completed, with its best move and ponder move.
+type HelperResult = (i8, Move, Option<Move>);
+
+impl Search {
+    pub fn lazy_smp(refs: &mut SearchRefs) -> SearchResult {
+        let helpers = refs.search_params.threads.saturating_sub(1);
+
+        // Nothing to share if there is only one thread.
+        if helpers == 0 {
+            return Search::iterative_deepening(refs);
+        }
+
+        // Helpers search until the main thread stops them, but not deeper
+        // than the main thread may. They don't report to the GUI, and leave
+        // MultiPV, pondering and rating the sharpness to the main thread.
+        let mut helper_params = refs.search_params.clone();
+        helper_params.search_mode = SearchMode::Infinite;
+        helper_params.quiet = true;
+        helper_params.multi_pv = 1;
+        helper_params.ponder = false;
+        helper_params.show_sharpness = false;
+
+        // All threads start out with the main thread's history.
+        refs.shared_history
+            .lock()
+            .expect(ErrFatal::LOCK)
+            .reset(refs.history);
+
+        thread::scope(|s| {
+            let mut controls = Vec::with_capacity(helpers);
+            let mut handles = Vec::with_capacity(helpers);
+
+            for id in 1..=helpers {
+                let (control_tx, control_rx) = crossbeam_channel::unbounded::<SearchControl>();
+                let mut board = refs.board.clone();
+                let mut search_params = helper_params.clone();
+                let mg = refs.mg;
+                let tt = refs.tt;
+                let tt_enabled = refs.tt_enabled;
+                let tt_prefetch = refs.tt_prefetch;
+                let report_tx = refs.report_tx;
+                let helper_nodes = refs.helper_nodes;
+                let paused = refs.paused;
+                let lmr = refs.lmr;
+                let mut history = refs.history.clone();
+                let shared_history = refs.shared_history;
+                let pawn_hash = refs.search_params.pawn_hash;
+                let tt_generation = refs.search_info.tt_generation;
+
+                let builder = thread::Builder::new().stack_size(STACK_SIZE);
+                let spawned = builder.spawn_scoped(s, move || -> HelperResult {
+                    let mut search_info = SearchInfo::new();
+                    search_info.thread_id = id;
+                    search_info.tt_generation = tt_generation;
+                    let mut pawn_tt: TT<PawnData> = TT::new(pawn_hash);
+                    let mut pv_arena = PvArena::new();
+
+                    let mut helper_refs = SearchRefs {
+                        board: &mut board,
+                        mg,
+                        tt,
+                        tt_enabled,
+                        tt_prefetch,
+                        search_params: &mut search_params,
+                        search_info: &mut search_info,
+                        control_rx: &control_rx,
+                        report_tx,
+                        on_report: None,
+                        helper_nodes,
+                        paused,
+                        lmr,
+                        history: &mut history,
+                        shared_history,
+                        pawn_tt: &mut pawn_tt,
+                        pv_arena: &mut pv_arena,
+                    };
+
+                    let (best_move, ponder_move, _) = Search::iterative_deepening(&mut helper_refs);
+                    Search::publish_nodes(&mut helper_refs);
+                    Search::publish_tt_stats(&mut helper_refs);
+
+                    (search_info.completed_depth, best_move, ponder_move)
+                });
+
+                controls.push(control_tx);
+                handles.push(spawned.expect(ErrFatal::THREAD));
+            }
+
+            // The main thread does the actual search, and decides when
+            // it is finished.
+            let (mut best_move, mut ponder_move, terminate) = Search::iterative_deepening(refs);
+            let mut best_depth = refs.search_info.completed_depth;
+
+            // Stop the helpers. (A helper that reached its maximum depth
+            // has already stopped by itself, so its channel may be closed.)
+            // If the search was limited by time and one of them got further
+            // than the main thread, its best move is the better one. Depth,
+            // node and mate searches send the move of the PV the GUI was
+            // shown, so they can be reproduced. A weakened engine plays the
+            // move the main thread picked among its candidates.
+            for tx in controls.iter() {
+                let _ = tx.send(SearchControl::Stop);
+            }
+
+            let time_based = matches!(
+                refs.search_params.search_mode,
+                SearchMode::GameTime | SearchMode::MoveTime | SearchMode::Infinite
+            );
+            let adopt = time_based && refs.search_params.strength.is_none();
+
+            for h in handles {
+                let (depth, m, p) = h.join().expect(ErrFatal::THREAD);
+                if adopt && depth > best_depth {
+                    best_depth = depth;
+                    best_move = m;
+                    ponder_move = p;
+                }
+            }
+
+            (best_move, ponder_move, terminate)
+        })
+    }
+
+    // Merge this thread's history and killer moves with those of the
+    // other threads, after completing an iteration. This is skipped at
+    // shallow depths, which finish too quickly to have learned much.
+    pub fn merge_history(refs: &mut SearchRefs, depth: i8) {
+        if refs.search_params.threads > 1 && depth >= HISTORY_MERGE_MIN_DEPTH {
+            refs.shared_history.lock().expect(ErrFatal::LOCK).merge(
+                refs.history,
+                &mut refs.search_info.killer_moves,
+                depth,
+            );
+        }
+    }
+
+    // Helpers add the nodes they searched since the last time to the
+    // shared counter, so the main thread can report the total.
+    pub fn publish_nodes(refs: &mut SearchRefs) {
+        let nodes = refs.search_info.nodes - refs.search_info.nodes_published;
+        refs.helper_nodes.fetch_add(nodes, Ordering::Relaxed);
+        refs.search_info.nodes_published = refs.search_info.nodes;
+    }
+
+    // Returns the number of nodes searched by all threads together.
+    pub fn total_nodes(refs: &SearchRefs) -> usize {
+        refs.search_info.nodes + refs.helper_nodes.load(Ordering::Relaxed)
+    }
+}
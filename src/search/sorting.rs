@@ -28,10 +28,13 @@ use super::{
     Search,
 };
 use crate::{board::defs::Pieces, defs::NrOf, movegen::defs::MoveList, movegen::defs::ShortMove};
+use std::cmp::Ordering;
 
 const MVV_LVA_OFFSET: u32 = u32::MAX - 256;
 const TTMOVE_SORT_VALUE: u32 = 60;
 const KILLER_VALUE: u32 = 10;
+const COUNTER_MOVE_VALUE: u32 = MVV_LVA_OFFSET - ((MAX_KILLER_MOVES as u32 + 1) * KILLER_VALUE);
+const HELPER_JITTER: u32 = 8;
 
 // MVV_VLA[victim][attacker]
 pub const MVV_LVA: [[u16; NrOf::PIECE_TYPES + 1]; NrOf::PIECE_TYPES + 1] = [
@@ -46,12 +49,18 @@ pub const MVV_LVA: [[u16; NrOf::PIECE_TYPES + 1]; NrOf::PIECE_TYPES + 1] = [
 
 impl Search {
     pub fn score_moves(ml: &mut MoveList, tt_move: ShortMove, refs: &SearchRefs) {
+        let us = refs.board.us();
+        let previous = Search::previous_moves(refs);
+        let counter_move = refs.history.counter_move(us, previous[0]);
+
         for i in 0..ml.len() {
             let m = ml.get_mut_move(i);
             let mut value: u32 = 0;
 
             // Sort order priority is: TT Move first, then captures, then
-            // quiet moves that are in the list of killer moves.
+            // quiet moves that are in the list of killer moves, then the
+            // counter move, and then the other quiet moves by their
+            // history and continuation history.
             if m.get_move() == tt_move.get_move() {
                 value = MVV_LVA_OFFSET + TTMOVE_SORT_VALUE;
             } else if m.captured() != Pieces::NONE {
@@ -64,20 +73,31 @@ impl Search {
                     let killer = refs.search_info.killer_moves[ply][n];
                     if m.get_move() == killer.get_move() {
                         // Order killers below MVV_LVA_OFFSET
-                        value = MVV_LVA_OFFSET - ((i as u32 + 1) * KILLER_VALUE);
+                        value = MVV_LVA_OFFSET - ((n as u32 + 1) * KILLER_VALUE);
                     }
                     n += 1;
                 }
-            }
 
-            /*
-                // If still not sorted, try to sort by history heuristic.
-                if value == 0 {
-                    let piece = m.piece();
-                    let to = m.to();
-                    value = refs.search_info.history_heuristic[refs.board.us()][piece][to];
+                // The counter move comes right after the killers.
+                if value == 0 && m.get_move() == counter_move.get_move() {
+                    value = COUNTER_MOVE_VALUE;
+                }
+
+                // Otherwise, sort by history heuristic. This value always
+                // stays below the counter move value.
+                let is_ordinary = value == 0;
+                if is_ordinary {
+                    value = refs.history.get(us, *m, &previous);
                 }
-            */
+
+                // Helper threads in Lazy SMP shuffle the ordinary quiet
+                // moves a bit, so they don't search the same tree as the
+                // main thread.
+                if refs.search_info.is_helper() && is_ordinary {
+                    let seed = m.get_move().wrapping_mul(refs.search_info.thread_id as u32);
+                    value += (seed.wrapping_mul(0x9E37_79B9) >> 24) % HELPER_JITTER;
+                }
+            }
 
             m.set_sort_score(value);
         }
@@ -92,4 +112,37 @@ impl Search {
             }
         }
     }
+
+    // Alpha-beta keeps the first root move that reaches the best score;
+    // a later move with the same score can't raise alpha. Ties between
+    // root moves are therefore broken by the order in which they are
+    // searched. At the root, moves with the same sort score are picked
+    // in alphabetical order of their coordinate notation, so the move
+    // chosen doesn't depend on the order of the move generator. The TT
+    // move always has the highest sort score, so it is preferred first.
+    //
+    // This makes the choice repeatable for the same search, but not every
+    // search is repeatable. With a single thread and a fixed depth or node
+    // count, a search gives the same result when it starts with the same
+    // TT, history and pawn hash contents. These are kept between searches,
+    // including pondering; "ucinewgame" clears the TT, but only a restart
+    // resets the history and the pawn hash. Time-based searches stop at a
+    // different node on each run. With more than one thread, the threads
+    // share the TT in an order that depends on the operating system's
+    // scheduling. A weakened engine picks one of its best moves at random.
+    pub fn pick_root_move(ml: &mut MoveList, start_index: u8) {
+        for i in (start_index + 1)..ml.len() {
+            let current = ml.get_move(start_index);
+            let candidate = ml.get_move(i);
+            let is_better = match candidate.get_sort_score().cmp(&current.get_sort_score()) {
+                Ordering::Greater => true,
+                Ordering::Equal => candidate.as_string() < current.as_string(),
+                Ordering::Less => false,
+            };
+
+            if is_better {
+                ml.swap(start_index as usize, i as usize);
+            }
+        }
+    }
 }
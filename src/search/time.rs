@@ -21,24 +21,45 @@ You should have received a copy of the GNU General Public License along
 with this program.  If not, see <http://www.gnu.org/licenses/>.
 ======================================================================= */
 
-use super::{defs::SearchRefs, Search};
-use crate::defs::Sides;
+use super::{
+    defs::{GameTime, SearchRefs},
+    Search,
+};
+use crate::{
+    defs::{Side, Sides},
+    movegen::defs::Move,
+};
 
 pub const OVERHEAD: i128 = 50; // msecs
 const GAME_LENGTH: usize = 25; // moves
 const MOVES_BUFFER: usize = 5; //moves
 const CRITICAL_TIME: u128 = 1_000; // msecs
 const OK_TIME: u128 = CRITICAL_TIME * 5; // msecs
+const SHORT_CLOCK: u128 = CRITICAL_TIME * 10; // msecs
+
+// Extension of the soft limit if the best move changed at the last
+// completed depth, one depth before that, and so on.
+const INSTABILITY_FACTORS: [f64; 3] = [1.5, 1.25, 1.1];
+const SCORE_DROP_MIN: i16 = 20; // centipawns
+const SCORE_DROP_MAX: i16 = 100; // centipawns
+const SCORE_DROP_FACTOR: f64 = 0.5; // extension at the maximum drop
 
 impl Search {
-    // Determine if allocated search time has been used up.
+    // Determine if the hard time limit has been reached. The search is
+    // then aborted, even in the middle of a depth.
     pub fn out_of_time(refs: &mut SearchRefs) -> bool {
-        let elapsed = refs.search_info.timer_elapsed();
-        let allocated = refs.search_info.allocated_time;
+        refs.search_info.time_used() >= refs.search_info.hard_time
+    }
+
+    // Determine if the soft time limit has been reached. The search then
+    // doesn't start a new depth.
+    pub fn soft_time_up(refs: &SearchRefs) -> bool {
+        refs.search_info.time_used() > refs.search_info.soft_time
+    }
 
-        // Calculate a factor with which it is allowed to overshoot the
-        // allocated search time. The more time the engine has, the larger
-        // the overshoot-factor can be.
+    // Calculates the hard time limit from the allocated time. The more
+    // time the engine has, the more it may overshoot the allocated time.
+    pub fn hard_time_limit(allocated: u128) -> u128 {
         let overshoot_factor = match allocated {
             x if x > OK_TIME => 2.0,                       // Allow large overshoot.
             x if x > CRITICAL_TIME && x <= OK_TIME => 1.5, // Low on time. Reduce overshoot.
@@ -46,7 +67,65 @@ impl Search {
             _ => 1.0,                                      // This case shouldn't happen.
         };
 
-        elapsed >= (overshoot_factor * allocated as f64).round() as u128
+        (overshoot_factor * allocated as f64).round() as u128
+    }
+
+    // Calculates the soft time limit. It starts out as the allocated time,
+    // but it is extended while the best move keeps changing, or when the
+    // score dropped sharply at the last depth: in both cases, the engine
+    // is not sure about its move yet. It never exceeds the hard limit.
+    pub fn soft_time_limit(refs: &SearchRefs) -> u128 {
+        let info = &refs.search_info;
+
+        let instability = if info.best_move_changes > 0 {
+            let f = INSTABILITY_FACTORS.get(info.best_move_stable);
+            f.copied().unwrap_or(1.0)
+        } else {
+            1.0
+        };
+
+        let score_drop = if info.score_drop >= SCORE_DROP_MIN {
+            let drop = info.score_drop.min(SCORE_DROP_MAX) as f64 / SCORE_DROP_MAX as f64;
+            1.0 + SCORE_DROP_FACTOR * drop
+        } else {
+            1.0
+        };
+
+        let soft = (info.allocated_time as f64 * instability * score_drop).round() as u128;
+        soft.min(info.hard_time)
+    }
+
+    // After each completed depth, record if the best move changed and how
+    // much the score dropped compared to the previous depth, and then
+    // recalculate the soft time limit.
+    pub fn update_time_limits(refs: &mut SearchRefs, best: (Move, i16), last: Option<(Move, i16)>) {
+        let (best_move, eval) = best;
+
+        if let Some((last_move, last_eval)) = last {
+            let info = &mut refs.search_info;
+            if best_move.to_short_move() == last_move.to_short_move() {
+                info.best_move_stable += 1;
+            } else {
+                info.best_move_changes += 1;
+                info.best_move_stable = 0;
+            }
+            info.score_drop = last_eval.saturating_sub(eval).max(0);
+        }
+
+        if refs.search_params.is_game_time() {
+            refs.search_info.soft_time = Search::soft_time_limit(refs);
+        }
+    }
+
+    // Time is short if there is little left on the clock for the rest of
+    // the game, or if the time allocated for this move is critical.
+    pub fn is_time_short(refs: &SearchRefs) -> bool {
+        let gt = &refs.search_params.game_time;
+        let white = refs.board.us() == Sides::WHITE;
+        let clock = if white { gt.wtime } else { gt.btime };
+        let remaining = clock.saturating_sub(refs.search_info.time_used());
+
+        remaining <= SHORT_CLOCK || refs.search_info.allocated_time <= CRITICAL_TIME
     }
 
     // Calculates the time the engine allocates for searching a single
@@ -75,6 +154,36 @@ impl Search {
         }
     }
 
+    // The time an average move gets in the rest of the time control,
+    // without shaping it for the opening or extending it while searching.
+    // This tells how long the searches in this game will be.
+    pub fn average_move_time(gt: &GameTime, side: Side) -> u128 {
+        let white = side == Sides::WHITE;
+        let clock = if white { gt.wtime } else { gt.btime };
+        let increment = if white { gt.winc } else { gt.binc };
+        let mtg = gt
+            .moves_to_go
+            .filter(|x| *x > 0)
+            .unwrap_or(GAME_LENGTH + MOVES_BUFFER);
+
+        clock / (mtg as u128) + increment
+    }
+
+    // In the first moves of the game, evaluation differences between
+    // moves are small, so spending a lot of time there is mostly wasted.
+    // This function returns a factor to shape the time slice, following
+    // the opening time parameters. The time that is not used stays on the
+    // clock, so it automatically ends up in the time slices for the
+    // middlegame. Only a game that began in the start position has an
+    // opening; the move number of a position set up from a FEN-string
+    // says nothing about it.
+    pub fn time_shaping(refs: &SearchRefs) -> f64 {
+        let params = &refs.search_params.opening_time;
+        refs.search_params
+            .moves_played
+            .map_or(1.0, |played| params.factor(played))
+    }
+
     // Here we try to come up with some sort of sensible value for "moves
     // to go", if this value is not supplied.
     fn moves_to_go(refs: &SearchRefs) -> usize {
@@ -23,17 +23,25 @@ with this program.  If not, see <http://www.gnu.org/licenses/>.
 
 use super::{
     defs::{
-        SearchControl, SearchCurrentMove, SearchMode, SearchRefs, SearchReport, SearchStats,
-        SearchTerminate, MAX_KILLER_MOVES, MIN_TIME_CURR_MOVE, MIN_TIME_STATS,
+        RootMove, ScoreBound, SearchControl, SearchCurrentMove, SearchMode, SearchRefs,
+        SearchReport, SearchStats, SearchTerminate, CHECKMATE, CHECKMATE_THRESHOLD,
+        CONT_HISTORY_PLIES, MAX_KILLER_MOVES, MIN_TIME_CURR_MOVE, MIN_TIME_STATS, SHARPNESS_MARGIN,
     },
     Search,
 };
 use crate::{
-    board::{defs::Pieces, Board},
-    defs::{Sides, MAX_MOVE_RULE},
-    engine::defs::{ErrFatal, Information},
-    movegen::defs::Move,
+    board::Board,
+    defs::MAX_MOVE_RULE,
+    engine::defs::{ErrFatal, Information, SearchData, TTStats},
+    evaluation::draw,
+    misc::system::{self, Instant},
+    movegen::defs::{Move, MoveList, MoveType, ShortMove},
 };
+use rand::Rng;
+use std::{sync::atomic::Ordering, thread, time::Duration};
+
+// Milliseconds between checks of the pause flag by Lazy SMP helpers.
+const PAUSE_POLL: u64 = 10;
 
 impl Search {
     // This function calculates the number of nodes per second.
@@ -46,21 +54,78 @@ impl Search {
         nps
     }
 
+    // This function approximates the CPU load in permille, by comparing
+    // the number of active search workers to the number of logical cores.
+    pub fn cpu_load(workers: usize) -> u16 {
+        const PERMILLE: usize = 1000;
+
+        match system::logical_cores() {
+            Some(cores) => (workers * PERMILLE / cores).min(PERMILLE) as u16,
+            None => 0,
+        }
+    }
+
+    // Returns the legal moves in the position to be searched, leaving out
+    // those that are not in the "searchmoves" list.
+    pub fn legal_root_moves(refs: &mut SearchRefs) -> Vec<Move> {
+        let mut move_list = MoveList::new();
+        let mut legal_moves = Vec::new();
+
+        refs.mg
+            .generate_moves(refs.board, &mut move_list, MoveType::All);
+
+        for i in 0..move_list.len() {
+            let m = move_list.get_move(i);
+            if refs.search_params.search_moves.allows(m) && refs.board.make(m, refs.mg) {
+                refs.board.unmake();
+                legal_moves.push(m);
+            }
+        }
+
+        legal_moves
+    }
+
+    // Send a report to the engine, or to the caller of a synchronous
+    // search, which has no engine thread to receive it.
+    pub fn report(refs: &SearchRefs, report: SearchReport) {
+        match refs.on_report {
+            Some(on_report) => on_report(report),
+            None => refs
+                .report_tx
+                .send(Information::Search(report))
+                .expect(ErrFatal::CHANNEL),
+        }
+    }
+
     // Send intermediate statistics to GUI.
     pub fn send_stats_to_gui(refs: &mut SearchRefs) {
         let elapsed = refs.search_info.timer_elapsed();
         let last_stats = refs.search_info.last_stats_sent;
 
         if elapsed >= last_stats + MIN_TIME_STATS {
-            let hash_full = refs.tt.lock().expect(ErrFatal::LOCK).hash_full();
+            let hash_full = refs.tt.read().expect(ErrFatal::LOCK).hash_full();
             let msecs = refs.search_info.timer_elapsed();
-            let nps = Search::nodes_per_second(refs.search_info.nodes, msecs);
-            let stats = SearchStats::new(msecs, refs.search_info.nodes, nps, hash_full);
-            let stats_report = SearchReport::SearchStats(stats);
-            let information = Information::Search(stats_report);
-
-            refs.report_tx.send(information).expect(ErrFatal::CHANNEL);
+            let nodes = Search::total_nodes(refs);
+            let nps = Search::nodes_per_second(nodes, msecs);
+            let stats = SearchStats::new(
+                msecs,
+                nodes,
+                nps,
+                hash_full,
+                refs.search_info.tb_hits,
+                Search::cpu_load(refs.search_params.threads),
+            );
+            Search::report(refs, SearchReport::SearchStats(stats));
             refs.search_info.last_stats_sent = elapsed;
+
+            // Report the line that is being searched along with the
+            // statistics, if the GUI asked for it.
+            let curr_line = Search::current_line(refs);
+            if let Some(root_move) = curr_line.first() {
+                let number = refs.search_info.root_move_number;
+                let scm = SearchCurrentMove::new(*root_move, number, curr_line);
+                Search::report(refs, SearchReport::SearchCurrentMove(scm));
+            }
         }
     }
 
@@ -70,25 +135,56 @@ impl Search {
         let lcm = refs.search_info.last_curr_move_sent;
 
         if elapsed >= lcm + MIN_TIME_CURR_MOVE {
-            let scm = SearchCurrentMove::new(current_move, count);
-            let scm_report = SearchReport::SearchCurrentMove(scm);
-            let information = Information::Search(scm_report);
-
-            refs.report_tx.send(information).expect(ErrFatal::CHANNEL);
+            let curr_line = Search::current_line(refs);
+            let scm = SearchCurrentMove::new(current_move, count, curr_line);
+            Search::report(refs, SearchReport::SearchCurrentMove(scm));
             refs.search_info.last_curr_move_sent = elapsed;
         }
     }
 
+    // Send a root move that failed low to the GUI, together with the line
+    // that refutes it. The line is taken from the TT; if there is none,
+    // only the root move is sent.
+    pub fn send_refutation_to_gui(refs: &mut SearchRefs, root_move: Move, depth: i8) {
+        let mut refutation = vec![root_move];
+        Search::extend_pv_from_tt(&mut refutation, depth, 0, refs);
+
+        Search::report(refs, SearchReport::SearchRefutation(refutation));
+    }
+
+    // The moves played from the root up to the current node. This line is
+    // only collected if the GUI asked for it; otherwise it is empty. A
+    // null move is part of the line as an empty move.
+    fn current_line(refs: &SearchRefs) -> Vec<Move> {
+        if !refs.search_params.show_curr_line {
+            return Vec::new();
+        }
+
+        let ply = refs.search_info.ply.max(0) as usize;
+        let count = refs.board.history.len();
+        (count.saturating_sub(ply)..count)
+            .map(|i| refs.board.history.get_ref(i).next_move)
+            .collect()
+    }
+
     // This function checks termination conditions and sets the termination
     // flag if this is required.
     pub fn check_termination(refs: &mut SearchRefs) {
         // Terminate search if stop or quit command is received.
         let cmd = refs.control_rx.try_recv().unwrap_or(SearchControl::Nothing);
-        match cmd {
-            SearchControl::Stop => refs.search_info.terminate = SearchTerminate::Stop,
-            SearchControl::Quit => refs.search_info.terminate = SearchTerminate::Quit,
-            SearchControl::Start(_) | SearchControl::Nothing => (),
-        };
+        Search::handle_control(cmd, refs);
+
+        // Helpers publish their node count, to be reported as a total.
+        // They also wait if the main thread has paused the search.
+        if refs.search_info.is_helper() {
+            Search::publish_nodes(refs);
+            Search::wait_while_paused(refs);
+        }
+
+        // While pondering, only a command from the GUI ends the search.
+        if refs.search_params.ponder {
+            return;
+        }
 
         // Terminate search if certain conditions are met.
         let search_mode = refs.search_params.search_mode;
@@ -98,93 +194,358 @@ impl Search {
                     refs.search_info.terminate = SearchTerminate::Stop
                 }
             }
-            SearchMode::MoveTime => {
-                let elapsed = refs.search_info.timer_elapsed();
-                if elapsed >= refs.search_params.move_time {
-                    refs.search_info.terminate = SearchTerminate::Stop
-                }
-            }
             SearchMode::Nodes => {
-                if refs.search_info.nodes >= refs.search_params.nodes {
+                if Search::total_nodes(refs) >= refs.search_params.nodes {
                     refs.search_info.terminate = SearchTerminate::Stop
                 }
             }
-            SearchMode::GameTime => {
+            SearchMode::MoveTime | SearchMode::GameTime => {
                 if Search::out_of_time(refs) {
                     refs.search_info.terminate = SearchTerminate::Stop
                 }
             }
             SearchMode::Infinite => (), // Handled by a direct 'stop' command
+            SearchMode::Mate(_) => (),  // Handled by iterative deepening
             SearchMode::Nothing => (),  // We're not searching. Nothing to do.
         }
+
+        // A weakened engine stops at its node cap in every search mode.
+        if let Some(s) = refs.search_params.strength {
+            if Search::total_nodes(refs) >= s.nodes() {
+                refs.search_info.terminate = SearchTerminate::Stop
+            }
+        }
+    }
+
+    // Handles a command received from the engine while searching.
+    fn handle_control(cmd: SearchControl, refs: &mut SearchRefs) {
+        match cmd {
+            SearchControl::Stop => refs.search_info.terminate = SearchTerminate::Stop,
+            SearchControl::Quit => refs.search_info.terminate = SearchTerminate::Quit,
+            SearchControl::PonderHit => Search::ponder_hit(refs),
+            SearchControl::Pause => Search::pause(refs),
+            SearchControl::Start(_)
+            | SearchControl::Resume
+            | SearchControl::NewGame
+            | SearchControl::Nothing => (),
+        };
+    }
+
+    // Pausing suspends the search until it is resumed, stopped or quit.
+    // Waiting for the next command doesn't use any CPU time, and the time
+    // spent waiting doesn't count as search time. The flag tells the
+    // Lazy SMP helpers to wait as well.
+    fn pause(refs: &mut SearchRefs) {
+        let start = Instant::now();
+        refs.paused.store(true, Ordering::Relaxed);
+
+        loop {
+            match refs.control_rx.recv().expect(ErrFatal::CHANNEL) {
+                SearchControl::Resume => break,
+                SearchControl::Pause => (),
+                cmd => Search::handle_control(cmd, refs),
+            }
+
+            if refs.search_info.interrupted() {
+                break;
+            }
+        }
+
+        refs.search_info.paused_time += start.elapsed().as_millis();
+        refs.paused.store(false, Ordering::Relaxed);
+    }
+
+    // Helpers don't receive commands from the engine, so they check the
+    // pause flag once in a while, until the main thread resumes or stops
+    // the search.
+    fn wait_while_paused(refs: &mut SearchRefs) {
+        while refs.paused.load(Ordering::Relaxed) && !refs.search_info.interrupted() {
+            thread::sleep(Duration::from_millis(PAUSE_POLL));
+            let cmd = refs.control_rx.try_recv().unwrap_or(SearchControl::Nothing);
+            Search::handle_control(cmd, refs);
+        }
+    }
+
+    // On a ponderhit, the ponder search becomes a normal search. Our
+    // clock starts running now, so the time spent pondering is not
+    // counted against the time allotted for the move.
+    fn ponder_hit(refs: &mut SearchRefs) {
+        refs.search_params.ponder = false;
+        refs.search_info.ponder_time = refs.search_info.timer_elapsed();
+    }
+
+    // A ponder search may not report its best move before the GUI sends
+    // "ponderhit" or "stop", and an infinite search may not report it
+    // before "stop". If the search finishes early (because it reached the
+    // maximum depth, or there is only one move) wait for the command.
+    // Helpers are stopped by the main thread, so they don't wait.
+    pub fn wait_for_search_end(refs: &mut SearchRefs) {
+        let is_infinite = refs.search_params.search_mode == SearchMode::Infinite;
+
+        while (refs.search_params.ponder || is_infinite)
+            && !refs.search_info.is_helper()
+            && !refs.search_info.interrupted()
+        {
+            let cmd = refs.control_rx.recv().expect(ErrFatal::CHANNEL);
+            Search::handle_control(cmd, refs);
+        }
+    }
+
+    // Picks a random move among the root moves that score within the
+    // margin of the best one. Returns None if there are no root moves.
+    pub fn weakened_move(root_moves: &[RootMove], margin: i16) -> Option<(Move, Option<Move>)> {
+        let best = root_moves.iter().map(|rm| rm.2).max()?;
+        let threshold = best.saturating_sub(margin);
+        let candidates: Vec<&RootMove> = root_moves.iter().filter(|rm| rm.2 >= threshold).collect();
+        let (m, ponder, _) = *candidates[rand::thread_rng().gen_range(0..candidates.len())];
+
+        Some((m, ponder))
+    }
+
+    // Reports the sharpness of the position: the number of root moves
+    // that score within a small margin of the best one. The fewer there
+    // are, the sharper the position is. Only the root moves searched as
+    // separate lines are counted; if all of them are good, there may be
+    // even more good moves.
+    pub fn send_sharpness_to_gui(root_moves: &[RootMove], legal_moves: usize, refs: &SearchRefs) {
+        let best = match root_moves.iter().map(|rm| rm.2).max() {
+            Some(best) => best,
+            None => return,
+        };
+
+        let threshold = best.saturating_sub(SHARPNESS_MARGIN);
+        let good = root_moves.iter().filter(|rm| rm.2 >= threshold).count();
+        let at_least = if good == root_moves.len() && good < legal_moves {
+            "at least "
+        } else {
+            ""
+        };
+        let rating = match good {
+            1 => "only move",
+            2..=3 => "sharp",
+            _ => "quiet",
+        };
+
+        let msg = format!(
+            "Sharpness: {at_least}{good} of {legal_moves} moves within {SHARPNESS_MARGIN} cp of the best ({rating})"
+        );
+        Search::report(refs, SearchReport::InfoString(msg));
+    }
+
+    // Returns true if the score of a completed iteration proves that the
+    // side to move mates within the given number of moves.
+    pub fn is_mate_within(score: i16, moves: u8, refs: &SearchRefs) -> bool {
+        let is_exact = refs.search_info.root_bound == ScoreBound::Exact;
+        let is_mate = (CHECKMATE_THRESHOLD..CHECKMATE).contains(&score);
+        let plies = (CHECKMATE - score) as i32;
+
+        is_exact && is_mate && (plies + 1) / 2 <= moves as i32
     }
 
     // Returns true if the position should be evaluated as a draw.
-    pub fn is_draw(refs: &SearchRefs) -> bool {
-        let is_max_move_rule = refs.board.game_state.halfmove_clock >= MAX_MOVE_RULE;
-        Search::is_insufficient_material(refs)
-            || Search::is_repetition(refs.board) > 0
-            || is_max_move_rule
+    pub fn is_draw(refs: &mut SearchRefs) -> bool {
+        draw::is_material_draw(refs.board)
+            || Search::is_repetition_draw(refs.board, refs.search_info.ply)
+            || Search::is_max_move_draw(refs)
+    }
+
+    // A position that repeats within the search tree is a draw right
+    // away: if repeating is good for the side that can do so, it will
+    // repeat again. A position that was already on the board before the
+    // search started, must occur for the third time.
+    fn is_repetition_draw(board: &Board, ply: i16) -> bool {
+        let mut count = 0;
+
+        for plies_back in Search::repetitions(board) {
+            if plies_back < ply as usize {
+                return true;
+            }
+
+            count += 1;
+            if count == 2 {
+                return true;
+            }
+        }
+
+        false
+    }
+
+    // Checkmate on the 100th half move wins; the 50-move rule only
+    // draws if the side to move is not mated.
+    fn is_max_move_draw(refs: &mut SearchRefs) -> bool {
+        if refs.board.game_state.halfmove_clock < MAX_MOVE_RULE {
+            return false;
+        }
+
+        let us = refs.board.us();
+        let king_square = refs.board.king_square(us);
+        if !refs.mg.square_attacked(refs.board, us ^ 1, king_square) {
+            return true;
+        }
+
+        let mut move_list = MoveList::new();
+        refs.mg
+            .generate_moves(refs.board, &mut move_list, MoveType::All);
+
+        for i in 0..move_list.len() {
+            if refs.board.make(move_list.get_move(i), refs.mg) {
+                refs.board.unmake();
+                return true;
+            }
+        }
+
+        false
     }
 
     // Detects position repetitions in the game's history.
     pub fn is_repetition(board: &Board) -> u8 {
-        let mut count = 0;
-        let mut stop = false;
-        let mut i = board.history.len() - 1;
+        Search::repetitions(board).count() as u8
+    }
+
+    // Returns how many plies ago each earlier occurrence of the current
+    // position was on the board, most recent first. Only positions with
+    // the same side to move are compared. The search stops at the last
+    // irreversible move: positions before it can't ever repeat.
+    fn repetitions(board: &Board) -> impl Iterator<Item = usize> + '_ {
+        let len = board.history.len();
+        let reversible = len - (board.game_state.last_irreversible as usize).min(len);
+        let key = board.game_state.zobrist_key;
+
+        (2..=reversible)
+            .step_by(2)
+            .filter(move |plies_back| board.history.get_ref(len - plies_back).zobrist_key == key)
+    }
+}
+
+// Node budget per root move. In infinite analysis, a single root move can
+// explode and consume the search for hours. If a budget is set, a root
+// move that exceeds it is abandoned for this iteration, and the search
+// goes on with the next root move. The first move is never abandoned, as
+// it provides the score the other moves are compared against.
+impl Search {
+    pub fn root_move_limit(move_nr: u8, refs: &SearchRefs) -> Option<usize> {
+        let budget = refs.search_params.root_move_nodes;
+        let is_infinite = refs.search_params.search_mode == SearchMode::Infinite;
+
+        if is_infinite && budget > 0 && move_nr > 1 {
+            Some(refs.search_info.nodes + budget)
+        } else {
+            None
+        }
+    }
 
-        // Search the history list.
-        while i != 0 && !stop {
-            let historic = board.history.get_ref(i);
+    // Returns true if the root move currently being searched has used up
+    // its node budget.
+    pub fn root_move_exhausted(refs: &mut SearchRefs) -> bool {
+        if let Some(limit) = refs.search_info.root_move_limit {
+            if refs.search_info.nodes > limit {
+                refs.search_info.root_move_capped = true;
+            }
+        }
+
+        refs.search_info.root_move_capped
+    }
+}
 
-            // If the historic zobrist key is equal to the one of the board
-            // passed into the function, then we found a repetition.
-            if historic.zobrist_key == board.game_state.zobrist_key {
-                count += 1;
+// PV reconstruction. The PV of a node is collected while searching, but a
+// node that returns a value from the TT has no PV, so the PV can be cut
+// short. It is then completed with the best moves stored in the TT.
+impl Search {
+    // Extends the PV with moves from the TT, until it is as long as the
+    // depth. A mate PV is not extended: it ends with the mate. Each TT move
+    // is checked against the move generator, and the walk stops if a
+    // position repeats, so the PV is always legal and finite.
+    pub fn extend_pv_from_tt(pv: &mut Vec<Move>, depth: i8, eval: i16, refs: &mut SearchRefs) {
+        let length = depth.max(0) as usize;
+        if !refs.tt_enabled || eval.abs() >= CHECKMATE_THRESHOLD || pv.len() >= length {
+            return;
+        }
+
+        // Play the PV, and remember the positions that were visited.
+        let mut keys = vec![refs.board.game_state.zobrist_key];
+        let mut played = 0;
+        for m in pv.iter() {
+            if !refs.board.make(*m, refs.mg) {
+                break;
             }
+            played += 1;
+            keys.push(refs.board.game_state.zobrist_key);
+        }
 
-            // If the historic HMC is 0, it indicates that this position
-            // was created by a capture or pawn move. We don't have to
-            // search further back, because before this, we can't ever
-            // repeat. After all, the capture or pawn move can't be
-            // reverted or repeated.
-            stop = historic.halfmove_clock == 0;
+        // Add TT moves until the PV is long enough, or no move is found.
+        while played == pv.len() && pv.len() < length {
+            let key = refs.board.game_state.zobrist_key;
+            let tt_move = match refs.tt.read().expect(ErrFatal::LOCK).probe(key) {
+                Some(data) => data.best_move(),
+                None => break,
+            };
 
-            // Search backwards.
-            i -= 1;
+            match Search::legal_tt_move(tt_move, refs) {
+                Some(m) if !keys.contains(&refs.board.game_state.zobrist_key) => {
+                    keys.push(refs.board.game_state.zobrist_key);
+                    pv.push(m);
+                    played += 1;
+                }
+                Some(_) => {
+                    refs.board.unmake();
+                    break;
+                }
+                None => break,
+            }
+        }
+
+        for _ in 0..played {
+            refs.board.unmake();
         }
-        count
+    }
+
+    // Plays the TT move if it is legal in the current position.
+    fn legal_tt_move(tt_move: ShortMove, refs: &mut SearchRefs) -> Option<Move> {
+        if tt_move.get_move() == 0 {
+            return None;
+        }
+
+        let mut move_list = MoveList::new();
+        refs.mg
+            .generate_moves(refs.board, &mut move_list, MoveType::All);
+
+        for i in 0..move_list.len() {
+            let m = move_list.get_move(i);
+            if m.to_short_move() == tt_move {
+                return refs.board.make(m, refs.mg).then_some(m);
+            }
+        }
+
+        None
     }
 }
 
-// This is in its own block so rustfmt::skip can be applied. Otherwhise
-// the layout of this function becomes very messy.
-#[rustfmt::skip]
+// Storing search results in the TT.
 impl Search {
-    pub fn is_insufficient_material(refs: &SearchRefs) -> bool {
-        // It's not a draw if: ...there are still pawns.
-        let w_p = refs.board.get_pieces(Pieces::PAWN, Sides::WHITE).count_ones() > 0;     
-        let b_p = refs.board.get_pieces(Pieces::PAWN, Sides::BLACK).count_ones() > 0;        
-        // ...there's a major piece on the board.
-        let w_q = refs.board.get_pieces(Pieces::QUEEN, Sides::WHITE).count_ones() > 0;
-        let b_q = refs.board.get_pieces(Pieces::QUEEN, Sides::BLACK).count_ones() > 0;
-        let w_r = refs.board.get_pieces(Pieces::ROOK, Sides::WHITE).count_ones() > 0;
-        let b_r = refs.board.get_pieces(Pieces::ROOK, Sides::BLACK).count_ones() > 0;
-        // ...or two bishops for one side.
-        // FIXME : Bishops must be on squares of different color
-        let w_b = refs.board.get_pieces(Pieces::BISHOP, Sides::WHITE).count_ones() > 1;
-        let b_b = refs.board.get_pieces(Pieces::BISHOP, Sides::BLACK).count_ones() > 1;
-        // ... or a bishop+knight for at least one side.
-        let w_bn =
-            refs.board.get_pieces(Pieces::BISHOP, Sides::WHITE).count_ones() > 0 &&
-            refs.board.get_pieces(Pieces::KNIGHT, Sides::WHITE).count_ones() > 0;
-        let b_bn =
-            refs.board.get_pieces(Pieces::BISHOP, Sides::BLACK).count_ones() > 0 &&
-            refs.board.get_pieces(Pieces::KNIGHT, Sides::BLACK).count_ones() > 0;
-         
-        // If one of the conditions above is true, we still have enough
-        // material for checkmate, so insufficient_material returns false.
-        !(w_p || b_p || w_q || b_q || w_r || b_r || w_b || b_b ||  w_bn || b_bn)
+    // Stores the result of a node in the TT. A node that was interrupted
+    // has not been searched to its full depth, so its score is worthless
+    // and is not stored. A search also doesn't store anything after the
+    // TT was cleared or resized underneath it (by "ucinewgame" or a hash
+    // size change): its generation no longer matches, and its entries
+    // would leak into the next search.
+    pub fn tt_store(data: SearchData, refs: &mut SearchRefs) {
+        if refs.search_info.interrupted() {
+            return;
+        }
+
+        let key = refs.board.game_state.zobrist_key;
+        let tt = refs.tt.read().expect(ErrFatal::LOCK);
+        if tt.generation() == refs.search_info.tt_generation {
+            refs.search_info.tt_stats += tt.insert(key, data);
+        }
+    }
+
+    // Adds what this thread's stores did to the TT's counters, once it
+    // has finished searching.
+    pub fn publish_tt_stats(refs: &mut SearchRefs) {
+        let tt = refs.tt.read().expect(ErrFatal::LOCK);
+        tt.add_stats(&refs.search_info.tt_stats);
+        refs.search_info.tt_stats = TTStats::default();
     }
 }
 
@@ -214,4 +575,29 @@ impl Search {
             refs.search_info.killer_moves[ply][0] = current_move.to_short_move();
         }
     }
+
+    // A quiet move caused a beta-cutoff; reward it in the history table.
+    pub fn update_history_heuristic(current_move: Move, depth: i8, refs: &mut SearchRefs) {
+        let us = refs.board.us();
+        let previous = Search::previous_moves(refs);
+        refs.history.update(us, current_move, &previous, depth);
+    }
+
+    // The moves that led to the current position: the opponent's last
+    // move first, then our own move before that. The board's history
+    // already contains them, including the moves played before the
+    // search started. Where there is no move (at the start of the game)
+    // or it was a null move, the move is empty.
+    pub fn previous_moves(refs: &SearchRefs) -> [Move; CONT_HISTORY_PLIES] {
+        let mut previous = [Move::new(0); CONT_HISTORY_PLIES];
+        let len = refs.board.history.len();
+
+        for (plies_back, p) in previous.iter_mut().enumerate() {
+            if plies_back < len {
+                *p = refs.board.history.get_ref(len - 1 - plies_back).next_move;
+            }
+        }
+
+        previous
+    }
 }
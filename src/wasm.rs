@@ -0,0 +1,130 @@
+/* =======================================================================
+Rustic is a chess playing engine.
+Copyright (C) 2019-2024, Marcel Vanthoor
+https://rustic-chess.org/
+
+Rustic is written in the Rust programming language. It is an original
+work, not derived from any engine that came before it. However, it does
+use a lot of concepts which are well-known and are in use by most if not
+all classical alpha/beta-based chess engines.
+
+Rustic is free software: you can redistribute it and/or modify it under
+the terms of the GNU General Public License version 3 as published by
+the Free Software Foundation.
+
+Rustic is distributed in the hope that it will be useful, but WITHOUT
+ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License
+for more details.
+
+You should have received a copy of the GNU General Public License along
+with this program.  If not, see <http://www.gnu.org/licenses/>.
+======================================================================= */
+
+// wasm.rs exposes Rustic to JavaScript, for use in a browser. It wraps a
+// position and a SyncEngine, because a browser page has no threads to
+// run the normal engine in. A search blocks the page while it runs, so
+// it is best run in a Web Worker, which can pass the progress on to the
+// page. Build it with wasm-pack and the "wasm" feature:
+//
+//     wasm-pack build --target web -- --features wasm
+//
+// In JavaScript:
+//
+//     const rustic = new Rustic(16);
+//     rustic.setPosition("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3");
+//     rustic.onProgress((p) => console.log(p.depth, p.score, p.mate, p.pv));
+//     const best = rustic.go(8);            // Search to depth 8, or...
+//     const fast = rustic.go(undefined, 500); // ...search for 500 ms.
+
+use crate::api::{Position, Score, SearchLimits, SearchProgress, SyncEngine};
+use js_sys::{Function, Object, Reflect};
+use wasm_bindgen::prelude::*;
+
+/// Rustic, as seen from JavaScript.
+#[wasm_bindgen]
+pub struct Rustic {
+    position: Position,
+    engine: SyncEngine,
+    on_progress: Option<Function>,
+}
+
+#[wasm_bindgen]
+impl Rustic {
+    /// Creates the engine with a hash table of the given size in
+    /// megabytes, and sets up the starting position.
+    #[wasm_bindgen(constructor)]
+    pub fn new(hash_size: usize) -> Rustic {
+        Rustic {
+            position: Position::new(),
+            engine: SyncEngine::new(hash_size),
+            on_progress: None,
+        }
+    }
+
+    /// Sets up the position to search from a FEN-string.
+    #[wasm_bindgen(js_name = setPosition)]
+    pub fn set_position(&mut self, fen: &str) -> Result<(), JsError> {
+        self.position = Position::from_fen(fen)?;
+        Ok(())
+    }
+
+    /// Sets the function that is called after each completed depth, or
+    /// removes it if there is none.
+    #[wasm_bindgen(js_name = onProgress)]
+    pub fn on_progress(&mut self, callback: Option<Function>) {
+        self.on_progress = callback;
+    }
+
+    /// Searches the position to the given depth, or for the given number
+    /// of milliseconds if there is a move time. Returns the best move in
+    /// long algebraic notation, or undefined if there are no legal moves.
+    pub fn go(
+        &mut self,
+        depth: Option<i8>,
+        move_time: Option<u32>,
+    ) -> Result<Option<String>, JsError> {
+        let limits = match (depth, move_time) {
+            (_, Some(msecs)) => SearchLimits::new().move_time(u128::from(msecs)),
+            (Some(depth), None) => SearchLimits::new().depth(depth),
+            (None, None) => return Err(JsError::new("go needs a depth or a move time")),
+        };
+
+        let on_progress = self.on_progress.as_ref();
+        let result = self.engine.search_with(&self.position, &limits, |p| {
+            if let Some(callback) = on_progress {
+                let _ = callback.call1(&JsValue::NULL, &progress_object(p));
+            }
+        });
+
+        Ok(result.best_move)
+    }
+}
+
+// Converts search progress into an object JavaScript can read. A score is
+// either in centipawns ("score") or a mate in a number of moves ("mate").
+// The other one is undefined.
+fn progress_object(p: &SearchProgress) -> Object {
+    let (score, mate) = match p.score {
+        Score::Cp(cp) => (JsValue::from(cp), JsValue::UNDEFINED),
+        Score::Mate(moves) => (JsValue::UNDEFINED, JsValue::from(moves)),
+    };
+
+    let fields = [
+        ("depth", JsValue::from(p.depth)),
+        ("seldepth", JsValue::from(p.seldepth)),
+        ("score", score),
+        ("mate", mate),
+        ("nodes", JsValue::from(p.nodes as f64)),
+        ("nps", JsValue::from(p.nps as f64)),
+        ("time", JsValue::from(p.time_ms as f64)),
+        ("pv", JsValue::from(p.pv.join(" "))),
+    ];
+
+    let object = Object::new();
+    for (name, value) in fields {
+        let _ = Reflect::set(&object, &JsValue::from(name), &value);
+    }
+
+    object
+}